@@ -0,0 +1,139 @@
+use serde::{Deserialize, Serialize};
+use crate::config::StreamProfile;
+
+/// 节点准入控制配置
+#[derive(Debug, Deserialize, Clone)]
+pub struct AdmissionConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// 节点容量（负载单位），0 表示按 CPU 核数 × 100 估算
+    #[serde(default)]
+    pub capacity: u32,
+    /// 主码流各处理方式的预估负载
+    #[serde(default = "default_main_weights")]
+    pub main: CostWeights,
+    /// 子码流各处理方式的预估负载
+    #[serde(default = "default_sub_weights")]
+    pub sub: CostWeights,
+}
+
+impl Default for AdmissionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            capacity: 0,
+            main: default_main_weights(),
+            sub: default_sub_weights(),
+        }
+    }
+}
+
+/// 单个流各处理方式的预估负载（负载单位，100 约等于一个 CPU 核）
+#[derive(Debug, Deserialize, Clone)]
+pub struct CostWeights {
+    /// 只转封装
+    pub remux: u32,
+    /// 解码做画面检测或预览缩略图
+    pub analyze: u32,
+    /// 解码后重新编码
+    pub transcode: u32,
+}
+
+fn default_main_weights() -> CostWeights {
+    CostWeights { remux: 2, analyze: 15, transcode: 100 }
+}
+
+fn default_sub_weights() -> CostWeights {
+    CostWeights { remux: 1, analyze: 5, transcode: 30 }
+}
+
+/// 播放请求需要的处理
+pub struct Work {
+    pub profile: StreamProfile,
+    /// 启用了画面检测或预览缩略图，可以降级去掉
+    pub analyze: bool,
+    /// 源必须重新编码（如测试信号源），无法降级
+    pub transcode: bool,
+}
+
+/// 准入结果
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Accept,
+    /// 降级为只转封装，不做画面检测、预览缩略图等解码处理
+    Downgrade,
+    Reject,
+}
+
+/// 准入决定，返回在播放响应中
+#[derive(Debug, Serialize, Clone)]
+pub struct Decision {
+    pub action: Action,
+    /// 本次启动的预估负载（降级后为只转封装的负载）
+    pub cost: u32,
+    /// 启动前的节点负载
+    pub load: u32,
+    pub capacity: u32,
+}
+
+/// 节点负载过高，拒绝播放
+#[derive(Debug)]
+pub struct Rejected(pub Decision);
+
+impl std::fmt::Display for Rejected {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "节点负载过高（{} / {}），无法再承担预估负载 {}", self.0.load, self.0.capacity, self.0.cost)
+    }
+}
+
+impl std::error::Error for Rejected {}
+
+impl AdmissionConfig {
+    /// 节点容量，未配置时按 CPU 核数估算
+    pub fn capacity(&self) -> u32 {
+        match self.capacity {
+            0 => std::thread::available_parallelism().map_or(1, |n| n.get()) as u32 * 100,
+            capacity => capacity,
+        }
+    }
+
+    /// 评估播放请求，`committed` 为运行中流的预估负载之和；未启用时返回 None
+    ///
+    /// 节点负载取运行中流的预估负载与系统实际负载（1 分钟平均负载按核数折算）中的较大值。
+    /// 只转封装的请求总是接受；需要解码的请求超出容量时，能降级则降级为只转封装，否则拒绝。
+    pub fn evaluate(&self, work: &Work, committed: u32) -> Option<Decision> {
+        if !self.enabled {
+            return None;
+        }
+        let weights = match work.profile {
+            StreamProfile::Main => &self.main,
+            StreamProfile::Sub => &self.sub,
+        };
+        let capacity = self.capacity();
+        let load = committed.max(system_load(capacity));
+        let remux = weights.remux;
+        let full = remux + if work.analyze { weights.analyze } else { 0 } + if work.transcode { weights.transcode } else { 0 };
+
+        let (action, cost) = if full == remux || load + full <= capacity {
+            (Action::Accept, full)
+        } else if !work.transcode {
+            (Action::Downgrade, remux)
+        } else {
+            (Action::Reject, full)
+        };
+        Some(Decision { action, cost, load, capacity })
+    }
+}
+
+/// 系统 1 分钟平均负载按 CPU 核数折算为负载单位，无法读取时为 0
+fn system_load(capacity: u32) -> u32 {
+    let Ok(content) = std::fs::read_to_string("/proc/loadavg") else {
+        return 0;
+    };
+    let Some(load) = content.split_whitespace().next().and_then(|v| v.parse::<f64>().ok()) else {
+        return 0;
+    };
+    let cores = std::thread::available_parallelism().map_or(1, |n| n.get()) as f64;
+    (load / cores * capacity as f64) as u32
+}
@@ -0,0 +1,159 @@
+use serde::Deserialize;
+
+/// 异步转封装配置（需要以 `async-remux` 特性编译）
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct AsyncRemuxConfig {
+    /// 只转封装的流（准入控制降级）改用 ffmpeg 子进程运行，由异步运行时管理，不再每个流占用一个线程
+    #[serde(default)]
+    pub enabled: bool,
+    /// 所有流都以子进程方式只转封装，GOP 缓存、画面检测等需要在本进程处理数据包的功能对这些流不可用
+    #[serde(default)]
+    pub all_streams: bool,
+    /// ffmpeg 可执行文件，缺省从 PATH 查找
+    #[serde(default = "default_ffmpeg")]
+    pub ffmpeg: String,
+}
+
+fn default_ffmpeg() -> String {
+    "ffmpeg".to_string()
+}
+
+impl AsyncRemuxConfig {
+    /// 流是否以子进程方式运行
+    #[cfg_attr(not(feature = "async-remux"), allow(dead_code))]
+    pub fn applies(&self, remux_only: bool) -> bool {
+        self.enabled && (self.all_streams || remux_only)
+    }
+}
+
+#[cfg(feature = "async-remux")]
+pub use runner::*;
+
+#[cfg(feature = "async-remux")]
+mod runner {
+    use anyhow::{Result, anyhow};
+    use std::process::Stdio;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::Duration;
+    use tokio::io::{AsyncBufReadExt, BufReader};
+    use tokio::process::Command;
+    use tracing::{debug, info};
+    use crate::config::{InputOptions, OutputOptions};
+    use crate::http_flv;
+    use crate::stats::StreamStats;
+    use crate::{transcoder, vault};
+
+    /// 检查停止信号的间隔
+    const STOP_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+    /// 是否可以用子进程运行：需要本进程处理的输入、独立模式的本地输出、推流限速与 H.265 转码不支持
+    pub fn supports(input_url: &str, input_options: &InputOptions, output_url: &str, output_options: &OutputOptions) -> bool {
+        transcoder::subprocess_input(input_url, input_options).is_some()
+            && output_url != http_flv::LOCAL_OUTPUT
+            && output_options.max_kbps.is_none()
+            && output_options.hevc_to_h264.is_none()
+    }
+
+    /// 以 ffmpeg 子进程只转封装一个流
+    ///
+    /// 与 [`transcoder::Transcoder`] 每个流占用一个阻塞线程不同，这里只有一个异步任务等待子进程的进度输出，
+    /// 单个节点可以运行大量只转封装的流。进度中的累计字节数计入输出统计；子进程首次报告输出后调用 `on_started`。
+    /// `running` 被清除时结束子进程。
+    #[allow(clippy::too_many_arguments)]
+    pub async fn run(
+        ffmpeg: &str,
+        input_url: &str,
+        input_options: &InputOptions,
+        output_url: &str,
+        output_options: &OutputOptions,
+        running: &AtomicBool,
+        stats: &Arc<StreamStats>,
+        on_started: impl FnOnce(),
+    ) -> Result<()> {
+        // 重新解析主机名是阻塞调用
+        let (url, options) = {
+            let (url, options) = (input_url.to_string(), input_options.clone());
+            tokio::task::spawn_blocking(move || transcoder::subprocess_input(&url, &options))
+                .await?
+                .ok_or(anyhow!("输入不支持以子进程方式拉流"))?
+        };
+
+        let mut command = Command::new(ffmpeg);
+        command.args(["-hide_banner", "-nostdin", "-nostats", "-loglevel", "error", "-progress", "pipe:1"]);
+        for (key, value) in &options {
+            command.arg(format!("-{}", key)).arg(value);
+        }
+        command.arg("-i").arg(&url);
+        if output_options.audio_only {
+            command.arg("-vn");
+        }
+        if output_options.no_audio {
+            command.arg("-an");
+        }
+        if let Some(flags) = output_options.flv.muxer_flags() {
+            command.arg("-flvflags").arg(flags);
+        }
+        command
+            .args(["-c", "copy", "-f", "flv"])
+            .arg(output_url)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        let mut child = command.spawn().map_err(|e| anyhow!("启动 ffmpeg 失败 ({}): {}", ffmpeg, e))?;
+        info!("ffmpeg 子进程已启动 (pid {:?}): {}", child.id(), vault::redact_url(&url));
+
+        // 保留最后一行错误输出作为失败原因
+        let stderr = child.stderr.take().ok_or(anyhow!("无法读取 ffmpeg 错误输出"))?;
+        let last_error = tokio::spawn(async move {
+            let mut lines = BufReader::new(stderr).lines();
+            let mut last = String::new();
+            while let Ok(Some(line)) = lines.next_line().await {
+                debug!("ffmpeg: {}", line);
+                last = line;
+            }
+            last
+        });
+
+        let stdout = child.stdout.take().ok_or(anyhow!("无法读取 ffmpeg 进度输出"))?;
+        let mut progress = BufReader::new(stdout).lines();
+        let mut on_started = Some(on_started);
+        let mut reported = 0u64;
+        let mut poll = tokio::time::interval(STOP_POLL_INTERVAL);
+        loop {
+            tokio::select! {
+                line = progress.next_line() => {
+                    let Some(line) = line? else {
+                        break;
+                    };
+                    // 进度按 key=value 逐行输出，total_size 为已写出的累计字节数
+                    if let Some(total) = line.strip_prefix("total_size=").and_then(|v| v.parse::<u64>().ok())
+                        && total > reported
+                    {
+                        stats.output.record_bytes(total - reported);
+                        reported = total;
+                        if let Some(on_started) = on_started.take() {
+                            on_started();
+                        }
+                    }
+                }
+                _ = poll.tick() => {
+                    if !running.load(Ordering::Relaxed) {
+                        info!("收到停止转码请求。");
+                        child.kill().await?;
+                        return Ok(());
+                    }
+                }
+            }
+        }
+
+        let status = child.wait().await?;
+        let last_error = last_error.await.unwrap_or_default();
+        match status.success() {
+            true => Ok(()),
+            false if last_error.is_empty() => Err(anyhow!("ffmpeg 异常退出 ({})", status)),
+            false => Err(anyhow!("ffmpeg 异常退出 ({}): {}", status, last_error)),
+        }
+    }
+}
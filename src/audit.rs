@@ -0,0 +1,187 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Semaphore;
+use tracing::{info, warn};
+use crate::config::InputOptions;
+use crate::history::now_secs;
+use crate::transcoder::Transcoder;
+use crate::vault;
+
+/// 摄像头连通性巡检配置
+#[derive(Debug, Deserialize, Clone)]
+pub struct AuditConfig {
+    /// 服务启动时巡检全部配置的流
+    #[serde(default)]
+    pub on_startup: bool,
+    /// 单个流的探测超时（秒）
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+    /// 同时探测的流数量
+    #[serde(default = "default_concurrency")]
+    pub concurrency: usize,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self {
+            on_startup: false,
+            timeout_secs: default_timeout_secs(),
+            concurrency: default_concurrency(),
+        }
+    }
+}
+
+fn default_timeout_secs() -> u64 {
+    10
+}
+
+fn default_concurrency() -> usize {
+    16
+}
+
+/// 待巡检的流，地址解析失败（如凭据不存在）时直接记为不可达
+pub struct AuditTarget {
+    pub name: String,
+    pub source: Result<(String, InputOptions), String>,
+}
+
+/// 单个流的巡检结果
+#[derive(Debug, Serialize, Clone)]
+pub struct AuditEntry {
+    pub name: String,
+    /// 探测的地址（隐藏密码）
+    pub url: Option<String>,
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub elapsed_ms: u64,
+}
+
+/// 巡检报告
+#[derive(Debug, Serialize, Clone)]
+pub struct AuditReport {
+    pub running: bool,
+    pub started_at: u64,
+    pub finished_at: Option<u64>,
+    pub total: usize,
+    pub reachable: usize,
+    pub unreachable: usize,
+    /// 不可达的排在前面
+    pub results: Vec<AuditEntry>,
+}
+
+/// 探测源是否可连接，失败时返回原因
+pub async fn probe(url: String, options: InputOptions, timeout: Duration) -> Option<String> {
+    let task = tokio::task::spawn_blocking(move || Transcoder::probe(&url, &options));
+    match tokio::time::timeout(timeout, task).await {
+        Ok(Ok(Ok(()))) => None,
+        Ok(Ok(Err(e))) => Some(e.to_string()),
+        Ok(Err(e)) => Some(e.to_string()),
+        Err(_) => Some("探测超时".to_string()),
+    }
+}
+
+/// 连通性巡检
+///
+/// 新部署的站点一次性检查全部摄像头地址是否可用，结果保留到下一次巡检。
+#[derive(Default)]
+pub struct Auditor {
+    report: Mutex<Option<AuditReport>>,
+}
+
+impl Auditor {
+    /// 最近一次巡检报告
+    pub fn report(&self) -> Option<AuditReport> {
+        self.report.lock().unwrap().clone()
+    }
+
+    /// 开始巡检，已有巡检进行中时返回 false
+    pub fn start(self: &Arc<Self>, targets: Vec<AuditTarget>, config: &AuditConfig) -> bool {
+        {
+            let mut report = self.report.lock().unwrap();
+            if report.as_ref().is_some_and(|r| r.running) {
+                return false;
+            }
+            *report = Some(AuditReport {
+                running: true,
+                started_at: now_secs(),
+                finished_at: None,
+                total: targets.len(),
+                reachable: 0,
+                unreachable: 0,
+                results: Vec::new(),
+            });
+        }
+
+        let auditor = self.clone();
+        let timeout = Duration::from_secs(config.timeout_secs.max(1));
+        let semaphore = Arc::new(Semaphore::new(config.concurrency.max(1)));
+        tokio::spawn(async move {
+            info!("开始巡检 {} 个流的连通性", targets.len());
+            let mut tasks = tokio::task::JoinSet::new();
+            for target in targets {
+                let semaphore = semaphore.clone();
+                tasks.spawn(async move {
+                    let _permit = semaphore.acquire_owned().await;
+                    let started = Instant::now();
+                    let (url, error) = match target.source {
+                        Ok((url, options)) => (Some(vault::redact_url(&url)), probe(url, options, timeout).await),
+                        Err(e) => (None, Some(e)),
+                    };
+                    AuditEntry {
+                        name: target.name,
+                        url,
+                        ok: error.is_none(),
+                        error,
+                        elapsed_ms: started.elapsed().as_millis() as u64,
+                    }
+                });
+            }
+
+            let mut results = Vec::new();
+            while let Some(joined) = tasks.join_next().await {
+                let Ok(entry) = joined else { continue };
+                if let Some(error) = &entry.error {
+                    warn!("巡检: 流 '{}' 不可达: {}", entry.name, error);
+                }
+                results.push(entry);
+            }
+            results.sort_by(|a, b| a.ok.cmp(&b.ok).then_with(|| a.name.cmp(&b.name)));
+
+            let mut report = auditor.report.lock().unwrap();
+            if let Some(report) = report.as_mut() {
+                report.reachable = results.iter().filter(|r| r.ok).count();
+                report.unreachable = results.len() - report.reachable;
+                report.results = results;
+                report.running = false;
+                report.finished_at = Some(now_secs());
+                info!("巡检完成: 共 {} 个流，{} 个不可达", report.total, report.unreachable);
+            }
+        });
+        true
+    }
+}
+
+/// 巡检结果的 Prometheus 指标
+pub fn prometheus(report: &AuditReport) -> String {
+    let mut out = String::new();
+    if report.finished_at.is_none() {
+        return out;
+    }
+    let _ = writeln!(out, "# HELP rtsp2flv_audit_unreachable 最近一次巡检不可达的流数量");
+    let _ = writeln!(out, "# TYPE rtsp2flv_audit_unreachable gauge");
+    let _ = writeln!(out, "rtsp2flv_audit_unreachable {}", report.unreachable);
+    let _ = writeln!(out, "# HELP rtsp2flv_audit_reachable 最近一次巡检中流是否可达");
+    let _ = writeln!(out, "# TYPE rtsp2flv_audit_reachable gauge");
+    for entry in &report.results {
+        let _ = writeln!(
+            out,
+            "rtsp2flv_audit_reachable{{stream=\"{}\"}} {}",
+            crate::stats::escape_label(&entry.name),
+            if entry.ok { 1 } else { 0 }
+        );
+    }
+    out
+}
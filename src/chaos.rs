@@ -0,0 +1,127 @@
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// 未指定时故障持续的时长（秒）
+const DEFAULT_DURATION_SECS: u64 = 60;
+
+/// 故障持续时长上限（秒），避免测试结束后忘记清除
+const MAX_DURATION_SECS: u64 = 3600;
+
+lazy_static! {
+    static ref FAULTS: Mutex<Faults> = Mutex::new(Faults::default());
+}
+
+/// 进程级的故障，到期后自动清除
+///
+/// 故障注入接口只在以 `chaos` 特性编译时提供，用于在测试环境验证重启策略、主备切换与报警，不要用于生产环境。
+#[derive(Default)]
+struct Faults {
+    /// SRS 接口延迟与到期时间
+    srs_delay: Option<(Duration, Instant)>,
+    /// 丢弃心跳的流（None 为全部流）与到期时间
+    drop_heartbeats: Option<(Option<String>, Instant)>,
+}
+
+/// 单个转码任务的故障注入开关
+#[derive(Default)]
+pub struct StreamFault {
+    kill: AtomicBool,
+}
+
+impl StreamFault {
+    /// 让转码线程在处理下一个数据包时以错误退出
+    pub fn kill(&self) {
+        self.kill.store(true, Ordering::Relaxed);
+    }
+
+    /// 是否需要终止转码线程，每次注入只触发一次
+    pub fn take_kill(&self) -> bool {
+        self.kill.swap(false, Ordering::Relaxed)
+    }
+}
+
+/// 注入 SRS 接口延迟的请求
+#[derive(Debug, Deserialize)]
+pub struct SrsDelayRequest {
+    pub delay_ms: u64,
+    /// 持续时长（秒），缺省 60 秒
+    #[serde(default)]
+    pub duration_secs: Option<u64>,
+}
+
+/// 丢弃心跳的请求
+#[derive(Debug, Deserialize, Default)]
+pub struct DropHeartbeatsRequest {
+    /// 只丢弃该流的心跳，缺省丢弃全部流的心跳
+    #[serde(default)]
+    pub stream: Option<String>,
+    /// 持续时长（秒），缺省 60 秒
+    #[serde(default)]
+    pub duration_secs: Option<u64>,
+}
+
+/// 当前生效的故障
+#[derive(Debug, Serialize)]
+pub struct ChaosStatus {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub srs_delay_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub srs_delay_remaining_secs: Option<u64>,
+    pub drop_heartbeats: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub drop_heartbeats_stream: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub drop_heartbeats_remaining_secs: Option<u64>,
+}
+
+fn deadline(duration_secs: Option<u64>) -> Instant {
+    Instant::now() + Duration::from_secs(duration_secs.unwrap_or(DEFAULT_DURATION_SECS).clamp(1, MAX_DURATION_SECS))
+}
+
+/// 设置 SRS 接口延迟，`delay_ms` 为 0 时清除
+pub fn set_srs_delay(request: &SrsDelayRequest) {
+    let mut faults = FAULTS.lock().unwrap();
+    faults.srs_delay = (request.delay_ms > 0).then(|| (Duration::from_millis(request.delay_ms), deadline(request.duration_secs)));
+}
+
+/// 开始丢弃心跳
+pub fn set_drop_heartbeats(request: DropHeartbeatsRequest) {
+    FAULTS.lock().unwrap().drop_heartbeats = Some((request.stream, deadline(request.duration_secs)));
+}
+
+/// 清除全部进程级故障
+pub fn clear() {
+    *FAULTS.lock().unwrap() = Faults::default();
+}
+
+/// 当前生效的 SRS 接口延迟
+pub fn srs_delay() -> Option<Duration> {
+    let faults = FAULTS.lock().unwrap();
+    faults.srs_delay.filter(|(_, until)| Instant::now() < *until).map(|(delay, _)| delay)
+}
+
+/// 是否丢弃流的心跳
+pub fn drops_heartbeat(stream: &str) -> bool {
+    let faults = FAULTS.lock().unwrap();
+    faults
+        .drop_heartbeats
+        .as_ref()
+        .is_some_and(|(target, until)| Instant::now() < *until && target.as_deref().is_none_or(|t| t == stream))
+}
+
+pub fn status() -> ChaosStatus {
+    let faults = FAULTS.lock().unwrap();
+    let now = Instant::now();
+    let srs_delay = faults.srs_delay.filter(|(_, until)| now < *until);
+    let drop_heartbeats = faults.drop_heartbeats.as_ref().filter(|(_, until)| now < *until);
+    ChaosStatus {
+        srs_delay_ms: srs_delay.map(|(delay, _)| delay.as_millis() as u64),
+        srs_delay_remaining_secs: srs_delay.map(|(_, until)| until.duration_since(now).as_secs()),
+        drop_heartbeats: drop_heartbeats.is_some(),
+        drop_heartbeats_stream: drop_heartbeats.and_then(|(stream, _)| stream.clone()),
+        drop_heartbeats_remaining_secs: drop_heartbeats.map(|(_, until)| until.duration_since(now).as_secs()),
+    }
+}
@@ -0,0 +1,258 @@
+use anyhow::{Result, anyhow};
+use ffmpeg_next as ffmpeg;
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// 片段下载配置
+#[derive(Debug, Deserialize, Clone)]
+pub struct ClipConfig {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// 每个流在内存中保留的最近时长（秒），也是单个片段的最长时长
+    #[serde(default = "default_buffer_secs")]
+    pub buffer_secs: u64,
+    /// 单个流缓冲的最大字节数，超出时丢弃最早的 GOP
+    #[serde(default = "default_max_bytes")]
+    pub max_bytes: usize,
+}
+
+impl Default for ClipConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_enabled(),
+            buffer_secs: default_buffer_secs(),
+            max_bytes: default_max_bytes(),
+        }
+    }
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_buffer_secs() -> u64 {
+    60
+}
+
+fn default_max_bytes() -> usize {
+    64 * 1024 * 1024
+}
+
+struct BufferedPacket {
+    packet: ffmpeg::Packet,
+    /// 片段可以从该包开始（视频关键帧；纯音频流的每个包）
+    key: bool,
+    at: Instant,
+}
+
+#[derive(Default)]
+struct BufferState {
+    params: Vec<ffmpeg::codec::Parameters>,
+    time_bases: Vec<ffmpeg::Rational>,
+    video: Option<usize>,
+    packets: VecDeque<BufferedPacket>,
+    bytes: usize,
+    /// 每次重置加一，用于判断截取期间转码器是否重连过
+    generation: u64,
+}
+
+impl BufferState {
+    /// 丢弃最早的一个 GOP，返回是否有数据被丢弃
+    fn drop_first_gop(&mut self) -> bool {
+        let next_key = self.packets.iter().skip(1).position(|p| p.key).map(|i| i + 1);
+        let count = next_key.unwrap_or(self.packets.len());
+        for p in self.packets.drain(..count) {
+            self.bytes -= p.packet.size();
+        }
+        count > 0
+    }
+}
+
+/// 最近数据包缓冲
+///
+/// 按 GOP 保留推给 SRS 的数据包（已修正时间戳），用于下载最近或接下来 N 秒的片段，
+/// 不需要另外录像。转码器重连时清空。
+pub struct ClipBuffer {
+    config: ClipConfig,
+    state: Mutex<BufferState>,
+}
+
+/// 截取出的片段
+pub struct Clip {
+    params: Vec<ffmpeg::codec::Parameters>,
+    time_bases: Vec<ffmpeg::Rational>,
+    packets: Vec<ffmpeg::Packet>,
+    /// 首个与最后一个数据包的缓冲时间
+    first_at: Instant,
+    last_at: Instant,
+}
+
+impl ClipBuffer {
+    pub fn new(config: ClipConfig) -> Self {
+        Self {
+            config,
+            state: Mutex::new(BufferState::default()),
+        }
+    }
+
+    /// 单个片段的最长时长
+    pub fn max_duration(&self) -> Duration {
+        Duration::from_secs(self.config.buffer_secs)
+    }
+
+    /// 转码器（重新）连接后设置输出流参数并清空旧数据
+    pub fn reset(&self, params: &[ffmpeg::codec::Parameters], time_bases: &[ffmpeg::Rational]) {
+        let mut state = self.state.lock().unwrap();
+        *state = BufferState {
+            // 复制一份，不引用转码器的输出上下文
+            params: params.to_vec(),
+            time_bases: time_bases.to_vec(),
+            video: params.iter().position(|p| p.medium() == ffmpeg::media::Type::Video),
+            generation: state.generation + 1,
+            ..Default::default()
+        };
+    }
+
+    /// 缓冲重置的次数
+    pub fn generation(&self) -> u64 {
+        self.state.lock().unwrap().generation
+    }
+
+    /// 写入一个输出数据包（输出流时间基）
+    pub fn push(&self, index: usize, packet: &ffmpeg::Packet) {
+        let mut state = self.state.lock().unwrap();
+        let key = match state.video {
+            Some(video) => index == video && packet.is_key(),
+            None => true,
+        };
+        // 还没有可作为开头的关键帧时不缓冲
+        if state.packets.is_empty() && !key {
+            return;
+        }
+
+        let now = Instant::now();
+        if key {
+            // 新 GOP 开始时丢弃整段已超出保留时长的 GOP
+            let cutoff = now.checked_sub(self.max_duration()).unwrap_or(now);
+            while let Some(next_key) = state.packets.iter().skip(1).position(|p| p.key).map(|i| i + 1) {
+                if state.packets[next_key].at > cutoff {
+                    break;
+                }
+                state.drop_first_gop();
+            }
+        }
+
+        let mut packet = packet.clone();
+        packet.set_stream(index);
+        state.bytes += packet.size();
+        state.packets.push_back(BufferedPacket { packet, key, at: now });
+        while state.bytes > self.config.max_bytes && state.drop_first_gop() {}
+    }
+
+    /// 截取从 `since` 之前最近的关键帧开始到当前的片段，缓冲不足时从最早的关键帧开始
+    pub fn clip(&self, since: Instant) -> Option<Clip> {
+        let state = self.state.lock().unwrap();
+        let start = state
+            .packets
+            .iter()
+            .rposition(|p| p.key && p.at <= since)
+            .or_else(|| state.packets.iter().position(|p| p.key))?;
+        Some(Clip {
+            params: state.params.clone(),
+            time_bases: state.time_bases.clone(),
+            packets: state.packets.iter().skip(start).map(|p| p.packet.clone()).collect(),
+            first_at: state.packets[start].at,
+            last_at: state.packets.back()?.at,
+        })
+    }
+}
+
+impl Clip {
+    /// 片段覆盖的时间范围（按数据包缓冲时间）
+    pub fn span(&self) -> (Instant, Instant) {
+        (self.first_at, self.last_at)
+    }
+
+    /// 各路流的编码，如 `h264`、`aac`
+    pub fn codecs(&self) -> Vec<String> {
+        self.params.iter().map(|p| format!("{:?}", p.id()).to_lowercase()).collect()
+    }
+
+    /// 写为 MP4 文件（moov 前置，可边下边播）
+    pub fn write_mp4(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut octx = ffmpeg::format::output_as(&path, "mp4")?;
+        for params in &self.params {
+            let mut params = params.clone();
+            // FLV 的 codec_tag 与 MP4 不兼容，清空后由 MP4 封装器重新选择
+            unsafe {
+                (*params.as_mut_ptr()).codec_tag = 0;
+            }
+            let mut ostream = octx.add_stream(ffmpeg::encoder::find(ffmpeg::codec::Id::None))?;
+            ostream.set_parameters(params);
+        }
+        let mut options = ffmpeg::Dictionary::new();
+        options.set("movflags", "+faststart");
+        octx.write_header_with(options)?;
+
+        // 时间戳从 0 开始，丢弃早于首个关键帧的音频包
+        let first = self.packets.first().ok_or(anyhow!("片段为空"))?;
+        let origin_base = self.time_bases[first.stream()];
+        let origin = first.dts().or(first.pts()).unwrap_or(0);
+        let mut written = 0;
+        for packet in &self.packets {
+            let index = packet.stream();
+            let Some(ostream) = octx.stream(index) else {
+                continue;
+            };
+            let ostream_time_base = ostream.time_base();
+            let offset = rescale(origin, origin_base, self.time_bases[index]);
+            if packet.dts().is_some_and(|dts| dts < offset) {
+                continue;
+            }
+            let mut packet = packet.clone();
+            packet.set_pts(packet.pts().map(|t| t - offset));
+            packet.set_dts(packet.dts().map(|t| t - offset));
+            packet.rescale_ts(self.time_bases[index], ostream_time_base);
+            packet.set_position(-1);
+            packet.write_interleaved(&mut octx)?;
+            written += 1;
+        }
+        octx.write_trailer()?;
+        if written == 0 {
+            return Err(anyhow!("片段为空"));
+        }
+        Ok(())
+    }
+}
+
+/// 文件名只保留安全字符，避免流名称中的路径分隔符
+pub fn file_stem(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+        .collect()
+}
+
+/// 删除进程异常退出时残留的临时片段文件（片段发送给客户端后即删除，启动时不应存在）
+pub fn remove_leftovers(dir: &Path) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for path in entries.flatten().map(|entry| entry.path()).filter(|path| path.is_file()) {
+        let _ = std::fs::remove_file(&path);
+    }
+}
+
+fn rescale(value: i64, from: ffmpeg::Rational, to: ffmpeg::Rational) -> i64 {
+    let (from_num, from_den) = (from.numerator() as i128, from.denominator() as i128);
+    let (to_num, to_den) = (to.numerator() as i128, to.denominator() as i128);
+    if from_den == 0 || to_num == 0 {
+        return value;
+    }
+    (value as i128 * from_num * to_den / (from_den * to_num)) as i64
+}
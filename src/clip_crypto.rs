@@ -0,0 +1,314 @@
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use anyhow::{Context, Result, anyhow};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::{Read, Write};
+use std::path::Path;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// 加密录像文件的文件头标识
+const MAGIC: &[u8; 8] = b"R2FENC01";
+
+/// 随机 nonce 前缀长度，其后为 4 字节块序号与 1 字节结束标志
+const PREFIX_LEN: usize = 7;
+
+/// 每块明文长度
+const CHUNK_LEN: usize = 1 << 20;
+
+/// GCM 认证标签长度
+const TAG_LEN: usize = 16;
+
+/// 由密钥派生各流加密密钥时 HKDF 使用的盐
+const HKDF_SALT: &[u8] = b"rtsp2flv-clip-encryption";
+
+/// 录像加密密钥缺省所在的环境变量
+pub const RECORDING_KEY_ENV: &str = "RTSP2FLV_RECORDING_KEY";
+
+/// 录像加密配置
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct ClipEncryptionConfig {
+    /// 加密的流，为空时加密所有流的录像
+    #[serde(default)]
+    pub streams: Vec<String>,
+    /// 从文件读取密钥（如 KMS / Vault 代理挂载的密钥文件），优先于环境变量
+    #[serde(default)]
+    pub key_file: Option<String>,
+    /// 启动时执行命令并以标准输出作为密钥（如调用 KMS 解密数据密钥），优先于密钥文件
+    #[serde(default)]
+    pub key_command: Vec<String>,
+}
+
+impl ClipEncryptionConfig {
+    /// 流的录像是否需要加密
+    pub fn applies(&self, stream: &str) -> bool {
+        self.streams.is_empty() || self.streams.iter().any(|s| s == stream)
+    }
+
+    /// 读取密钥，依次尝试 `key_command`、`key_file` 与环境变量 `RTSP2FLV_RECORDING_KEY`
+    fn secret(&self) -> Result<String> {
+        let secret = if let Some((program, args)) = self.key_command.split_first() {
+            let output = std::process::Command::new(program)
+                .args(args)
+                .output()
+                .with_context(|| format!("执行密钥命令 {} 失败", program))?;
+            if !output.status.success() {
+                return Err(anyhow!(
+                    "密钥命令 {} 异常退出 ({}): {}",
+                    program,
+                    output.status,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ));
+            }
+            String::from_utf8(output.stdout).map_err(|_| anyhow!("密钥命令的输出不是 UTF-8 文本"))?
+        } else if let Some(path) = &self.key_file {
+            fs::read_to_string(path).with_context(|| format!("读取密钥文件 {} 失败", path))?
+        } else {
+            std::env::var(RECORDING_KEY_ENV).map_err(|_| anyhow!("未设置环境变量 {}", RECORDING_KEY_ENV))?
+        };
+        let secret = secret.trim().to_string();
+        if secret.is_empty() {
+            return Err(anyhow!("录像加密密钥为空"));
+        }
+        Ok(secret)
+    }
+}
+
+/// 录像文件加密
+///
+/// 每个流使用由密钥经 HKDF-SHA256（以流名称为 info）派生的独立密钥，一个流的密钥不能解密其他流的录像。
+/// 文件按 1 MiB 分块以 AES-256-GCM 加密，下载时可以边读边解密；
+/// 每块的 nonce 由文件随机前缀、块序号与结束标志组成，块被调换、删除或文件被截断时解密失败。
+/// 文件格式：`R2FENC01` + 7 字节前缀，之后每块为 1 字节结束标志 + 4 字节密文长度 + 密文。
+pub struct ClipCipher {
+    /// HKDF 提取得到的伪随机密钥
+    prk: [u8; 32],
+}
+
+impl ClipCipher {
+    pub fn load(config: &ClipEncryptionConfig) -> Result<Self> {
+        Ok(Self::from_secret(&config.secret()?))
+    }
+
+    fn from_secret(secret: &str) -> Self {
+        Self { prk: hmac_sha256(HKDF_SALT, &[secret.as_bytes()]) }
+    }
+
+    /// 流的加密密钥（HKDF-Expand，输出 32 字节只需一轮）
+    fn cipher(&self, stream: &str) -> Result<Aes256Gcm> {
+        let key = hmac_sha256(&self.prk, &[stream.as_bytes(), &[1]]);
+        Aes256Gcm::new_from_slice(&key).map_err(|e| anyhow!("初始化录像加密密钥失败: {}", e))
+    }
+
+    /// 以流的密钥加密 `src` 写入 `dst`
+    pub fn encrypt_file(&self, stream: &str, src: &Path, dst: &Path) -> Result<()> {
+        let cipher = self.cipher(stream)?;
+        let mut prefix = [0u8; PREFIX_LEN];
+        getrandom::getrandom(&mut prefix).map_err(|e| anyhow!("生成随机数失败: {}", e))?;
+        let mut input = fs::File::open(src)?;
+        let mut output = std::io::BufWriter::new(fs::File::create(dst)?);
+        output.write_all(MAGIC)?;
+        output.write_all(&prefix)?;
+
+        let mut chunk = vec![0u8; CHUNK_LEN];
+        let mut len = read_full(&mut input, &mut chunk)?;
+        let mut index = 0u32;
+        loop {
+            // 预读下一块以确定当前块是否为最后一块
+            let mut next = vec![0u8; CHUNK_LEN];
+            let next_len = if len == CHUNK_LEN { read_full(&mut input, &mut next)? } else { 0 };
+            let last = next_len == 0;
+            let ciphertext = cipher
+                .encrypt(Nonce::from_slice(&nonce(&prefix, index, last)), &chunk[..len])
+                .map_err(|_| anyhow!("加密录像失败"))?;
+            output.write_all(&[last as u8])?;
+            output.write_all(&(ciphertext.len() as u32).to_be_bytes())?;
+            output.write_all(&ciphertext)?;
+            if last {
+                break;
+            }
+            (chunk, len) = (next, next_len);
+            index = index.checked_add(1).ok_or(anyhow!("录像文件过大"))?;
+        }
+        output.flush()?;
+        output.get_ref().sync_all()?;
+        Ok(())
+    }
+
+    /// 以流的密钥解密录像并写入 `output`，密钥错误或文件损坏时返回错误
+    pub async fn decrypt_to<R, W>(&self, stream: &str, mut input: R, mut output: W) -> Result<()>
+    where
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        let cipher = self.cipher(stream)?;
+        let mut header = [0u8; MAGIC.len() + PREFIX_LEN];
+        input.read_exact(&mut header).await.map_err(|_| anyhow!("不是加密的录像文件"))?;
+        if &header[..MAGIC.len()] != MAGIC {
+            return Err(anyhow!("不是加密的录像文件"));
+        }
+        let prefix = &header[MAGIC.len()..];
+        let mut index = 0u32;
+        loop {
+            let mut chunk_header = [0u8; 5];
+            input.read_exact(&mut chunk_header).await.map_err(|_| anyhow!("录像文件被截断"))?;
+            let last = chunk_header[0] == 1;
+            let len = u32::from_be_bytes([chunk_header[1], chunk_header[2], chunk_header[3], chunk_header[4]]) as usize;
+            if len > CHUNK_LEN + TAG_LEN {
+                return Err(anyhow!("录像文件已损坏"));
+            }
+            let mut ciphertext = vec![0u8; len];
+            input.read_exact(&mut ciphertext).await.map_err(|_| anyhow!("录像文件被截断"))?;
+            let plaintext = cipher
+                .decrypt(Nonce::from_slice(&nonce(prefix, index, last)), ciphertext.as_slice())
+                .map_err(|_| anyhow!("密钥错误或录像文件已损坏"))?;
+            output.write_all(&plaintext).await?;
+            if last {
+                break;
+            }
+            index = index.checked_add(1).ok_or(anyhow!("录像文件已损坏"))?;
+        }
+        output.shutdown().await?;
+        Ok(())
+    }
+}
+
+/// HMAC-SHA256（RFC 2104），`message` 各部分依次拼接
+fn hmac_sha256(key: &[u8], message: &[&[u8]]) -> [u8; 32] {
+    const BLOCK_LEN: usize = 64;
+    let mut block = [0u8; BLOCK_LEN];
+    if key.len() > BLOCK_LEN {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let mut inner = Sha256::new();
+    inner.update(block.map(|b| b ^ 0x36));
+    for part in message {
+        inner.update(part);
+    }
+    let mut outer = Sha256::new();
+    outer.update(block.map(|b| b ^ 0x5c));
+    outer.update(inner.finalize());
+    outer.finalize().into()
+}
+
+fn nonce(prefix: &[u8], index: u32, last: bool) -> [u8; 12] {
+    let mut nonce = [0u8; 12];
+    nonce[..PREFIX_LEN].copy_from_slice(prefix);
+    nonce[PREFIX_LEN..PREFIX_LEN + 4].copy_from_slice(&index.to_be_bytes());
+    nonce[11] = last as u8;
+    nonce
+}
+
+/// 读满缓冲区，返回实际读取的字节数（到达文件末尾时小于缓冲区长度）
+fn read_full(input: &mut impl Read, buf: &mut [u8]) -> Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match input.read(&mut buf[filled..])? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 密文头部长度与每块的额外长度（块头 + 认证标签）
+    const HEADER_LEN: usize = MAGIC.len() + PREFIX_LEN;
+    const CHUNK_OVERHEAD: usize = 5 + TAG_LEN;
+
+    fn plaintext(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+
+    fn encrypt(cipher: &ClipCipher, stream: &str, data: &[u8]) -> Vec<u8> {
+        let dir = std::env::temp_dir();
+        let name = format!("rtsp2flv-clip-test-{}-{}-{}", std::process::id(), stream, data.len());
+        let (src, dst) = (dir.join(format!("{}.mp4", name)), dir.join(format!("{}.enc", name)));
+        fs::write(&src, data).unwrap();
+        cipher.encrypt_file(stream, &src, &dst).unwrap();
+        let encrypted = fs::read(&dst).unwrap();
+        let _ = fs::remove_file(&src);
+        let _ = fs::remove_file(&dst);
+        encrypted
+    }
+
+    async fn decrypt(cipher: &ClipCipher, stream: &str, encrypted: &[u8]) -> Result<Vec<u8>> {
+        let mut output = Vec::new();
+        cipher.decrypt_to(stream, encrypted, &mut output).await?;
+        Ok(output)
+    }
+
+    async fn round_trip(len: usize) -> Vec<u8> {
+        let cipher = ClipCipher::from_secret("secret");
+        let data = plaintext(len);
+        let encrypted = encrypt(&cipher, "cam1", &data);
+        assert_eq!(decrypt(&cipher, "cam1", &encrypted).await.unwrap(), data);
+        encrypted
+    }
+
+    #[test]
+    fn hmac_matches_rfc4231() {
+        let mac = hmac_sha256(b"Jefe", &[b"what do ya want ", b"for nothing?"]);
+        let hex: String = mac.iter().map(|b| format!("{:02x}", b)).collect();
+        assert_eq!(hex, "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843");
+    }
+
+    #[tokio::test]
+    async fn round_trips_empty_file() {
+        let encrypted = round_trip(0).await;
+        assert_eq!(encrypted.len(), HEADER_LEN + CHUNK_OVERHEAD);
+    }
+
+    #[tokio::test]
+    async fn round_trips_exactly_one_chunk() {
+        let encrypted = round_trip(CHUNK_LEN).await;
+        assert_eq!(encrypted.len(), HEADER_LEN + CHUNK_LEN + CHUNK_OVERHEAD);
+    }
+
+    #[tokio::test]
+    async fn round_trips_several_chunks() {
+        let encrypted = round_trip(2 * CHUNK_LEN + 123).await;
+        assert_eq!(encrypted.len(), HEADER_LEN + 2 * CHUNK_LEN + 123 + 3 * CHUNK_OVERHEAD);
+    }
+
+    #[tokio::test]
+    async fn rejects_wrong_key() {
+        let encrypted = encrypt(&ClipCipher::from_secret("secret"), "cam1", &plaintext(1000));
+        assert!(decrypt(&ClipCipher::from_secret("other"), "cam1", &encrypted).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn keys_differ_per_stream() {
+        let cipher = ClipCipher::from_secret("secret");
+        let encrypted = encrypt(&cipher, "cam1", &plaintext(1000));
+        assert!(decrypt(&cipher, "cam2", &encrypted).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn rejects_truncated_file() {
+        let cipher = ClipCipher::from_secret("secret");
+        let encrypted = encrypt(&cipher, "cam1", &plaintext(CHUNK_LEN + 10));
+        // 在块边界截断（去掉最后一块）与在块中间截断都应失败
+        let first_chunk_end = HEADER_LEN + CHUNK_LEN + CHUNK_OVERHEAD;
+        assert!(decrypt(&cipher, "cam1", &encrypted[..first_chunk_end]).await.is_err());
+        assert!(decrypt(&cipher, "cam1", &encrypted[..encrypted.len() - 1]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn rejects_swapped_chunks() {
+        let cipher = ClipCipher::from_secret("secret");
+        let mut data = plaintext(3 * CHUNK_LEN);
+        data[CHUNK_LEN..2 * CHUNK_LEN].reverse();
+        let mut encrypted = encrypt(&cipher, "cam1", &data);
+        let block = CHUNK_LEN + CHUNK_OVERHEAD;
+        let (first, second) = encrypted[HEADER_LEN..HEADER_LEN + 2 * block].split_at_mut(block);
+        first.swap_with_slice(second);
+        assert!(decrypt(&cipher, "cam1", &encrypted).await.is_err());
+    }
+}
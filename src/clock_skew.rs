@@ -0,0 +1,78 @@
+use serde::Deserialize;
+use std::sync::{Arc, Mutex};
+use tracing::{info, warn};
+use crate::history::HealthHistory;
+
+/// 摄像头时钟偏差检测配置
+#[derive(Debug, Deserialize, Clone)]
+pub struct ClockSkewConfig {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// 偏差（绝对值）超过该秒数时记录时钟异常事件，回落到一半以下时记录恢复
+    #[serde(default = "default_threshold_secs")]
+    pub threshold_secs: f64,
+}
+
+impl Default for ClockSkewConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_enabled(),
+            threshold_secs: default_threshold_secs(),
+        }
+    }
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_threshold_secs() -> f64 {
+    10.0
+}
+
+/// 摄像头时钟偏差检测
+///
+/// 偏差由输入统计根据数据包到达时间与摄像头时间戳（RTCP SR 提供的 NTP 时间）推算，
+/// 超过阈值说明摄像头时钟明显不准（NTP 未配置或失效），录像与事件时间会对不上。
+/// 与画面事件一样，状态变化记录到健康历史，不计入可用率；流重启后沿用，避免重复报警。
+pub struct ClockMonitor {
+    stream: String,
+    config: ClockSkewConfig,
+    history: Arc<HealthHistory>,
+    skewed: Mutex<bool>,
+}
+
+impl ClockMonitor {
+    pub fn new(stream: &str, config: ClockSkewConfig, history: Arc<HealthHistory>) -> Self {
+        Self {
+            stream: stream.to_string(),
+            config,
+            history,
+            skewed: Mutex::new(false),
+        }
+    }
+
+    /// 是否处于时钟异常状态
+    pub fn skewed(&self) -> bool {
+        *self.skewed.lock().unwrap()
+    }
+
+    /// 更新当前偏差（秒，正值表示摄像头时钟落后），跨过阈值时记录事件
+    pub fn observe(&self, skew_secs: f64) {
+        let threshold = self.config.threshold_secs;
+        if threshold <= 0.0 {
+            return;
+        }
+        let mut skewed = self.skewed.lock().unwrap();
+        if !*skewed && skew_secs.abs() > threshold {
+            *skewed = true;
+            let reason = format!("摄像头时钟偏差 {:.1} 秒", skew_secs);
+            warn!("流 {} {}", self.stream, reason);
+            self.history.record_clock(&self.stream, false, &reason);
+        } else if *skewed && skew_secs.abs() < threshold / 2.0 {
+            *skewed = false;
+            info!("流 {} 摄像头时钟恢复正常（偏差 {:.1} 秒）", self.stream, skew_secs);
+            self.history.record_clock(&self.stream, true, "摄像头时钟恢复正常");
+        }
+    }
+}
@@ -0,0 +1,273 @@
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+use crate::frame_tap::{GrayFrame, TAP_HEIGHT, TAP_WIDTH};
+use crate::history::{HealthHistory, now_secs};
+
+/// 画面哈希差异连续超过阈值多少次采样才判定为画面变化，避免有人经过镜头前时误报
+const CHANGE_CONFIRM_SAMPLES: u32 = 3;
+
+/// 画面检测配置
+#[derive(Debug, Deserialize, Clone)]
+pub struct ContentCheckConfig {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// 抽帧间隔（秒）
+    #[serde(default = "default_interval_secs")]
+    pub interval_secs: u64,
+    /// 画面持续不变多少秒判定为冻结
+    #[serde(default = "default_freeze_secs")]
+    pub freeze_secs: u64,
+    /// 相邻采样的平均亮度差（0-255）低于该值视为画面未变化
+    #[serde(default = "default_freeze_noise")]
+    pub freeze_noise: f64,
+    /// 画面持续全黑多少秒判定为黑屏
+    #[serde(default = "default_black_secs")]
+    pub black_secs: u64,
+    /// 亮度低于该值的像素视为黑色
+    #[serde(default = "default_black_threshold")]
+    pub black_threshold: u8,
+    /// 黑色像素占比达到该值视为黑屏
+    #[serde(default = "default_black_ratio")]
+    pub black_ratio: f64,
+    /// 画面哈希（64 位）与基准相差的位数达到该值视为画面明显变化（摄像头被移动或遮挡），0 表示不检测
+    #[serde(default = "default_change_threshold")]
+    pub change_threshold: u32,
+}
+
+impl Default for ContentCheckConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_enabled(),
+            interval_secs: default_interval_secs(),
+            freeze_secs: default_freeze_secs(),
+            freeze_noise: default_freeze_noise(),
+            black_secs: default_black_secs(),
+            black_threshold: default_black_threshold(),
+            black_ratio: default_black_ratio(),
+            change_threshold: default_change_threshold(),
+        }
+    }
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_interval_secs() -> u64 {
+    2
+}
+
+fn default_freeze_secs() -> u64 {
+    60
+}
+
+fn default_freeze_noise() -> f64 {
+    0.5
+}
+
+fn default_black_secs() -> u64 {
+    10
+}
+
+fn default_black_threshold() -> u8 {
+    32
+}
+
+fn default_black_ratio() -> f64 {
+    0.98
+}
+
+fn default_change_threshold() -> u32 {
+    18
+}
+
+/// 画面内容问题
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContentProblem {
+    /// 画面冻结（摄像头内部故障后持续推送同一帧）
+    Frozen,
+    /// 黑屏
+    Black,
+}
+
+impl ContentProblem {
+    fn describe(&self) -> &'static str {
+        match self {
+            ContentProblem::Frozen => "画面冻结",
+            ContentProblem::Black => "黑屏",
+        }
+    }
+}
+
+#[derive(Default)]
+struct DetectState {
+    last: Option<GrayFrame>,
+    frozen_since: Option<Instant>,
+    black_since: Option<Instant>,
+    problem: Option<ContentProblem>,
+    /// 画面哈希基准，流重启后保留
+    reference: Option<u64>,
+    /// 与基准差异超过阈值的候选画面及连续次数
+    candidate: Option<(u64, u32)>,
+    /// 最近一次画面明显变化的时间（Unix 秒）
+    changed_at: Option<u64>,
+}
+
+/// 画面检测
+///
+/// 对抽帧得到的画面做黑屏 / 冻结检测。与连接故障不同，出现问题时流仍在正常推送，
+/// 状态变化以画面事件记录到健康历史，不计入可用率。同时计算画面的差值哈希 (dHash)，
+/// 与基准相差较大且持续时视为画面明显变化，可用于发现摄像头被移动或遮挡。
+pub struct ContentMonitor {
+    stream: String,
+    config: ContentCheckConfig,
+    history: Arc<HealthHistory>,
+    state: Mutex<DetectState>,
+}
+
+impl ContentMonitor {
+    pub fn new(stream: &str, config: ContentCheckConfig, history: Arc<HealthHistory>) -> Self {
+        Self {
+            stream: stream.to_string(),
+            config,
+            history,
+            state: Mutex::new(DetectState::default()),
+        }
+    }
+
+    /// 抽帧间隔
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.config.interval_secs.max(1))
+    }
+
+    /// 当前画面问题
+    pub fn problem(&self) -> Option<ContentProblem> {
+        self.state.lock().unwrap().problem
+    }
+
+    /// 最近一次画面明显变化的时间（Unix 秒）
+    pub fn changed_at(&self) -> Option<u64> {
+        self.state.lock().unwrap().changed_at
+    }
+
+    /// 重新连接源时清空比较基准，避免把断线前的画面与新画面比较（画面哈希基准保留）
+    pub fn reset(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.last = None;
+        state.frozen_since = None;
+        state.black_since = None;
+        state.candidate = None;
+    }
+
+    /// 分析一帧采样，问题出现或消失时记录事件
+    pub fn analyze(&self, frame: GrayFrame) {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+
+        let black_pixels = frame.data.iter().filter(|p| **p < self.config.black_threshold).count();
+        let is_black = black_pixels as f64 >= frame.data.len().max(1) as f64 * self.config.black_ratio;
+        let unchanged = state.last.as_ref().is_some_and(|last| {
+            last.data.len() == frame.data.len() && mean_abs_diff(&last.data, &frame.data) < self.config.freeze_noise
+        });
+        // 黑屏（如夜间关灯）不参与画面变化检测
+        if self.config.change_threshold > 0 && !is_black {
+            self.detect_change(&mut state, dhash(&frame.data));
+        }
+        state.last = Some(frame);
+
+        if is_black {
+            state.black_since.get_or_insert(now);
+        } else {
+            state.black_since = None;
+        }
+        // 黑屏画面本身也不变化，只报告黑屏
+        if unchanged && !is_black {
+            state.frozen_since.get_or_insert(now);
+        } else {
+            state.frozen_since = None;
+        }
+
+        let lasted = |since: Option<Instant>, secs: u64| since.is_some_and(|at| now.duration_since(at) >= Duration::from_secs(secs));
+        let problem = if lasted(state.black_since, self.config.black_secs) {
+            Some(ContentProblem::Black)
+        } else if lasted(state.frozen_since, self.config.freeze_secs) {
+            Some(ContentProblem::Frozen)
+        } else {
+            None
+        };
+
+        if problem == state.problem {
+            return;
+        }
+        match (state.problem, problem) {
+            (_, Some(p)) => {
+                warn!("流 {} 检测到{}", self.stream, p.describe());
+                self.history.record_content(&self.stream, false, p.describe());
+            }
+            (Some(p), None) => {
+                info!("流 {} 画面恢复（{}已消失）", self.stream, p.describe());
+                self.history.record_content(&self.stream, true, "画面恢复");
+            }
+            (None, None) => {}
+        }
+        state.problem = problem;
+    }
+
+    fn detect_change(&self, state: &mut DetectState, hash: u64) {
+        let threshold = self.config.change_threshold;
+        let Some(reference) = state.reference else {
+            state.reference = Some(hash);
+            return;
+        };
+        let distance = (hash ^ reference).count_ones();
+        if distance < threshold {
+            state.candidate = None;
+            return;
+        }
+        // 候选画面需要保持稳定，短暂遮挡后恢复不算变化
+        let count = match state.candidate {
+            Some((candidate, count)) if (hash ^ candidate).count_ones() < threshold / 2 => count + 1,
+            _ => 1,
+        };
+        if count < CHANGE_CONFIRM_SAMPLES {
+            state.candidate = Some((hash, count));
+            return;
+        }
+        state.reference = Some(hash);
+        state.candidate = None;
+        state.changed_at = Some(now_secs());
+        let reason = format!("画面明显变化（差异 {}/64）", distance);
+        warn!("流 {} {}", self.stream, reason);
+        self.history.record_change(&self.stream, &reason);
+    }
+}
+
+/// 差值哈希：把画面缩小为 9x8 个区块，按每行相邻区块的亮度大小关系得到 64 位
+fn dhash(data: &[u8]) -> u64 {
+    let (width, height) = (TAP_WIDTH as usize, TAP_HEIGHT as usize);
+    if data.len() < width * height {
+        return 0;
+    }
+    let mut blocks = [[0u64; 9]; 8];
+    for (y, row) in data.chunks_exact(width).take(height).enumerate() {
+        for (x, pixel) in row.iter().enumerate() {
+            blocks[y * 8 / height][x * 9 / width] += *pixel as u64;
+        }
+    }
+    let mut hash = 0u64;
+    for row in &blocks {
+        for pair in row.windows(2) {
+            hash = hash << 1 | (pair[0] < pair[1]) as u64;
+        }
+    }
+    hash
+}
+
+/// 两幅画面的平均像素差
+fn mean_abs_diff(a: &[u8], b: &[u8]) -> f64 {
+    let total: u64 = a.iter().zip(b).map(|(x, y)| x.abs_diff(*y) as u64).sum();
+    total as f64 / a.len().max(1) as f64
+}
@@ -0,0 +1,175 @@
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use crate::config::StreamConfig;
+use crate::content_check::ContentProblem;
+use crate::history::HealthEvent;
+use crate::srs::SrsHealth;
+use crate::stream_manager::StreamStatus;
+
+/// 管理面板数据，网页一次请求即可刷新全部状态
+#[derive(Serialize)]
+pub struct Dashboard {
+    /// Unix 时间戳（秒）
+    pub generated_at: u64,
+    pub summary: Summary,
+    pub streams: Vec<StreamOverview>,
+    pub srs: SrsHealth,
+    pub resources: ResourceUsage,
+    /// 最近的流状态变化，最新的在前
+    pub recent_events: Vec<HealthEvent>,
+}
+
+#[derive(Serialize, Default)]
+pub struct Summary {
+    pub total: usize,
+    pub running: usize,
+    /// 正在使用备用源、发生过重启、画面异常或正在重试推流的流
+    pub degraded: usize,
+}
+
+/// 单个流的概览
+#[derive(Serialize)]
+pub struct StreamOverview {
+    pub name: String,
+    /// 是否为配置中的流（否则为自定义地址播放）
+    pub configured: bool,
+    pub running: bool,
+    pub active_source: Option<usize>,
+    pub restart_count: Option<u32>,
+    /// 剩余的自动重启次数
+    pub restart_budget: Option<u32>,
+    /// 重启次数已用尽，不再自动重启
+    pub restarts_exhausted: bool,
+    /// SRS 不可用，正在重试推流连接
+    pub publish_degraded: bool,
+    /// 播放时调用 SRS 接口失败
+    pub srs_degraded: bool,
+    pub last_heartbeat_secs: Option<u64>,
+    /// 观看会话数
+    pub viewers: Option<usize>,
+    /// 画面问题（冻结 / 黑屏）
+    pub content_problem: Option<ContentProblem>,
+    /// 当前处于保护时段
+    pub protected: bool,
+    /// 已降级为子码流
+    pub downgraded: bool,
+    /// 最近一次画面明显变化的时间（Unix 秒）
+    pub view_changed_at: Option<u64>,
+    /// 摄像头时钟偏差超过阈值
+    pub clock_skewed: bool,
+    /// 最近 24 小时可用率，作为健康评分
+    pub uptime_percent_24h: Option<f64>,
+    /// 距最近一次从源收到数据包的秒数
+    pub input_idle_secs: Option<u64>,
+    /// 距最近一次成功推给 SRS 的秒数
+    pub output_idle_secs: Option<u64>,
+    /// 流配置中的自定义元数据
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: BTreeMap<String, String>,
+}
+
+/// 进程资源占用（仅 Linux 下可用，其他平台为空）
+#[derive(Serialize)]
+pub struct ResourceUsage {
+    pub uptime_secs: u64,
+    pub rss_bytes: Option<u64>,
+    pub threads: Option<u64>,
+    pub open_fds: Option<usize>,
+    /// 累计 CPU 时间（秒）
+    pub cpu_seconds: Option<f64>,
+    pub load_average: Option<[f64; 3]>,
+}
+
+/// 合并配置中的流与已启动的流
+pub fn streams(configured: &[StreamConfig], statuses: Vec<StreamStatus>, uptime: &HashMap<String, f64>) -> (Summary, Vec<StreamOverview>) {
+    let mut statuses: HashMap<String, StreamStatus> = statuses.into_iter().map(|s| (s.name.clone(), s)).collect();
+    let mut overviews: Vec<StreamOverview> = configured
+        .iter()
+        .map(|stream| {
+            let mut overview = overview(&stream.name, true, statuses.remove(&stream.name), uptime);
+            overview.metadata = stream.metadata.clone();
+            overview
+        })
+        .collect();
+    let mut adhoc: Vec<StreamStatus> = statuses.into_values().collect();
+    adhoc.sort_by(|a, b| a.name.cmp(&b.name));
+    overviews.extend(adhoc.into_iter().map(|status| overview(&status.name.clone(), false, Some(status), uptime)));
+
+    let summary = Summary {
+        total: overviews.len(),
+        running: overviews.iter().filter(|s| s.running).count(),
+        degraded: overviews.iter().filter(|s| s.degraded()).count(),
+    };
+    (summary, overviews)
+}
+
+impl StreamOverview {
+    /// 运行中，但正在使用备用源、发生过重启、画面异常或推流受阻
+    pub fn degraded(&self) -> bool {
+        self.running
+            && (self.active_source.unwrap_or(0) > 0
+                || self.restart_count.unwrap_or(0) > 0
+                || self.content_problem.is_some()
+                || self.publish_degraded
+                || self.srs_degraded)
+    }
+}
+
+fn overview(name: &str, configured: bool, status: Option<StreamStatus>, uptime: &HashMap<String, f64>) -> StreamOverview {
+    StreamOverview {
+        name: name.to_string(),
+        configured,
+        running: status.as_ref().is_some_and(|s| s.running),
+        active_source: status.as_ref().map(|s| s.active_source),
+        restart_count: status.as_ref().map(|s| s.restart_count),
+        restart_budget: status.as_ref().map(|s| s.restart_budget),
+        restarts_exhausted: status.as_ref().is_some_and(|s| s.restarts_exhausted),
+        publish_degraded: status.as_ref().is_some_and(|s| s.publish_degraded),
+        srs_degraded: status.as_ref().is_some_and(|s| s.srs_error.is_some()),
+        last_heartbeat_secs: status.as_ref().map(|s| s.last_heartbeat_secs),
+        viewers: status.as_ref().map(|s| s.viewers),
+        content_problem: status.as_ref().and_then(|s| s.content_problem),
+        protected: status.as_ref().is_some_and(|s| s.protected),
+        downgraded: status.as_ref().is_some_and(|s| s.downgraded),
+        view_changed_at: status.as_ref().and_then(|s| s.view_changed_at),
+        clock_skewed: status.as_ref().is_some_and(|s| s.clock_skewed),
+        uptime_percent_24h: uptime.get(name).copied(),
+        input_idle_secs: status.as_ref().and_then(|s| s.input_idle_secs),
+        output_idle_secs: status.as_ref().and_then(|s| s.output_idle_secs),
+        metadata: BTreeMap::new(),
+    }
+}
+
+/// 读取 /proc 下的进程资源占用
+pub fn resource_usage(uptime_secs: u64) -> ResourceUsage {
+    let status = fs::read_to_string("/proc/self/status").unwrap_or_default();
+    let status_field = |key: &str| {
+        status
+            .lines()
+            .find_map(|l| l.strip_prefix(key))
+            .and_then(|v| v.split_whitespace().next())
+            .and_then(|v| v.parse::<u64>().ok())
+    };
+
+    // /proc/self/stat 中进程名之后第 12、13 项为用户态与内核态时钟滴答数
+    let cpu_seconds = fs::read_to_string("/proc/self/stat").ok().and_then(|stat| {
+        let fields: Vec<&str> = stat.rsplit_once(')')?.1.split_whitespace().collect();
+        let ticks = fields.get(11)?.parse::<u64>().ok()? + fields.get(12)?.parse::<u64>().ok()?;
+        Some(ticks as f64 / 100.0)
+    });
+
+    let load_average = fs::read_to_string("/proc/loadavg").ok().and_then(|l| {
+        let values: Vec<f64> = l.split_whitespace().take(3).filter_map(|v| v.parse().ok()).collect();
+        Some([*values.first()?, *values.get(1)?, *values.get(2)?])
+    });
+
+    ResourceUsage {
+        uptime_secs,
+        rss_bytes: status_field("VmRSS:").map(|kb| kb * 1024),
+        threads: status_field("Threads:"),
+        open_fds: fs::read_dir("/proc/self/fd").ok().map(|d| d.count()),
+        cpu_seconds,
+        load_average,
+    }
+}
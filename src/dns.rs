@@ -0,0 +1,58 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, ToSocketAddrs};
+use std::sync::Mutex;
+use lazy_static::lazy_static;
+use tracing::{debug, info, warn};
+
+lazy_static! {
+    /// 每个主机名上一次解析到的地址，用于记录 DDNS 地址变化
+    static ref LAST_RESOLVED: Mutex<HashMap<String, IpAddr>> = Mutex::new(HashMap::new());
+}
+
+/// 连接前重新解析地址中的主机名，并把主机替换为解析出的 IP
+///
+/// 每次（重新）连接都会调用，保证 DDNS 摄像头地址变化后不会一直连接旧地址。
+/// `static_ip` 不为空时直接使用该地址，不做解析；解析失败时返回原地址，由 FFmpeg 报告错误。
+pub fn refresh(url: &str, static_ip: Option<IpAddr>) -> String {
+    let Ok(mut parsed) = url::Url::parse(url) else {
+        return url.to_string();
+    };
+    let Some(url::Host::Domain(host)) = parsed.host().map(|h| h.to_owned()) else {
+        return url.to_string();
+    };
+
+    let ip = match static_ip {
+        Some(ip) => {
+            debug!("{} 使用固定地址 {}", host, ip);
+            ip
+        }
+        None => {
+            let port = parsed.port_or_known_default().unwrap_or(0);
+            match (host.as_str(), port).to_socket_addrs().map(|mut addrs| addrs.next()) {
+                Ok(Some(addr)) => {
+                    let ip = addr.ip();
+                    let previous = LAST_RESOLVED.lock().unwrap().insert(host.clone(), ip);
+                    match previous {
+                        Some(old) if old != ip => info!("{} 的解析地址已变化: {} -> {}", host, old, ip),
+                        None => debug!("{} 解析为 {}", host, ip),
+                        _ => {}
+                    }
+                    ip
+                }
+                Ok(None) | Err(_) => {
+                    warn!("解析主机名 {} 失败，使用原地址连接", host);
+                    return url.to_string();
+                }
+            }
+        }
+    };
+
+    // RTMP 的 vhost 取自 tcUrl 中的主机名，替换为 IP 后通过 vhost 参数保留
+    if parsed.scheme() == "rtmp" && !parsed.query_pairs().any(|(k, _)| k == "vhost") {
+        parsed.query_pairs_mut().append_pair("vhost", &host);
+    }
+    if parsed.set_ip_host(ip).is_err() {
+        return url.to_string();
+    }
+    parsed.to_string()
+}
@@ -0,0 +1,385 @@
+use anyhow::{Result, anyhow};
+use ffmpeg_next as ffmpeg;
+use std::ffi::CString;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+use crate::clip_crypto::ClipCipher;
+use crate::config::{AppConfig, OVERRIDABLE_FIELDS};
+use crate::hwaccel::VideoEncoder;
+use crate::registry::StreamRegistry;
+use crate::srs::{self, SrsClient};
+use crate::store::Store;
+use crate::vault::{self, CredentialVault};
+use crate::vendor::VendorPresets;
+use crate::{map, migrate, transcoder};
+
+#[derive(Clone, Copy, PartialEq)]
+enum Level {
+    Pass,
+    Warn,
+    Fail,
+}
+
+/// 自检结果，逐项输出到终端
+#[derive(Default)]
+struct Report {
+    failures: usize,
+    warnings: usize,
+}
+
+impl Report {
+    fn section(&self, title: &str) {
+        println!("\n{}", title);
+    }
+
+    fn add(&mut self, level: Level, item: &str, detail: impl AsRef<str>) {
+        let tag = match level {
+            Level::Pass => "通过",
+            Level::Warn => {
+                self.warnings += 1;
+                "警告"
+            }
+            Level::Fail => {
+                self.failures += 1;
+                "失败"
+            }
+        };
+        match detail.as_ref() {
+            "" => println!("  [{}] {}", tag, item),
+            detail => println!("  [{}] {}: {}", tag, item, detail),
+        }
+    }
+
+    /// 必需项缺失记为失败，可选项缺失记为警告
+    fn require(&mut self, ok: bool, required: bool, item: &str, purpose: &str) {
+        let level = match (ok, required) {
+            (true, _) => Level::Pass,
+            (false, true) => Level::Fail,
+            (false, false) => Level::Warn,
+        };
+        self.add(level, item, if ok { String::new() } else { format!("不可用，影响: {}", purpose) });
+    }
+}
+
+/// `rtsp2flv doctor`
+///
+/// 检查运行环境并输出报告：配置文件、FFmpeg 编译特性、SRS 连通性、数据目录写权限与监听端口。
+/// 存在失败项时返回 false。
+pub async fn run(args: &[String]) -> Result<bool> {
+    if let Some(arg) = args.first() {
+        return Err(anyhow!("未知参数: {}", arg));
+    }
+    println!("rtsp2flv {} 环境自检", env!("CARGO_PKG_VERSION"));
+    let mut report = Report::default();
+
+    report.section("配置");
+    let config = match AppConfig::new() {
+        Ok(config) => {
+            report.add(Level::Pass, "加载配置文件", "");
+            Some(config)
+        }
+        Err(e) => {
+            report.add(Level::Fail, "加载配置文件", e.to_string());
+            None
+        }
+    };
+    match migrate::review() {
+        Ok(notes) => notes.iter().for_each(|note| report.add(Level::Warn, "配置检查", note)),
+        Err(e) => report.add(Level::Warn, "配置检查", e.to_string()),
+    }
+    if let Some(config) = &config {
+        check_streams(config, &mut report);
+    }
+
+    report.section("FFmpeg");
+    check_ffmpeg(&mut report);
+
+    if let Some(config) = &config {
+        report.section("SRS");
+        check_srs(config, &mut report).await;
+
+        report.section("目录与端口");
+        check_dirs(config, &mut report);
+        check_ports(config, &mut report);
+    }
+
+    println!("\n自检完成: {} 项失败，{} 项警告", report.failures, report.warnings);
+    Ok(report.failures == 0)
+}
+
+/// 启动时输出版本与运行环境摘要，便于从日志排查环境问题
+pub fn banner(config: &AppConfig, stream_count: usize) {
+    info!("rtsp2flv {} 启动，配置文件版本 {}", env!("CARGO_PKG_VERSION"), config.version);
+    match ffmpeg::init() {
+        Ok(()) => info!("FFmpeg: libavformat {}", version_string(ffmpeg::format::version())),
+        Err(e) => warn!("FFmpeg 初始化失败: {}，可执行 `rtsp2flv doctor` 检查运行环境", e),
+    }
+    info!("SRS API: {}", config.srs.api_url);
+    info!("数据目录: {}，已配置 {} 个流", config.data_dir, stream_count);
+}
+
+fn version_string(version: u32) -> String {
+    format!("{}.{}.{}", version >> 16, (version >> 8) & 0xff, version & 0xff)
+}
+
+fn check_streams(config: &AppConfig, report: &mut Report) {
+    if config.api_keys.is_empty() {
+        report.add(Level::Warn, "API 密钥", "未配置 api_keys，只能通过网页登录调用需要认证的接口");
+    }
+    for (i, scope) in config.api_keys.iter().enumerate().filter_map(|(i, k)| Some((i, k.scope()?))) {
+        if scope.is_empty() {
+            let name = scope.name.clone().unwrap_or_else(|| format!("第 {} 个", i + 1));
+            report.add(Level::Warn, "API 密钥", format!("{} API Key 未配置 allowed_streams / tenants / groups，不能访问任何流", name));
+        }
+    }
+    let vault = match CredentialVault::new(&config.credentials) {
+        Ok(vault) => {
+            report.add(Level::Pass, "凭据", format!("共 {} 个账号", config.credentials.len()));
+            Some(vault)
+        }
+        Err(e) => {
+            report.add(Level::Fail, "凭据", format!("{:#}", e));
+            None
+        }
+    };
+
+    let store = match Store::open(&config.data_dir) {
+        Ok(store) => Arc::new(store),
+        Err(e) => {
+            report.add(Level::Fail, "数据库", format!("{:#}", e));
+            return;
+        }
+    };
+    let presets = VendorPresets::new(&config.vendor_presets);
+    let registry = match StreamRegistry::new(config.streams.clone(), store, presets.clone()) {
+        Ok(registry) => registry,
+        Err(e) => {
+            report.add(Level::Fail, "数据库", format!("{:#}", e));
+            return;
+        }
+    };
+    let streams = registry.list();
+    let failures = report.failures;
+    for stream in &streams {
+        let item = format!("流 '{}'", stream.name);
+        if let Some(device) = &stream.device
+            && let Err(e) = presets.url(device, device.subtype)
+        {
+            report.add(Level::Fail, &item, e.to_string());
+            continue;
+        }
+        let urls = std::iter::once(&stream.url).chain(&stream.backup_urls).chain(&stream.sub_url);
+        for url in urls.filter(|u| !transcoder::is_supported_input(u)) {
+            report.add(Level::Fail, &item, format!("不支持的地址 {}", vault::redact_url(url)));
+        }
+        if let (Some(vault), Some(credential)) = (&vault, &stream.credential)
+            && !vault.contains(credential)
+        {
+            report.add(Level::Fail, &item, format!("引用的凭据 '{}' 不存在", credential));
+        }
+        if let Some(url) = &stream.output.url
+            && let Err(e) = config.srs.check_output_url(url)
+        {
+            report.add(Level::Fail, &item, e.to_string());
+        }
+        for error in stream.protected_hours.iter().flat_map(|p| p.errors()) {
+            report.add(Level::Fail, &item, error);
+        }
+        for error in stream.input.errors().into_iter().chain(stream.output.errors()) {
+            report.add(Level::Fail, &item, error);
+        }
+        // 元数据不影响拉流，只提示；通过接口修改时会被拒绝
+        for error in stream.metadata_errors() {
+            report.add(Level::Warn, &item, error);
+        }
+        if let Some(Err(error)) = map::coordinates(stream) {
+            report.add(Level::Warn, &item, format!("{}，不会显示在地图上", error));
+        }
+    }
+    if report.failures == failures {
+        report.add(Level::Pass, "流配置", format!("共 {} 个流", streams.len()));
+    }
+
+    let srs = srs_client(config);
+    for stream in &streams {
+        if let Err(e) = srs.stream_path(&stream.name, Some(stream)) {
+            report.add(Level::Fail, "SRS 推流路径", e.to_string());
+        }
+    }
+    for (key, names) in srs.key_collisions(&streams).into_iter().filter(|(key, _)| !key.is_empty()) {
+        report.add(Level::Warn, "SRS 推流路径", format!("流 {:?} 的推流路径相同 ({})", names, key));
+    }
+    for field in config.play_overrides.iter().filter(|f| !OVERRIDABLE_FIELDS.contains(&f.as_str())) {
+        report.add(Level::Warn, "play_overrides", format!("未知字段 '{}'，可选: {:?}", field, OVERRIDABLE_FIELDS));
+    }
+    for error in config.soak_restart.window.iter().flat_map(|w| w.errors()) {
+        report.add(Level::Fail, "soak_restart", error);
+    }
+    if config.event_clips.enabled {
+        if !config.clip.enabled {
+            report.add(Level::Fail, "event_clips", "事件录像需要启用片段缓冲 (clip.enabled)");
+        }
+        for error in config.event_clips.errors(config.clip.buffer_secs) {
+            report.add(Level::Warn, "event_clips", error);
+        }
+        if let Some(encryption) = &config.event_clips.encryption {
+            match ClipCipher::load(encryption) {
+                Ok(_) => report.add(Level::Pass, "event_clips", "录像加密密钥已加载"),
+                Err(e) => report.add(Level::Fail, "event_clips", format!("录像加密密钥加载失败: {:#}", e)),
+            }
+            report.add(Level::Warn, "recording", "录制文件不支持加密，配置了录像加密的流不能开始录制");
+        }
+    }
+    if config.async_remux.enabled {
+        if cfg!(feature = "async-remux") {
+            let ok = std::process::Command::new(&config.async_remux.ffmpeg)
+                .arg("-version")
+                .output()
+                .is_ok_and(|o| o.status.success());
+            report.require(ok, false, &format!("ffmpeg 可执行文件 ({})", config.async_remux.ffmpeg), "异步转封装");
+        } else {
+            report.add(Level::Warn, "async_remux", "未以 async-remux 特性编译，配置不生效");
+        }
+    }
+    if config.http_flv.standalone && !config.http_flv.enabled {
+        report.add(Level::Warn, "http_flv", "standalone 需要同时启用 enabled，当前仍推流到 SRS");
+    }
+    // 硬件编码器还需要驱动与设备，这里只检查 FFmpeg 是否编译了该编码器
+    let encoders = std::iter::once(&config.encoder).chain(streams.iter().filter_map(|s| s.output.encoder.as_ref()));
+    let mut checked = Vec::new();
+    for kind in encoders.map(|e| e.video).filter(|k| *k != VideoEncoder::Libx264) {
+        if checked.contains(&kind) {
+            continue;
+        }
+        checked.push(kind);
+        match kind.find() {
+            Ok(_) => report.add(Level::Pass, "encoder", format!("编码器 {} 可用", kind.codec_name())),
+            Err(e) => report.add(Level::Fail, "encoder", e.to_string()),
+        }
+    }
+    for alias in registry.alias_conflicts() {
+        report.add(Level::Warn, "流别名", format!("'{}' 与其他流的名称或别名重复", alias));
+    }
+}
+
+fn check_ffmpeg(report: &mut Report) {
+    if let Err(e) = ffmpeg::init() {
+        report.add(Level::Fail, "初始化", e.to_string());
+        return;
+    }
+    report.add(Level::Pass, "版本", format!("libavformat {}", version_string(ffmpeg::format::version())));
+
+    // (名称, 是否必需, 用途)
+    for (name, required, purpose) in [("rtsp", true, "RTSP 拉流"), ("mpeg", false, "GB28181 PS 流")] {
+        let ok = with_cstr(name, |n| unsafe { !ffmpeg::ffi::av_find_input_format(n).is_null() });
+        report.require(ok, required, &format!("解复用器 {}", name), purpose);
+    }
+    for (name, required, purpose) in [("flv", true, "推流到 SRS"), ("mp4", false, "片段下载"), ("matroska", false, "输入抓包"), ("rtsp", false, "RTSP 转发服务")] {
+        let ok = with_cstr(name, |n| unsafe {
+            !ffmpeg::ffi::av_guess_format(n, std::ptr::null(), std::ptr::null()).is_null()
+        });
+        report.require(ok, required, &format!("封装器 {}", name), purpose);
+    }
+    for (name, required, purpose) in [
+        ("tcp", true, "RTSP / RTMP 连接"),
+        ("rtmp", true, "推流到 SRS"),
+        ("udp", false, "UDP 传输与组播输入"),
+        ("rtp", false, "RTP 输入"),
+        ("rtmps", false, "rtmps 推流地址"),
+        ("https", false, "RTSP over HTTPS 隧道"),
+    ] {
+        let ok = with_cstr(&format!("{}://127.0.0.1/", name), |u| unsafe {
+            !ffmpeg::ffi::avio_find_protocol_name(u).is_null()
+        });
+        report.require(ok, required, &format!("协议 {}", name), purpose);
+    }
+    for (id, name, purpose) in [
+        (ffmpeg::codec::Id::H264, "h264", "画面冻结 / 黑屏检测"),
+        (ffmpeg::codec::Id::HEVC, "hevc", "H.265 画面冻结 / 黑屏检测"),
+    ] {
+        report.require(ffmpeg::decoder::find(id).is_some(), false, &format!("解码器 {}", name), purpose);
+    }
+    for (id, name, purpose) in [
+        (ffmpeg::codec::Id::H264, "h264", "测试信号源与 H.265 转 H.264"),
+        (ffmpeg::codec::Id::AAC, "aac", "测试信号源与 G.711 音频转码"),
+    ] {
+        report.require(ffmpeg::encoder::find(id).is_some(), false, &format!("编码器 {}", name), purpose);
+    }
+    report.require(ffmpeg::encoder::find(ffmpeg::codec::Id::MJPEG).is_some(), false, "编码器 mjpeg", "预览墙");
+    let lavfi = ffmpeg::device::input::video().any(|f| f.name() == "lavfi");
+    report.require(lavfi, false, "设备 lavfi", "测试信号源");
+}
+
+async fn check_srs(config: &AppConfig, report: &mut Report) {
+    let health = srs_client(config).health().await;
+    if health.reachable {
+        let version = health.version.unwrap_or_else(|| "未知".to_string());
+        report.add(Level::Pass, "HTTP API", format!("版本 {}，延迟 {} ms", version, health.latency_ms.unwrap_or_default()));
+    } else {
+        report.add(Level::Fail, "HTTP API", health.error.unwrap_or_default());
+    }
+
+    let Some(host) = reqwest::Url::parse(&config.srs.api_url).ok().and_then(|u| u.host_str().map(str::to_string)) else {
+        report.add(Level::Fail, "RTMP 端口", "无法从 srs.api_url 解析主机名");
+        return;
+    };
+    let addr = format!("{}:1935", host);
+    match tokio::time::timeout(Duration::from_secs(3), tokio::net::TcpStream::connect(&addr)).await {
+        Ok(Ok(_)) => report.add(Level::Pass, "RTMP 端口", &addr),
+        Ok(Err(e)) => report.add(Level::Fail, "RTMP 端口", format!("{}: {}", addr, e)),
+        Err(_) => report.add(Level::Fail, "RTMP 端口", format!("{}: 连接超时", addr)),
+    }
+}
+
+fn check_dirs(config: &AppConfig, report: &mut Report) {
+    let data_dir = PathBuf::from(&config.data_dir);
+    for dir in [data_dir.clone(), data_dir.join("dumps"), data_dir.join("traces"), data_dir.join("clips")] {
+        match check_writable(&dir) {
+            Ok(()) => report.add(Level::Pass, "目录可写", dir.display().to_string()),
+            Err(e) => report.add(Level::Fail, "目录可写", format!("{}: {}", dir.display(), e)),
+        }
+    }
+    if let Some(web_dir) = &config.server.web_dir {
+        let index = Path::new(web_dir).join("index.html");
+        let level = if index.is_file() { Level::Pass } else { Level::Fail };
+        report.add(level, "前端目录", index.display().to_string());
+    }
+}
+
+/// 创建目录并写入、删除一个临时文件
+fn check_writable(dir: &Path) -> Result<()> {
+    std::fs::create_dir_all(dir)?;
+    let probe = dir.join(".doctor");
+    std::fs::write(&probe, b"ok")?;
+    std::fs::remove_file(&probe)?;
+    Ok(())
+}
+
+fn check_ports(config: &AppConfig, report: &mut Report) {
+    for bind in &config.server.bind {
+        let ip: IpAddr = match bind.trim_start_matches('[').trim_end_matches(']').parse() {
+            Ok(ip) => ip,
+            Err(e) => {
+                report.add(Level::Fail, "监听地址", format!("'{}': {}", bind, e));
+                continue;
+            }
+        };
+        let addr = SocketAddr::new(ip, config.server.port);
+        match std::net::TcpListener::bind(addr) {
+            Ok(_) => report.add(Level::Pass, "监听端口", addr.to_string()),
+            Err(e) => report.add(Level::Warn, "监听端口", format!("{}: {}（服务可能已在运行）", addr, e)),
+        }
+    }
+}
+
+fn srs_client(config: &AppConfig) -> SrsClient {
+    SrsClient::new(&config.srs, srs::http_client(&config.srs.http))
+}
+
+fn with_cstr<T>(value: &str, f: impl FnOnce(*const std::os::raw::c_char) -> T) -> T {
+    let value = CString::new(value).unwrap_or_default();
+    f(value.as_ptr())
+}
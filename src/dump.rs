@@ -0,0 +1,326 @@
+use anyhow::{Result, anyhow};
+use ffmpeg_next as ffmpeg;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use tracing::{error, info, warn};
+use crate::history::now_secs;
+
+/// 单次抓包的最长时长
+pub const MAX_DUMP_DURATION: Duration = Duration::from_secs(300);
+
+/// 单次抓包文件大小上限
+const MAX_DUMP_BYTES: u64 = 512 * 1024 * 1024;
+
+/// 写入中的抓包文件追加的后缀，正常结束后去掉；异常退出留下的文件在下次启动时修复
+const PARTIAL_EXTENSION: &str = "part";
+
+/// 抓包位置
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DumpMode {
+    /// 原始输入数据包（未修正时间戳），保存为 MKV
+    #[default]
+    Input,
+    /// 修正时间戳后推给 SRS 的数据包，保存为 FLV
+    Output,
+}
+
+impl DumpMode {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            DumpMode::Input => "mkv",
+            DumpMode::Output => "flv",
+        }
+    }
+
+    fn format(&self) -> &'static str {
+        match self {
+            DumpMode::Input => "matroska",
+            DumpMode::Output => "flv",
+        }
+    }
+}
+
+/// 抓包状态
+#[derive(Debug, Clone, Serialize, Default)]
+pub struct DumpStatus {
+    pub active: bool,
+    pub mode: DumpMode,
+    #[serde(skip)]
+    pub path: Option<PathBuf>,
+    pub started_at: Option<u64>,
+    pub finished_at: Option<u64>,
+    pub packets: u64,
+    pub bytes: u64,
+    pub error: Option<String>,
+}
+
+struct DumpRequest {
+    mode: DumpMode,
+    path: PathBuf,
+    duration: Duration,
+}
+
+/// 单个流的抓包控制
+///
+/// API 提交请求，转码线程在数据包循环中领取并写入文件。
+#[derive(Default)]
+pub struct DumpControl {
+    // 有待领取的请求，避免每个数据包都加锁
+    pending: AtomicBool,
+    request: Mutex<Option<DumpRequest>>,
+    status: Mutex<DumpStatus>,
+}
+
+impl DumpControl {
+    /// 提交抓包请求，已有抓包进行中时返回 false
+    pub fn request(&self, mode: DumpMode, path: PathBuf, duration: Duration) -> bool {
+        let mut status = self.status.lock().unwrap();
+        if status.active || self.pending.load(Ordering::Relaxed) {
+            return false;
+        }
+        *status = DumpStatus {
+            mode,
+            ..Default::default()
+        };
+        *self.request.lock().unwrap() = Some(DumpRequest {
+            mode,
+            path,
+            duration: duration.min(MAX_DUMP_DURATION),
+        });
+        self.pending.store(true, Ordering::Release);
+        true
+    }
+
+    pub fn status(&self) -> DumpStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    /// 转码线程领取指定位置的抓包请求
+    pub fn take(&self, mode: DumpMode) -> Option<(PathBuf, Duration)> {
+        if !self.pending.load(Ordering::Acquire) {
+            return None;
+        }
+        let mut request = self.request.lock().unwrap();
+        if request.as_ref()?.mode != mode {
+            return None;
+        }
+        let req = request.take()?;
+        self.pending.store(false, Ordering::Release);
+        Some((req.path, req.duration))
+    }
+
+    fn update(&self, f: impl FnOnce(&mut DumpStatus)) {
+        f(&mut self.status.lock().unwrap());
+    }
+}
+
+/// 抓包文件写入器
+pub struct Dumper<'a> {
+    octx: ffmpeg::format::context::Output,
+    /// 写入中的文件与结束后的文件名
+    partial: PathBuf,
+    path: PathBuf,
+    control: &'a DumpControl,
+    until: Instant,
+    packets: u64,
+    bytes: u64,
+}
+
+impl<'a> Dumper<'a> {
+    /// 按给定的流参数创建抓包文件
+    pub fn start(
+        control: &'a DumpControl,
+        mode: DumpMode,
+        path: PathBuf,
+        duration: Duration,
+        streams: &[ffmpeg::codec::Parameters],
+    ) -> Option<Self> {
+        let partial = partial_path(&path);
+        let result = (|| -> Result<ffmpeg::format::context::Output> {
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            let mut octx = ffmpeg::format::output_as(&partial, mode.format())?;
+            for params in streams {
+                let mut ostream = octx.add_stream(ffmpeg::encoder::find(ffmpeg::codec::Id::None))?;
+                ostream.set_parameters(params.clone());
+            }
+            // 每个数据包立即写入磁盘，进程崩溃时只丢失最后几个包
+            let mut options = ffmpeg::Dictionary::new();
+            options.set("flush_packets", "1");
+            octx.write_header_with(options)?;
+            Ok(octx)
+        })();
+
+        match result {
+            Ok(octx) => {
+                info!("开始抓包 ({:?}): {}", mode, path.display());
+                control.update(|s| {
+                    s.active = true;
+                    s.path = Some(path.clone());
+                    s.started_at = Some(now_secs());
+                });
+                Some(Self {
+                    octx,
+                    partial,
+                    path,
+                    control,
+                    until: Instant::now() + duration,
+                    packets: 0,
+                    bytes: 0,
+                })
+            }
+            Err(e) => {
+                error!("创建抓包文件失败: {}", e);
+                control.update(|s| {
+                    s.error = Some(e.to_string());
+                    s.finished_at = Some(now_secs());
+                });
+                None
+            }
+        }
+    }
+
+    /// 写入一个数据包，`time_base` 为数据包当前的时间基；返回是否继续抓包
+    pub fn write(&mut self, index: usize, packet: &ffmpeg::Packet, time_base: ffmpeg::Rational) -> bool {
+        if Instant::now() >= self.until || self.bytes >= MAX_DUMP_BYTES {
+            return false;
+        }
+        let Some(ostream) = self.octx.stream(index) else {
+            return true;
+        };
+        let mut packet = packet.clone();
+        packet.rescale_ts(time_base, ostream.time_base());
+        packet.set_stream(index);
+        packet.set_position(-1);
+        if let Err(e) = packet.write_interleaved(&mut self.octx) {
+            // 抓包失败不影响推流
+            self.control.update(|s| s.error = Some(e.to_string()));
+            return false;
+        }
+        self.packets += 1;
+        self.bytes += packet.size() as u64;
+        if self.packets.is_multiple_of(100) {
+            let (packets, bytes) = (self.packets, self.bytes);
+            self.control.update(|s| {
+                s.packets = packets;
+                s.bytes = bytes;
+            });
+        }
+        true
+    }
+
+}
+
+/// 结束抓包：写入文件尾并更新状态（转码出错退出时同样会执行）
+impl Drop for Dumper<'_> {
+    fn drop(&mut self) {
+        let trailer = self
+            .octx
+            .write_trailer()
+            .map_err(anyhow::Error::from)
+            .and_then(|_| Ok(std::fs::rename(&self.partial, &self.path)?));
+        let (packets, bytes) = (self.packets, self.bytes);
+        info!("抓包结束: {} 个数据包, {} 字节", packets, bytes);
+        self.control.update(|s| {
+            s.active = false;
+            s.packets = packets;
+            s.bytes = bytes;
+            s.finished_at = Some(now_secs());
+            if let Err(e) = trailer {
+                s.error.get_or_insert(e.to_string());
+            }
+        });
+    }
+}
+
+/// 写入抓包数据，抓包结束（超时、超出大小或出错）时关闭文件
+pub fn write_packet(dumper: &mut Option<Dumper>, index: usize, packet: &ffmpeg::Packet, time_base: ffmpeg::Rational) {
+    if let Some(d) = dumper.as_mut()
+        && !d.write(index, packet, time_base)
+    {
+        *dumper = None;
+    }
+}
+
+/// 写入中的文件路径，如 `a.mkv` -> `a.mkv.part`
+fn partial_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".");
+    name.push(PARTIAL_EXTENSION);
+    PathBuf::from(name)
+}
+
+/// 修复进程异常退出时未正常结束的抓包文件
+///
+/// 逐个转封装一遍补写文件尾（MKV 索引、FLV 时长），截断处之前的数据全部保留；
+/// 无法读取的文件改名为 `.damaged` 保留，便于人工处理。
+pub fn recover(dir: &Path) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    let partials = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == PARTIAL_EXTENSION));
+    for partial in partials {
+        // a.mkv.part -> a.mkv
+        let path = partial.with_extension("");
+        match remux(&partial, &path) {
+            Ok(packets) => {
+                info!("已修复未正常结束的抓包文件 {}（{} 个数据包）", path.display(), packets);
+                let _ = std::fs::remove_file(&partial);
+            }
+            Err(e) => {
+                warn!("修复抓包文件 {} 失败: {}，保留为 .damaged", partial.display(), e);
+                let _ = std::fs::remove_file(&path);
+                let _ = std::fs::rename(&partial, partial.with_extension("damaged"));
+            }
+        }
+    }
+}
+
+/// 转封装为同一格式，返回写入的数据包数
+fn remux(source: &Path, target: &Path) -> Result<u64> {
+    let format = match target.extension().and_then(|ext| ext.to_str()) {
+        Some("mkv") => "matroska",
+        Some("flv") => "flv",
+        _ => return Err(anyhow!("未知的文件类型")),
+    };
+    let mut ictx = ffmpeg::format::input(&source)?;
+    if ictx.nb_streams() == 0 {
+        return Err(anyhow!("文件中没有可用的流"));
+    }
+    let mut octx = ffmpeg::format::output_as(&target, format)?;
+    let mut time_bases = Vec::new();
+    for istream in ictx.streams() {
+        let mut ostream = octx.add_stream(ffmpeg::encoder::find(ffmpeg::codec::Id::None))?;
+        ostream.set_parameters(istream.parameters());
+        time_bases.push(istream.time_base());
+    }
+    octx.write_header()?;
+
+    let mut packets = 0;
+    // 文件被截断时读到截断处即结束
+    for (istream, mut packet) in ictx.packets() {
+        let index = istream.index();
+        let Some(ostream) = octx.stream(index) else {
+            continue;
+        };
+        packet.rescale_ts(time_bases[index], ostream.time_base());
+        packet.set_position(-1);
+        if packet.write_interleaved(&mut octx).is_err() {
+            break;
+        }
+        packets += 1;
+    }
+    octx.write_trailer()?;
+    if packets == 0 {
+        return Err(anyhow!("没有可读取的数据包"));
+    }
+    Ok(packets)
+}
@@ -0,0 +1,509 @@
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::io::AsyncRead;
+use tracing::{error, info, warn};
+use crate::clip::{ClipBuffer, file_stem};
+use crate::clip_crypto::{ClipCipher, ClipEncryptionConfig};
+use crate::history::{EventKind, HealthEvent, HealthHistory};
+use crate::stream_manager::StreamManager;
+
+/// 下载加密录像时解密输出的缓冲大小
+const DECRYPT_BUFFER: usize = 256 * 1024;
+
+/// 事件录像配置
+#[derive(Debug, Deserialize, Clone)]
+pub struct EventClipConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// 触发录像的事件
+    #[serde(default = "default_triggers")]
+    pub triggers: Vec<Trigger>,
+    /// 录入事件发生前的秒数（取自片段缓冲，需启用 `clip`）
+    #[serde(default = "default_pre_secs")]
+    pub pre_secs: u64,
+    /// 录入事件发生后的秒数，转码失败时流已中断，只录事件前
+    #[serde(default = "default_post_secs")]
+    pub post_secs: u64,
+    /// 同一个流两次自动录像的最小间隔（秒），避免反复重连时连续录像
+    #[serde(default = "default_cooldown_secs")]
+    pub cooldown_secs: u64,
+    /// 录像保留天数
+    #[serde(default = "default_retention_days")]
+    pub retention_days: u64,
+    /// 录像文件加密（AES-256-GCM），录像目录位于共享存储时使用
+    #[serde(default)]
+    pub encryption: Option<ClipEncryptionConfig>,
+}
+
+impl Default for EventClipConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            triggers: default_triggers(),
+            pre_secs: default_pre_secs(),
+            post_secs: default_post_secs(),
+            cooldown_secs: default_cooldown_secs(),
+            retention_days: default_retention_days(),
+            encryption: None,
+        }
+    }
+}
+
+fn default_triggers() -> Vec<Trigger> {
+    vec![Trigger::Failure, Trigger::Content, Trigger::Change, Trigger::Webhook]
+}
+
+fn default_pre_secs() -> u64 {
+    10
+}
+
+fn default_post_secs() -> u64 {
+    20
+}
+
+fn default_cooldown_secs() -> u64 {
+    60
+}
+
+fn default_retention_days() -> u64 {
+    7
+}
+
+impl EventClipConfig {
+    /// 配置错误说明，`buffer_secs` 为片段缓冲的保留时长
+    pub fn errors(&self, buffer_secs: u64) -> Vec<String> {
+        let mut errors = Vec::new();
+        if self.pre_secs + self.post_secs > buffer_secs {
+            errors.push(format!(
+                "pre_secs + post_secs ({} 秒) 超过片段缓冲时长 clip.buffer_secs ({} 秒)，录像开头会被截断",
+                self.pre_secs + self.post_secs,
+                buffer_secs
+            ));
+        }
+        if self.triggers.is_empty() {
+            errors.push("triggers 为空，不会自动录像".to_string());
+        }
+        errors
+    }
+}
+
+/// 录像触发事件
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Trigger {
+    /// 转码失败或输入流结束
+    Failure,
+    /// 画面冻结 / 黑屏
+    Content,
+    /// 画面明显变化（移动侦测），查询时也可写作 `motion`
+    #[serde(alias = "motion")]
+    Change,
+    /// 外部系统通过接口触发
+    Webhook,
+}
+
+impl Trigger {
+    /// 健康历史事件对应的触发类型，恢复类事件不触发
+    fn of(event: &HealthEvent) -> Option<Self> {
+        match event.kind {
+            EventKind::Connectivity if !event.up && is_failure(&event.reason) => Some(Self::Failure),
+            EventKind::Content if !event.up => Some(Self::Content),
+            EventKind::Change => Some(Self::Change),
+            _ => None,
+        }
+    }
+}
+
+/// 转码器异常结束时记录的原因（主动停止、超时等由管理器记录，不触发录像）
+fn is_failure(reason: &str) -> bool {
+    reason.starts_with("转码失败") || reason == "输入流结束"
+}
+
+/// 一段事件录像
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventClip {
+    pub id: String,
+    pub stream: String,
+    /// 事件发生时间，Unix 时间戳（秒）
+    pub at: u64,
+    pub trigger: Trigger,
+    pub reason: String,
+    /// 录像覆盖事件前后的秒数
+    pub pre_secs: u64,
+    pub post_secs: u64,
+    pub bytes: u64,
+    /// 录像实际的起止时间，Unix 时间戳（秒）；旧版本的录像按事件时间与前后秒数推算
+    #[serde(default)]
+    pub start: u64,
+    #[serde(default)]
+    pub end: u64,
+    /// 各路流的编码，如 `["h264", "aac"]`
+    #[serde(default)]
+    pub codecs: Vec<String>,
+    /// 录像文件已加密（保存为 `<id>.mp4.enc`），下载时解密
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub encrypted: bool,
+}
+
+impl EventClip {
+    /// 录像的起止时间
+    fn span(&self) -> (u64, u64) {
+        if self.end > 0 {
+            (self.start, self.end)
+        } else {
+            (self.at.saturating_sub(self.pre_secs), self.at + self.post_secs)
+        }
+    }
+}
+
+/// 录像查询条件，均为可选
+#[derive(Debug, Default, Deserialize)]
+pub struct ClipSearch {
+    pub stream: Option<String>,
+    /// 时间范围（Unix 秒），与录像起止时间有重叠即匹配
+    pub from: Option<u64>,
+    pub to: Option<u64>,
+    pub trigger: Option<Trigger>,
+}
+
+impl ClipSearch {
+    fn matches(&self, clip: &EventClip) -> bool {
+        let (start, end) = clip.span();
+        self.stream.as_ref().is_none_or(|s| clip.stream == *s)
+            && self.from.is_none_or(|from| end >= from)
+            && self.to.is_none_or(|to| start <= to)
+            && self.trigger.is_none_or(|t| clip.trigger == t)
+    }
+}
+
+/// 事件录像
+///
+/// 订阅健康历史事件，按配置的触发类型从片段缓冲截取事件前后的录像，
+/// 保存为 `<data_dir>/event_clips/<id>.mp4`，同名的 `<id>.json` 记录录像信息（流、起止时间、编码、大小、触发类型），
+/// 索引以 JSON Lines 写入同目录的 `index.jsonl`，索引丢失时启动时根据这些文件重建。
+/// 配置了加密的流，录像加密后保存为 `<id>.mp4.enc`，录像信息文件与索引不加密。
+pub struct EventClips {
+    config: EventClipConfig,
+    /// 录像加密密钥，配置了加密但密钥加载失败时为 None，此时不保存需要加密的录像
+    cipher: Option<Arc<ClipCipher>>,
+    dir: PathBuf,
+    /// 每个流最近一次自动录像的时间
+    last: Mutex<HashMap<String, Instant>>,
+    // 串行化索引文件读写
+    lock: Mutex<()>,
+}
+
+impl EventClips {
+    /// 打开录像目录，并清理超过保留期的录像
+    pub fn new(data_dir: &str, config: EventClipConfig) -> Self {
+        let cipher = config.encryption.as_ref().and_then(|encryption| match ClipCipher::load(encryption) {
+            Ok(cipher) => Some(Arc::new(cipher)),
+            Err(e) => {
+                error!("加载录像加密密钥失败，需要加密的流将不保存录像: {:#}", e);
+                None
+            }
+        });
+        let clips = Self {
+            config,
+            cipher,
+            dir: PathBuf::from(data_dir).join("event_clips"),
+            last: Mutex::new(HashMap::new()),
+            lock: Mutex::new(()),
+        };
+        if !clips.index_path().exists() {
+            match clips.rebuild_index() {
+                Ok(0) => {}
+                Ok(n) => info!("已根据录像信息文件重建事件录像索引（{} 段）", n),
+                Err(e) => error!("重建事件录像索引失败: {}", e),
+            }
+        }
+        if let Err(e) = clips.prune() {
+            error!("清理事件录像失败: {}", e);
+        }
+        clips
+    }
+
+    pub fn enabled(&self, trigger: Trigger) -> bool {
+        self.config.enabled && self.config.triggers.contains(&trigger)
+    }
+
+    /// 在后台订阅健康历史事件并自动录像
+    pub fn spawn(self: &Arc<Self>, history: &HealthHistory, manager: Arc<StreamManager>) {
+        if !self.config.enabled {
+            return;
+        }
+        let mut events = history.subscribe();
+        let clips = self.clone();
+        tokio::spawn(async move {
+            loop {
+                let event = match events.recv().await {
+                    Ok(event) => event,
+                    Err(tokio::sync::broadcast::error::RecvError::Lagged(n)) => {
+                        warn!("事件录像处理过慢，跳过 {} 个事件", n);
+                        continue;
+                    }
+                    Err(_) => break,
+                };
+                let Some(trigger) = Trigger::of(&event).filter(|t| clips.enabled(*t)) else {
+                    continue;
+                };
+                let Some(buffer) = manager.clip_buffer(&event.stream) else {
+                    continue;
+                };
+                if !clips.cool_down(&event.stream) {
+                    continue;
+                }
+                clips.record(&event.stream, buffer, trigger, &event.reason);
+            }
+        });
+    }
+
+    /// 距上次自动录像超过冷却时间时记下本次时间并返回 true
+    fn cool_down(&self, stream: &str) -> bool {
+        let now = Instant::now();
+        let mut last = self.last.lock().unwrap();
+        let cooldown = Duration::from_secs(self.config.cooldown_secs);
+        if last.get(stream).is_some_and(|at| now.duration_since(*at) < cooldown) {
+            return false;
+        }
+        last.insert(stream.to_string(), now);
+        true
+    }
+
+    /// 开始录制一段事件录像，返回录像 ID；录像在事件后 `post_secs` 秒写入
+    pub fn record(self: &Arc<Self>, stream: &str, buffer: Arc<ClipBuffer>, trigger: Trigger, reason: &str) -> String {
+        let mark = Instant::now();
+        let at_ms = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis()).unwrap_or(0);
+        let id = format!("{}-{}", file_stem(stream), at_ms);
+        let since = mark.checked_sub(Duration::from_secs(self.config.pre_secs)).unwrap_or(mark);
+        // 先截取事件前的部分，等待期间转码器重连会清空缓冲
+        let before = buffer.clip(since);
+        let generation = buffer.generation();
+        let post_secs = if trigger == Trigger::Failure { 0 } else { self.config.post_secs };
+        info!("流 '{}' 触发事件录像 ({:?}: {})", stream, trigger, reason);
+
+        let mut entry = EventClip {
+            id: id.clone(),
+            stream: stream.to_string(),
+            at: (at_ms / 1000) as u64,
+            trigger,
+            reason: reason.to_string(),
+            pre_secs: self.config.pre_secs,
+            post_secs,
+            bytes: 0,
+            start: 0,
+            end: 0,
+            codecs: Vec::new(),
+            encrypted: false,
+        };
+        let clips = self.clone();
+        tokio::spawn(async move {
+            let clip = if post_secs > 0 {
+                tokio::time::sleep(Duration::from_secs(post_secs)).await;
+                if buffer.generation() == generation {
+                    buffer.clip(since)
+                } else {
+                    entry.post_secs = 0;
+                    before
+                }
+            } else {
+                before
+            };
+            let Some(clip) = clip else {
+                warn!("流 '{}' 尚未缓冲到关键帧，无法录制事件录像", entry.stream);
+                return;
+            };
+            let (first, last) = clip.span();
+            entry.start = unix_secs(at_ms, mark, first);
+            entry.end = unix_secs(at_ms, mark, last);
+            entry.codecs = clip.codecs();
+            let result = tokio::task::spawn_blocking(move || {
+                let cipher = clips.cipher_for(&entry.stream)?;
+                entry.encrypted = cipher.is_some();
+                let path = clips.path(&entry);
+                match cipher {
+                    Some(cipher) => {
+                        // 明文先写入本机临时目录，加密后删除，不出现在录像目录（可能是共享存储）中
+                        let plain = std::env::temp_dir().join(format!("rtsp2flv-{}.mp4", entry.id));
+                        fs::create_dir_all(&clips.dir)?;
+                        let written = clip.write_mp4(&plain).and_then(|_| cipher.encrypt_file(&entry.stream, &plain, &path));
+                        let _ = fs::remove_file(&plain);
+                        written?;
+                    }
+                    None => clip.write_mp4(&path)?,
+                }
+                entry.bytes = fs::metadata(&path)?.len();
+                fs::write(clips.sidecar_path(&entry.id), serde_json::to_string_pretty(&entry)?)?;
+                clips.append(&entry)?;
+                info!("流 '{}' 事件录像已保存: {} ({} 字节)", entry.stream, entry.id, entry.bytes);
+                Ok::<_, anyhow::Error>(())
+            })
+            .await;
+            match result {
+                Ok(Err(e)) => error!("保存事件录像失败: {:#}", e),
+                Err(e) => error!("保存事件录像失败: {}", e),
+                Ok(Ok(())) => {}
+            }
+        });
+        id
+    }
+
+    /// 录像文件路径
+    fn path(&self, clip: &EventClip) -> PathBuf {
+        match clip.encrypted {
+            true => self.dir.join(format!("{}.mp4.enc", clip.id)),
+            false => self.dir.join(format!("{}.mp4", clip.id)),
+        }
+    }
+
+    /// 流的录像需要加密时返回密钥，密钥未能加载时返回错误
+    fn cipher_for(&self, stream: &str) -> Result<Option<Arc<ClipCipher>>> {
+        match &self.config.encryption {
+            Some(encryption) if encryption.applies(stream) => match &self.cipher {
+                Some(cipher) => Ok(Some(cipher.clone())),
+                None => Err(anyhow!("流 '{}' 的录像需要加密，但录像加密密钥未能加载，不保存明文录像", stream)),
+            },
+            _ => Ok(None),
+        }
+    }
+
+    /// 打开录像用于下载，加密的录像在后台边读边解密
+    pub async fn open(&self, clip: &EventClip) -> Result<Box<dyn AsyncRead + Send + Unpin>> {
+        let file = tokio::fs::File::open(self.path(clip)).await?;
+        if !clip.encrypted {
+            return Ok(Box::new(file));
+        }
+        let cipher = self.cipher.clone().ok_or(anyhow!("录像加密密钥未能加载，无法解密录像"))?;
+        let (reader, writer) = tokio::io::duplex(DECRYPT_BUFFER);
+        let (id, stream) = (clip.id.clone(), clip.stream.clone());
+        tokio::spawn(async move {
+            if let Err(e) = cipher.decrypt_to(&stream, tokio::io::BufReader::new(file), writer).await {
+                error!("解密事件录像 {} 失败: {:#}", id, e);
+            }
+        });
+        Ok(Box::new(reader))
+    }
+
+    /// 录像信息文件路径
+    fn sidecar_path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", id))
+    }
+
+    /// 按时间倒序查找录像
+    pub fn search(&self, query: &ClipSearch) -> Result<Vec<EventClip>> {
+        let mut clips: Vec<EventClip> = self.load()?.into_iter().filter(|c| query.matches(c)).collect();
+        clips.reverse();
+        Ok(clips)
+    }
+
+    /// 按时间倒序列出录像，可按流过滤
+    pub fn list(&self, stream: Option<&str>) -> Result<Vec<EventClip>> {
+        let mut clips: Vec<EventClip> = self
+            .load()?
+            .into_iter()
+            .filter(|c| stream.is_none_or(|s| c.stream == s))
+            .collect();
+        clips.reverse();
+        Ok(clips)
+    }
+
+    /// 查找录像
+    pub fn get(&self, id: &str) -> Result<Option<EventClip>> {
+        Ok(self.load()?.into_iter().find(|c| c.id == id))
+    }
+
+    fn index_path(&self) -> PathBuf {
+        self.dir.join("index.jsonl")
+    }
+
+    fn append(&self, entry: &EventClip) -> Result<()> {
+        {
+            let _guard = self.lock.lock().unwrap();
+            fs::create_dir_all(&self.dir)?;
+            let mut file = OpenOptions::new().create(true).append(true).open(self.index_path())?;
+            writeln!(file, "{}", serde_json::to_string(entry)?)?;
+        }
+        self.prune()
+    }
+
+    fn load(&self) -> Result<Vec<EventClip>> {
+        let _guard = self.lock.lock().unwrap();
+        let file = match fs::File::open(self.index_path()) {
+            Ok(f) => f,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(BufReader::new(file)
+            .lines()
+            .map_while(|l| l.ok())
+            .filter_map(|l| serde_json::from_str(&l).ok())
+            .collect())
+    }
+
+    /// 根据录像信息文件重建索引，返回录像数
+    fn rebuild_index(&self) -> Result<usize> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e.into()),
+        };
+        let mut clips: Vec<EventClip> = entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .filter_map(|path| serde_json::from_str(&fs::read_to_string(&path).ok()?).ok())
+            .collect();
+        if clips.is_empty() {
+            return Ok(0);
+        }
+        clips.sort_by_key(|c| c.at);
+        let _guard = self.lock.lock().unwrap();
+        let mut content = String::new();
+        for clip in &clips {
+            content.push_str(&serde_json::to_string(clip)?);
+            content.push('\n');
+        }
+        fs::write(self.index_path(), content)?;
+        Ok(clips.len())
+    }
+
+    /// 删除超过保留期的录像及其索引
+    fn prune(&self) -> Result<()> {
+        let cutoff = crate::history::now_secs().saturating_sub(self.config.retention_days * 86400);
+        let clips = self.load()?;
+        let (kept, expired): (Vec<&EventClip>, Vec<&EventClip>) = clips.iter().partition(|c| c.at >= cutoff);
+        if expired.is_empty() {
+            return Ok(());
+        }
+
+        let _guard = self.lock.lock().unwrap();
+        for clip in &expired {
+            let _ = fs::remove_file(self.path(clip));
+            let _ = fs::remove_file(self.sidecar_path(&clip.id));
+        }
+        let mut content = String::new();
+        for clip in &kept {
+            content.push_str(&serde_json::to_string(clip)?);
+            content.push('\n');
+        }
+        fs::write(self.index_path(), content)?;
+        info!("已清理 {} 段过期的事件录像", expired.len());
+        Ok(())
+    }
+}
+
+/// 以 `mark`（对应 Unix 毫秒 `mark_ms`）为基准把缓冲时间换算为 Unix 秒
+fn unix_secs(mark_ms: u128, mark: Instant, at: Instant) -> u64 {
+    let ms = if at <= mark {
+        mark_ms.saturating_sub(mark.duration_since(at).as_millis())
+    } else {
+        mark_ms + at.duration_since(mark).as_millis()
+    };
+    (ms / 1000) as u64
+}
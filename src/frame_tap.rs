@@ -0,0 +1,166 @@
+use anyhow::Result;
+use ffmpeg_next as ffmpeg;
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+/// 分析用画面宽度
+pub const TAP_WIDTH: u32 = 160;
+/// 分析用画面高度
+pub const TAP_HEIGHT: u32 = 90;
+
+/// 缩小为 TAP_WIDTH x TAP_HEIGHT 的灰度画面（按行紧密排列）
+pub struct GrayFrame {
+    pub data: Vec<u8>,
+}
+
+/// 一次抽帧的结果，只包含到达各自采样间隔的画面
+#[derive(Default)]
+pub struct Sample {
+    /// 画面检测用的灰度画面
+    pub gray: Option<GrayFrame>,
+    /// 预览用的彩色缩略图（YUVJ420P）
+    pub preview: Option<ffmpeg::frame::Video>,
+}
+
+/// 一种输出画面的采样间隔与缩放器
+struct Output {
+    format: ffmpeg::format::Pixel,
+    width: u32,
+    height: u32,
+    interval: Duration,
+    last_sample: Option<Instant>,
+    /// 本次送入的关键帧是否需要输出该画面
+    due: bool,
+    scaler: Option<ffmpeg::software::scaling::Context>,
+}
+
+impl Output {
+    fn new(format: ffmpeg::format::Pixel, width: u32, height: u32, interval: Duration) -> Self {
+        Self { format, width, height, interval, last_sample: None, due: false, scaler: None }
+    }
+
+    /// 到达采样间隔时标记为待输出
+    fn schedule(&mut self, now: Instant) -> bool {
+        self.due = self.last_sample.is_none_or(|at| now.duration_since(at) >= self.interval);
+        if self.due {
+            self.last_sample = Some(now);
+        }
+        self.due
+    }
+
+    fn convert(&mut self, frame: &ffmpeg::frame::Video) -> Result<ffmpeg::frame::Video> {
+        // 分辨率或像素格式变化时重建缩放器
+        let stale = self.scaler.as_ref().is_none_or(|s| {
+            let input = s.input();
+            input.width != frame.width() || input.height != frame.height() || input.format != frame.format()
+        });
+        if stale {
+            self.scaler = Some(ffmpeg::software::scaling::Context::get(
+                frame.format(),
+                frame.width(),
+                frame.height(),
+                self.format,
+                self.width,
+                self.height,
+                ffmpeg::software::scaling::Flags::FAST_BILINEAR,
+            )?);
+        }
+
+        let mut scaled = ffmpeg::frame::Video::empty();
+        if let Some(scaler) = self.scaler.as_mut() {
+            scaler.run(frame, &mut scaled)?;
+        }
+        Ok(scaled)
+    }
+}
+
+/// 抽帧器
+///
+/// 转码只做转封装，不解码视频。这里按间隔只解码关键帧（可独立解码，代价很小），
+/// 缩小为灰度画面供画面检测、缩小为彩色缩略图供预览墙使用，两者的采样间隔相互独立。
+pub struct FrameTap {
+    stream_index: usize,
+    decoder: ffmpeg::decoder::Video,
+    frame: ffmpeg::frame::Video,
+    gray: Option<Output>,
+    preview: Option<Output>,
+}
+
+impl FrameTap {
+    /// 为输入中的主视频流创建抽帧器，没有视频流时返回 None
+    pub fn new(ictx: &ffmpeg::format::context::Input) -> Result<Option<Self>> {
+        let Some(stream) = ictx.streams().best(ffmpeg::media::Type::Video) else {
+            return Ok(None);
+        };
+        let decoder = ffmpeg::codec::context::Context::from_parameters(stream.parameters())?
+            .decoder()
+            .video()?;
+        Ok(Some(Self {
+            stream_index: stream.index(),
+            decoder,
+            frame: ffmpeg::frame::Video::empty(),
+            gray: None,
+            preview: None,
+        }))
+    }
+
+    /// 按间隔输出灰度画面
+    pub fn with_gray(mut self, interval: Duration) -> Self {
+        self.gray = Some(Output::new(ffmpeg::format::Pixel::GRAY8, TAP_WIDTH, TAP_HEIGHT, interval));
+        self
+    }
+
+    /// 按间隔输出指定尺寸的彩色缩略图
+    pub fn with_preview(mut self, (width, height): (u32, u32), interval: Duration) -> Self {
+        self.preview = Some(Output::new(ffmpeg::format::Pixel::YUVJ420P, width, height, interval));
+        self
+    }
+
+    /// 送入一个输入包，到达采样间隔的关键帧会被解码，返回缩小后的画面
+    pub fn feed(&mut self, stream_index: usize, packet: &ffmpeg::Packet) -> Option<Sample> {
+        if stream_index != self.stream_index || !packet.is_key() {
+            return None;
+        }
+        let now = Instant::now();
+        let mut due = false;
+        for output in [self.gray.as_mut(), self.preview.as_mut()].into_iter().flatten() {
+            due |= output.schedule(now);
+        }
+        if !due {
+            return None;
+        }
+
+        if let Err(e) = self.decoder.send_packet(packet) {
+            debug!("抽帧解码失败: {}", e);
+            return None;
+        }
+        // 解码器有延迟时画面在下一次送入关键帧后取出
+        let mut sample = Sample::default();
+        while self.decoder.receive_frame(&mut self.frame).is_ok() {
+            if let Some(output) = self.gray.as_mut().filter(|o| o.due) {
+                match output.convert(&self.frame) {
+                    Ok(gray) => sample.gray = Some(pack_gray(&gray)),
+                    Err(e) => debug!("抽帧缩放失败: {}", e),
+                }
+            }
+            if let Some(output) = self.preview.as_mut().filter(|o| o.due) {
+                match output.convert(&self.frame) {
+                    Ok(preview) => sample.preview = Some(preview),
+                    Err(e) => debug!("抽帧缩放失败: {}", e),
+                }
+            }
+        }
+        Some(sample)
+    }
+}
+
+/// 去掉行尾的对齐填充
+fn pack_gray(gray: &ffmpeg::frame::Video) -> GrayFrame {
+    let (width, height) = (TAP_WIDTH as usize, TAP_HEIGHT as usize);
+    let stride = gray.stride(0);
+    let mut data = Vec::with_capacity(width * height);
+    for row in gray.data(0).chunks(stride).take(height) {
+        data.extend_from_slice(&row[..width]);
+    }
+    GrayFrame { data }
+}
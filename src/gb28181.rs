@@ -0,0 +1,734 @@
+use anyhow::{Context, Result, anyhow, bail};
+use md5::Md5;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, UdpSocket};
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+/// GB28181 输入地址前缀
+///
+/// 形如 `gb28181://<设备编码>/<通道编码>`，通道编码省略时使用设备编码（单通道 IPC）。
+pub const SCHEME: &str = "gb28181://";
+
+/// 点播请求等待设备应答的时长
+const INVITE_TIMEOUT: Duration = Duration::from_secs(10);
+/// 点播请求的重发间隔（UDP 信令可能丢包）
+const INVITE_RETRANSMIT: Duration = Duration::from_millis(1000);
+/// 收流超时，超时后断开转码器连接，由流管理器重连
+const RTP_TIMEOUT: Duration = Duration::from_secs(10);
+/// 超过该时长未收到心跳视为设备离线
+const KEEPALIVE_TIMEOUT: Duration = Duration::from_secs(180);
+/// 注册鉴权 nonce 有效期
+const NONCE_TTL: Duration = Duration::from_secs(300);
+
+const USER_AGENT: &str = "rtsp2flv";
+
+/// GB28181 接入配置
+#[derive(Debug, Deserialize, Clone)]
+pub struct Gb28181Config {
+    #[serde(default)]
+    pub enabled: bool,
+    /// 本平台 SIP 服务器编码（20 位国标编码）
+    #[serde(default = "default_sip_id")]
+    pub sip_id: String,
+    /// SIP 域，一般为编码前 10 位
+    #[serde(default = "default_realm")]
+    pub realm: String,
+    /// SIP 信令监听地址（UDP）
+    #[serde(default = "default_listen")]
+    pub listen: String,
+    /// 设备注册密码，不配置时不校验
+    #[serde(default)]
+    pub password: Option<String>,
+    /// SDP 中通告的收流地址，不配置时按到设备的路由自动选择本机地址
+    #[serde(default)]
+    pub media_ip: Option<IpAddr>,
+    /// 收流 RTP 端口范围 [起始, 结束]
+    #[serde(default = "default_rtp_ports")]
+    pub rtp_ports: [u16; 2],
+}
+
+impl Default for Gb28181Config {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            sip_id: default_sip_id(),
+            realm: default_realm(),
+            listen: default_listen(),
+            password: None,
+            media_ip: None,
+            rtp_ports: default_rtp_ports(),
+        }
+    }
+}
+
+fn default_sip_id() -> String {
+    "34020000002000000001".to_string()
+}
+
+fn default_realm() -> String {
+    "3402000000".to_string()
+}
+
+fn default_listen() -> String {
+    "0.0.0.0:5060".to_string()
+}
+
+fn default_rtp_ports() -> [u16; 2] {
+    [30000, 30999]
+}
+
+/// 设备通道（来自目录查询）
+#[derive(Debug, Clone, Serialize)]
+pub struct Channel {
+    pub id: String,
+    pub name: String,
+    pub status: String,
+}
+
+/// 已注册设备信息
+#[derive(Debug, Serialize)]
+pub struct DeviceInfo {
+    pub id: String,
+    pub addr: String,
+    pub online: bool,
+    /// 距最近一次注册 / 心跳的秒数
+    pub last_seen_secs: u64,
+    pub channels: Vec<Channel>,
+}
+
+struct Device {
+    addr: SocketAddr,
+    expires: Instant,
+    last_seen: Instant,
+    channels: Vec<Channel>,
+}
+
+/// SIP 消息（请求或响应）
+struct SipMessage {
+    start_line: String,
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+impl SipMessage {
+    fn parse(data: &[u8]) -> Option<Self> {
+        let text = String::from_utf8_lossy(data);
+        let (head, body) = text.split_once("\r\n\r\n").unwrap_or((&text, ""));
+        let mut lines = head.split("\r\n");
+        let start_line = lines.next()?.trim().to_string();
+        if start_line.is_empty() {
+            return None;
+        }
+        let headers = lines
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, value)| (expand_compact(name.trim()).to_string(), value.trim().to_string()))
+            .collect();
+        Some(Self {
+            start_line,
+            headers,
+            body: body.to_string(),
+        })
+    }
+
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// 请求方法，响应消息返回 None
+    fn method(&self) -> Option<&str> {
+        (!self.start_line.starts_with("SIP/")).then(|| self.start_line.split(' ').next().unwrap_or_default())
+    }
+
+    fn status(&self) -> Option<u16> {
+        self.start_line.strip_prefix("SIP/2.0 ")?.get(..3)?.parse().ok()
+    }
+
+    fn call_id(&self) -> &str {
+        self.header("Call-ID").unwrap_or_default()
+    }
+
+    /// 生成对本请求的响应，`from` 为请求的实际来源地址
+    fn response(&self, from: SocketAddr, status: u16, reason: &str, extra: &[(&str, String)]) -> String {
+        let mut out = format!("SIP/2.0 {} {}\r\n", status, reason);
+        let mut first_via = true;
+        for (name, value) in &self.headers {
+            if name.eq_ignore_ascii_case("Via") {
+                // 设备在 NAT 后时回填实际来源地址
+                let value = if first_via { fill_rport(value, from) } else { value.clone() };
+                first_via = false;
+                out.push_str(&format!("Via: {}\r\n", value));
+            } else if name.eq_ignore_ascii_case("To") && !value.contains(";tag=") {
+                out.push_str(&format!("To: {};tag={}\r\n", value, random_token()));
+            } else if ["From", "To", "Call-ID", "CSeq"].iter().any(|h| name.eq_ignore_ascii_case(h)) {
+                out.push_str(&format!("{}: {}\r\n", name, value));
+            }
+        }
+        for (name, value) in extra {
+            out.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        out.push_str(&format!("User-Agent: {}\r\nContent-Length: 0\r\n\r\n", USER_AGENT));
+        out
+    }
+}
+
+/// SIP 紧凑头名转为完整头名
+fn expand_compact(name: &str) -> &str {
+    match name {
+        "v" => "Via",
+        "f" => "From",
+        "t" => "To",
+        "i" => "Call-ID",
+        "m" => "Contact",
+        "l" => "Content-Length",
+        "c" => "Content-Type",
+        other => other,
+    }
+}
+
+/// Via 头带空 rport 参数时回填来源端口与地址 (RFC 3581)
+fn fill_rport(via: &str, from: SocketAddr) -> String {
+    if !via.split(';').any(|p| p.trim() == "rport") {
+        return via.to_string();
+    }
+    let params: Vec<String> = via
+        .split(';')
+        .map(|p| if p.trim() == "rport" { format!("rport={}", from.port()) } else { p.to_string() })
+        .collect();
+    format!("{};received={}", params.join(";"), from.ip())
+}
+
+/// 从 `<sip:34020000001320000001@3402000000>;tag=...` 中取出编码
+fn uri_user(value: &str) -> Option<&str> {
+    let rest = &value[value.find("sip:")? + 4..];
+    let end = rest.find(['@', '>', ';']).unwrap_or(rest.len());
+    Some(&rest[..end]).filter(|user| !user.is_empty())
+}
+
+/// 解析 Digest 鉴权参数
+fn digest_params(value: &str) -> HashMap<String, String> {
+    value
+        .split(',')
+        .filter_map(|p| p.split_once('='))
+        .map(|(k, v)| (k.trim().to_lowercase(), v.trim().trim_matches('"').to_string()))
+        .collect()
+}
+
+/// 取 MANSCDP XML 中第一个指定标签的内容
+fn xml_value<'a>(xml: &'a str, tag: &str) -> Option<&'a str> {
+    let start = xml.find(&format!("<{}>", tag))? + tag.len() + 2;
+    let end = xml[start..].find(&format!("</{}>", tag))? + start;
+    Some(xml[start..end].trim())
+}
+
+fn md5_hex(text: &str) -> String {
+    format!("{:x}", Md5::digest(text.as_bytes()))
+}
+
+/// 生成 tag / branch / Call-ID / nonce 使用的随机串
+fn random_token() -> String {
+    static COUNTER: AtomicU32 = AtomicU32::new(0);
+    let seed = format!(
+        "{:?}-{}-{}",
+        SystemTime::now(),
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    );
+    Sha256::digest(seed.as_bytes())[..8].iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// 去掉 RTP 头（含 CSRC、扩展头与填充），返回负载
+fn rtp_payload(packet: &[u8]) -> Option<&[u8]> {
+    if packet.len() < 12 || packet[0] >> 6 != 2 {
+        return None;
+    }
+    let mut offset = 12 + (packet[0] & 0x0f) as usize * 4;
+    if packet[0] & 0x10 != 0 {
+        let ext = packet.get(offset + 2..offset + 4)?;
+        offset += 4 + u16::from_be_bytes([ext[0], ext[1]]) as usize * 4;
+    }
+    let mut end = packet.len();
+    if packet[0] & 0x20 != 0 {
+        end = end.checked_sub(*packet.last()? as usize)?;
+    }
+    packet.get(offset..end)
+}
+
+/// 解析 `gb28181://<设备编码>[/<通道编码>]`
+fn parse_url(url: &str) -> Result<(String, String)> {
+    let rest = url.get(SCHEME.len()..).filter(|_| url.to_lowercase().starts_with(SCHEME)).unwrap_or_default();
+    let mut parts = rest.trim_end_matches('/').splitn(2, '/');
+    let device = parts.next().filter(|d| !d.is_empty()).ok_or_else(|| anyhow!("GB28181 地址缺少设备编码: {}", url))?;
+    let channel = parts.next().filter(|c| !c.is_empty()).unwrap_or(device);
+    Ok((device.to_string(), channel.to_string()))
+}
+
+/// SDP 中的地址类型
+fn addr_type(ip: IpAddr) -> &'static str {
+    if ip.is_ipv4() { "IP4" } else { "IP6" }
+}
+
+/// 对话信息，用于发送 ACK / BYE
+#[derive(Clone)]
+struct Dialog {
+    addr: SocketAddr,
+    /// 请求目标编码（设备或通道）
+    target: String,
+    call_id: String,
+    from_tag: String,
+    /// To 头，收到 200 OK 后带上设备的 tag
+    to: String,
+}
+
+/// GB28181 SIP 信令服务
+pub struct Server {
+    config: Gb28181Config,
+    socket: UdpSocket,
+    local_port: u16,
+    devices: Mutex<HashMap<String, Device>>,
+    nonces: Mutex<HashMap<String, Instant>>,
+    /// 等待最终响应的点播请求，按 Call-ID 索引
+    pending: Mutex<HashMap<String, oneshot::Sender<SipMessage>>>,
+    /// 正在收流的会话，设备发送 BYE 时通知结束
+    sessions: Mutex<HashMap<String, oneshot::Sender<()>>>,
+    cseq: AtomicU32,
+    sn: AtomicU32,
+    next_port: AtomicU32,
+}
+
+static SERVER: OnceLock<Arc<Server>> = OnceLock::new();
+
+/// 启动 SIP 信令服务
+pub async fn start(config: Gb28181Config) -> Result<()> {
+    let socket = UdpSocket::bind(&config.listen)
+        .await
+        .with_context(|| format!("GB28181 信令监听 {} 失败", config.listen))?;
+    let local_port = socket.local_addr()?.port();
+    info!("GB28181 信令服务监听 {}，平台编码 {}", config.listen, config.sip_id);
+
+    let server = Arc::new(Server {
+        config,
+        socket,
+        local_port,
+        devices: Mutex::new(HashMap::new()),
+        nonces: Mutex::new(HashMap::new()),
+        pending: Mutex::new(HashMap::new()),
+        sessions: Mutex::new(HashMap::new()),
+        cseq: AtomicU32::new(1),
+        sn: AtomicU32::new(1),
+        next_port: AtomicU32::new(0),
+    });
+    SERVER.set(server.clone()).map_err(|_| anyhow!("GB28181 信令服务已启动"))?;
+    tokio::spawn(server.serve());
+    Ok(())
+}
+
+/// 已注册设备列表，未启用 GB28181 时返回 None
+pub fn devices() -> Option<Vec<DeviceInfo>> {
+    SERVER.get().map(|server| server.devices())
+}
+
+/// 向设备点播，返回收流会话与转码器读取 PS 流的本地地址
+///
+/// 需要在 tokio 运行时的阻塞线程中调用（转码线程）。
+pub fn open(url: &str) -> Result<(Session, String)> {
+    let server = SERVER.get().ok_or_else(|| anyhow!("未启用 GB28181 接入 (gb28181.enabled)"))?.clone();
+    let (device_id, channel_id) = parse_url(url)?;
+    let handle = tokio::runtime::Handle::try_current().map_err(|_| anyhow!("GB28181 点播需要在 tokio 运行时中执行"))?;
+    handle.block_on(server.invite(&device_id, &channel_id))
+}
+
+impl Server {
+    async fn serve(self: Arc<Self>) {
+        let mut buf = vec![0u8; 65536];
+        loop {
+            let (n, from) = match self.socket.recv_from(&mut buf).await {
+                Ok(r) => r,
+                Err(e) => {
+                    warn!("GB28181 接收信令失败: {}", e);
+                    continue;
+                }
+            };
+            // 忽略保活用的空行
+            let Some(msg) = SipMessage::parse(&buf[..n]) else { continue };
+            match msg.method() {
+                None => self.handle_response(msg),
+                Some("REGISTER") => self.handle_register(msg, from).await,
+                Some("MESSAGE") => self.handle_message(msg, from).await,
+                Some("BYE") => {
+                    if let Some(closed) = self.sessions.lock().unwrap().remove(msg.call_id()) {
+                        let _ = closed.send(());
+                    }
+                    self.reply(&msg, from, 200, "OK", &[]).await;
+                }
+                Some("ACK") => {}
+                Some(method) => {
+                    debug!("GB28181 收到 {} 请求，来自 {}", method, from);
+                    self.reply(&msg, from, 200, "OK", &[]).await;
+                }
+            }
+        }
+    }
+
+    fn handle_response(&self, msg: SipMessage) {
+        // 临时响应 (1xx) 不结束事务
+        if msg.status().is_none_or(|status| status < 200) {
+            return;
+        }
+        if let Some(waiter) = self.pending.lock().unwrap().remove(msg.call_id()) {
+            let _ = waiter.send(msg);
+        }
+    }
+
+    async fn handle_register(&self, msg: SipMessage, from: SocketAddr) {
+        let Some(device_id) = msg.header("From").and_then(uri_user).map(str::to_string) else {
+            self.reply(&msg, from, 400, "Bad Request", &[]).await;
+            return;
+        };
+        if let Some(password) = &self.config.password
+            && !self.authorized(&msg, password)
+        {
+            let nonce = random_token();
+            self.nonces.lock().unwrap().insert(nonce.clone(), Instant::now());
+            let challenge = format!("Digest realm=\"{}\",nonce=\"{}\",algorithm=MD5", self.config.realm, nonce);
+            self.reply(&msg, from, 401, "Unauthorized", &[("WWW-Authenticate", challenge)]).await;
+            return;
+        }
+
+        let expires = msg.header("Expires").and_then(|v| v.parse::<u64>().ok()).unwrap_or(3600);
+        if expires == 0 {
+            self.devices.lock().unwrap().remove(&device_id);
+            info!("GB28181 设备注销: {}", device_id);
+            self.reply(&msg, from, 200, "OK", &[("Expires", "0".to_string())]).await;
+            return;
+        }
+
+        let now = Instant::now();
+        let is_new = {
+            let mut devices = self.devices.lock().unwrap();
+            let is_new = devices.get(&device_id).is_none_or(|d| d.addr != from || d.expires < now);
+            let device = devices.entry(device_id.clone()).or_insert_with(|| Device {
+                addr: from,
+                expires: now,
+                last_seen: now,
+                channels: Vec::new(),
+            });
+            device.addr = from;
+            device.expires = now + Duration::from_secs(expires);
+            device.last_seen = now;
+            is_new
+        };
+        self.reply(&msg, from, 200, "OK", &[("Expires", expires.to_string())]).await;
+        if is_new {
+            info!("GB28181 设备注册: {} ({})", device_id, from);
+            self.query_catalog(&device_id, from).await;
+        }
+    }
+
+    /// 校验 REGISTER 的 Digest 鉴权
+    fn authorized(&self, msg: &SipMessage, password: &str) -> bool {
+        let Some(auth) = msg.header("Authorization").and_then(|v| v.strip_prefix("Digest")) else {
+            return false;
+        };
+        let params = digest_params(auth);
+        let get = |key: &str| params.get(key).map(String::as_str).unwrap_or_default();
+        {
+            let mut nonces = self.nonces.lock().unwrap();
+            nonces.retain(|_, issued| issued.elapsed() < NONCE_TTL);
+            if !nonces.contains_key(get("nonce")) {
+                return false;
+            }
+        }
+
+        let ha1 = md5_hex(&format!("{}:{}:{}", get("username"), get("realm"), password));
+        let ha2 = md5_hex(&format!("REGISTER:{}", get("uri")));
+        let expected = match params.get("qop") {
+            Some(qop) => md5_hex(&format!("{}:{}:{}:{}:{}:{}", ha1, get("nonce"), get("nc"), get("cnonce"), qop, ha2)),
+            None => md5_hex(&format!("{}:{}:{}", ha1, get("nonce"), ha2)),
+        };
+        expected.eq_ignore_ascii_case(get("response"))
+    }
+
+    /// 处理心跳与目录查询结果
+    async fn handle_message(&self, msg: SipMessage, from: SocketAddr) {
+        self.reply(&msg, from, 200, "OK", &[]).await;
+        let Some(device_id) = msg.header("From").and_then(uri_user) else { return };
+
+        let mut devices = self.devices.lock().unwrap();
+        let Some(device) = devices.get_mut(device_id) else {
+            debug!("忽略未注册 GB28181 设备 {} 的消息", device_id);
+            return;
+        };
+        // MESSAGE 不做鉴权，设备地址只在 REGISTER 时更新，来源不一致的消息可能是伪造的
+        if device.addr != from {
+            warn!("忽略 GB28181 设备 {} 来自 {} 的消息，与注册地址 {} 不一致", device_id, from, device.addr);
+            return;
+        }
+        device.last_seen = Instant::now();
+
+        if xml_value(&msg.body, "CmdType") == Some("Catalog") {
+            // 通道较多时设备分多条消息返回，按通道编码合并
+            for item in msg.body.split("<Item>").skip(1) {
+                let Some(id) = xml_value(item, "DeviceID") else { continue };
+                let channel = Channel {
+                    id: id.to_string(),
+                    name: xml_value(item, "Name").unwrap_or_default().to_string(),
+                    status: xml_value(item, "Status").unwrap_or_default().to_string(),
+                };
+                match device.channels.iter_mut().find(|c| c.id == channel.id) {
+                    Some(existing) => *existing = channel,
+                    None => device.channels.push(channel),
+                }
+            }
+            debug!("GB28181 设备 {} 目录: {} 个通道", device_id, device.channels.len());
+        }
+    }
+
+    /// 查询设备目录（通道列表）
+    async fn query_catalog(&self, device_id: &str, addr: SocketAddr) {
+        let body = format!(
+            "<?xml version=\"1.0\" encoding=\"GB2312\"?>\r\n<Query>\r\n<CmdType>Catalog</CmdType>\r\n<SN>{}</SN>\r\n<DeviceID>{}</DeviceID>\r\n</Query>\r\n",
+            self.sn.fetch_add(1, Ordering::Relaxed),
+            device_id
+        );
+        let dialog = self.dialog(device_id, addr);
+        let cseq = self.cseq.fetch_add(1, Ordering::Relaxed);
+        let request = self.request("MESSAGE", &dialog, cseq, &[], Some(("Application/MANSCDP+xml", &body)));
+        self.send(&request, addr).await;
+    }
+
+    /// 点播设备通道，设备通过 RTP/UDP 推送 PS 流
+    async fn invite(self: Arc<Self>, device_id: &str, channel_id: &str) -> Result<(Session, String)> {
+        let addr = {
+            let devices = self.devices.lock().unwrap();
+            let device = devices.get(device_id).ok_or_else(|| anyhow!("GB28181 设备 {} 未注册", device_id))?;
+            if device.expires < Instant::now() {
+                bail!("GB28181 设备 {} 注册已过期", device_id);
+            }
+            device.addr
+        };
+        let (rtp, rtp_port) = self.bind_rtp().await?;
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let local = listener.local_addr()?;
+
+        let media_ip = self.local_ip(addr);
+        let ssrc = self.next_ssrc();
+        let sdp = format!(
+            "v=0\r\no={id} 0 0 IN {t} {ip}\r\ns=Play\r\nc=IN {t} {ip}\r\nt=0 0\r\nm=video {port} RTP/AVP 96\r\na=recvonly\r\na=rtpmap:96 PS/90000\r\ny={ssrc}\r\n",
+            id = self.config.sip_id,
+            t = addr_type(media_ip),
+            ip = media_ip,
+            port = rtp_port,
+            ssrc = ssrc,
+        );
+        let mut dialog = self.dialog(channel_id, addr);
+        let subject = format!("{}:{},{}:0", channel_id, ssrc, self.config.sip_id);
+        let cseq = self.cseq.fetch_add(1, Ordering::Relaxed);
+        let invite = self.request("INVITE", &dialog, cseq, &[("Subject", subject)], Some(("APPLICATION/SDP", &sdp)));
+
+        info!("GB28181 点播设备 {} 通道 {}，收流地址 {}:{}", device_id, channel_id, media_ip, rtp_port);
+        let (tx, mut rx) = oneshot::channel();
+        self.pending.lock().unwrap().insert(dialog.call_id.clone(), tx);
+        let response = tokio::time::timeout(INVITE_TIMEOUT, async {
+            loop {
+                self.send(&invite, addr).await;
+                if let Ok(response) = tokio::time::timeout(INVITE_RETRANSMIT, &mut rx).await {
+                    return response.ok();
+                }
+            }
+        })
+        .await;
+        self.pending.lock().unwrap().remove(&dialog.call_id);
+
+        let response = response
+            .ok()
+            .flatten()
+            .ok_or_else(|| anyhow!("GB28181 设备 {} 未响应点播请求", device_id))?;
+        if !response.status().is_some_and(|status| (200..300).contains(&status)) {
+            bail!("GB28181 设备 {} 拒绝点播: {}", device_id, response.start_line);
+        }
+        if let Some(to) = response.header("To") {
+            dialog.to = to.to_string();
+        }
+        let ack = self.request("ACK", &dialog, cseq, &[], None);
+        self.send(&ack, addr).await;
+
+        let (closed_tx, closed_rx) = oneshot::channel();
+        self.sessions.lock().unwrap().insert(dialog.call_id.clone(), closed_tx);
+        let label = format!("{}/{}", device_id, channel_id);
+        let task = tokio::spawn(forward(rtp, addr.ip(), listener, closed_rx, label));
+        let session = Session {
+            server: self.clone(),
+            dialog,
+            cseq,
+            task,
+        };
+        Ok((session, format!("tcp://{}", local)))
+    }
+
+    /// 在配置的端口范围内轮流选择可用 RTP 端口
+    async fn bind_rtp(&self) -> Result<(UdpSocket, u16)> {
+        let [min, max] = self.config.rtp_ports.map(u32::from);
+        let count = max.saturating_sub(min) + 1;
+        for _ in 0..count {
+            let port = (min + self.next_port.fetch_add(1, Ordering::Relaxed) % count) as u16;
+            if let Ok(socket) = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, port)).await {
+                return Ok((socket, port));
+            }
+        }
+        bail!("GB28181 RTP 端口 {}-{} 均被占用", min, max)
+    }
+
+    /// 本机与设备通信使用的地址，用于 Via / Contact / SDP
+    fn local_ip(&self, peer: SocketAddr) -> IpAddr {
+        if let Some(ip) = self.config.media_ip {
+            return ip;
+        }
+        let unspecified: SocketAddr = if peer.is_ipv4() { (Ipv4Addr::UNSPECIFIED, 0).into() } else { "[::]:0".parse().unwrap() };
+        std::net::UdpSocket::bind(unspecified)
+            .and_then(|socket| socket.connect(peer).and_then(|_| socket.local_addr()))
+            .map(|addr| addr.ip())
+            .unwrap_or(unspecified.ip())
+    }
+
+    /// 点播使用的 SSRC：0 (实时) + 域编码 5 位 + 序号 4 位
+    fn next_ssrc(&self) -> String {
+        let domain = self.config.realm.get(3..8).unwrap_or("00000");
+        format!("0{}{:04}", domain, self.sn.fetch_add(1, Ordering::Relaxed) % 10000)
+    }
+
+    fn dialog(&self, target: &str, addr: SocketAddr) -> Dialog {
+        Dialog {
+            addr,
+            target: target.to_string(),
+            call_id: random_token(),
+            from_tag: random_token(),
+            to: format!("<sip:{}@{}>", target, self.config.realm),
+        }
+    }
+
+    /// 构造发往设备的请求
+    fn request(&self, method: &str, dialog: &Dialog, cseq: u32, extra: &[(&str, String)], body: Option<(&str, &str)>) -> String {
+        let local_ip = self.local_ip(dialog.addr);
+        let mut out = format!("{} sip:{}@{} SIP/2.0\r\n", method, dialog.target, dialog.addr);
+        out.push_str(&format!(
+            "Via: SIP/2.0/UDP {}:{};rport;branch=z9hG4bK{}\r\n",
+            local_ip,
+            self.local_port,
+            random_token()
+        ));
+        out.push_str(&format!("From: <sip:{}@{}>;tag={}\r\n", self.config.sip_id, self.config.realm, dialog.from_tag));
+        out.push_str(&format!("To: {}\r\n", dialog.to));
+        out.push_str(&format!("Call-ID: {}\r\n", dialog.call_id));
+        out.push_str(&format!("CSeq: {} {}\r\n", cseq, method));
+        out.push_str(&format!("Contact: <sip:{}@{}:{}>\r\n", self.config.sip_id, local_ip, self.local_port));
+        out.push_str(&format!("Max-Forwards: 70\r\nUser-Agent: {}\r\n", USER_AGENT));
+        for (name, value) in extra {
+            out.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        match body {
+            Some((content_type, body)) => {
+                out.push_str(&format!("Content-Type: {}\r\nContent-Length: {}\r\n\r\n{}", content_type, body.len(), body));
+            }
+            None => out.push_str("Content-Length: 0\r\n\r\n"),
+        }
+        out
+    }
+
+    async fn send(&self, data: &str, addr: SocketAddr) {
+        if let Err(e) = self.socket.send_to(data.as_bytes(), addr).await {
+            warn!("GB28181 发送信令到 {} 失败: {}", addr, e);
+        }
+    }
+
+    async fn reply(&self, msg: &SipMessage, from: SocketAddr, status: u16, reason: &str, extra: &[(&str, String)]) {
+        self.send(&msg.response(from, status, reason, extra), from).await;
+    }
+
+    fn devices(&self) -> Vec<DeviceInfo> {
+        let now = Instant::now();
+        let devices = self.devices.lock().unwrap();
+        let mut list: Vec<DeviceInfo> = devices
+            .iter()
+            .map(|(id, device)| DeviceInfo {
+                id: id.clone(),
+                addr: device.addr.to_string(),
+                online: device.expires > now && now.duration_since(device.last_seen) < KEEPALIVE_TIMEOUT,
+                last_seen_secs: now.duration_since(device.last_seen).as_secs(),
+                channels: device.channels.clone(),
+            })
+            .collect();
+        list.sort_by(|a, b| a.id.cmp(&b.id));
+        list
+    }
+}
+
+/// 接收 RTP 包，去掉 RTP 头后把 PS 流写给转码器的本地连接
+async fn forward(rtp: UdpSocket, device_ip: IpAddr, listener: TcpListener, mut closed: oneshot::Receiver<()>, label: String) {
+    let mut conn = match tokio::time::timeout(RTP_TIMEOUT, listener.accept()).await {
+        Ok(Ok((conn, _))) => conn,
+        _ => {
+            warn!("GB28181 {} 转码器未连接收流端口", label);
+            return;
+        }
+    };
+    let mut buf = vec![0u8; 65536];
+    loop {
+        tokio::select! {
+            _ = &mut closed => {
+                info!("GB28181 {} 设备结束推流", label);
+                return;
+            }
+            received = tokio::time::timeout(RTP_TIMEOUT, rtp.recv_from(&mut buf)) => match received {
+                // 只接收设备地址发来的 RTP，其他来源的数据包直接丢弃
+                Ok(Ok((_, source))) if source.ip().to_canonical() != device_ip.to_canonical() => {
+                    debug!("GB28181 {} 丢弃来自 {} 的 RTP 数据包", label, source);
+                }
+                Ok(Ok((n, _))) => {
+                    if let Some(payload) = rtp_payload(&buf[..n])
+                        && conn.write_all(payload).await.is_err()
+                    {
+                        return;
+                    }
+                }
+                Ok(Err(e)) => {
+                    warn!("GB28181 {} 接收 RTP 失败: {}", label, e);
+                    return;
+                }
+                Err(_) => {
+                    warn!("GB28181 {} 超过 {} 秒未收到 RTP 数据", label, RTP_TIMEOUT.as_secs());
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// 点播会话，随转码结束向设备发送 BYE 并停止收流
+pub struct Session {
+    server: Arc<Server>,
+    dialog: Dialog,
+    cseq: u32,
+    task: JoinHandle<()>,
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.task.abort();
+        self.server.sessions.lock().unwrap().remove(&self.dialog.call_id);
+        let bye = self.server.request("BYE", &self.dialog, self.cseq + 1, &[], None);
+        let _ = self.server.socket.try_send_to(bye.as_bytes(), self.dialog.addr);
+        info!("GB28181 结束点播: {}", self.dialog.target);
+    }
+}
@@ -0,0 +1,280 @@
+use anyhow::Result;
+use bytes::Bytes;
+use ffmpeg_next as ffmpeg;
+use serde::{Deserialize, Serialize};
+use std::io::{ErrorKind, Read};
+use std::os::fd::AsRawFd;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+use tracing::{debug, warn};
+use crate::config::FlvOptions;
+
+const TAG_AUDIO: u8 = 8;
+const TAG_VIDEO: u8 = 9;
+const TAG_SCRIPT: u8 = 18;
+
+/// FLV 文件头中的音频 / 视频标志位
+const FLAG_AUDIO: u8 = 0x04;
+const FLAG_VIDEO: u8 = 0x01;
+
+/// FLV tag 数据长度字段为 24 位，这里做合法性检查
+const MAX_TAG_SIZE: usize = 16 * 1024 * 1024;
+
+/// 转发给 HTTP-FLV 观众的 tag 队列长度，观众落后超过该数量时断开
+const SUBSCRIBER_QUEUE: usize = 1024;
+
+/// 视频 tag 是否为增强型 FLV (Enhanced RTMP) 格式，HEVC 等编码使用
+fn is_ex_video(body: &[u8]) -> bool {
+    body[0] & 0x80 != 0
+}
+
+/// GOP 缓存配置
+#[derive(Debug, Deserialize, Clone)]
+pub struct GopCacheConfig {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// 单个流缓存的最大字节数，超出时丢弃当前 GOP 直到下一个关键帧
+    #[serde(default = "default_max_bytes")]
+    pub max_bytes: usize,
+}
+
+impl Default for GopCacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_enabled(),
+            max_bytes: default_max_bytes(),
+        }
+    }
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_max_bytes() -> usize {
+    16 * 1024 * 1024
+}
+
+/// GOP 缓存统计
+#[derive(Debug, Serialize)]
+pub struct GopStats {
+    /// 是否已缓存到完整的序列头与关键帧
+    pub ready: bool,
+    pub header_tags: usize,
+    pub gop_tags: usize,
+    pub gop_bytes: usize,
+    pub gop_duration_ms: u32,
+}
+
+#[derive(Default)]
+struct CacheState {
+    /// FLV 文件头、onMetaData 与音视频序列头
+    header: Vec<Bytes>,
+    /// 最近一个视频关键帧开始的全部 tag
+    gop: Vec<Bytes>,
+    gop_bytes: usize,
+    gop_start_ts: u32,
+    last_ts: u32,
+    // 超出大小上限后丢弃，直到下一个关键帧
+    overflow: bool,
+}
+
+/// 单个流的 GOP 缓存
+///
+/// 以 FLV tag 为单位缓存序列头和最近一个 GOP，新观众可以立即从关键帧开始播放，
+/// 不必等待下一个关键帧。数据来自转码器额外封装的本地 FLV 输出，同时转发给 HTTP-FLV 观众。
+pub struct GopCache {
+    max_bytes: usize,
+    state: Mutex<CacheState>,
+    tags: broadcast::Sender<Bytes>,
+}
+
+impl GopCache {
+    pub fn new(max_bytes: usize) -> Self {
+        Self {
+            max_bytes,
+            state: Mutex::new(CacheState::default()),
+            tags: broadcast::channel(SUBSCRIBER_QUEUE).0,
+        }
+    }
+
+    /// 订阅之后的 tag，同时返回当前缓存的文件头、序列头与 GOP，新观众先发送这部分
+    pub fn subscribe(&self) -> (Vec<Bytes>, broadcast::Receiver<Bytes>) {
+        let state = self.state.lock().unwrap();
+        let initial = state.header.iter().chain(state.gop.iter()).cloned().collect();
+        (initial, self.tags.subscribe())
+    }
+
+    /// 是否为 FLV 文件头，转码器重连后本地 FLV 输出重新开始时出现
+    pub fn is_file_header(tag: &Bytes) -> bool {
+        tag.len() == 13 && tag.starts_with(b"FLV")
+    }
+
+    pub fn stats(&self) -> GopStats {
+        let state = self.state.lock().unwrap();
+        GopStats {
+            ready: !state.header.is_empty() && !state.gop.is_empty(),
+            header_tags: state.header.len(),
+            gop_tags: state.gop.len(),
+            gop_bytes: state.gop_bytes,
+            gop_duration_ms: state.last_ts.saturating_sub(state.gop_start_ts),
+        }
+    }
+
+    /// 读取本地 FLV 字节流并更新缓存，直到写端关闭；按 `flv` 覆盖文件头中的音视频标志
+    fn ingest(&self, mut reader: impl Read, flv: &FlvOptions) {
+        *self.state.lock().unwrap() = CacheState::default();
+
+        // FLV 文件头 (9 字节) + 首个 PreviousTagSize (4 字节)
+        let mut file_header = [0u8; 13];
+        if reader.read_exact(&mut file_header).is_err() {
+            return;
+        }
+        for (flag, value) in [(FLAG_AUDIO, flv.has_audio), (FLAG_VIDEO, flv.has_video)] {
+            match value {
+                Some(true) => file_header[4] |= flag,
+                Some(false) => file_header[4] &= !flag,
+                None => {}
+            }
+        }
+        let file_header = Bytes::copy_from_slice(&file_header);
+        let mut state = self.state.lock().unwrap();
+        state.header.push(file_header.clone());
+        let _ = self.tags.send(file_header);
+        drop(state);
+
+        loop {
+            let mut tag_header = [0u8; 11];
+            match reader.read_exact(&mut tag_header) {
+                Ok(()) => {}
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => break,
+                Err(e) => {
+                    warn!("读取本地 FLV 输出失败: {}", e);
+                    break;
+                }
+            }
+            let data_size = u32::from_be_bytes([0, tag_header[1], tag_header[2], tag_header[3]]) as usize;
+            if data_size > MAX_TAG_SIZE {
+                warn!("本地 FLV 输出 tag 大小异常: {} 字节", data_size);
+                break;
+            }
+            let mut tag = vec![0u8; 11 + data_size + 4];
+            tag[..11].copy_from_slice(&tag_header);
+            if reader.read_exact(&mut tag[11..]).is_err() {
+                break;
+            }
+            self.push_tag(Bytes::from(tag));
+        }
+        debug!("本地 FLV 输出已结束");
+    }
+
+    fn push_tag(&self, tag: Bytes) {
+        let kind = tag[0] & 0x1f;
+        let timestamp = u32::from_be_bytes([tag[7], tag[4], tag[5], tag[6]]);
+        let body = &tag[11..tag.len() - 4];
+
+        let mut state = self.state.lock().unwrap();
+        // 没有观众时发送失败，忽略
+        let _ = self.tags.send(tag.clone());
+        let is_sequence_header = match kind {
+            TAG_SCRIPT => true,
+            // 序列头: AVCPacketType == 0，增强型 FLV 为 PacketType == SequenceStart
+            TAG_VIDEO if body.len() > 1 => {
+                if is_ex_video(body) { body[0] & 0x0f == 0 } else { body[1] == 0 }
+            }
+            // AAC 序列头: SoundFormat == 10 且 AACPacketType == 0
+            TAG_AUDIO => body.len() > 1 && body[0] >> 4 == 10 && body[1] == 0,
+            _ => false,
+        };
+        if is_sequence_header {
+            // 编码参数变化时替换同类型的旧序列头
+            state.header.retain(|t| t.len() <= 13 || t[0] & 0x1f != kind);
+            state.header.push(tag);
+            return;
+        }
+
+        let is_keyframe = kind == TAG_VIDEO && !body.is_empty() && (body[0] >> 4) & 0x07 == 1;
+        if is_keyframe {
+            state.gop.clear();
+            state.gop_bytes = 0;
+            state.gop_start_ts = timestamp;
+            state.overflow = false;
+        } else if state.gop.is_empty() || state.overflow {
+            // 尚未收到关键帧，缓存没有意义
+            return;
+        }
+
+        state.gop_bytes += tag.len();
+        state.last_ts = timestamp;
+        state.gop.push(tag);
+        if state.gop_bytes > self.max_bytes {
+            debug!("GOP 缓存超出上限 {} 字节，等待下一个关键帧", self.max_bytes);
+            state.gop.clear();
+            state.gop_bytes = 0;
+            state.overflow = true;
+        }
+    }
+}
+
+/// FLV 封装器选项
+pub fn flv_muxer_options(flv: &FlvOptions) -> ffmpeg::Dictionary<'static> {
+    let mut options = ffmpeg::Dictionary::new();
+    if let Some(flags) = flv.muxer_flags() {
+        options.set("flvflags", &flags);
+    }
+    options
+}
+
+/// 转码器内的本地 FLV 输出
+///
+/// 将推流数据额外封装一份 FLV，经管道交给读取线程解析后写入 GOP 缓存。
+pub struct LocalFlv {
+    octx: ffmpeg::format::context::Output,
+    writer: Option<std::io::PipeWriter>,
+    reader: Option<std::thread::JoinHandle<()>>,
+}
+
+impl LocalFlv {
+    pub fn open(streams: &[ffmpeg::codec::Parameters], cache: Arc<GopCache>, flv: &FlvOptions) -> Result<Self> {
+        let (reader, writer) = std::io::pipe()?;
+        let header_flags = flv.clone();
+        let reader = std::thread::spawn(move || cache.ingest(reader, &header_flags));
+
+        let mut octx = ffmpeg::format::output_as(&format!("pipe:{}", writer.as_raw_fd()), "flv")?;
+        for params in streams {
+            let mut ostream = octx.add_stream(ffmpeg::encoder::find(ffmpeg::codec::Id::None))?;
+            ostream.set_parameters(params.clone());
+        }
+        octx.write_header_with(flv_muxer_options(flv))?;
+
+        Ok(Self {
+            octx,
+            writer: Some(writer),
+            reader: Some(reader),
+        })
+    }
+
+    /// 写入一个数据包，`time_base` 为数据包当前的时间基
+    pub fn write(&mut self, index: usize, packet: &ffmpeg::Packet, time_base: ffmpeg::Rational) -> Result<()> {
+        let Some(ostream) = self.octx.stream(index) else {
+            return Ok(());
+        };
+        let mut packet = packet.clone();
+        packet.rescale_ts(time_base, ostream.time_base());
+        packet.set_stream(index);
+        packet.set_position(-1);
+        packet.write_interleaved(&mut self.octx)?;
+        Ok(())
+    }
+}
+
+impl Drop for LocalFlv {
+    fn drop(&mut self) {
+        let _ = self.octx.write_trailer();
+        // 关闭写端，读取线程读到 EOF 后退出
+        self.writer.take();
+        if let Some(reader) = self.reader.take() {
+            let _ = reader.join();
+        }
+    }
+}
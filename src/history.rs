@@ -0,0 +1,273 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast;
+use tracing::{error, info};
+
+/// 事件类型
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EventKind {
+    /// 连接状态（推流开始 / 中断），计入可用率
+    #[default]
+    Connectivity,
+    /// 画面内容（冻结 / 黑屏），流仍在推送，不计入可用率
+    Content,
+    /// 主码流故障降级为子码流及恢复，流仍在推送，不计入可用率
+    Degraded,
+    /// 画面明显变化（摄像头被移动或遮挡），不计入可用率
+    Change,
+    /// 摄像头时钟偏差超过阈值及恢复，不计入可用率
+    Clock,
+}
+
+/// 单条健康状态变化记录
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthEvent {
+    pub stream: String,
+    /// Unix 时间戳（秒）
+    pub at: u64,
+    pub up: bool,
+    pub reason: String,
+    #[serde(default)]
+    pub kind: EventKind,
+}
+
+/// 时间线上的一段连续状态
+#[derive(Debug, Serialize)]
+pub struct HealthSegment {
+    pub start: u64,
+    pub end: u64,
+    pub up: bool,
+}
+
+/// 某个流在时间窗口内的可用性报告
+#[derive(Debug, Serialize)]
+pub struct HealthReport {
+    pub stream: String,
+    pub from: u64,
+    pub to: u64,
+    pub uptime_percent: f64,
+    pub segments: Vec<HealthSegment>,
+    pub events: Vec<HealthEvent>,
+}
+
+/// 流健康历史
+///
+/// 以 JSON Lines 追加写入 `<data_dir>/health_history.jsonl`，用于可用性统计 (SLA)。
+/// 新事件同时广播给订阅者（如事件录像）。
+pub struct HealthHistory {
+    path: PathBuf,
+    // 串行化文件写入
+    lock: Mutex<()>,
+    events: broadcast::Sender<HealthEvent>,
+}
+
+pub fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+impl HealthHistory {
+    /// 打开健康历史文件，并清理超过保留期的记录
+    pub fn new(data_dir: &str, retention_days: u64) -> Self {
+        let history = Self {
+            path: PathBuf::from(data_dir).join("health_history.jsonl"),
+            lock: Mutex::new(()),
+            events: broadcast::channel(64).0,
+        };
+        if let Err(e) = history.prune(retention_days) {
+            error!("清理健康历史失败: {}", e);
+        }
+        history
+    }
+
+    /// 记录一次状态变化
+    pub fn record(&self, stream: &str, up: bool, reason: &str) {
+        self.record_kind(stream, EventKind::Connectivity, up, reason);
+    }
+
+    /// 记录一次画面内容状态变化，`ok` 为 false 表示出现问题
+    pub fn record_content(&self, stream: &str, ok: bool, reason: &str) {
+        self.record_kind(stream, EventKind::Content, ok, reason);
+    }
+
+    /// 记录一次降级状态变化，`ok` 为 false 表示降级为子码流
+    pub fn record_degraded(&self, stream: &str, ok: bool, reason: &str) {
+        self.record_kind(stream, EventKind::Degraded, ok, reason);
+    }
+
+    /// 记录一次画面明显变化
+    pub fn record_change(&self, stream: &str, reason: &str) {
+        self.record_kind(stream, EventKind::Change, true, reason);
+    }
+
+    /// 记录一次摄像头时钟状态变化，`ok` 为 false 表示偏差超过阈值
+    pub fn record_clock(&self, stream: &str, ok: bool, reason: &str) {
+        self.record_kind(stream, EventKind::Clock, ok, reason);
+    }
+
+    fn record_kind(&self, stream: &str, kind: EventKind, up: bool, reason: &str) {
+        let event = HealthEvent {
+            stream: stream.to_string(),
+            at: now_secs(),
+            up,
+            reason: reason.to_string(),
+            kind,
+        };
+        if let Err(e) = self.append(&event) {
+            error!("写入健康历史失败: {}", e);
+        }
+        // 没有订阅者时发送失败，忽略
+        let _ = self.events.send(event);
+    }
+
+    /// 订阅之后记录的事件
+    pub fn subscribe(&self) -> broadcast::Receiver<HealthEvent> {
+        self.events.subscribe()
+    }
+
+    fn append(&self, event: &HealthEvent) -> Result<()> {
+        let _guard = self.lock.lock().unwrap();
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        writeln!(file, "{}", serde_json::to_string(event)?)?;
+        Ok(())
+    }
+
+    fn load(&self) -> Result<Vec<HealthEvent>> {
+        let _guard = self.lock.lock().unwrap();
+        let file = match fs::File::open(&self.path) {
+            Ok(f) => f,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(BufReader::new(file)
+            .lines()
+            .map_while(|l| l.ok())
+            .filter_map(|l| serde_json::from_str(&l).ok())
+            .collect())
+    }
+
+    fn prune(&self, retention_days: u64) -> Result<()> {
+        let cutoff = now_secs().saturating_sub(retention_days * 86400);
+        let events = self.load()?;
+        let kept: Vec<&HealthEvent> = events.iter().filter(|e| e.at >= cutoff).collect();
+        if kept.len() == events.len() {
+            return Ok(());
+        }
+
+        let _guard = self.lock.lock().unwrap();
+        let mut content = String::new();
+        for event in &kept {
+            content.push_str(&serde_json::to_string(event)?);
+            content.push('\n');
+        }
+        fs::write(&self.path, content)?;
+        info!("已清理 {} 条过期的健康历史记录", events.len() - kept.len());
+        Ok(())
+    }
+
+    /// 生成最近 `days` 天的可用性报告
+    pub fn report(&self, stream: &str, days: u64) -> Result<HealthReport> {
+        let to = now_secs();
+        let from = to.saturating_sub(days * 86400);
+        let all: Vec<HealthEvent> = self.load()?.into_iter().filter(|e| e.stream == stream).collect();
+        Ok(build_report(stream, all, from, to))
+    }
+
+    /// 所有流最近 `days` 天的可用率（百分比）
+    pub fn uptime_all(&self, days: u64) -> Result<HashMap<String, f64>> {
+        let to = now_secs();
+        let from = to.saturating_sub(days * 86400);
+        let mut by_stream: HashMap<String, Vec<HealthEvent>> = HashMap::new();
+        for event in self.load()? {
+            by_stream.entry(event.stream.clone()).or_default().push(event);
+        }
+        Ok(by_stream
+            .into_iter()
+            .map(|(stream, events)| {
+                let uptime = build_report(&stream, events, from, to).uptime_percent;
+                (stream, uptime)
+            })
+            .collect())
+    }
+
+    /// 所有流在各个时间窗口 [from, to) 内的可用率（百分比），按窗口顺序排列
+    pub fn uptime_windows(&self, windows: &[(u64, u64)]) -> Result<HashMap<String, Vec<f64>>> {
+        let mut by_stream: HashMap<String, Vec<HealthEvent>> = HashMap::new();
+        for event in self.load()? {
+            by_stream.entry(event.stream.clone()).or_default().push(event);
+        }
+        Ok(by_stream
+            .into_iter()
+            .map(|(stream, events)| {
+                let uptime = windows
+                    .iter()
+                    .map(|(from, to)| {
+                        if to <= from {
+                            return 0.0;
+                        }
+                        // 只需要窗口结束前的记录
+                        let events = events.iter().filter(|e| e.at < *to).cloned().collect();
+                        build_report(&stream, events, *from, *to).uptime_percent
+                    })
+                    .collect();
+                (stream, uptime)
+            })
+            .collect())
+    }
+
+    /// 最近的状态变化记录，最新的在前
+    pub fn recent(&self, limit: usize) -> Result<Vec<HealthEvent>> {
+        let mut events = self.load()?;
+        events.reverse();
+        events.truncate(limit);
+        Ok(events)
+    }
+}
+
+/// 由单个流按时间排序的记录生成 [from, to] 窗口内的报告
+fn build_report(stream: &str, all: Vec<HealthEvent>, from: u64, to: u64) -> HealthReport {
+    // 可用率只按连接状态计算，画面事件仅列出
+    let connectivity = |e: &&HealthEvent| e.kind == EventKind::Connectivity;
+    // 窗口开始时的状态取窗口前的最后一条记录，没有记录视为停止
+    let mut up = all.iter().rev().filter(connectivity).find(|e| e.at < from).map(|e| e.up).unwrap_or(false);
+    let events: Vec<HealthEvent> = all.into_iter().filter(|e| e.at >= from).collect();
+
+    let mut segments: Vec<HealthSegment> = Vec::new();
+    let mut start = from;
+    for event in events.iter().filter(connectivity) {
+        if event.up != up {
+            if event.at > start {
+                segments.push(HealthSegment { start, end: event.at, up });
+            }
+            start = event.at;
+            up = event.up;
+        }
+    }
+    if to > start {
+        segments.push(HealthSegment { start, end: to, up });
+    }
+
+    let total = (to - from).max(1) as f64;
+    let up_secs: u64 = segments.iter().filter(|s| s.up).map(|s| s.end - s.start).sum();
+
+    HealthReport {
+        stream: stream.to_string(),
+        from,
+        to,
+        uptime_percent: (up_secs as f64 / total * 10000.0).round() / 100.0,
+        segments,
+        events,
+    }
+}
@@ -0,0 +1,190 @@
+use anyhow::Result;
+use ffmpeg_next as ffmpeg;
+use percent_encoding::{NON_ALPHANUMERIC, utf8_percent_encode};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use tracing::warn;
+use crate::clip::file_stem;
+
+/// 播放列表文件名
+pub const PLAYLIST: &str = "index.m3u8";
+
+/// HLS 分片格式
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum HlsFormat {
+    /// MPEG-TS 分片，兼容性最好
+    #[default]
+    Ts,
+    /// fMP4 分片，HEVC 在 Safari 中需要使用
+    Fmp4,
+}
+
+/// HLS 输出配置
+#[derive(Debug, Deserialize, Clone)]
+pub struct HlsConfig {
+    /// 转码时额外输出 HLS，通过 `GET /hls/{name}/index.m3u8` 播放（供无法播放 FLV 的 iOS Safari 使用）
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    pub format: HlsFormat,
+    /// 分片目标时长（秒），实际在关键帧处切分
+    #[serde(default = "default_segment_secs")]
+    pub segment_secs: u32,
+    /// 播放列表保留的分片数，更早的分片自动删除
+    #[serde(default = "default_list_size")]
+    pub list_size: u32,
+    /// 分片目录，缺省为 `<data_dir>/hls`；建议使用 tmpfs 以减少磁盘写入
+    #[serde(default)]
+    pub dir: Option<String>,
+}
+
+impl Default for HlsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            format: HlsFormat::default(),
+            segment_secs: default_segment_secs(),
+            list_size: default_list_size(),
+            dir: None,
+        }
+    }
+}
+
+fn default_segment_secs() -> u32 {
+    2
+}
+
+fn default_list_size() -> u32 {
+    6
+}
+
+impl HlsConfig {
+    /// 所有流的分片目录
+    pub fn root(&self, data_dir: &str) -> PathBuf {
+        match &self.dir {
+            Some(dir) => PathBuf::from(dir),
+            None => PathBuf::from(data_dir).join("hls"),
+        }
+    }
+}
+
+/// 单个流的分片目录
+///
+/// 目录名由安全字符与名称哈希组成，避免不同流名称替换字符后重名。
+pub fn stream_dir(root: &Path, name: &str) -> PathBuf {
+    let hash: String = Sha256::digest(name.as_bytes())[..4].iter().map(|b| format!("{:02x}", b)).collect();
+    root.join(format!("{}-{}", file_stem(name), hash))
+}
+
+/// 流的播放列表路径（相对于服务根路径）
+pub fn playlist_path(base_path: &str, name: &str) -> String {
+    format!("{}/hls/{}/{}", base_path, utf8_percent_encode(name, NON_ALPHANUMERIC), PLAYLIST)
+}
+
+/// 分片目录中允许访问的文件及其类型，其他文件名（含路径分隔符）返回 None
+pub fn content_type(file: &str) -> Option<&'static str> {
+    if file.contains(['/', '\\']) || file.starts_with('.') {
+        return None;
+    }
+    match file.rsplit_once('.')?.1 {
+        "m3u8" => Some("application/vnd.apple.mpegurl"),
+        "ts" => Some("video/mp2t"),
+        "m4s" => Some("video/iso.segment"),
+        "mp4" => Some("video/mp4"),
+        _ => None,
+    }
+}
+
+/// 目录名是否符合 [`stream_dir`] 生成的格式：安全字符 + `-` + 名称哈希前 4 字节的十六进制
+fn is_stream_dir(dir_name: &str) -> bool {
+    let Some((stem, hash)) = dir_name.rsplit_once('-') else {
+        return false;
+    };
+    !stem.is_empty()
+        && stem.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        && hash.len() == 8
+        && hash.chars().all(|c| matches!(c, '0'..='9' | 'a'..='f'))
+}
+
+/// 删除进程异常退出时残留的分片目录（转码结束时会删除自己的目录，启动时不应存在）
+///
+/// 只删除符合流分片目录命名格式的子目录，`hls.dir` 指向共享目录时不影响其他数据。
+pub fn remove_leftovers(root: &Path) {
+    let Ok(entries) = std::fs::read_dir(root) else {
+        return;
+    };
+    let leftovers = entries
+        .flatten()
+        .filter(|entry| entry.file_type().is_ok_and(|t| t.is_dir()))
+        .filter(|entry| entry.file_name().to_str().is_some_and(is_stream_dir));
+    for entry in leftovers {
+        if let Err(e) = std::fs::remove_dir_all(entry.path()) {
+            warn!("删除残留的 HLS 目录失败 {}: {}", entry.path().display(), e);
+        }
+    }
+}
+
+/// 转码器内的 HLS 输出
+///
+/// 将推流数据额外经 FFmpeg HLS 封装器写入流的分片目录，转码结束（包括断线重连）时删除目录。
+pub struct HlsOutput {
+    octx: ffmpeg::format::context::Output,
+    dir: PathBuf,
+}
+
+impl HlsOutput {
+    pub fn open(dir: &Path, config: &HlsConfig, streams: &[ffmpeg::codec::Parameters]) -> Result<Self> {
+        // 清空上一次连接留下的分片
+        let _ = std::fs::remove_dir_all(dir);
+        std::fs::create_dir_all(dir)?;
+
+        let mut octx = ffmpeg::format::output_as(&dir.join(PLAYLIST), "hls")?;
+        for params in streams {
+            let mut params = params.clone();
+            // FLV 的 codec_tag 与 TS / fMP4 不兼容，清空后由封装器重新选择
+            unsafe {
+                (*params.as_mut_ptr()).codec_tag = 0;
+            }
+            let mut ostream = octx.add_stream(ffmpeg::encoder::find(ffmpeg::codec::Id::None))?;
+            ostream.set_parameters(params);
+        }
+        let (segment_type, extension) = match config.format {
+            HlsFormat::Ts => ("mpegts", "ts"),
+            HlsFormat::Fmp4 => ("fmp4", "m4s"),
+        };
+        let mut options = ffmpeg::Dictionary::new();
+        options.set("hls_time", &config.segment_secs.max(1).to_string());
+        options.set("hls_list_size", &config.list_size.max(2).to_string());
+        options.set("hls_segment_type", segment_type);
+        options.set("hls_segment_filename", &dir.join(format!("seg_%d.{}", extension)).to_string_lossy());
+        // 分片编号从当前时间起算，重连后不会与播放器已缓存的分片重号
+        options.set("hls_start_number_source", "epoch");
+        options.set("hls_flags", "delete_segments+independent_segments+omit_endlist");
+        octx.write_header_with(options)?;
+        Ok(Self { octx, dir: dir.to_path_buf() })
+    }
+
+    /// 写入一个数据包，`time_base` 为数据包当前的时间基
+    pub fn write(&mut self, index: usize, packet: &ffmpeg::Packet, time_base: ffmpeg::Rational) -> Result<()> {
+        let Some(ostream) = self.octx.stream(index) else {
+            return Ok(());
+        };
+        let mut packet = packet.clone();
+        packet.rescale_ts(time_base, ostream.time_base());
+        packet.set_stream(index);
+        packet.set_position(-1);
+        packet.write_interleaved(&mut self.octx)?;
+        Ok(())
+    }
+}
+
+impl Drop for HlsOutput {
+    fn drop(&mut self) {
+        let _ = self.octx.write_trailer();
+        if let Err(e) = std::fs::remove_dir_all(&self.dir) {
+            warn!("删除 HLS 目录失败 {}: {}", self.dir.display(), e);
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use axum::body::Body;
+use axum::extract::ws::{Message, WebSocket};
+use bytes::Bytes;
+use percent_encoding::{NON_ALPHANUMERIC, utf8_percent_encode};
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::debug;
+use crate::gop_cache::GopCache;
+
+/// 独立模式下转码任务的推流地址，转码器不连接 SRS，只输出到 GOP 缓存
+pub const LOCAL_OUTPUT: &str = "local://";
+
+/// 独立模式下主输出写入的位置，观众数据来自 GOP 缓存的本地 FLV 输出
+pub const DISCARD_OUTPUT: &str = "/dev/null";
+
+/// 播放期间为流续期心跳的间隔
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(20);
+
+/// HTTP-FLV 直接输出配置
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct HttpFlvConfig {
+    /// 通过 `GET /live/{name}.flv` 与 WebSocket `GET /ws/live/{name}` 直接提供 FLV，数据来自 GOP 缓存（启用时 GOP 缓存自动启用）
+    #[serde(default)]
+    pub enabled: bool,
+    /// 不使用 SRS：转码任务不推流，播放地址返回本服务的 `/live/{name}.flv`（需同时启用 `enabled`）
+    #[serde(default)]
+    pub standalone: bool,
+}
+
+impl HttpFlvConfig {
+    /// 是否以独立模式运行
+    pub fn standalone(&self) -> bool {
+        self.enabled && self.standalone
+    }
+}
+
+/// 流的播放路径（相对于服务根路径）
+pub fn playback_path(base_path: &str, name: &str) -> String {
+    format!("{}/live/{}.flv", base_path, utf8_percent_encode(name, NON_ALPHANUMERIC))
+}
+
+/// 一个观众的 FLV 数据：先是文件头、序列头与最近的 GOP，再是新的 tag
+struct Viewer<F> {
+    initial: VecDeque<Bytes>,
+    rx: broadcast::Receiver<Bytes>,
+    started: bool,
+    renewed: Instant,
+    alive: F,
+}
+
+impl<F: Fn() -> bool> Viewer<F> {
+    fn new(cache: &GopCache, alive: F) -> Self {
+        let (initial, rx) = cache.subscribe();
+        Self {
+            // 订阅时转码器尚未输出文件头，收到的第一个文件头正常发送
+            started: !initial.is_empty(),
+            initial: initial.into(),
+            rx,
+            renewed: Instant::now(),
+            alive,
+        }
+    }
+
+    /// 下一个要发送的 tag，需要结束连接时返回 None
+    ///
+    /// 转码器重连时 FLV 重新开始，此时结束连接，由播放器重新连接；观众接收过慢跟不上时同样断开。
+    /// 连接期间每隔一段时间调用 `alive` 为流续期，返回 false 时（如分享链接已过期）结束连接。
+    async fn next(&mut self) -> Option<Bytes> {
+        if let Some(tag) = self.initial.pop_front() {
+            return Some(tag);
+        }
+        let tag = match self.rx.recv().await {
+            Ok(tag) if self.started && GopCache::is_file_header(&tag) => {
+                debug!("转码器已重连，结束 FLV 连接");
+                return None;
+            }
+            Ok(tag) => tag,
+            Err(RecvError::Lagged(n)) => {
+                debug!("FLV 观众接收过慢，丢失 {} 个 tag，断开连接", n);
+                return None;
+            }
+            Err(RecvError::Closed) => return None,
+        };
+        self.started = true;
+        if self.renewed.elapsed() >= HEARTBEAT_INTERVAL {
+            if !(self.alive)() {
+                return None;
+            }
+            self.renewed = Instant::now();
+        }
+        Some(tag)
+    }
+}
+
+/// 以 HTTP 响应体向一个观众持续发送 FLV
+pub fn stream(cache: &Arc<GopCache>, alive: impl Fn() -> bool + Send + 'static) -> Body {
+    let mut viewer = Viewer::new(cache, alive);
+    let (mut writer, reader) = tokio::io::duplex(256 * 1024);
+    // 观众断开后读端释放，写入失败时结束
+    tokio::spawn(async move {
+        while let Some(tag) = viewer.next().await {
+            if writer.write_all(&tag).await.is_err() {
+                break;
+            }
+        }
+    });
+    Body::from_stream(tokio_util::io::ReaderStream::new(reader))
+}
+
+/// 以 WebSocket 二进制消息向一个观众持续发送 FLV，每个 tag 一条消息（flv.js / mpegts.js 的 WebSocket 模式）
+pub async fn websocket(mut socket: WebSocket, cache: Arc<GopCache>, alive: impl Fn() -> bool + Send + 'static) {
+    let mut viewer = Viewer::new(&cache, alive);
+    loop {
+        tokio::select! {
+            tag = viewer.next() => {
+                let Some(tag) = tag else {
+                    break;
+                };
+                if socket.send(Message::Binary(tag.to_vec())).await.is_err() {
+                    return;
+                }
+            }
+            // 播放器不发送数据，只需处理关闭
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+    let _ = socket.send(Message::Close(None)).await;
+}
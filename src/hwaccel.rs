@@ -0,0 +1,222 @@
+use anyhow::{Result, anyhow};
+use ffmpeg_next as ffmpeg;
+use ffmpeg::ffi;
+use serde::{Deserialize, Serialize};
+use std::ffi::CString;
+use std::ptr;
+
+/// 缺省的 VAAPI 渲染节点
+const DEFAULT_VAAPI_DEVICE: &str = "/dev/dri/renderD128";
+
+/// 显存帧池的初始帧数
+const HW_POOL_SIZE: i32 = 8;
+
+/// 重新编码使用的 H.264 编码器
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum VideoEncoder {
+    /// CPU 软件编码
+    #[default]
+    Libx264,
+    /// NVIDIA 显卡（NVENC）
+    H264Nvenc,
+    /// Intel 核显 / 独显（Quick Sync Video）
+    H264Qsv,
+    /// VAAPI（Intel / AMD，Linux）
+    H264Vaapi,
+}
+
+/// 视频编码器配置
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+pub struct EncoderConfig {
+    #[serde(default)]
+    pub video: VideoEncoder,
+    /// 硬件设备：NVENC 为 GPU 序号，VAAPI / QSV 为 DRM 渲染节点（VAAPI 缺省 `/dev/dri/renderD128`）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+}
+
+impl EncoderConfig {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl VideoEncoder {
+    /// FFmpeg 编码器名称
+    pub fn codec_name(self) -> &'static str {
+        match self {
+            VideoEncoder::Libx264 => "libx264",
+            VideoEncoder::H264Nvenc => "h264_nvenc",
+            VideoEncoder::H264Qsv => "h264_qsv",
+            VideoEncoder::H264Vaapi => "h264_vaapi",
+        }
+    }
+
+    /// 查找编码器，libx264 不可用时使用 FFmpeg 缺省的 H.264 编码器
+    pub fn find(self) -> Result<ffmpeg::Codec> {
+        match self {
+            VideoEncoder::Libx264 => ffmpeg::encoder::find_by_name(self.codec_name())
+                .or_else(|| ffmpeg::encoder::find(ffmpeg::codec::Id::H264))
+                .ok_or(anyhow!("未找到 H.264 编码器")),
+            _ => ffmpeg::encoder::find_by_name(self.codec_name())
+                .ok_or(anyhow!("未找到编码器 {}，FFmpeg 编译时未启用该硬件编码器", self.codec_name())),
+        }
+    }
+
+    /// 送入编码器（VAAPI 为上传显存前）的像素格式
+    pub fn input_format(self, decoded: ffmpeg::format::Pixel) -> ffmpeg::format::Pixel {
+        match self {
+            VideoEncoder::Libx264 if decoded == ffmpeg::format::Pixel::YUVJ420P => decoded,
+            VideoEncoder::Libx264 | VideoEncoder::H264Nvenc => ffmpeg::format::Pixel::YUV420P,
+            VideoEncoder::H264Qsv | VideoEncoder::H264Vaapi => ffmpeg::format::Pixel::NV12,
+        }
+    }
+
+    /// 编码器参数，`preset` 为 x264 预设名称，映射为各硬件编码器的对应档位
+    pub fn options(self, preset: &str, device: Option<&str>) -> ffmpeg::Dictionary<'static> {
+        let mut opts = ffmpeg::Dictionary::new();
+        match self {
+            VideoEncoder::Libx264 => {
+                opts.set("preset", preset);
+                opts.set("tune", "zerolatency");
+            }
+            VideoEncoder::H264Nvenc => {
+                let level = match preset {
+                    "ultrafast" | "superfast" => "p1",
+                    "veryfast" => "p2",
+                    "faster" => "p3",
+                    "fast" => "p4",
+                    "medium" => "p5",
+                    "slow" => "p6",
+                    _ => "p7",
+                };
+                opts.set("preset", level);
+                opts.set("tune", "ll");
+                opts.set("zerolatency", "1");
+                if let Some(gpu) = device {
+                    opts.set("gpu", gpu);
+                }
+            }
+            VideoEncoder::H264Qsv => {
+                let level = match preset {
+                    "ultrafast" | "superfast" => "veryfast",
+                    other => other,
+                };
+                opts.set("preset", level);
+                opts.set("async_depth", "1");
+            }
+            // VAAPI 没有预设，按驱动缺省的质量档位编码
+            VideoEncoder::H264Vaapi => {}
+        }
+        opts
+    }
+
+    /// 打开编码器需要的硬件设备并设置到编码器上下文，VAAPI 同时创建显存帧池并返回用于上传解码帧
+    ///
+    /// 须在设置宽高、打开编码器之前调用；NVENC 按 `gpu` 参数选择显卡，不需要设备上下文。
+    pub fn attach(
+        self,
+        encoder: &mut ffmpeg::encoder::video::Video,
+        device: Option<&str>,
+    ) -> Result<Option<HwFrames>> {
+        match self {
+            VideoEncoder::Libx264 | VideoEncoder::H264Nvenc => Ok(None),
+            VideoEncoder::H264Qsv => {
+                // 未指定设备时由 QSV 选择缺省显卡
+                if let Some(device) = device {
+                    let hw = HwDevice::open(ffi::AVHWDeviceType::AV_HWDEVICE_TYPE_QSV, device)?;
+                    unsafe {
+                        (*encoder.as_mut_ptr()).hw_device_ctx = ffi::av_buffer_ref(hw.0);
+                    }
+                }
+                Ok(None)
+            }
+            VideoEncoder::H264Vaapi => {
+                let hw = HwDevice::open(
+                    ffi::AVHWDeviceType::AV_HWDEVICE_TYPE_VAAPI,
+                    device.unwrap_or(DEFAULT_VAAPI_DEVICE),
+                )?;
+                let frames = HwFrames::new(&hw, encoder.width(), encoder.height())?;
+                unsafe {
+                    let context = encoder.as_mut_ptr();
+                    (*context).pix_fmt = ffi::AVPixelFormat::AV_PIX_FMT_VAAPI;
+                    (*context).hw_frames_ctx = ffi::av_buffer_ref(frames.frames);
+                }
+                Ok(Some(frames))
+            }
+        }
+    }
+}
+
+/// 硬件设备上下文
+struct HwDevice(*mut ffi::AVBufferRef);
+
+impl HwDevice {
+    fn open(kind: ffi::AVHWDeviceType, device: &str) -> Result<Self> {
+        let name = CString::new(device).map_err(|_| anyhow!("无效的硬件设备 '{}'", device))?;
+        let mut context = ptr::null_mut();
+        let ret = unsafe { ffi::av_hwdevice_ctx_create(&mut context, kind, name.as_ptr(), ptr::null_mut(), 0) };
+        if ret < 0 {
+            return Err(anyhow!("打开硬件设备 {} 失败: {}", device, ffmpeg::Error::from(ret)));
+        }
+        Ok(Self(context))
+    }
+}
+
+impl Drop for HwDevice {
+    fn drop(&mut self) {
+        unsafe { ffi::av_buffer_unref(&mut self.0) };
+    }
+}
+
+/// 显存帧池，解码后的 NV12 帧上传到显存后再送入 VAAPI 编码器
+pub struct HwFrames {
+    frames: *mut ffi::AVBufferRef,
+    /// 最近一次上传的显存帧，下一次上传时释放
+    frame: ffmpeg::frame::Video,
+}
+
+impl HwFrames {
+    fn new(device: &HwDevice, width: u32, height: u32) -> Result<Self> {
+        let frames = unsafe { ffi::av_hwframe_ctx_alloc(device.0) };
+        if frames.is_null() {
+            return Err(anyhow!("创建显存帧池失败"));
+        }
+        // 先交给 HwFrames 管理，初始化失败时随之释放
+        let pool = Self { frames, frame: ffmpeg::frame::Video::empty() };
+        let ret = unsafe {
+            let context = (*frames).data as *mut ffi::AVHWFramesContext;
+            (*context).format = ffi::AVPixelFormat::AV_PIX_FMT_VAAPI;
+            (*context).sw_format = ffi::AVPixelFormat::AV_PIX_FMT_NV12;
+            (*context).width = width as i32;
+            (*context).height = height as i32;
+            (*context).initial_pool_size = HW_POOL_SIZE;
+            ffi::av_hwframe_ctx_init(frames)
+        };
+        if ret < 0 {
+            return Err(anyhow!("初始化显存帧池失败: {}", ffmpeg::Error::from(ret)));
+        }
+        Ok(pool)
+    }
+
+    /// 上传一帧到显存，返回显存帧
+    pub fn upload(&mut self, frame: &ffmpeg::frame::Video) -> Result<&mut ffmpeg::frame::Video> {
+        self.frame = ffmpeg::frame::Video::empty();
+        let ret = unsafe { ffi::av_hwframe_get_buffer(self.frames, self.frame.as_mut_ptr(), 0) };
+        if ret < 0 {
+            return Err(anyhow!("分配显存帧失败: {}", ffmpeg::Error::from(ret)));
+        }
+        let ret = unsafe { ffi::av_hwframe_transfer_data(self.frame.as_mut_ptr(), frame.as_ptr(), 0) };
+        if ret < 0 {
+            return Err(anyhow!("上传帧到显存失败: {}", ffmpeg::Error::from(ret)));
+        }
+        Ok(&mut self.frame)
+    }
+}
+
+impl Drop for HwFrames {
+    fn drop(&mut self) {
+        unsafe { ffi::av_buffer_unref(&mut self.frames) };
+    }
+}
@@ -0,0 +1,73 @@
+use bytes::Bytes;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::OnceCell;
+
+/// 幂等响应的缓存时长
+pub const IDEMPOTENCY_WINDOW: Duration = Duration::from_secs(600);
+
+/// 单个幂等键最长长度
+pub const MAX_KEY_LEN: usize = 255;
+
+struct Entry {
+    created: Instant,
+    /// 请求体指纹，同一个键用于不同请求时拒绝
+    fingerprint: u64,
+    response: Arc<OnceCell<Bytes>>,
+}
+
+/// 幂等键查询结果
+pub enum Lookup {
+    /// 缓存的响应（或由本请求负责生成）
+    Cell(Arc<OnceCell<Bytes>>),
+    /// 同一个键此前用于内容不同的请求
+    Mismatch,
+}
+
+/// 幂等请求缓存
+///
+/// 相同 `Idempotency-Key` 的重试请求直接返回第一次的结果；
+/// 并发的重复请求会等待第一个请求完成，不会重复启动转码。失败的请求不缓存，允许重试。
+#[derive(Default)]
+pub struct IdempotencyCache {
+    entries: Mutex<HashMap<String, Entry>>,
+}
+
+impl IdempotencyCache {
+    pub fn lookup(&self, key: &str, fingerprint: u64) -> Lookup {
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, e| now.duration_since(e.created) < IDEMPOTENCY_WINDOW);
+
+        let entry = entries.entry(key.to_string()).or_insert_with(|| Entry {
+            created: now,
+            fingerprint,
+            response: Arc::new(OnceCell::new()),
+        });
+        if entry.fingerprint != fingerprint {
+            return Lookup::Mismatch;
+        }
+        Lookup::Cell(entry.response.clone())
+    }
+}
+
+/// 执行请求或等待/复用已缓存的响应，返回响应体及是否为重放
+pub async fn run<F, Fut, E>(cell: &OnceCell<Bytes>, f: F) -> Result<(Bytes, bool), E>
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<Bytes, E>>,
+{
+    if let Some(body) = cell.get() {
+        return Ok((body.clone(), true));
+    }
+    let mut executed = false;
+    let body = cell
+        .get_or_try_init(|| {
+            executed = true;
+            f()
+        })
+        .await?;
+    Ok((body.clone(), !executed))
+}
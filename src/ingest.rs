@@ -0,0 +1,104 @@
+use anyhow::{Result, anyhow};
+use axum::body::{Body, HttpBody};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+use lazy_static::lazy_static;
+
+/// HTTP 推流源，地址形如 ingest://<会话编号>，由推流接口生成
+pub const SCHEME: &str = "ingest://";
+
+/// 等待转码器连接的时长
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// 推流端超过该时长没有发送数据时断开
+const IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+lazy_static! {
+    /// 进行中的推流会话：会话编号 -> 转码器读取数据的本地地址
+    static ref PENDING: Mutex<HashMap<u64, SocketAddr>> = Mutex::new(HashMap::new());
+}
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// 推流会话
+///
+/// 在本地监听一个 TCP 端口，转码器通过 `ingest://` 地址连接后，把请求体原样转给转码器，
+/// 由 FFmpeg 识别 FLV / MPEG-TS 并解复用。会话结束时注销。
+pub struct Push {
+    id: u64,
+    listener: TcpListener,
+}
+
+impl Push {
+    pub async fn bind() -> Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        PENDING.lock().unwrap().insert(id, listener.local_addr()?);
+        Ok(Self { id, listener })
+    }
+
+    /// 转码器使用的输入地址
+    pub fn url(&self) -> String {
+        format!("{}{}", SCHEME, self.id)
+    }
+
+    /// 等待转码器连接
+    pub async fn accept(&self) -> Result<TcpStream> {
+        let (conn, _) = tokio::time::timeout(CONNECT_TIMEOUT, self.listener.accept())
+            .await
+            .map_err(|_| anyhow!("等待转码器连接超时"))??;
+        // 只接受一个连接，转码器重连时推流端需重新推送
+        PENDING.lock().unwrap().remove(&self.id);
+        conn.set_nodelay(true)?;
+        Ok(conn)
+    }
+}
+
+impl Drop for Push {
+    fn drop(&mut self) {
+        PENDING.lock().unwrap().remove(&self.id);
+    }
+}
+
+/// 转码器读取推流数据的本地地址
+pub fn open(url: &str) -> Result<String> {
+    let id = url
+        .get(SCHEME.len()..)
+        .filter(|_| url.to_lowercase().starts_with(SCHEME))
+        .and_then(|id| id.parse::<u64>().ok())
+        .ok_or_else(|| anyhow!("无效的推流地址: {}", url))?;
+    let addr = PENDING
+        .lock()
+        .unwrap()
+        .get(&id)
+        .copied()
+        .ok_or_else(|| anyhow!("推流已结束，等待推流端重新推送"))?;
+    Ok(format!("tcp://{}", addr))
+}
+
+/// 把请求体转发给转码器，每收到一段数据调用一次 `alive`，返回转发的字节数
+///
+/// 请求体结束时正常返回；推流端断开、超时或转码器断开时返回错误。
+pub async fn forward(mut body: Body, conn: &mut TcpStream, mut alive: impl FnMut()) -> Result<u64> {
+    let mut total = 0u64;
+    loop {
+        let frame = tokio::time::timeout(IDLE_TIMEOUT, std::future::poll_fn(|cx| Pin::new(&mut body).poll_frame(cx)))
+            .await
+            .map_err(|_| anyhow!("超过 {} 秒未收到推流数据", IDLE_TIMEOUT.as_secs()))?;
+        let Some(frame) = frame else {
+            return Ok(total);
+        };
+        let Ok(data) = frame.map_err(|e| anyhow!("推流端断开: {}", e))?.into_data() else {
+            continue;
+        };
+        conn.write_all(&data).await.map_err(|e| anyhow!("转码器已断开: {}", e))?;
+        total += data.len() as u64;
+        alive();
+    }
+}
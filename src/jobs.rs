@@ -0,0 +1,283 @@
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tracing::{debug, error, info, warn};
+use crate::history::now_secs;
+use crate::reload::LiveConfig;
+use crate::srs::StreamPath;
+use crate::store::{JobRow, Store};
+use crate::stream_manager::StreamManager;
+
+/// 检查到期任务的间隔
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 每次取出的到期任务数
+const BATCH: usize = 32;
+
+/// 数据库中保留的已结束任务数
+const KEEP_FINISHED: usize = 500;
+
+/// 回调请求超时
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 后台任务配置
+#[derive(Debug, Deserialize, Clone)]
+pub struct JobsConfig {
+    /// 最多尝试次数，用尽后标记为失败，不再重试
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+    /// 首次重试间隔（秒），之后每次加倍
+    #[serde(default = "default_backoff_secs")]
+    pub backoff_secs: u64,
+    /// 重试间隔上限（秒）
+    #[serde(default = "default_max_backoff_secs")]
+    pub max_backoff_secs: u64,
+}
+
+impl Default for JobsConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: default_max_attempts(),
+            backoff_secs: default_backoff_secs(),
+            max_backoff_secs: default_max_backoff_secs(),
+        }
+    }
+}
+
+fn default_max_attempts() -> u32 {
+    8
+}
+
+fn default_backoff_secs() -> u64 {
+    5
+}
+
+fn default_max_backoff_secs() -> u64 {
+    600
+}
+
+impl JobsConfig {
+    /// 第 `attempts` 次失败后的重试间隔（秒）
+    fn backoff(&self, attempts: u32) -> u64 {
+        let shift = attempts.saturating_sub(1).min(20);
+        self.backoff_secs.max(1).saturating_mul(1 << shift).min(self.max_backoff_secs.max(1))
+    }
+}
+
+/// 后台任务
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Job {
+    /// 向 API Key 登记的地址发送流停止通知
+    StopWebhook { stream: String, url: String, body: serde_json::Value },
+    /// 停止推流后清理 SRS：踢掉该流的客户端并调用 `srs.teardown.hook_url`
+    SrsTeardown { stream: String, app: String, srs_stream: String },
+}
+
+impl std::fmt::Display for Job {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Job::StopWebhook { stream, url, .. } => write!(f, "流 '{}' 停止通知 ({})", stream, url),
+            Job::SrsTeardown { stream, .. } => write!(f, "流 '{}' SRS 清理", stream),
+        }
+    }
+}
+
+/// 任务状态，执行中只记录在内存中，进程退出后重新执行
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    Pending,
+    Running,
+    Done,
+    Failed,
+}
+
+impl JobState {
+    fn as_str(self) -> &'static str {
+        match self {
+            JobState::Pending | JobState::Running => "pending",
+            JobState::Done => "done",
+            JobState::Failed => "failed",
+        }
+    }
+}
+
+/// 任务查询参数
+#[derive(Debug, Deserialize)]
+pub struct JobsQuery {
+    #[serde(default)]
+    pub state: Option<JobState>,
+    /// 最多返回的任务数，缺省 100
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+/// 任务查询结果
+#[derive(Debug, Serialize)]
+pub struct JobInfo {
+    pub id: i64,
+    pub state: JobState,
+    pub stream: Option<String>,
+    pub job: serde_json::Value,
+    pub attempts: u32,
+    /// 下次执行时间（Unix 秒），只对等待中的任务有意义
+    pub next_at: u64,
+    pub last_error: Option<String>,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+/// 持久化的后台任务队列
+///
+/// 停止通知回调、SRS 清理等副作用先写入本地数据库再执行，失败时按退避间隔重试，
+/// 进程崩溃或重启后未完成的任务继续执行；`GET /api/jobs` 可查看等待、执行中与失败的任务。
+pub struct JobQueue {
+    store: Arc<Store>,
+    live: Arc<LiveConfig>,
+    client: reqwest::Client,
+    /// 正在执行的任务 ID
+    running: Mutex<HashSet<i64>>,
+    wake: Notify,
+}
+
+impl JobQueue {
+    pub fn new(store: Arc<Store>, live: Arc<LiveConfig>) -> Self {
+        Self {
+            store,
+            live,
+            client: reqwest::Client::builder().timeout(REQUEST_TIMEOUT).build().unwrap_or_default(),
+            running: Mutex::new(HashSet::new()),
+            wake: Notify::new(),
+        }
+    }
+
+    /// 加入任务，写入数据库后立即唤醒执行
+    pub fn enqueue(&self, job: Job) {
+        let result = serde_json::to_string(&job)
+            .map_err(anyhow::Error::from)
+            .and_then(|payload| self.store.add_job(&payload, now_secs()));
+        match result {
+            Ok(id) => {
+                debug!("后台任务 #{} 已加入: {}", id, job);
+                self.wake.notify_one();
+            }
+            Err(e) => error!("保存后台任务失败 ({}): {:#}", job, e),
+        }
+    }
+
+    /// 最近的任务，按 ID 倒序
+    pub fn list(&self, query: &JobsQuery) -> Result<Vec<JobInfo>> {
+        let limit = query.limit.unwrap_or(100).clamp(1, 1000);
+        let rows = self.store.jobs(query.state.map(JobState::as_str), limit)?;
+        let running = self.running.lock().unwrap().clone();
+        let jobs = rows.into_iter().filter_map(|row| {
+            let state = match row.state.as_str() {
+                "pending" if running.contains(&row.id) => JobState::Running,
+                "pending" => JobState::Pending,
+                "done" => JobState::Done,
+                _ => JobState::Failed,
+            };
+            if query.state.is_some_and(|wanted| wanted != state) {
+                return None;
+            }
+            let job: serde_json::Value = serde_json::from_str(&row.payload).unwrap_or(serde_json::Value::Null);
+            Some(JobInfo {
+                id: row.id,
+                state,
+                stream: job["stream"].as_str().map(str::to_string),
+                job,
+                attempts: row.attempts,
+                next_at: row.next_at,
+                last_error: row.last_error,
+                created_at: row.created_at,
+                updated_at: row.updated_at,
+            })
+        });
+        Ok(jobs.collect())
+    }
+
+    /// 启动后台执行任务
+    pub fn spawn(self: &Arc<Self>, manager: Arc<StreamManager>) {
+        let queue = self.clone();
+        tokio::spawn(async move {
+            loop {
+                queue.dispatch(&manager);
+                tokio::select! {
+                    _ = queue.wake.notified() => {}
+                    _ = tokio::time::sleep(POLL_INTERVAL) => {}
+                }
+            }
+        });
+    }
+
+    /// 取出到期的任务并发执行
+    fn dispatch(self: &Arc<Self>, manager: &Arc<StreamManager>) {
+        let due = match self.store.due_jobs(now_secs(), BATCH) {
+            Ok(due) => due,
+            Err(e) => {
+                error!("读取后台任务失败: {:#}", e);
+                return;
+            }
+        };
+        for row in due {
+            if !self.running.lock().unwrap().insert(row.id) {
+                continue;
+            }
+            let queue = self.clone();
+            let manager = manager.clone();
+            tokio::spawn(async move {
+                let result = match serde_json::from_str::<Job>(&row.payload) {
+                    Ok(job) => queue.execute(&job, &manager).await.map_err(|e| (job.to_string(), e)),
+                    Err(e) => Err((format!("#{}", row.id), anyhow!("无法解析任务: {}", e))),
+                };
+                queue.finish(&row, result);
+                queue.running.lock().unwrap().remove(&row.id);
+            });
+        }
+    }
+
+    async fn execute(&self, job: &Job, manager: &StreamManager) -> Result<()> {
+        match job {
+            Job::StopWebhook { stream, url, body } => {
+                self.client.post(url).json(body).send().await?.error_for_status()?;
+                info!("已通知流 '{}' 停止: {}", stream, url);
+                Ok(())
+            }
+            Job::SrsTeardown { stream, app, srs_stream } => {
+                // 期间（或重试等待期间）重新开始播放的流不再清理
+                if manager.is_running(stream) {
+                    return Ok(());
+                }
+                let path = StreamPath { app: app.clone(), stream: srs_stream.clone() };
+                self.live.srs().teardown(stream, &path).await
+            }
+        }
+    }
+
+    /// 记录执行结果，失败时按退避间隔安排重试
+    fn finish(&self, row: &JobRow, result: Result<(), (String, anyhow::Error)>) {
+        let config = self.live.config().jobs.clone();
+        let attempts = row.attempts + 1;
+        let now = now_secs();
+        let update = match result {
+            Ok(()) => self.store.update_job(row.id, JobState::Done.as_str(), attempts, now, None, now),
+            Err((job, e)) if attempts >= config.max_attempts => {
+                warn!("后台任务 #{} {} 第 {} 次失败，不再重试: {:#}", row.id, job, attempts, e);
+                self.store.update_job(row.id, JobState::Failed.as_str(), attempts, now, Some(&format!("{:#}", e)), now)
+            }
+            Err((job, e)) => {
+                let delay = config.backoff(attempts);
+                warn!("后台任务 #{} {} 第 {} 次失败，{} 秒后重试: {:#}", row.id, job, attempts, delay, e);
+                let next_at = now + delay;
+                self.store.update_job(row.id, JobState::Pending.as_str(), attempts, next_at, Some(&format!("{:#}", e)), now)
+            }
+        };
+        if let Err(e) = update.and_then(|_| self.store.prune_jobs(KEEP_FINISHED)) {
+            error!("更新后台任务 #{} 失败: {:#}", row.id, e);
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use crate::content_check::ContentProblem;
+use crate::stream_manager::StreamStatus;
+
+/// 单次长轮询的默认等待时长，低于常见代理的空闲连接超时
+pub const DEFAULT_WAIT: Duration = Duration::from_secs(25);
+
+/// 单次长轮询的最长等待时长
+pub const MAX_WAIT: Duration = Duration::from_secs(60);
+
+/// 等待期间检查流状态的间隔
+pub const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 等待期间续期心跳的间隔，远小于无观众超时
+pub const RENEW_INTERVAL: Duration = Duration::from_secs(20);
+
+/// 会话标识最长长度
+pub const MAX_SESSION_LEN: usize = 128;
+
+/// 会话超过该时长没有轮询时丢弃其记录
+const SESSION_TTL: Duration = Duration::from_secs(300);
+
+/// 长轮询关注的流状态，任一字段变化即返回
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WatchedState {
+    pub running: bool,
+    pub active_source: usize,
+    pub downgraded: bool,
+    pub restarts_exhausted: bool,
+    pub publish_degraded: bool,
+    pub content_problem: Option<ContentProblem>,
+}
+
+impl From<&StreamStatus> for WatchedState {
+    fn from(status: &StreamStatus) -> Self {
+        Self {
+            running: status.running,
+            active_source: status.active_source,
+            downgraded: status.downgraded,
+            restarts_exhausted: status.restarts_exhausted,
+            publish_degraded: status.publish_degraded,
+            content_problem: status.content_problem,
+        }
+    }
+}
+
+struct Seen {
+    /// 最近一次返回给该会话的状态，None 表示流已停止
+    state: Option<WatchedState>,
+    at: Instant,
+}
+
+/// 长轮询会话
+///
+/// 按 (会话, 流) 记录上次返回给客户端的状态，下一次轮询时状态已不同则立即返回，
+/// 两次轮询之间发生的变化不会丢失。会话标识由客户端生成，同一播放页面保持不变即可。
+#[derive(Default)]
+pub struct LongPollSessions {
+    seen: Mutex<HashMap<(String, String), Seen>>,
+}
+
+impl LongPollSessions {
+    /// 上次返回给该会话的状态，首次轮询时返回 None
+    pub fn last_seen(&self, session: &str, name: &str) -> Option<Option<WatchedState>> {
+        let seen = self.seen.lock().unwrap();
+        seen.get(&(session.to_string(), name.to_string())).map(|s| s.state.clone())
+    }
+
+    pub fn remember(&self, session: &str, name: &str, state: Option<WatchedState>) {
+        let now = Instant::now();
+        let mut seen = self.seen.lock().unwrap();
+        seen.retain(|_, s| now.duration_since(s.at) < SESSION_TTL);
+        seen.insert((session.to_string(), name.to_string()), Seen { state, at: now });
+    }
+}
@@ -1,29 +1,121 @@
+mod admission;
+mod async_remux;
+mod audit;
+#[cfg(feature = "chaos")]
+mod chaos;
+mod clip;
+mod clip_crypto;
+mod clock_skew;
 mod config;
+mod content_check;
+mod dashboard;
+mod dns;
+mod doctor;
+mod dump;
+mod event_clip;
+mod frame_tap;
+mod gb28181;
+mod gop_cache;
+mod history;
+mod hls;
+mod http_flv;
+mod hwaccel;
+mod idempotency;
+mod ingest;
+mod jobs;
+mod longpoll;
+mod map;
+mod migrate;
+mod packet_trace;
+mod preview;
+mod probe;
+mod proxy;
+mod qrcode;
+mod recording;
+mod registry;
+mod reload;
+mod rtsp_server;
+mod sessions;
+mod share;
+mod snapshot;
 mod srs;
+mod store;
+mod standby;
+mod stats;
+mod stream_io;
+mod thumbnail;
 mod transcoder;
 mod stream_manager;
+mod upgrade;
+mod usage;
+mod users;
+mod vault;
+mod vendor;
+mod web;
+mod webhooks;
 
 use axum::{
-    extract::{State, Json, FromRef},
-    routing::{get, post},
+    extract::{State, Json, FromRef, ConnectInfo, Path, Query, ws::WebSocketUpgrade},
+    routing::{delete, get, post, put},
     Router,
     response::{IntoResponse, Response},
-    http::StatusCode,
+    http::{header, HeaderMap, HeaderName, HeaderValue, StatusCode},
+    middleware::{self, Next},
 };
+use std::net::{IpAddr, SocketAddr};
+use std::os::fd::AsRawFd;
 use std::sync::Arc;
 use tower_http::services::ServeDir;
 use tower_http::cors::CorsLayer;
+use tower_http::compression::CompressionLayer;
+use tower_http::compression::predicate::{DefaultPredicate, NotForContentType, Predicate};
+use tower_http::set_header::SetResponseHeader;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio::sync::broadcast;
+use tokio_util::sync::CancellationToken;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
-use crate::config::AppConfig;
+use crate::clip::file_stem;
+use crate::config::{AppConfig, InputOptions, OutputOptions, PlayOverrides, ProtectedHours, ScopedApiKey, SrsErrorPolicy, StreamProfile};
+use crate::history::HealthHistory;
+use crate::registry::{Changes, StreamRegistry};
 use crate::srs::SrsClient;
-use crate::stream_manager::StreamManager;
+use crate::stream_manager::{Closing, RunningConfig, RunningJob, StopReason, StreamManager, StreamPolicy};
+use crate::users::{LoginError, UserStore};
+use crate::vault::CredentialVault;
+use crate::vendor::VendorPresets;
 use serde::{Serialize, Deserialize};
 
 #[derive(Clone)]
 struct AppState {
-    config: Arc<AppConfig>,
-    srs: SrsClient,
+    /// 配置与 SRS 客户端，配置文件修改后重新加载
+    live: Arc<reload::LiveConfig>,
     stream_manager: Arc<StreamManager>,
+    history: Arc<HealthHistory>,
+    idempotency: Arc<idempotency::IdempotencyCache>,
+    registry: Arc<StreamRegistry>,
+    vault: Arc<CredentialVault>,
+    users: Arc<UserStore>,
+    started_at: std::time::Instant,
+    auditor: Arc<audit::Auditor>,
+    usage: Arc<usage::UsageLedger>,
+    longpoll: Arc<longpoll::LongPollSessions>,
+    event_clips: Arc<event_clip::EventClips>,
+    sessions: Arc<sessions::ViewerSessions>,
+    webhooks: Arc<webhooks::StopWebhooks>,
+    jobs: Arc<jobs::JobQueue>,
+    shares: Arc<share::ShareLinks>,
+    /// 启动阶段 SRS 是否已可用（或已放弃等待）
+    srs_ready: tokio::sync::watch::Receiver<bool>,
+}
+
+impl AppState {
+    fn config(&self) -> Arc<AppConfig> {
+        self.live.config()
+    }
+
+    fn srs(&self) -> Arc<SrsClient> {
+        self.live.srs()
+    }
 }
 
 // 自定义应用错误类型，用于统一处理 HTTP 响应
@@ -32,6 +124,18 @@ struct AppError(anyhow::Error);
 // 实现 IntoResponse 让 AppError 可以直接作为 Handler 的返回值
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
+        if let Some(admission::Rejected(decision)) = self.0.downcast_ref() {
+            return (StatusCode::SERVICE_UNAVAILABLE, Json(decision)).into_response();
+        }
+        if let Some(srs::ApiError(e)) = self.0.downcast_ref() {
+            return (StatusCode::BAD_GATEWAY, e.clone()).into_response();
+        }
+        if let Some(Forbidden(e)) = self.0.downcast_ref() {
+            return (StatusCode::FORBIDDEN, e.clone()).into_response();
+        }
+        if let Some(closing) = self.0.downcast_ref::<Closing>() {
+            return (StatusCode::SERVICE_UNAVAILABLE, closing.to_string()).into_response();
+        }
         (
             StatusCode::INTERNAL_SERVER_ERROR,
             format!("服务器内部错误: {}", self.0),
@@ -50,7 +154,89 @@ where
     }
 }
 
+/// 返回带 ETag 的 JSON 响应
+///
+/// 客户端携带的 If-None-Match 与当前内容一致时返回 304，避免大列表重复传输。
+fn json_with_etag<T: Serialize>(headers: &HeaderMap, value: &T) -> Response {
+    use std::hash::{DefaultHasher, Hash, Hasher};
+
+    let body = match serde_json::to_vec(value) {
+        Ok(body) => body,
+        Err(e) => return AppError(e.into()).into_response(),
+    };
+    let mut hasher = DefaultHasher::new();
+    body.hash(&mut hasher);
+    let etag = format!("\"{:016x}\"", hasher.finish());
+
+    let not_modified = web::etag_matches(headers, &etag);
+    let cache_headers = [
+        (header::ETAG, etag),
+        (header::CACHE_CONTROL, "no-cache".to_string()),
+    ];
+    if not_modified {
+        (StatusCode::NOT_MODIFIED, cache_headers).into_response()
+    } else {
+        (cache_headers, [(header::CONTENT_TYPE, "application/json")], body).into_response()
+    }
+}
+
+// 客户端信息提取器
+//
+// 直连时使用 TCP 对端地址；对端为可信反向代理时，
+// 从 X-Forwarded-For 自右向左取第一个非代理地址，并读取 X-Forwarded-Proto
+struct ClientInfo {
+    ip: IpAddr,
+    scheme: String,
+}
+
+#[axum::async_trait]
+impl<S> axum::extract::FromRequestParts<S> for ClientInfo
+where
+    S: Send + Sync,
+    AppState: axum::extract::FromRef<S>,
+{
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(parts: &mut axum::http::request::Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let app_state = AppState::from_ref(state);
+        let trusted = &app_state.config().server.trusted_proxies;
+        let is_trusted = |ip: &IpAddr| trusted.iter().any(|net| net.contains(ip));
+
+        let peer = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|c| c.0.ip().to_canonical())
+            .unwrap_or(IpAddr::from([127, 0, 0, 1]));
+
+        if !is_trusted(&peer) {
+            return Ok(ClientInfo { ip: peer, scheme: "http".to_string() });
+        }
+
+        let forwarded_ip = parts
+            .headers
+            .get_all("X-Forwarded-For")
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .filter_map(|s| s.trim().parse::<IpAddr>().ok())
+            .map(|ip| ip.to_canonical())
+            .rev()
+            .find(|ip| !is_trusted(ip));
+
+        let scheme = parts
+            .headers
+            .get("X-Forwarded-Proto")
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.split(',').next().unwrap_or("http").trim().to_lowercase())
+            .unwrap_or_else(|| "http".to_string());
+
+        Ok(ClientInfo { ip: forwarded_ip.unwrap_or(peer), scheme })
+    }
+}
+
 // API 鉴权提取器
+//
+// 限制了可访问流的 API Key 只能调用播放类接口（见 StreamAccess），其他接口返回 403
 struct AuthToken;
 
 #[axum::async_trait]
@@ -61,6 +247,29 @@ where
 {
     type Rejection = (StatusCode, &'static str);
 
+    async fn from_request_parts(parts: &mut axum::http::request::Parts, state: &S) -> Result<Self, Self::Rejection> {
+        match StreamAccess::from_request_parts(parts, state).await? {
+            StreamAccess { scope: None, .. } => Ok(AuthToken),
+            StreamAccess { scope: Some(_), .. } => Err((StatusCode::FORBIDDEN, "该 API Key 只能用于播放指定的流")),
+        }
+    }
+}
+
+// 播放类接口的鉴权提取器，同时取出 API Key 可访问的流，由处理函数调用 check 按流检查
+struct StreamAccess {
+    scope: Option<ScopedApiKey>,
+    /// API Key 的指纹，网页登录会话为 None
+    key_id: Option<String>,
+}
+
+#[axum::async_trait]
+impl<S> axum::extract::FromRequestParts<S> for StreamAccess
+where
+    S: Send + Sync,
+    AppState: axum::extract::FromRef<S>,
+{
+    type Rejection = (StatusCode, &'static str);
+
     async fn from_request_parts(parts: &mut axum::http::request::Parts, state: &S) -> Result<Self, Self::Rejection> {
         // 1. 尝试从 Header 获取 Authorization
         if let Some(auth_header) = parts.headers.get("Authorization") {
@@ -68,21 +277,146 @@ where
                 // 支持 "Bearer <token>" 或直接 "<token>"
                 let token = token.trim_start_matches("Bearer ").trim();
                 let app_state = AppState::from_ref(state);
-                if app_state.config.api_keys.iter().any(|k| k == token) {
-                    return Ok(AuthToken);
+                if let Some(key) = app_state.config().api_keys.iter().find(|k| k.key() == token) {
+                    return Ok(StreamAccess { scope: key.scope().cloned(), key_id: Some(webhooks::StopWebhooks::key_id(token)) });
                 }
             }
         }
 
-        // 2. (可选) 尝试从 Query 参数获取 ?token=xxx
+        // 2. 网页登录会话
+        if let Some(token) = cookie_session(&parts.headers)
+            && AppState::from_ref(state).users.session_user(token).is_some()
+        {
+            return Ok(StreamAccess { scope: None, key_id: None });
+        }
+
+        // 3. (可选) 尝试从 Query 参数获取 ?token=xxx
         // 这里为了简单暂不实现，强制使用 Header
 
         Err((StatusCode::UNAUTHORIZED, "无效的 API Token"))
     }
 }
 
+impl StreamAccess {
+    /// 检查是否可以访问流（`{name}_sub` 按所属的流检查），只能访问配置中的流
+    fn check(&self, state: &AppState, name: &str) -> Result<(), AppError> {
+        let Some(scope) = &self.scope else {
+            return Ok(());
+        };
+        let name = state.registry.canonical(name);
+        let stream = state
+            .registry
+            .get(&name)
+            .or_else(|| name.strip_suffix("_sub").and_then(|base| state.registry.get(base)));
+        if stream.is_some_and(|s| scope.allows(&s)) {
+            Ok(())
+        } else {
+            Err(Forbidden(format!("该 API Key 无权访问流 '{}'", name)).into())
+        }
+    }
+
+    /// 是否可以访问流，用于批量请求中逐个检查
+    fn allows(&self, state: &AppState, name: &str) -> bool {
+        self.check(state, name).is_ok()
+    }
+
+    /// 以 API Key 播放的流停止时通知该 Key 登记的回调地址
+    fn subscribe(&self, state: &AppState, stream: &str) {
+        if let Some(key_id) = &self.key_id {
+            state.webhooks.subscribe(stream, key_id);
+        }
+    }
+}
+
+/// 无权访问，返回 403
+#[derive(Debug)]
+struct Forbidden(String);
+
+impl std::fmt::Display for Forbidden {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Forbidden {}
+
+/// 从请求头中取出登录会话令牌
+fn cookie_session(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::COOKIE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(users::session_token)
+}
+
+// 登录用户提取器，仅接受网页登录会话
+struct SessionUser {
+    username: String,
+    token: String,
+}
+
+#[axum::async_trait]
+impl<S> axum::extract::FromRequestParts<S> for SessionUser
+where
+    S: Send + Sync,
+    AppState: axum::extract::FromRef<S>,
+{
+    type Rejection = (StatusCode, &'static str);
+
+    async fn from_request_parts(parts: &mut axum::http::request::Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let app_state = AppState::from_ref(state);
+        cookie_session(&parts.headers)
+            .and_then(|token| {
+                app_state.users.session_user(token).map(|username| SessionUser {
+                    username,
+                    token: token.to_string(),
+                })
+            })
+            .ok_or((StatusCode::UNAUTHORIZED, "未登录或会话已过期"))
+    }
+}
+
 #[tokio::main]
 async fn main() {
+    // 子命令
+    let args: Vec<String> = std::env::args().collect();
+    match args.get(1).map(String::as_str) {
+        Some("migrate-config") => {
+            if let Err(e) = migrate::run(&args[2..]) {
+                eprintln!("配置迁移失败: {}", e);
+                std::process::exit(1);
+            }
+            return;
+        }
+        Some("add-user") => {
+            let result = AppConfig::new()
+                .map_err(anyhow::Error::from)
+                .and_then(|config| users::run_add_user(&args[2..], &config.data_dir));
+            if let Err(e) = result {
+                eprintln!("创建用户失败: {}", e);
+                std::process::exit(1);
+            }
+            return;
+        }
+        Some("doctor") => {
+            match doctor::run(&args[2..]).await {
+                Ok(true) => return,
+                Ok(false) => std::process::exit(1),
+                Err(e) => {
+                    eprintln!("自检失败: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Some("encrypt-password") => {
+            if let Err(e) = vault::run_encrypt(&args[2..]) {
+                eprintln!("加密密码失败: {}", e);
+                std::process::exit(1);
+            }
+            return;
+        }
+        _ => {}
+    }
+
     // 初始化日志追踪
     tracing_subscriber::registry()
         .with(tracing_subscriber::EnvFilter::new(
@@ -92,136 +426,2860 @@ async fn main() {
         .init();
 
     // 加载配置
-    let config = match AppConfig::new() {
-        Ok(c) => Arc::new(c),
+    let (config, raw_config) = match AppConfig::load() {
+        Ok((c, raw)) => (Arc::new(c), raw),
         Err(e) => {
             tracing::error!("加载配置失败: {}", e);
             return;
         }
     };
+    if let Some(msg) = migrate::outdated_version(config.version) {
+        tracing::warn!("{}", msg);
+    }
 
     // 初始化 SRS 客户端
-    let srs_client = SrsClient::new(
-        config.srs.api_url.clone(),
-        config.srs.playback_url_template.clone()
+    let live = Arc::new(reload::LiveConfig::new(config.clone(), raw_config));
+    let srs_client = live.srs();
+
+    // 初始化健康历史与流管理器
+    let history = Arc::new(HealthHistory::new(&config.data_dir, config.history.retention_days));
+    // HTTP-FLV 直接输出的数据来自 GOP 缓存
+    let mut gop_cache = config.gop_cache.clone();
+    gop_cache.enabled |= config.http_flv.enabled;
+    let stream_manager = StreamManager::new(
+        history.clone(),
+        gop_cache,
+        config.content_check.clone(),
+        config.clock_skew.clone(),
+        config.clip.clone(),
+        config.srs.publish_retry.clone(),
+        config.preview.clone(),
+        config.soak_restart.clone(),
+        config.hls.enabled.then(|| (config.hls.root(&config.data_dir), config.hls.clone())),
+        (config.recording.root(&config.data_dir), config.recording.clone()),
     );
+    #[cfg(feature = "async-remux")]
+    let stream_manager = stream_manager.with_async_remux(config.async_remux.clone());
+    #[cfg(not(feature = "async-remux"))]
+    if config.async_remux.enabled {
+        tracing::warn!("未以 async-remux 特性编译，忽略 async_remux 配置");
+    }
+    let stream_manager = if config.thumbnails.enabled {
+        stream_manager.with_thumbnails(config.thumbnails.clone(), config.thumbnails.root(&config.data_dir))
+    } else {
+        stream_manager
+    };
+    let stream_manager = Arc::new(stream_manager.with_recording_retention());
+    let store = match store::Store::open(&config.data_dir) {
+        Ok(s) => Arc::new(s),
+        Err(e) => {
+            tracing::error!("打开数据库失败: {:#}", e);
+            return;
+        }
+    };
+    let registry = match StreamRegistry::new(config.streams.clone(), store.clone(), VendorPresets::new(&config.vendor_presets)) {
+        Ok(r) => Arc::new(r),
+        Err(e) => {
+            tracing::error!("{:#}", e);
+            return;
+        }
+    };
+    doctor::banner(&config, registry.list().len());
+    // 上次异常退出时残留的临时片段直接删除，未正常结束的抓包文件在后台修复
+    let data_dir = std::path::PathBuf::from(&config.data_dir);
+    clip::remove_leftovers(&data_dir.join("clips"));
+    if config.hls.enabled {
+        hls::remove_leftovers(&config.hls.root(&config.data_dir));
+    }
+    tokio::task::spawn_blocking(move || dump::recover(&data_dir.join("dumps")));
+    for (key, names) in srs_client.key_collisions(&registry.list()) {
+        if key.is_empty() {
+            tracing::warn!("流 {:?} 无法生成 SRS 推流路径，请检查名称、tenant / group 或 srs.output_name_template 配置", names);
+        } else {
+            tracing::warn!("流 {:?} 的 SRS 推流路径相同 ({})，将互相覆盖", names, key);
+        }
+    }
+    for alias in registry.alias_conflicts() {
+        tracing::warn!("别名 '{}' 与其他流的名称或别名重复，请求该名称时优先匹配流名称", alias);
+    }
+    let vault = match CredentialVault::new(&config.credentials) {
+        Ok(v) => Arc::new(v),
+        Err(e) => {
+            tracing::error!("加载凭据失败: {:#}", e);
+            return;
+        }
+    };
+
+    // 由旧进程平滑升级启动时，旧进程退出前仍占用 RTSP / SIP 端口，在后台重试
+    let upgrading = upgrade::is_successor();
+    if config.gb28181.enabled {
+        if upgrading {
+            let gb28181_config = config.gb28181.clone();
+            start_after_predecessor("GB28181 接入", config.server.drain_secs, move || {
+                gb28181::start(gb28181_config.clone())
+            });
+        } else if let Err(e) = gb28181::start(config.gb28181.clone()).await {
+            tracing::error!("启动 GB28181 接入失败: {:#}", e);
+            return;
+        }
+    }
 
-    // 初始化流管理器
-    let stream_manager = Arc::new(StreamManager::new());
+    let (srs_ready_tx, srs_ready) = tokio::sync::watch::channel(config.srs.startup_wait_secs == 0);
+    if config.srs.startup_wait_secs > 0 {
+        let srs = srs_client.clone();
+        let max_wait = std::time::Duration::from_secs(config.srs.startup_wait_secs);
+        tokio::spawn(async move {
+            if srs.wait_ready(max_wait).await {
+                tracing::info!("SRS 已可用，开始处理播放请求");
+            } else {
+                tracing::warn!("等待 SRS 超过 {} 秒仍不可用，不再等待", max_wait.as_secs());
+            }
+            let _ = srs_ready_tx.send(true);
+        });
+    }
 
+    let shares = match share::ShareLinks::new(store.clone(), &config.data_dir) {
+        Ok(s) => Arc::new(s),
+        Err(e) => {
+            tracing::error!("加载分享链接密钥失败: {:#}", e);
+            return;
+        }
+    };
+    let users = match UserStore::new(store.clone(), config.auth.clone()) {
+        Ok(u) => Arc::new(u),
+        Err(e) => {
+            tracing::error!("{:#}", e);
+            return;
+        }
+    };
+    let jobs = Arc::new(jobs::JobQueue::new(store.clone(), live.clone()));
     let state = AppState {
-        config: config.clone(),
-        srs: srs_client,
+        live: live.clone(),
+        sessions: stream_manager.sessions(),
         stream_manager,
+        history,
+        idempotency: Arc::new(idempotency::IdempotencyCache::default()),
+        registry,
+        vault,
+        users,
+        started_at: std::time::Instant::now(),
+        auditor: Arc::new(audit::Auditor::default()),
+        usage: Arc::new(usage::UsageLedger::new(store.clone(), config.history.retention_days)),
+        longpoll: Arc::new(longpoll::LongPollSessions::default()),
+        event_clips: Arc::new(event_clip::EventClips::new(&config.data_dir, config.event_clips.clone())),
+        webhooks: Arc::new(webhooks::StopWebhooks::new(store, jobs.clone())),
+        jobs,
+        shares,
+        srs_ready,
     };
+    state.jobs.spawn(state.stream_manager.clone());
+    state.webhooks.spawn(state.stream_manager.stopped_events());
+    state.usage.spawn(state.stream_manager.clone());
+    state.live.watch(state.registry.clone());
+    state.event_clips.spawn(&state.history, state.stream_manager.clone());
+    if config.srs.teardown.kick_clients || config.srs.teardown.hook_url.is_some() {
+        let mut stopped = state.stream_manager.stopped_events();
+        let app_state = state.clone();
+        tokio::spawn(async move {
+            loop {
+                let name = match stopped.recv().await {
+                    // 重启次数用尽的流仍在推流列表中，等到被移除时再清理
+                    Ok(event) if event.reason == StopReason::Exhausted => continue,
+                    Ok(event) => event.stream,
+                    Err(broadcast::error::RecvError::Lagged(n)) => {
+                        tracing::warn!("SRS 清理落后，跳过 {} 个停止事件", n);
+                        continue;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+                // 期间重新开始播放的流不再清理
+                if app_state.stream_manager.is_running(&name) {
+                    continue;
+                }
+                match srs_stream_path(&app_state, &name) {
+                    Ok(path) => app_state.jobs.enqueue(jobs::Job::SrsTeardown { stream: name, app: path.app, srs_stream: path.stream }),
+                    Err(e) => tracing::warn!("流 '{}' 停止推流后无法清理 SRS: {}", name, e),
+                }
+            }
+        });
+    }
+    for stream in state.registry.list().into_iter().filter(|s| s.preconnect) {
+        match resolve_configured(&state, &stream.name, StreamProfile::Main) {
+            Ok((name, urls, input, _)) => state.stream_manager.add_standby(name, urls[0].clone(), input),
+            Err(e) => tracing::warn!("流 '{}' 无法预连接: {}", stream.name, e),
+        }
+    }
+    if config.audit.on_startup {
+        state.auditor.start(audit_targets(&state), &config.audit);
+    }
+    if config.rtsp_server.enabled {
+        let app_state = state.clone();
+        let start = move || {
+            let play_state = app_state.clone();
+            let start: rtsp_server::StartFn = Box::new(move |name, ip| {
+                let state = play_state.clone();
+                Box::pin(async move { play_on_demand(&state, name, ip).await })
+            });
+            rtsp_server::start(app_state.config().rtsp_server.clone(), app_state.stream_manager.clone(), start)
+        };
+        if upgrading {
+            start_after_predecessor("RTSP 转发服务", config.server.drain_secs, start);
+        } else if let Err(e) = start().await {
+            tracing::error!("启动 RTSP 转发服务失败: {:#}", e);
+            return;
+        }
+    }
 
     // 设置路由
     let app = Router::new()
-        .route("/api/streams", get(list_streams))
+        .route("/api/dashboard", get(dashboard))
+        .route("/api/map", get(camera_map))
+        .route("/api/metrics", get(metrics))
+        .route("/api/stats/export", get(export_stats))
+        .route("/api/streams", get(list_streams).post(create_stream))
+        .route("/api/streams/changes", get(stream_changes))
+        .route("/api/streams/export", get(export_streams))
+        .route("/api/streams/import", post(import_streams))
+        .route("/api/streams/audit", get(audit_report).post(start_audit))
+        .route("/api/streams/:name", put(update_stream).delete(delete_stream))
+        .route("/api/streams/:name/history", get(stream_history))
+        .route("/api/streams/:name/gop", get(gop_stats))
+        .route("/api/streams/:name/stats", get(stream_stats))
+        .route("/api/streams/:name/status", get(stream_status))
+        .route("/api/streams/:name/config", get(effective_config))
+        .route("/api/streams/:name/reset-failures", post(reset_failures))
+        .route("/api/streams/:name/dump", get(dump_status).post(start_dump))
+        .route("/api/streams/:name/dump/file", get(download_dump))
+        .route("/api/streams/:name/trace", get(trace_status).post(start_trace).delete(stop_trace))
+        .route("/api/streams/:name/trace/file", get(download_trace))
+        .route("/api/streams/:name/record", get(record_status))
+        .route("/api/streams/:name/record/start", post(start_record))
+        .route("/api/streams/:name/record/stop", post(stop_record))
+        .route("/api/streams/:name/clip", get(download_clip))
+        .route("/api/streams/:name/event-clips", post(trigger_event_clip))
+        .route("/api/streams/:name/share", post(create_share))
+        .route("/api/streams/:name/snapshot", get(stream_snapshot))
+        .route("/api/streams/:name/thumb", get(stream_thumbnail))
+        .route("/api/streams/:name/qrcode.png", get(stream_qrcode))
+        .route("/api/shares", get(list_shares))
+        .route("/api/shares/:id", delete(revoke_share))
+        .route("/api/event-clips", get(list_event_clips))
+        .route("/api/event-clips/:id", get(download_event_clip))
+        .route("/api/recordings", get(list_recordings))
+        .route("/api/recordings/search", get(search_recordings))
+        .route("/api/recordings/:name/:file", get(recording_file))
+        .route("/api/wall.jpg", get(preview_wall))
+        .route("/api/gb28181/devices", get(gb28181_devices))
+        .route("/api/login", post(login))
+        .route("/api/logout", post(logout))
+        .route("/api/password", post(change_password))
         .route("/api/play", post(play_stream))
+        .route("/api/probe", post(probe_stream))
+        .route("/api/stop", post(stop_stream))
+        .route("/api/sessions/:id/transfer", post(transfer_session))
+        .route("/api/webhook", get(get_webhook).put(put_webhook).delete(delete_webhook))
+        .route("/api/jobs", get(list_jobs))
+        .route("/api/config/reload", post(reload_config))
+        .route("/api/ingest/:name", post(ingest_stream))
         .route("/api/heartbeat", post(heartbeat))
-        .nest_service("/", ServeDir::new("web"))
+        .route("/api/heartbeat/batch", post(heartbeat_batch))
+        .route("/api/heartbeat/longpoll", get(heartbeat_longpoll))
+        .route("/live/:file", get(http_flv_play))
+        .route("/ws/live/:file", get(ws_flv_play))
+        .route("/share/:file", get(share_play))
+        .route("/hls/:name/:file", get(hls_file));
+
+    // 故障注入接口只在以 chaos 特性编译时提供
+    #[cfg(feature = "chaos")]
+    let app = {
+        tracing::warn!("已启用故障注入接口 (/api/chaos)，不要用于生产环境");
+        app.route("/api/chaos", get(chaos_status).delete(clear_chaos))
+            .route("/api/chaos/streams/:name/kill", post(chaos_kill))
+            .route("/api/chaos/srs-delay", post(chaos_srs_delay))
+            .route("/api/chaos/drop-heartbeats", post(chaos_drop_heartbeats))
+    };
+
+    // 前端页面：默认使用内嵌资源，配置 web_dir 时从磁盘读取
+    let app = match &config.server.web_dir {
+        Some(dir) => {
+            tracing::info!("从磁盘目录提供前端页面: {}", dir);
+            // 静态文件每次使用前通过 Last-Modified 重新验证，页面更新后可立即生效
+            app.nest_service("/", SetResponseHeader::if_not_present(
+                ServeDir::new(dir),
+                header::CACHE_CONTROL,
+                HeaderValue::from_static("no-cache"),
+            ))
+        }
+        None => app.fallback(web::serve_embedded),
+    };
+
+    let app = app
+        .layer(middleware::from_fn_with_state(state.clone(), require_login))
+        // HTTP-FLV 等视频流边收边发，压缩会缓冲数据
+        .layer(CompressionLayer::new().compress_when(DefaultPredicate::new().and(NotForContentType::const_new("video/"))))
         .layer(CorsLayer::permissive())
-        .with_state(state);
-
-    let addr = format!("0.0.0.0:{}", config.server.port);
-    tracing::info!("服务启动监听: {}", addr);
-    
-    // 优雅处理端口绑定错误
-    let listener = match tokio::net::TcpListener::bind(&addr).await {
-        Ok(l) => l,
+        .with_state(state.clone());
+
+    // 部署在反向代理子路径下时，所有路由与静态文件统一挂载到前缀下
+    let base_path = config.server.base_path();
+    let app = if base_path.is_empty() {
+        app
+    } else {
+        tracing::info!("使用 URL 前缀: {}", base_path);
+        Router::new()
+            .nest(&base_path, app)
+            .layer(middleware::from_fn_with_state(base_path.clone(), redirect_base_path))
+    };
+
+    // 平滑升级时等旧进程停止推流、交出运行中的流后再接受连接，期间的连接在监听队列中等待
+    let handoff = if upgrading {
+        tokio::task::spawn_blocking(upgrade::take_handoff).await.unwrap_or_default()
+    } else {
+        Vec::new()
+    };
+
+    // 为每个监听地址启动一个服务实例（支持 IPv6 / 双栈）
+    let mut servers = Vec::new();
+    let mut inherited = upgrade::inherited_listeners();
+    let mut listener_fds = Vec::new();
+    let shutdown = CancellationToken::new();
+    let mut ips = Vec::new();
+    for bind in &config.server.bind {
+        match bind.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() {
+            Ok(ip) => ips.push(ip),
+            Err(e) => {
+                tracing::error!("无效的监听地址 '{}': {}", bind, e);
+                return;
+            }
+        }
+    }
+    // Linux 上 "::" 默认双栈，同时配置了 IPv4 地址时 IPv6 只监听 IPv6，否则端口冲突
+    let v6_only = ips.iter().any(IpAddr::is_ipv4);
+    for ip in ips {
+        let addr = SocketAddr::new(ip, config.server.port);
+        tracing::info!("服务启动监听: {}", addr);
+
+        // 优雅处理端口绑定错误；平滑升级时直接使用旧进程交出的 socket
+        let listener = match inherited.remove(&addr) {
+            Some(l) => l.set_nonblocking(true).and_then(|()| tokio::net::TcpListener::from_std(l)),
+            None => bind_listener(addr, v6_only),
+        };
+        let listener = match listener {
+            Ok(l) => l,
+            Err(e) => {
+                tracing::error!("无法绑定端口 {}: {}", addr, e);
+                return;
+            }
+        };
+        listener_fds.push(listener.as_raw_fd());
+
+        // 携带客户端地址，用于选择播放边缘节点
+        let app = app.clone();
+        let shutdown = shutdown.clone();
+        servers.push(tokio::spawn(async move {
+            let result = axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+                .with_graceful_shutdown(shutdown.cancelled_owned())
+                .await;
+            if let Err(e) = result {
+                tracing::error!("服务器运行错误 ({}): {}", addr, e);
+            }
+        }));
+    }
+
+    // 配置变更后不再监听的地址，关闭旧进程交出的 socket
+    drop(inherited);
+    if upgrading {
+        upgrade::notify_main_pid();
+        tracing::info!("平滑升级完成，重新启动旧进程交接的 {} 个流", handoff.len());
+        tokio::spawn(resume_streams(state.clone(), handoff));
+    }
+    // 所有服务实例处理完进行中的请求后取消，平滑升级据此提前退出
+    let drained = CancellationToken::new();
+    tokio::spawn(shutdown_on_signal(state.clone(), shutdown.clone()));
+    tokio::spawn(upgrade_on_signal(state, listener_fds, shutdown.clone(), drained.clone()));
+
+    for server in servers {
+        let _ = server.await;
+    }
+    drained.cancel();
+    // 由信号触发的停止与平滑升级在各自的任务中等待转码线程后退出进程
+    if shutdown.is_cancelled() {
+        std::future::pending::<()>().await;
+    }
+}
+
+/// 监听 HTTP 端口，`v6_only` 时 IPv6 地址不接受 IPv4 连接（关闭双栈）
+fn bind_listener(addr: SocketAddr, v6_only: bool) -> std::io::Result<tokio::net::TcpListener> {
+    let socket = match addr {
+        SocketAddr::V4(_) => tokio::net::TcpSocket::new_v4()?,
+        SocketAddr::V6(_) => tokio::net::TcpSocket::new_v6()?,
+    };
+    if addr.is_ipv6() && v6_only {
+        let enable: libc::c_int = 1;
+        // SAFETY: 描述符由 socket 持有且有效，选项值为 c_int
+        let result = unsafe {
+            libc::setsockopt(
+                socket.as_raw_fd(),
+                libc::IPPROTO_IPV6,
+                libc::IPV6_V6ONLY,
+                &enable as *const libc::c_int as *const libc::c_void,
+                std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+            )
+        };
+        if result == -1 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+    // 与 TcpListener::bind 相同，允许重启后立即重新监听 TIME_WAIT 中的端口
+    socket.set_reuseaddr(true)?;
+    socket.bind(addr)?;
+    socket.listen(1024)
+}
+
+/// 停止转码后等待进行中的请求结束的最长时间
+const SHUTDOWN_DRAIN: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// 收到 SIGTERM / SIGINT 时正常退出
+///
+/// 不再接受新连接，停止所有流并等待转码线程写完文件尾、断开与 SRS 的推流连接（最长 `server.stop_timeout_secs`），
+/// 之后退出进程。`docker stop` 不会再在写入中途杀掉转码线程，SRS 上也不会残留推流连接。
+async fn shutdown_on_signal(state: AppState, shutdown: CancellationToken) {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    let mut terminate = match signal(SignalKind::terminate()) {
+        Ok(s) => s,
         Err(e) => {
-            tracing::error!("无法绑定端口 {}: {}", addr, e);
+            tracing::warn!("无法监听 SIGTERM，退出时不会等待转码线程: {}", e);
             return;
         }
     };
+    let name = tokio::select! {
+        _ = terminate.recv() => "SIGTERM",
+        _ = tokio::signal::ctrl_c() => "SIGINT",
+    };
+    let timeout = std::time::Duration::from_secs(state.config().server.stop_timeout_secs);
+    tracing::info!("收到 {}，停止接受新连接并停止 {} 个流", name, state.stream_manager.running_count());
+    shutdown.cancel();
+    state.stream_manager.stop_all("服务停止", false, timeout).await;
+    tracing::info!("所有流已停止，退出");
+    // HTTP-FLV 等长连接不会自行结束，短暂等待其他请求完成后直接退出
+    tokio::time::sleep(SHUTDOWN_DRAIN).await;
+    std::process::exit(0);
+}
+
+/// 收到 SIGUSR2 时平滑升级
+///
+/// 停止本进程的流，以相同参数启动新进程并交出监听 socket 与运行中的流，
+/// 之后不再接受新连接，已有请求处理完成（最长 `server.drain_secs`）后退出。
+async fn upgrade_on_signal(state: AppState, listener_fds: Vec<std::os::fd::RawFd>, shutdown: CancellationToken, drained: CancellationToken) {
+    use tokio::signal::unix::{SignalKind, signal};
 
-    if let Err(e) = axum::serve(listener, app).await {
-        tracing::error!("服务器运行错误: {}", e);
+    let mut signals = match signal(SignalKind::user_defined2()) {
+        Ok(s) => s,
+        Err(e) => {
+            tracing::warn!("无法监听 SIGUSR2，平滑升级不可用: {}", e);
+            return;
+        }
+    };
+    while signals.recv().await.is_some() {
+        tracing::info!("收到 SIGUSR2，开始平滑升级");
+        // 新进程收到交接的流之前不接受连接，期间的新连接在监听队列中等待
+        let successor = match upgrade::spawn_successor(&listener_fds) {
+            Ok(successor) => successor,
+            Err(e) => {
+                tracing::error!("平滑升级失败: {:#}，继续运行", e);
+                continue;
+            }
+        };
+        tracing::info!("新进程已启动 (PID {})，停止接受新连接", successor.pid);
+        shutdown.cancel();
+        // 再停止推流，避免新旧进程同时推到同一地址；之后处理中的播放请求不再启动流
+        let jobs = state.stream_manager.stop_all("平滑升级，交给新进程", true, std::time::Duration::from_secs(5)).await;
+        let streams = handoff_streams(&state, jobs);
+        tracing::info!("交接 {} 个流", streams.len());
+        if let Err(e) = successor.hand_off(&streams) {
+            tracing::error!("交接流失败: {:#}", e);
+        }
+        let drain = std::time::Duration::from_secs(state.config().server.drain_secs);
+        match tokio::time::timeout(drain, drained.cancelled()).await {
+            Ok(()) => tracing::info!("请求已处理完成，退出"),
+            Err(_) => tracing::warn!("等待请求处理完成超时，退出"),
+        }
+        std::process::exit(0);
     }
 }
 
-/// 获取流列表接口
-async fn list_streams(State(state): State<AppState>) -> Json<Vec<crate::config::StreamConfig>> {
-    Json(state.config.streams.clone())
+/// 停止的流转换为交接信息：配置中的流由新进程按当前配置解析地址，输入输出参数沿用旧进程中的
+fn handoff_streams(state: &AppState, jobs: Vec<RunningJob>) -> Vec<upgrade::HandoffStream> {
+    jobs.into_iter()
+        .map(|job| {
+            let output_url = match srs_stream_path(state, &job.name).and_then(|path| srs_output_url(state, &path)) {
+                Ok(default) if default == job.output_url => None,
+                _ => Some(job.output_url),
+            };
+            let base = job.name.strip_suffix("_sub").filter(|base| state.registry.get(base).is_some());
+            let (name, profile, url) = match (state.registry.get(&job.name), base) {
+                (Some(_), _) => (job.name.clone(), StreamProfile::Main, None),
+                (None, Some(base)) => (base.to_string(), StreamProfile::Sub, None),
+                (None, None) => (job.name.clone(), StreamProfile::Main, Some(job.url)),
+            };
+            upgrade::HandoffStream {
+                name,
+                profile,
+                url,
+                output_url,
+                proxy_is_global: job.input.proxy_is_global,
+                input: Some(job.input),
+                output: Some(job.output),
+            }
+        })
+        .collect()
+}
+
+/// 重新启动交接的流
+async fn resume_streams(state: AppState, streams: Vec<upgrade::HandoffStream>) {
+    let client = ClientInfo { ip: IpAddr::from([127, 0, 0, 1]), scheme: "http".to_string() };
+    for stream in streams {
+        let name = stream.name.clone();
+        let request = PlayRequest {
+            name: stream.name,
+            url: stream.url,
+            profile: stream.profile,
+            dry_run: false,
+            probe: false,
+            output_url: stream.output_url,
+            overrides: None,
+            device: None,
+        };
+        let options = stream.input.zip(stream.output).map(|(mut input, output)| {
+            input.proxy_is_global = stream.proxy_is_global;
+            (input, output)
+        });
+        if let Err(e) = play_with_options(&state, &client, request, options).await {
+            tracing::error!("重新启动流 '{}' 失败: {:#}", name, e.0);
+        }
+    }
+}
+
+/// 平滑升级期间在后台重试启动，直到旧进程退出并释放端口
+fn start_after_predecessor<F, Fut>(what: &'static str, drain_secs: u64, mut start: F)
+where
+    F: FnMut() -> Fut + Send + 'static,
+    Fut: std::future::Future<Output = anyhow::Result<()>> + Send,
+{
+    tokio::spawn(async move {
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(drain_secs + 5);
+        loop {
+            match start().await {
+                Ok(()) => return,
+                Err(e) if std::time::Instant::now() < deadline => {
+                    tracing::debug!("{}暂不可用，等待旧进程退出: {:#}", what, e);
+                    tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+                }
+                Err(e) => {
+                    tracing::error!("启动{}失败: {:#}", what, e);
+                    return;
+                }
+            }
+        }
+    });
 }
 
 #[derive(Deserialize)]
-struct PlayRequest {
-    name: String,
-    url: Option<String>,
+struct StreamListQuery {
+    /// 按元数据过滤：`键` 返回有该元数据的流，`键:值` 返回值相等的流
+    meta: Option<String>,
 }
 
-#[derive(Serialize)]
-struct PlayResponse {
-    playback_url: String,
+/// 获取流列表接口
+async fn list_streams(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<StreamListQuery>,
+) -> Response {
+    let mut streams: Vec<_> = state.registry.list().into_iter().map(config::StreamConfig::redacted).collect();
+    if let Some(filter) = &query.meta {
+        streams.retain(|s| s.matches_metadata(filter));
+    }
+    json_with_etag(&headers, &streams)
 }
 
-/// 播放流接口
-/// 接收流名称或自定义 URL，调用 SRS 接口，返回播放地址
-async fn play_stream(
+/// 新增流接口
+/// 校验规则与导入相同，保存为动态流；同名流已存在时返回 409
+async fn create_stream(
     State(state): State<AppState>,
     _: AuthToken, // 验证 Token
-    Json(payload): Json<PlayRequest>,
-) -> Result<Json<PlayResponse>, AppError> {
-    let (name, rtsp_url) = if let Some(custom_url) = &payload.url {
-        if !custom_url.is_empty() {
-             // 1. 如果提供了 URL，直接使用（自定义播放模式）
-            if !custom_url.to_lowercase().starts_with("rtsp://") {
-                 return Err(anyhow::anyhow!("自定义地址必须以 rtsp:// 开头").into());
-            }
-            (payload.name.as_str(), custom_url.as_str())
-        } else {
-             // URL 字段存在但为空字符串，视为查找配置
-             let stream_config = state.config.streams.iter()
-                .find(|s| s.name == payload.name)
-                .ok_or_else(|| anyhow::anyhow!("未找到名称为 '{}' 的流配置", payload.name))?;
-            (stream_config.name.as_str(), stream_config.url.as_str())
-        }
-    } else {
-        // 2. 如果没有提供 URL，从配置中查找
-        let stream_config = state.config.streams.iter()
-            .find(|s| s.name == payload.name)
-            .ok_or_else(|| anyhow::anyhow!("未找到名称为 '{}' 的流配置", payload.name))?;
-        (stream_config.name.as_str(), stream_config.url.as_str())
-    };
+    Json(stream): Json<config::StreamConfig>,
+) -> Result<Response, AppError> {
+    if state.registry.list().iter().any(|s| s.name == stream.name) {
+        return Ok((StatusCode::CONFLICT, "已存在同名流").into_response());
+    }
+    save_stream(&state, stream)
+}
 
-    // 1. 获取 SRS 播放地址 (用于返回给前端)
-    // 注意：这里我们仍然调用 srs.play_stream 主要是为了利用它的 URL 生成逻辑
-    // 实际上 SRS 的 API 调用可能是不必要的，但保留也没坏处
-    let playback_url = state.srs.play_stream(name, rtsp_url).await?;
-    
-    // 2. 构造推流地址 (RTMP)
-    // 从配置的 API URL 中提取主机名，默认端口 1935
-    let api_url = reqwest::Url::parse(&state.config.srs.api_url)
-        .map_err(|e| anyhow::anyhow!("配置的 SRS API URL 无效: {}", e))?;
-    
-    let host = api_url.host_str().unwrap_or("127.0.0.1");
-    
-    let safe_name = name.replace(" ", "_").to_lowercase();
-    let rtmp_url = format!("rtmp://{}:1935/live/{}", host, safe_name);
+/// 修改流接口
+/// 流不存在时新增；修改配置文件中的流时保存为覆盖它的动态流。运行中的流在下次启动时使用新配置
+async fn update_stream(
+    State(state): State<AppState>,
+    _: AuthToken, // 验证 Token
+    Path(name): Path<String>,
+    Json(stream): Json<config::StreamConfig>,
+) -> Result<Response, AppError> {
+    if stream.name != name {
+        return Ok((StatusCode::BAD_REQUEST, "请求体中的名称与路径不一致（不支持改名）").into_response());
+    }
+    save_stream(&state, stream)
+}
 
-    // 3. 启动转码任务
-    // 这里我们启动本地的 FFmpeg 转码任务，将 RTSP 流推送到 SRS
-    // SRS 接收 RTMP 推流后，会分发 HTTP-FLV 供前端播放
-    state.stream_manager.start_stream(name.to_string(), rtsp_url.to_string(), rtmp_url);
-    
-    Ok(Json(PlayResponse { playback_url }))
+fn save_stream(state: &AppState, stream: config::StreamConfig) -> Result<Response, AppError> {
+    let name = stream.name.clone();
+    let report = stream_io::import_entries(
+        &state.registry,
+        &state.vault,
+        vec![stream],
+        stream_io::ConflictPolicy::Overwrite,
+        false,
+    )?;
+    if let Some(error) = report.errors.into_iter().next() {
+        return Ok((StatusCode::UNPROCESSABLE_ENTITY, error.message).into_response());
+    }
+    let created = !report.added.is_empty();
+    tracing::info!("{}流配置: {}", if created { "新增" } else { "修改" }, name);
+    if state.registry.get(&name).is_some_and(|s| s.preconnect) {
+        tracing::info!("流 '{}' 的预连接在服务重启后生效", name);
+    }
+    let status = if created { StatusCode::CREATED } else { StatusCode::OK };
+    Ok((status, Json(state.registry.get(&name))).into_response())
 }
 
-#[derive(Deserialize)]
-struct HeartbeatRequest {
-    name: String,
+/// 删除流接口
+/// 删除动态流并停止其转码；覆盖了配置文件中同名流的，恢复为配置文件中的配置
+async fn delete_stream(
+    State(state): State<AppState>,
+    _: AuthToken, // 验证 Token
+    Path(name): Path<String>,
+) -> Result<Response, AppError> {
+    match state.registry.remove(&name)? {
+        registry::Removal::Removed => {
+            tracing::info!("删除流配置: {}", name);
+            state.stream_manager.remove_standby(&name);
+            for job in [name.clone(), format!("{}_sub", name)] {
+                state.stream_manager.stop_stream(&job, "流配置已删除", std::time::Duration::from_secs(10)).await;
+            }
+            Ok(StatusCode::NO_CONTENT.into_response())
+        }
+        registry::Removal::Reverted => {
+            tracing::info!("删除流 '{}' 的运行时修改，恢复为配置文件中的配置", name);
+            Ok((StatusCode::OK, Json(state.registry.get(&name))).into_response())
+        }
+        registry::Removal::Static => {
+            Ok((StatusCode::CONFLICT, "配置文件中的流不能通过接口删除，请修改配置文件").into_response())
+        }
+        registry::Removal::NotFound => Ok((StatusCode::NOT_FOUND, "未找到流配置").into_response()),
+    }
 }
 
-async fn heartbeat(
+#[derive(Deserialize, Default)]
+struct EventClipRequest {
+    /// 触发原因，如外部系统的告警内容
+    #[serde(default)]
+    reason: String,
+}
+
+/// 外部触发事件录像接口
+/// 截取事件前后的片段保存为事件录像，不受自动录像的冷却时间限制
+async fn trigger_event_clip(
     State(state): State<AppState>,
     _: AuthToken, // 验证 Token
-    Json(payload): Json<HeartbeatRequest>,
-) -> StatusCode {
-    if state.stream_manager.heartbeat(&payload.name) {
-        StatusCode::OK
-    } else {
-        StatusCode::NOT_FOUND
+    Path(name): Path<String>,
+    payload: Option<Json<EventClipRequest>>,
+) -> Response {
+    if !state.event_clips.enabled(event_clip::Trigger::Webhook) {
+        return (StatusCode::FORBIDDEN, "未启用外部触发事件录像").into_response();
     }
+    let name = state.registry.canonical(&name);
+    let Some(buffer) = state.stream_manager.clip_buffer(&name) else {
+        return (StatusCode::NOT_FOUND, "流未运行或未启用片段缓冲").into_response();
+    };
+    let Json(payload) = payload.unwrap_or_default();
+    let reason = if payload.reason.is_empty() { "外部触发".to_string() } else { payload.reason };
+    let id = state.event_clips.record(&name, buffer, event_clip::Trigger::Webhook, &reason);
+    (StatusCode::ACCEPTED, Json(serde_json::json!({ "id": id }))).into_response()
+}
+
+/// 创建访客分享链接接口
+/// 返回限时、限人数的 HTTP-FLV 播放地址，访客无需账号即可观看
+async fn create_share(
+    State(state): State<AppState>,
+    _: AuthToken, // 验证 Token
+    Path(name): Path<String>,
+    payload: Option<Json<share::ShareRequest>>,
+) -> Result<Response, AppError> {
+    let config = state.config();
+    if !config.http_flv.enabled {
+        return Ok((StatusCode::BAD_REQUEST, "分享链接通过 HTTP-FLV 播放，需要启用 http_flv").into_response());
+    }
+    let name = state.registry.canonical(&name);
+    if state.registry.get(&name).is_none() {
+        return Ok((StatusCode::NOT_FOUND, format!("流 '{}' 不存在", name)).into_response());
+    }
+    let Json(payload) = payload.unwrap_or_default();
+    match state.shares.create(&name, payload, &config.share, &config.server.base_path()) {
+        Ok(share) => Ok((StatusCode::CREATED, Json(share)).into_response()),
+        Err(e) => Ok((StatusCode::BAD_REQUEST, e.to_string()).into_response()),
+    }
+}
+
+/// 分享链接列表接口
+async fn list_shares(State(state): State<AppState>, _: AuthToken) -> Result<Response, AppError> {
+    Ok(Json(state.shares.list()?).into_response())
+}
+
+/// 撤销分享链接接口，正在观看的访客随后断开
+async fn revoke_share(State(state): State<AppState>, _: AuthToken, Path(id): Path<String>) -> Result<Response, AppError> {
+    if state.shares.revoke(&id)? {
+        Ok(StatusCode::NO_CONTENT.into_response())
+    } else {
+        Ok((StatusCode::NOT_FOUND, "分享链接不存在").into_response())
+    }
+}
+
+#[derive(Deserialize)]
+struct QrCodeQuery {
+    /// 编码限时分享链接（手机无需登录即可播放），否则编码 SRS 播放地址
+    #[serde(default)]
+    signed: bool,
+    /// 分享链接有效期（秒），缺省 `share.default_ttl_secs`
+    ttl_secs: Option<u64>,
+    /// 每个模块的像素数
+    #[serde(default = "default_qr_scale")]
+    scale: u32,
+}
+
+fn default_qr_scale() -> u32 {
+    8
+}
+
+/// 播放地址二维码接口
+/// 把流的播放地址渲染为 PNG 二维码，现场人员用手机扫描管理界面上的二维码即可在手机上核对摄像头画面
+async fn stream_qrcode(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    access: StreamAccess, // 验证 Token
+    headers: HeaderMap,
+    Path(name): Path<String>,
+    Query(query): Query<QrCodeQuery>,
+) -> Result<Response, AppError> {
+    access.check(&state, &name)?;
+    let name = state.registry.canonical(&name);
+    if state.registry.get(&name).is_none() {
+        return Ok((StatusCode::NOT_FOUND, format!("流 '{}' 不存在", name)).into_response());
+    }
+
+    let url = if query.signed {
+        if access.scope.is_some() {
+            return Err(Forbidden("该 API Key 不能创建分享链接".to_string()).into());
+        }
+        let config = state.config();
+        if !config.http_flv.enabled {
+            return Ok((StatusCode::BAD_REQUEST, "分享链接通过 HTTP-FLV 播放，需要启用 http_flv").into_response());
+        }
+        // 手机需要完整地址，按本次请求的 Host 拼接
+        let Some(host) = headers.get(header::HOST).and_then(|h| h.to_str().ok()) else {
+            return Ok((StatusCode::BAD_REQUEST, "缺少 Host 请求头").into_response());
+        };
+        let request = share::ShareRequest { ttl_secs: query.ttl_secs, max_viewers: None, note: Some("二维码".to_string()) };
+        let share = match state.shares.create(&name, request, &config.share, &config.server.base_path()) {
+            Ok(share) => share,
+            Err(e) => return Ok((StatusCode::BAD_REQUEST, e.to_string()).into_response()),
+        };
+        format!("{}://{}{}", client.scheme, host, share.url.unwrap_or_default())
+    } else {
+        let path = srs_stream_path(&state, &name)?;
+        state.srs().playback_url(&path, Some(client.ip), &client.scheme)
+    };
+
+    let scale = query.scale;
+    let png = tokio::task::spawn_blocking(move || qrcode::QrCode::encode(&url)?.to_png(scale)).await?;
+    match png {
+        Ok(data) => Ok(([(header::CONTENT_TYPE, "image/png"), (header::CACHE_CONTROL, "no-store")], data).into_response()),
+        Err(e) => Ok((StatusCode::BAD_REQUEST, e.to_string()).into_response()),
+    }
+}
+
+#[derive(Deserialize)]
+struct EventClipsQuery {
+    stream: Option<String>,
+}
+
+/// 事件录像列表接口
+async fn list_event_clips(
+    State(state): State<AppState>,
+    _: AuthToken, // 验证 Token
+    Query(query): Query<EventClipsQuery>,
+) -> Result<Response, AppError> {
+    let stream = query.stream.map(|s| state.registry.canonical(&s));
+    Ok(Json(state.event_clips.list(stream.as_deref())?).into_response())
+}
+
+/// 录像查询接口
+/// 按流、时间范围与触发类型查找事件录像，按时间倒序返回
+async fn search_recordings(
+    State(state): State<AppState>,
+    _: AuthToken, // 验证 Token
+    Query(mut query): Query<event_clip::ClipSearch>,
+) -> Result<Response, AppError> {
+    query.stream = query.stream.map(|s| state.registry.canonical(&s));
+    Ok(Json(state.event_clips.search(&query)?).into_response())
+}
+
+/// 下载事件录像接口
+/// 加密保存的录像在下载时解密
+async fn download_event_clip(
+    State(state): State<AppState>,
+    _: AuthToken, // 验证 Token
+    Path(id): Path<String>,
+) -> Result<Response, AppError> {
+    let Some(clip) = state.event_clips.get(&id)? else {
+        return Ok((StatusCode::NOT_FOUND, "未找到事件录像").into_response());
+    };
+    let file = state.event_clips.open(&clip).await?;
+    Ok((
+        [
+            (header::CONTENT_TYPE, "video/mp4".to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}.mp4\"", clip.id)),
+        ],
+        axum::body::Body::from_stream(tokio_util::io::ReaderStream::new(file)),
+    )
+        .into_response())
+}
+
+#[derive(Deserialize)]
+struct ChangesQuery {
+    since: Option<String>,
+}
+
+/// 增量同步流列表接口
+/// 只返回游标之后新增、修改、删除的流，流较多的客户端无需每次下载完整列表
+async fn stream_changes(State(state): State<AppState>, Query(query): Query<ChangesQuery>) -> Json<Changes> {
+    let mut changes = state.registry.changes(query.since.as_deref());
+    for list in [&mut changes.added, &mut changes.modified] {
+        *list = std::mem::take(list).into_iter().map(config::StreamConfig::redacted).collect();
+    }
+    Json(changes)
+}
+
+#[derive(Deserialize)]
+struct ExportQuery {
+    #[serde(default)]
+    format: stream_io::Format,
+}
+
+/// 导出流配置接口 (JSON / CSV)
+async fn export_streams(
+    State(state): State<AppState>,
+    _: AuthToken, // 验证 Token，导出内容包含摄像头地址（代理密码已隐藏）
+    Query(query): Query<ExportQuery>,
+) -> Result<Response, AppError> {
+    let streams: Vec<_> = state.registry.list().into_iter().map(config::StreamConfig::redacted).collect();
+    let body = stream_io::export(&streams, query.format)?;
+    let (content_type, filename) = match query.format {
+        stream_io::Format::Json => ("application/json", "streams.json"),
+        stream_io::Format::Csv => ("text/csv; charset=utf-8", "streams.csv"),
+    };
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", filename)),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+#[derive(Deserialize)]
+struct ImportQuery {
+    #[serde(default)]
+    format: stream_io::Format,
+    #[serde(default)]
+    conflict: stream_io::ConflictPolicy,
+    #[serde(default)]
+    dry_run: bool,
+}
+
+/// 导入流配置接口 (JSON / CSV)
+/// dry_run=true 时只校验并返回报告；存在错误时不会写入任何条目
+async fn import_streams(
+    State(state): State<AppState>,
+    _: AuthToken, // 验证 Token
+    Query(query): Query<ImportQuery>,
+    body: String,
+) -> Result<Response, AppError> {
+    let report = match stream_io::import(&state.registry, &state.vault, &body, query.format, query.conflict, query.dry_run) {
+        Ok(report) => report,
+        Err(e) => return Ok((StatusCode::BAD_REQUEST, format!("导入失败: {}", e)).into_response()),
+    };
+    if !report.dry_run && report.errors.is_empty() {
+        tracing::info!(
+            "导入流配置: 新增 {} 个, 覆盖 {} 个, 跳过 {} 个",
+            report.added.len(), report.updated.len(), report.skipped.len()
+        );
+    }
+    let status = if report.errors.is_empty() {
+        StatusCode::OK
+    } else {
+        StatusCode::UNPROCESSABLE_ENTITY
+    };
+    Ok((status, Json(report)).into_response())
+}
+
+#[derive(Deserialize)]
+struct HistoryQuery {
+    #[serde(default = "default_history_days")]
+    days: u64,
+}
+
+fn default_history_days() -> u64 {
+    7
+}
+
+/// 流健康历史接口
+/// 返回最近 N 天的状态时间线与可用率，用于 SLA 统计
+async fn stream_history(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    Query(query): Query<HistoryQuery>,
+) -> Result<Json<crate::history::HealthReport>, AppError> {
+    let name = state.registry.canonical(&name);
+    let history = state.history.clone();
+    let report = tokio::task::spawn_blocking(move || history.report(&name, query.days)).await??;
+    Ok(Json(report))
+}
+
+/// 管理面板数据接口
+/// 汇总流状态、健康评分、SRS 状态、资源占用与最近事件，网页每次刷新只需请求一次
+async fn dashboard(State(state): State<AppState>, _: AuthToken) -> Result<Json<dashboard::Dashboard>, AppError> {
+    let history = state.history.clone();
+    let history_task = tokio::task::spawn_blocking(move || -> anyhow::Result<_> {
+        Ok((history.uptime_all(1)?, history.recent(20)?))
+    });
+    let srs = state.srs().health().await;
+    let (uptime, recent_events) = history_task.await??;
+
+    let (summary, streams) = dashboard::streams(&state.registry.list(), state.stream_manager.snapshot(), &uptime);
+    Ok(Json(dashboard::Dashboard {
+        generated_at: history::now_secs(),
+        summary,
+        streams,
+        srs,
+        resources: dashboard::resource_usage(state.started_at.elapsed().as_secs()),
+        recent_events,
+    }))
+}
+
+/// 摄像头地图接口
+/// 以 GeoJSON 返回元数据中带经纬度的流及其实时健康状态，可直接加载到 Leaflet、OpenLayers 或 GIS 工具
+async fn camera_map(State(state): State<AppState>, access: StreamAccess) -> Result<Response, AppError> {
+    let history = state.history.clone();
+    let uptime = tokio::task::spawn_blocking(move || history.uptime_all(1)).await??;
+    // API Key 只能看到可访问的流
+    let configured: Vec<config::StreamConfig> =
+        state.registry.list().into_iter().filter(|s| access.check(&state, &s.name).is_ok()).collect();
+    let (_, overviews) = dashboard::streams(&configured, state.stream_manager.snapshot(), &uptime);
+
+    let config = state.config();
+    let base_path = config.server.base_path();
+    let collection = map::collection(&configured, overviews, history::now_secs(), |name| {
+        config.thumbnails.enabled.then(|| {
+            let name = percent_encoding::utf8_percent_encode(name, percent_encoding::NON_ALPHANUMERIC);
+            format!("{}/api/streams/{}/thumb", base_path, name)
+        })
+    });
+    Ok(([(header::CONTENT_TYPE, "application/geo+json")], Json(collection)).into_response())
+}
+
+#[derive(Deserialize)]
+struct EffectiveConfigQuery {
+    #[serde(default)]
+    profile: StreamProfile,
+}
+
+/// 流的生效配置（合并默认值、全局配置与凭据后的结果）
+#[derive(Serialize)]
+struct EffectiveConfig {
+    /// 正式名称（请求使用别名时解析为正式名称）
+    name: String,
+    /// 转码任务名称，子码流为 `{name}_sub`
+    stream: String,
+    /// 配置来源：static（配置文件）或 dynamic（导入）
+    origin: &'static str,
+    aliases: Vec<String>,
+    /// 拉流地址，主源在前（密码已隐藏）
+    input_urls: Vec<String>,
+    /// 降级用的子码流地址
+    sub_fallback_url: Option<String>,
+    input: InputOptions,
+    output: OutputOptions,
+    output_url: String,
+    srs_stream_key: String,
+    preconnect: bool,
+    protected_hours: Option<ProtectedHours>,
+    /// 当前是否处于保护时段
+    protected_now: bool,
+    /// 运行中转码任务实际使用的参数（含播放请求中的覆盖项），未运行时为空
+    running: Option<RunningConfig>,
+}
+
+/// 流生效配置接口
+async fn effective_config(
+    State(state): State<AppState>,
+    _: AuthToken, // 验证 Token
+    Path(name): Path<String>,
+    Query(query): Query<EffectiveConfigQuery>,
+) -> Result<Response, AppError> {
+    let Some(stream) = state.registry.get(&name) else {
+        return Ok((StatusCode::NOT_FOUND, "未找到流配置").into_response());
+    };
+    let (stream_name, urls, mut input, output) = resolve_configured(&state, &stream.name, query.profile)?;
+    let policy = stream_policy(&state, &stream.name, query.profile)?;
+    let srs_path = srs_stream_path(&state, &stream_name)?;
+    let output_url = match &output.url {
+        Some(url) => url.clone(),
+        None => srs_output_url(&state, &srs_path)?,
+    };
+    input.proxy = input.proxy.as_deref().map(vault::redact_url);
+
+    Ok(Json(EffectiveConfig {
+        origin: if state.registry.is_dynamic(&stream.name) { "dynamic" } else { "static" },
+        aliases: stream.aliases,
+        input_urls: urls.iter().map(|u| vault::redact_url(u)).collect(),
+        sub_fallback_url: policy.sub_fallback.as_deref().map(vault::redact_url),
+        input,
+        output,
+        output_url: vault::redact_url(&output_url),
+        srs_stream_key: srs_path.stream,
+        preconnect: stream.preconnect,
+        protected_now: policy.protected_hours.as_ref().is_some_and(|p| p.contains(history::now_secs())),
+        protected_hours: policy.protected_hours,
+        running: state.stream_manager.running_config(&stream_name),
+        name: stream.name,
+        stream: stream_name,
+    })
+    .into_response())
+}
+
+/// 流运行统计接口
+async fn stream_stats(State(state): State<AppState>, Path(name): Path<String>) -> Response {
+    match state.stream_manager.stats(&state.registry.canonical(&name)) {
+        Some(stats) => Json(stats).into_response(),
+        None => (StatusCode::NOT_FOUND, "流未运行").into_response(),
+    }
+}
+
+/// 流运行详情接口
+/// 前端据此判断播放请求是否真正启动了健康的流
+async fn stream_status(
+    State(state): State<AppState>,
+    _: AuthToken, // 验证 Token，详情包含拉流地址
+    Path(name): Path<String>,
+) -> Response {
+    let name = state.registry.canonical(&name);
+    let Some(mut detail) = state.stream_manager.detail(&name) else {
+        return (StatusCode::NOT_FOUND, "流未运行").into_response();
+    };
+    let stream = state
+        .registry
+        .get(&name)
+        .or_else(|| name.strip_suffix("_sub").and_then(|base| state.registry.get(base)));
+    if let Some(stream) = stream {
+        detail.metadata = stream.metadata;
+    }
+    Json(detail).into_response()
+}
+
+#[derive(Deserialize)]
+struct StatsExportQuery {
+    #[serde(default)]
+    format: stream_io::Format,
+    /// 开始日期 YYYY-MM-DD，缺省为 7 天前
+    from: Option<String>,
+    /// 结束日期 YYYY-MM-DD（含），缺省为今天
+    to: Option<String>,
+    /// 按哪个时区划分日期（相对 UTC 的小时数，如北京时间为 8）
+    #[serde(default)]
+    utc_offset: i64,
+}
+
+/// 每日统计导出接口
+/// 按天汇总每个流的可用率、重启次数与转发流量，CSV 可直接用 Excel 打开
+async fn export_stats(
+    State(state): State<AppState>,
+    _: AuthToken, // 验证 Token
+    Query(query): Query<StatsExportQuery>,
+) -> Result<Response, AppError> {
+    if !(-12..=14).contains(&query.utc_offset) {
+        return Ok((StatusCode::BAD_REQUEST, "utc_offset 应在 -12 到 14 之间").into_response());
+    }
+    let now = history::now_secs();
+    let parse = |value: &Option<String>, default_secs: u64| match value {
+        Some(v) => usage::parse_date(v),
+        None => Ok(usage::date_of(default_secs, query.utc_offset)),
+    };
+    let (from, to) = match (parse(&query.from, now.saturating_sub(6 * 86400)), parse(&query.to, now)) {
+        (Ok(from), Ok(to)) => (from, to),
+        (Err(e), _) | (_, Err(e)) => return Ok((StatusCode::BAD_REQUEST, e.to_string()).into_response()),
+    };
+
+    let mut streams: Vec<String> = state.registry.list().into_iter().map(|s| s.name).collect();
+    for name in state.usage.streams() {
+        if !streams.contains(&name) {
+            streams.push(name);
+        }
+    }
+    let (ledger, history, utc_offset) = (state.usage.clone(), state.history.clone(), query.utc_offset);
+    let rows = match tokio::task::spawn_blocking(move || ledger.daily(&history, &streams, from, to, utc_offset)).await? {
+        Ok(rows) => rows,
+        Err(e) => return Ok((StatusCode::BAD_REQUEST, e.to_string()).into_response()),
+    };
+
+    let (content_type, extension, body) = match query.format {
+        stream_io::Format::Json => ("application/json", "json", serde_json::to_string(&rows)?),
+        stream_io::Format::Csv => ("text/csv; charset=utf-8", "csv", usage::to_csv(&rows)),
+    };
+    let filename = format!(
+        "stats-{:04}{:02}{:02}-{:04}{:02}{:02}.{}",
+        from.0, from.1, from.2, to.0, to.1, to.2, extension
+    );
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", filename)),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+/// Prometheus 指标接口
+async fn metrics(State(state): State<AppState>) -> Response {
+    let mut body = stats::prometheus(&state.stream_manager.snapshot_with_stats());
+    if let Some(report) = state.auditor.report() {
+        body.push_str(&audit::prometheus(&report));
+    }
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4; charset=utf-8")], body).into_response()
+}
+
+/// GOP 缓存统计接口
+async fn gop_stats(State(state): State<AppState>, Path(name): Path<String>) -> Response {
+    match state.stream_manager.gop_cache(&state.registry.canonical(&name)) {
+        Some(cache) => Json(cache.stats()).into_response(),
+        None => (StatusCode::NOT_FOUND, "流未运行或未启用 GOP 缓存").into_response(),
+    }
+}
+
+/// HTTP-FLV 播放接口
+/// 从流的 GOP 缓存向观众直接发送 FLV，不经过 SRS；只提供已在运行的流，连接期间自动续期心跳
+async fn http_flv_play(State(state): State<AppState>, Path(file): Path<String>) -> Response {
+    if !state.config().http_flv.enabled {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    let Some(name) = file.strip_suffix(".flv") else {
+        return (StatusCode::NOT_FOUND, "播放地址须以 .flv 结尾").into_response();
+    };
+    let name = state.registry.canonical(name);
+    let Some(cache) = state.stream_manager.gop_cache(&name) else {
+        return (StatusCode::NOT_FOUND, "流未运行，需要先调用 /api/play").into_response();
+    };
+    let manager = state.stream_manager.clone();
+    let body = http_flv::stream(&cache, move || {
+        manager.heartbeat(&name);
+        true
+    });
+    (
+        [(header::CONTENT_TYPE, "video/x-flv"), (header::CACHE_CONTROL, "no-cache")],
+        body,
+    )
+        .into_response()
+}
+
+/// WebSocket-FLV 播放接口
+/// 与 HTTP-FLV 共用流的 GOP 缓存，名称可带 `.flv` 后缀
+async fn ws_flv_play(State(state): State<AppState>, Path(file): Path<String>, ws: WebSocketUpgrade) -> Response {
+    if !state.config().http_flv.enabled {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    let name = state.registry.canonical(file.strip_suffix(".flv").unwrap_or(&file));
+    let Some(cache) = state.stream_manager.gop_cache(&name) else {
+        return (StatusCode::NOT_FOUND, "流未运行，需要先调用 /api/play").into_response();
+    };
+    let manager = state.stream_manager.clone();
+    ws.on_upgrade(move |socket| {
+        http_flv::websocket(socket, cache, move || {
+            manager.heartbeat(&name);
+            true
+        })
+    })
+}
+
+/// 访客分享链接播放接口
+/// 校验令牌并占用一个访客名额，流未运行时按需启动；链接过期或被撤销后断开
+async fn share_play(State(state): State<AppState>, client: ClientInfo, Path(file): Path<String>) -> Response {
+    let config = state.config();
+    if !config.http_flv.enabled {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    let Some(token) = file.strip_suffix(".flv") else {
+        return (StatusCode::NOT_FOUND, "播放地址须以 .flv 结尾").into_response();
+    };
+    let pass = match state.shares.admit(token, &config.share) {
+        Ok(pass) => pass,
+        Err(share::ShareError::Invalid) => return (StatusCode::NOT_FOUND, "分享链接无效").into_response(),
+        Err(share::ShareError::Gone) => return (StatusCode::GONE, "分享链接已过期或已撤销").into_response(),
+        Err(share::ShareError::Full(true)) => return (StatusCode::TOO_MANY_REQUESTS, "访客名额已满，请稍后再试").into_response(),
+        Err(share::ShareError::Full(false)) => return (StatusCode::TOO_MANY_REQUESTS, "该分享链接观看人数已满").into_response(),
+        Err(share::ShareError::Internal(e)) => return AppError(e).into_response(),
+    };
+    let name = match play_on_demand(&state, pass.stream.clone(), client.ip).await {
+        Ok(name) => name,
+        Err(e) => return AppError(e).into_response(),
+    };
+    let Some(cache) = state.stream_manager.gop_cache(&name) else {
+        return (StatusCode::SERVICE_UNAVAILABLE, "流未就绪，请稍后重试").into_response();
+    };
+    tracing::info!("访客 {} 通过分享链接观看流 '{}'", client.ip, name);
+    let manager = state.stream_manager.clone();
+    let body = http_flv::stream(&cache, move || {
+        manager.heartbeat(&name);
+        pass.valid()
+    });
+    (
+        [(header::CONTENT_TYPE, "video/x-flv"), (header::CACHE_CONTROL, "no-cache")],
+        body,
+    )
+        .into_response()
+}
+
+/// HLS 播放接口
+/// 提供运行中流的播放列表与分片；播放器定期刷新播放列表，每次刷新为流续期心跳
+async fn hls_file(State(state): State<AppState>, Path((name, file)): Path<(String, String)>) -> Result<Response, AppError> {
+    let config = state.config();
+    if !config.hls.enabled {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    }
+    let Some(content_type) = hls::content_type(&file) else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    let name = state.registry.canonical(&name);
+    if !state.stream_manager.is_running(&name) {
+        return Ok((StatusCode::NOT_FOUND, "流未运行，需要先调用 /api/play").into_response());
+    }
+    let path = hls::stream_dir(&config.hls.root(&config.data_dir), &name).join(&file);
+    let data = match tokio::fs::read(&path).await {
+        Ok(data) => data,
+        // 刚启动尚未生成播放列表，或分片已过期被删除
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(StatusCode::NOT_FOUND.into_response()),
+        Err(e) => return Err(e.into()),
+    };
+    // 分片内容不变，播放列表随时更新
+    let cache_control = if file == hls::PLAYLIST {
+        state.stream_manager.heartbeat(&name);
+        "no-cache"
+    } else {
+        "max-age=60"
+    };
+    Ok(([(header::CONTENT_TYPE, content_type), (header::CACHE_CONTROL, cache_control)], data).into_response())
+}
+
+/// 创建了登录用户时，未登录访问网页跳转到登录页（API 仍由 AuthToken 校验，HTTP-FLV 与 SRS 播放地址一样不校验，分享链接校验访客令牌）
+async fn require_login(State(state): State<AppState>, request: axum::extract::Request, next: Next) -> Response {
+    let path = request.uri().path();
+    if !state.users.is_enabled() || path.starts_with("/api/") || path.starts_with("/live/") || path.starts_with("/ws/live/") || path.starts_with("/hls/") || path.starts_with("/share/") || path == "/login.html" {
+        return next.run(request).await;
+    }
+    let logged_in = cookie_session(request.headers()).is_some_and(|token| state.users.session_user(token).is_some());
+    if logged_in {
+        return next.run(request).await;
+    }
+    axum::response::Redirect::to(&format!("{}/login.html", state.config().server.base_path())).into_response()
+}
+
+/// 访问不带结尾 "/" 的 URL 前缀时跳转到 `<前缀>/`，否则页面中的相对地址（`api/...`）会解析到前缀之外
+async fn redirect_base_path(State(base_path): State<String>, request: axum::extract::Request, next: Next) -> Response {
+    if request.uri().path() != base_path {
+        return next.run(request).await;
+    }
+    let location = match request.uri().query() {
+        Some(query) => format!("{}/?{}", base_path, query),
+        None => format!("{}/", base_path),
+    };
+    axum::response::Redirect::permanent(&location).into_response()
+}
+
+/// 生成会话 Cookie，`max_age` 为 0 时清除
+fn session_cookie(state: &AppState, client: &ClientInfo, token: &str, max_age: u64) -> String {
+    let base_path = state.config().server.base_path();
+    format!(
+        "{}={}; Path={}; Max-Age={}; HttpOnly; SameSite=Strict{}",
+        users::SESSION_COOKIE,
+        token,
+        if base_path.is_empty() { "/" } else { &base_path },
+        max_age,
+        if client.scheme == "https" { "; Secure" } else { "" }
+    )
+}
+
+fn login_error(e: LoginError) -> Response {
+    match e {
+        LoginError::Invalid => (StatusCode::UNAUTHORIZED, "用户名或密码错误").into_response(),
+        LoginError::Locked(secs) => {
+            (StatusCode::LOCKED, format!("登录失败次数过多，账号已锁定，请 {} 分钟后重试", secs.div_ceil(60))).into_response()
+        }
+        LoginError::Internal(e) => AppError(e).into_response(),
+    }
+}
+
+#[derive(Deserialize)]
+struct LoginRequest {
+    username: String,
+    password: String,
+}
+
+/// 登录接口，成功后下发会话 Cookie
+async fn login(State(state): State<AppState>, client: ClientInfo, Json(payload): Json<LoginRequest>) -> Response {
+    match state.users.login(&payload.username, &payload.password).await {
+        Ok(token) => {
+            let cookie = session_cookie(&state, &client, &token, state.users.session_ttl().as_secs());
+            ([(header::SET_COOKIE, cookie)], Json(serde_json::json!({ "username": payload.username }))).into_response()
+        }
+        Err(e) => login_error(e),
+    }
+}
+
+async fn logout(State(state): State<AppState>, client: ClientInfo, headers: HeaderMap) -> Response {
+    if let Some(token) = cookie_session(&headers) {
+        state.users.logout(token);
+    }
+    ([(header::SET_COOKIE, session_cookie(&state, &client, "", 0))], StatusCode::NO_CONTENT).into_response()
+}
+
+#[derive(Deserialize)]
+struct ChangePasswordRequest {
+    old_password: String,
+    new_password: String,
+}
+
+/// 修改当前登录用户的密码
+async fn change_password(
+    State(state): State<AppState>,
+    user: SessionUser,
+    Json(payload): Json<ChangePasswordRequest>,
+) -> Response {
+    if payload.new_password.chars().count() < users::MIN_PASSWORD_LEN {
+        return (StatusCode::BAD_REQUEST, format!("密码长度不能少于 {} 位", users::MIN_PASSWORD_LEN)).into_response();
+    }
+    match state
+        .users
+        .change_password(&user.username, &payload.old_password, &payload.new_password, &user.token)
+        .await
+    {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(LoginError::Invalid) => (StatusCode::FORBIDDEN, "原密码错误").into_response(),
+        Err(e) => login_error(e),
+    }
+}
+
+/// GB28181 已注册设备及通道
+async fn gb28181_devices(_: AuthToken) -> Response {
+    match gb28181::devices() {
+        Some(devices) => Json(devices).into_response(),
+        None => (StatusCode::NOT_FOUND, "未启用 GB28181 接入").into_response(),
+    }
+}
+
+#[derive(Deserialize)]
+struct DumpRequest {
+    #[serde(default)]
+    mode: dump::DumpMode,
+    /// 抓包时长（秒），最长 300 秒
+    #[serde(default = "default_dump_seconds")]
+    seconds: u64,
+}
+
+fn default_dump_seconds() -> u64 {
+    30
+}
+
+/// 重置重启次数接口
+/// 修复摄像头后重新启用自动重启，因重启次数用尽而停止的流立即重新启动
+async fn reset_failures(
+    State(state): State<AppState>,
+    _: AuthToken, // 验证 Token
+    Path(name): Path<String>,
+) -> Response {
+    let name = state.registry.canonical(&name);
+    if !state.stream_manager.reset_failures(&name) {
+        return (StatusCode::NOT_FOUND, "流未运行").into_response();
+    }
+    tracing::info!("流 '{}' 的重启次数已手动重置", name);
+    StatusCode::NO_CONTENT.into_response()
+}
+
+/// 开始调试抓包接口
+/// 将运行中流的原始输入或推流输出数据包写入文件，用于排查摄像头时间戳等问题
+async fn start_dump(
+    State(state): State<AppState>,
+    _: AuthToken, // 验证 Token
+    Path(name): Path<String>,
+    Json(payload): Json<DumpRequest>,
+) -> Response {
+    let name = state.registry.canonical(&name);
+    let Some(control) = state.stream_manager.dump_control(&name) else {
+        return (StatusCode::NOT_FOUND, "流未运行").into_response();
+    };
+
+    let path = std::path::PathBuf::from(&state.config().data_dir)
+        .join("dumps")
+        .join(format!("{}-{}.{}", file_stem(&name), history::now_secs(), payload.mode.extension()));
+
+    let duration = std::time::Duration::from_secs(payload.seconds.max(1));
+    if !control.request(payload.mode, path, duration) {
+        return (StatusCode::CONFLICT, "该流已有抓包任务进行中").into_response();
+    }
+    tracing::info!("流 '{}' 请求抓包 ({:?}, {} 秒)", name, payload.mode, payload.seconds);
+    (StatusCode::ACCEPTED, Json(control.status())).into_response()
+}
+
+/// 查询抓包状态接口
+async fn dump_status(
+    State(state): State<AppState>,
+    _: AuthToken, // 验证 Token
+    Path(name): Path<String>,
+) -> Response {
+    match state.stream_manager.dump_control(&state.registry.canonical(&name)) {
+        Some(control) => Json(control.status()).into_response(),
+        None => (StatusCode::NOT_FOUND, "流未运行").into_response(),
+    }
+}
+
+/// 下载抓包文件接口
+async fn download_dump(
+    State(state): State<AppState>,
+    _: AuthToken, // 验证 Token
+    Path(name): Path<String>,
+) -> Result<Response, AppError> {
+    let Some(control) = state.stream_manager.dump_control(&state.registry.canonical(&name)) else {
+        return Ok((StatusCode::NOT_FOUND, "流未运行").into_response());
+    };
+    let status = control.status();
+    let Some(path) = status.path.filter(|_| !status.active) else {
+        return Ok((StatusCode::CONFLICT, "抓包尚未完成").into_response());
+    };
+
+    let file = tokio::fs::File::open(&path).await?;
+    let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or("dump").to_string();
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/octet-stream".to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", file_name)),
+        ],
+        axum::body::Body::from_stream(tokio_util::io::ReaderStream::new(file)),
+    )
+        .into_response())
+}
+
+/// 查找运行中流的录制开关，流未运行或不支持录制时返回错误响应
+fn record_control(state: &AppState, name: &str) -> Result<Arc<recording::RecordControl>, (StatusCode, &'static str)> {
+    let name = state.registry.canonical(name);
+    match state.stream_manager.record_control(&name) {
+        Some(control) => Ok(control),
+        None if state.stream_manager.is_running(&name) => Err((StatusCode::BAD_REQUEST, "由 ffmpeg 子进程转封装的流不支持录制")),
+        None => Err((StatusCode::NOT_FOUND, "流未运行，需要先调用 /api/play")),
+    }
+}
+
+/// 查询录制状态接口
+async fn record_status(State(state): State<AppState>, _: AuthToken, Path(name): Path<String>) -> Response {
+    match record_control(&state, &name) {
+        Ok(control) => Json(control.status()).into_response(),
+        Err(rejection) => rejection.into_response(),
+    }
+}
+
+/// 开始录制接口
+/// 转码器把推流数据额外写入录制目录，按时长或大小切换文件；录制中的流不会因无观众停止
+async fn start_record(State(state): State<AppState>, _: AuthToken, Path(name): Path<String>) -> Response {
+    // 录制文件边写边落盘，无法加密；配置了录像加密的流不录制明文
+    if state.config().event_clips.encryption.as_ref().is_some_and(|e| e.applies(&state.registry.canonical(&name))) {
+        return (StatusCode::CONFLICT, "该流配置了录像加密，录制文件不支持加密，不能录制").into_response();
+    }
+    let control = match record_control(&state, &name) {
+        Ok(control) => control,
+        Err(rejection) => return rejection.into_response(),
+    };
+    if control.start() {
+        tracing::info!("流 '{}' 开始录制", name);
+    }
+    Json(control.status()).into_response()
+}
+
+/// 停止录制接口
+/// 当前文件写入文件尾后关闭，之后流按正常规则在无观众时停止
+async fn stop_record(State(state): State<AppState>, _: AuthToken, Path(name): Path<String>) -> Response {
+    let control = match record_control(&state, &name) {
+        Ok(control) => control,
+        Err(rejection) => return rejection.into_response(),
+    };
+    if control.stop() {
+        tracing::info!("流 '{}' 停止录制", name);
+    }
+    Json(control.status()).into_response()
+}
+
+#[derive(Deserialize)]
+struct RecordingsQuery {
+    /// 流名称，缺省为配置中的所有流（含子码流）
+    stream: Option<String>,
+    /// 时间范围（Unix 秒），与文件起止时间有重叠即匹配
+    from: Option<u64>,
+    to: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct RecordingEntry {
+    stream: String,
+    #[serde(flatten)]
+    file: recording::RecordingFile,
+    /// 正在写入
+    recording: bool,
+    /// 下载 / 播放地址
+    url: String,
+}
+
+/// 录制文件列表接口
+/// 按流与时间范围列出已保存的录制文件，按开始时间倒序返回
+async fn list_recordings(
+    State(state): State<AppState>,
+    _: AuthToken, // 验证 Token
+    Query(query): Query<RecordingsQuery>,
+) -> Result<Response, AppError> {
+    let streams: Vec<String> = match &query.stream {
+        Some(name) => vec![state.registry.canonical(name)],
+        None => state
+            .registry
+            .list()
+            .into_iter()
+            .flat_map(|s| {
+                let sub = s.sub_url.is_some().then(|| format!("{}_sub", s.name));
+                std::iter::once(s.name).chain(sub)
+            })
+            .collect(),
+    };
+    let manager = state.stream_manager.clone();
+    let base_path = state.config().server.base_path();
+    let mut entries = tokio::task::spawn_blocking(move || {
+        let mut entries = Vec::new();
+        for stream in streams {
+            let encoded = percent_encoding::utf8_percent_encode(&stream, percent_encoding::NON_ALPHANUMERIC).to_string();
+            for (file, recording) in manager.recordings(&stream) {
+                if query.from.is_some_and(|from| file.end < from) || query.to.is_some_and(|to| file.start > to) {
+                    continue;
+                }
+                let url = format!("{}/api/recordings/{}/{}", base_path, encoded, file.file);
+                entries.push(RecordingEntry { stream: stream.clone(), file, recording, url });
+            }
+        }
+        entries
+    })
+    .await?;
+    entries.sort_by_key(|e| std::cmp::Reverse(e.file.start));
+    Ok(Json(entries).into_response())
+}
+
+#[derive(Deserialize)]
+struct RecordingFileQuery {
+    /// 作为附件下载，缺省在浏览器中直接播放
+    #[serde(default)]
+    download: bool,
+}
+
+/// 录制文件下载 / 播放接口
+/// 支持 Range 请求，播放器可以拖动进度；正在写入的文件返回请求时已写入的部分
+async fn recording_file(
+    State(state): State<AppState>,
+    _: AuthToken, // 验证 Token
+    Path((name, file)): Path<(String, String)>,
+    Query(query): Query<RecordingFileQuery>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    // 只接受录制文件名，防止读取目录外的文件
+    if recording::parse_file_name(&file).is_none() {
+        return Ok((StatusCode::NOT_FOUND, "未找到录制文件").into_response());
+    }
+    let path = state.stream_manager.recording_dir(&state.registry.canonical(&name)).join(&file);
+    let mut reader = match tokio::fs::File::open(&path).await {
+        Ok(reader) => reader,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Ok((StatusCode::NOT_FOUND, "未找到录制文件").into_response());
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let len = reader.metadata().await?.len();
+    let (status, start, count) = match byte_range(&headers, len) {
+        Ok(Some((start, end))) => (StatusCode::PARTIAL_CONTENT, start, end - start + 1),
+        Ok(None) => (StatusCode::OK, 0, len),
+        Err(status) => return Ok((status, [(header::CONTENT_RANGE, format!("bytes */{}", len))]).into_response()),
+    };
+    reader.seek(std::io::SeekFrom::Start(start)).await?;
+
+    let content_type = if file.ends_with(".mkv") { "video/x-matroska" } else { "video/mp4" };
+    let disposition = if query.download { "attachment" } else { "inline" };
+    let mut response = (
+        status,
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (header::CONTENT_LENGTH, count.to_string()),
+            (header::ACCEPT_RANGES, "bytes".to_string()),
+            (header::CONTENT_DISPOSITION, format!("{}; filename=\"{}\"", disposition, file)),
+        ],
+        axum::body::Body::from_stream(tokio_util::io::ReaderStream::new(reader.take(count))),
+    )
+        .into_response();
+    if status == StatusCode::PARTIAL_CONTENT {
+        let range = format!("bytes {}-{}/{}", start, start + count - 1, len);
+        response.headers_mut().insert(header::CONTENT_RANGE, HeaderValue::from_str(&range)?);
+    }
+    Ok(response)
+}
+
+/// 解析单个区间的 `Range: bytes=...` 请求头，返回闭区间 (开始, 结束)
+///
+/// 没有请求头或格式不支持（如多个区间）时返回 None，按完整文件响应；区间超出文件大小时返回 416。
+fn byte_range(headers: &HeaderMap, len: u64) -> Result<Option<(u64, u64)>, StatusCode> {
+    let Some(spec) = headers.get(header::RANGE).and_then(|v| v.to_str().ok()).and_then(|v| v.strip_prefix("bytes=")) else {
+        return Ok(None);
+    };
+    let Some((start, end)) = spec.split_once('-').filter(|_| !spec.contains(',')) else {
+        return Ok(None);
+    };
+    let (start, end) = (start.trim(), end.trim());
+    let range = if start.is_empty() {
+        // 最后 N 个字节
+        let Ok(suffix) = end.parse::<u64>() else {
+            return Ok(None);
+        };
+        (len.saturating_sub(suffix), len.saturating_sub(1))
+    } else {
+        let Ok(start) = start.parse::<u64>() else {
+            return Ok(None);
+        };
+        let end = match end {
+            "" => u64::MAX,
+            end => match end.parse::<u64>() {
+                Ok(end) if end >= start => end,
+                _ => return Ok(None),
+            },
+        };
+        (start, end.min(len.saturating_sub(1)))
+    };
+    if range.0 >= len {
+        return Err(StatusCode::RANGE_NOT_SATISFIABLE);
+    }
+    Ok(Some(range))
+}
+
+#[derive(Deserialize)]
+struct TraceRequest {
+    /// 跟踪时长（秒），最长 600 秒
+    #[serde(default = "default_dump_seconds")]
+    seconds: u64,
+}
+
+/// 开始数据包跟踪接口
+/// 在限定时长内把单个流每个数据包的时间戳（修正前后）、大小与关键帧标志写入独立文件，
+/// 排查单个摄像头的时间戳问题时无需把全局日志级别调到 trace
+async fn start_trace(
+    State(state): State<AppState>,
+    _: AuthToken, // 验证 Token
+    Path(name): Path<String>,
+    Json(payload): Json<TraceRequest>,
+) -> Result<Response, AppError> {
+    let name = state.registry.canonical(&name);
+    let Some(control) = state.stream_manager.trace_control(&name) else {
+        return Ok((StatusCode::NOT_FOUND, "流未运行").into_response());
+    };
+
+    let path = std::path::PathBuf::from(&state.config().data_dir)
+        .join("traces")
+        .join(format!("{}-{}.log", file_stem(&name), history::now_secs()));
+    let duration = std::time::Duration::from_secs(payload.seconds.max(1));
+    if !control.start(&name, path, duration)? {
+        return Ok((StatusCode::CONFLICT, "该流已有跟踪进行中").into_response());
+    }
+    Ok((StatusCode::ACCEPTED, Json(control.status())).into_response())
+}
+
+/// 查询数据包跟踪状态接口
+async fn trace_status(
+    State(state): State<AppState>,
+    _: AuthToken, // 验证 Token
+    Path(name): Path<String>,
+) -> Response {
+    match state.stream_manager.trace_control(&state.registry.canonical(&name)) {
+        Some(control) => Json(control.status()).into_response(),
+        None => (StatusCode::NOT_FOUND, "流未运行").into_response(),
+    }
+}
+
+/// 提前结束数据包跟踪接口
+async fn stop_trace(
+    State(state): State<AppState>,
+    _: AuthToken, // 验证 Token
+    Path(name): Path<String>,
+) -> Response {
+    let Some(control) = state.stream_manager.trace_control(&state.registry.canonical(&name)) else {
+        return (StatusCode::NOT_FOUND, "流未运行").into_response();
+    };
+    if !control.stop() {
+        return (StatusCode::CONFLICT, "没有进行中的跟踪").into_response();
+    }
+    Json(control.status()).into_response()
+}
+
+/// 下载数据包跟踪文件接口
+async fn download_trace(
+    State(state): State<AppState>,
+    _: AuthToken, // 验证 Token
+    Path(name): Path<String>,
+) -> Result<Response, AppError> {
+    let Some(control) = state.stream_manager.trace_control(&state.registry.canonical(&name)) else {
+        return Ok((StatusCode::NOT_FOUND, "流未运行").into_response());
+    };
+    let status = control.status();
+    let Some(path) = status.path.filter(|_| !status.active) else {
+        return Ok((StatusCode::CONFLICT, "跟踪尚未完成").into_response());
+    };
+
+    let file = tokio::fs::File::open(&path).await?;
+    let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or("trace.log").to_string();
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/plain; charset=utf-8".to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", file_name)),
+        ],
+        axum::body::Body::from_stream(tokio_util::io::ReaderStream::new(file)),
+    )
+        .into_response())
+}
+
+#[derive(Deserialize, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum ClipMode {
+    /// 最近 N 秒，立即返回
+    #[default]
+    Last,
+    /// 接下来的 N 秒，录制完成后返回
+    Next,
+}
+
+#[derive(Deserialize)]
+struct ClipQuery {
+    /// 片段时长，如 `30s`、`2m`，缺省单位为秒
+    duration: Option<String>,
+    #[serde(default)]
+    mode: ClipMode,
+}
+
+/// 解析 `30s` / `2m` / `30` 形式的时长
+fn parse_clip_duration(value: &str) -> Option<std::time::Duration> {
+    let value = value.trim();
+    let (number, unit) = match value.strip_suffix('m') {
+        Some(n) => (n, 60),
+        None => (value.strip_suffix('s').unwrap_or(value), 1),
+    };
+    number.trim().parse::<u64>().ok().filter(|n| *n > 0).map(|n| std::time::Duration::from_secs(n * unit))
+}
+
+/// 片段下载接口
+/// 从运行中流的内存缓冲截取最近（或等待接下来）N 秒，封装为 MP4 下载
+async fn download_clip(
+    State(state): State<AppState>,
+    _: AuthToken, // 验证 Token
+    Path(name): Path<String>,
+    Query(query): Query<ClipQuery>,
+) -> Result<Response, AppError> {
+    let name = state.registry.canonical(&name);
+    let Some(buffer) = state.stream_manager.clip_buffer(&name) else {
+        return Ok((StatusCode::NOT_FOUND, "流未运行或未启用片段缓冲").into_response());
+    };
+    let duration = match query.duration.as_deref() {
+        None => std::time::Duration::from_secs(30),
+        Some(value) => match parse_clip_duration(value) {
+            Some(d) => d,
+            None => return Ok((StatusCode::BAD_REQUEST, format!("无效的时长: {}", value)).into_response()),
+        },
+    };
+    if duration > buffer.max_duration() {
+        return Ok((
+            StatusCode::BAD_REQUEST,
+            format!("片段时长不能超过 {} 秒", buffer.max_duration().as_secs()),
+        )
+            .into_response());
+    }
+
+    let since = match query.mode {
+        ClipMode::Last => std::time::Instant::now().checked_sub(duration).unwrap_or_else(std::time::Instant::now),
+        ClipMode::Next => {
+            let mark = std::time::Instant::now();
+            tokio::time::sleep(duration).await;
+            mark
+        }
+    };
+    let Some(clip) = buffer.clip(since) else {
+        return Ok((StatusCode::SERVICE_UNAVAILABLE, "尚未缓冲到关键帧，请稍后重试").into_response());
+    };
+
+    let file_name = format!("{}-{}.mp4", file_stem(&name), history::now_secs());
+    let path = std::path::PathBuf::from(&state.config().data_dir).join("clips").join(&file_name);
+    let data = tokio::task::spawn_blocking(move || {
+        let result = clip.write_mp4(&path).and_then(|_| Ok(std::fs::read(&path)?));
+        // 临时文件读入内存后即删除，片段大小受缓冲上限约束
+        let _ = std::fs::remove_file(&path);
+        result
+    })
+    .await??;
+    tracing::info!("流 '{}' 下载片段 ({} 秒, {} 字节)", name, duration.as_secs(), data.len());
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "video/mp4".to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", file_name)),
+        ],
+        data,
+    )
+        .into_response())
+}
+
+#[derive(Deserialize)]
+struct WallQuery {
+    /// 逗号分隔的流名称，按顺序从左到右、从上到下排列
+    streams: String,
+    /// 列数，缺省为接近正方形的列数
+    cols: Option<usize>,
+}
+
+/// 预览墙接口
+/// 把各流最近一次的缩略图拼接为一张 JPEG，供性能较弱的显示屏轮询单张图片，
+/// 代替同时运行多个视频播放器；没有缩略图的流显示为黑色，并在 `X-Wall-Missing` 中列出
+async fn preview_wall(
+    State(state): State<AppState>,
+    _: AuthToken, // 验证 Token
+    Query(query): Query<WallQuery>,
+) -> Result<Response, AppError> {
+    let names: Vec<String> = query
+        .streams
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(|name| state.registry.canonical(name))
+        .collect();
+    if names.is_empty() {
+        return Ok((StatusCode::BAD_REQUEST, "streams 不能为空").into_response());
+    }
+    if names.len() > preview::MAX_WALL_STREAMS {
+        return Ok((StatusCode::BAD_REQUEST, format!("最多拼接 {} 个流", preview::MAX_WALL_STREAMS)).into_response());
+    }
+    if !state.config().preview.enabled {
+        return Ok((StatusCode::NOT_FOUND, "未启用预览缩略图").into_response());
+    }
+
+    let tiles: Vec<_> = names.iter().map(|name| state.stream_manager.preview(name)).collect();
+    let missing: Vec<&str> = names.iter().zip(&tiles).filter(|(_, tile)| tile.is_none()).map(|(name, _)| name.as_str()).collect();
+    let missing = missing.join(",");
+    let cols = query.cols.unwrap_or_else(|| (names.len() as f64).sqrt().ceil() as usize);
+    let config = state.config().preview.clone();
+    let data = tokio::task::spawn_blocking(move || preview::mosaic(&tiles, cols, &config)).await??;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "image/jpeg".to_string()),
+            (header::CACHE_CONTROL, "no-store".to_string()),
+            (HeaderName::from_static("x-wall-missing"), missing),
+        ],
+        data,
+    )
+        .into_response())
+}
+
+#[derive(Deserialize)]
+struct SnapshotQuery {
+    /// 图片宽度（像素），高度按比例缩放，缺省为原始分辨率
+    width: Option<u32>,
+}
+
+/// 截图接口
+/// 从运行中的转码任务取下一个关键帧，流未运行时临时连接输入源截取一帧，返回 JPEG；
+/// 供仪表盘显示摄像头缩略图，不必启动播放器
+async fn stream_snapshot(
+    State(state): State<AppState>,
+    access: StreamAccess, // 验证 Token
+    Path(name): Path<String>,
+    Query(query): Query<SnapshotQuery>,
+) -> Result<Response, AppError> {
+    access.check(&state, &name)?;
+    let name = state.registry.canonical(&name);
+    let quality = state.config().preview.quality;
+
+    let frame = match state.stream_manager.snapshot_requests(&name) {
+        Some(requests) => match tokio::time::timeout(snapshot::SNAPSHOT_TIMEOUT, requests.request()).await {
+            Ok(Ok(Ok(frame))) => frame,
+            Ok(Ok(Err(e))) => return Ok((StatusCode::BAD_GATEWAY, e).into_response()),
+            // 转码线程退出时请求被丢弃
+            Ok(Err(_)) => return Ok((StatusCode::SERVICE_UNAVAILABLE, "流已停止").into_response()),
+            Err(_) => {
+                return Ok((
+                    StatusCode::GATEWAY_TIMEOUT,
+                    format!("{} 秒内未收到关键帧", snapshot::SNAPSHOT_TIMEOUT.as_secs()),
+                )
+                    .into_response());
+            }
+        },
+        None => {
+            let (_, urls, input, _) = match resolve_configured(&state, &name, StreamProfile::Main) {
+                Ok(resolved) => resolved,
+                Err(e) => return Ok((StatusCode::NOT_FOUND, e.to_string()).into_response()),
+            };
+            let Some(url) = urls.into_iter().next() else {
+                return Ok((StatusCode::NOT_FOUND, "流没有配置拉流地址").into_response());
+            };
+            match tokio::task::spawn_blocking(move || snapshot::capture(&url, &input)).await? {
+                Some(Ok(frame)) => frame,
+                Some(Err(e)) => return Ok((StatusCode::BAD_GATEWAY, format!("截图失败: {}", e)).into_response()),
+                None => return Ok((StatusCode::TOO_MANY_REQUESTS, "截图请求过多，请稍后重试").into_response()),
+            }
+        }
+    };
+    let data = tokio::task::spawn_blocking(move || snapshot::encode(&frame, query.width, quality)).await??;
+
+    Ok((
+        [(header::CONTENT_TYPE, "image/jpeg"), (header::CACHE_CONTROL, "no-store")],
+        data,
+    )
+        .into_response())
+}
+
+/// 缩略图接口
+/// 返回定时缩略图任务为该流生成的最新一张 JPEG，流停止后返回停止前的最后一张
+async fn stream_thumbnail(
+    State(state): State<AppState>,
+    access: StreamAccess, // 验证 Token
+    Path(name): Path<String>,
+) -> Result<Response, AppError> {
+    access.check(&state, &name)?;
+    let name = state.registry.canonical(&name);
+    let config = state.config().thumbnails.clone();
+    if !config.enabled {
+        return Ok((StatusCode::NOT_FOUND, "未启用定时缩略图").into_response());
+    }
+    let path = thumbnail::path(&config.root(&state.config().data_dir), &name);
+    let (data, modified) = match tokio::fs::read(&path).await {
+        Ok(data) => (data, tokio::fs::metadata(&path).await?.modified()?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Ok((StatusCode::NOT_FOUND, "该流尚无缩略图").into_response());
+        }
+        Err(e) => return Err(anyhow::anyhow!("读取缩略图失败: {}", e).into()),
+    };
+    let captured_at = modified.duration_since(std::time::UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "image/jpeg".to_string()),
+            (header::CACHE_CONTROL, "no-cache".to_string()),
+            (HeaderName::from_static("x-captured-at"), captured_at.to_string()),
+        ],
+        data,
+    )
+        .into_response())
+}
+
+#[derive(Deserialize, Hash)]
+struct PlayRequest {
+    name: String,
+    url: Option<String>,
+    /// 码流类型：main（默认，全屏）或 sub（子码流，宫格预览）
+    #[serde(default)]
+    profile: StreamProfile,
+    /// 只校验并返回将要执行的操作，不调用 SRS、不启动转码
+    #[serde(default)]
+    dry_run: bool,
+    /// dry_run 时是否探测源是否可连接
+    #[serde(default)]
+    probe: bool,
+    /// 自定义推流地址，覆盖流配置中的 output.url，必须在 srs.output_allowlist 中
+    #[serde(default)]
+    output_url: Option<String>,
+    /// 临时覆盖项，只作用于本次启动的转码任务，字段须在 play_overrides 中
+    #[serde(default)]
+    overrides: Option<PlayOverrides>,
+    /// 播放设备名称，记录在观看会话中
+    #[serde(default)]
+    device: Option<String>,
+}
+
+#[derive(Serialize)]
+struct PlayResponse {
+    playback_url: String,
+    /// 实际运行的流名称（子码流为 `{name}_sub`），心跳需使用该名称
+    stream: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dry_run: Option<DryRunReport>,
+    /// 准入控制的决定，未启用或流已在运行时为空
+    #[serde(skip_serializing_if = "Option::is_none")]
+    admission: Option<admission::Decision>,
+    /// 开启播放地址确认时，流是否已在 SRS 上可用
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ready: Option<bool>,
+    /// 观看会话，心跳与停止时携带，可转移到其他设备；dry_run 时为空
+    #[serde(skip_serializing_if = "Option::is_none")]
+    session: Option<String>,
+    /// HLS 播放地址（相对地址），开启 `hls` 时返回，供无法播放 FLV 的客户端使用
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hls_url: Option<String>,
+}
+
+/// dry_run 报告
+#[derive(Serialize)]
+struct DryRunReport {
+    /// 拉流地址（主源在前，密码已隐藏）
+    input_urls: Vec<String>,
+    output_url: String,
+    /// 流是否已在运行（实际请求时只会续期）
+    running: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    probe: Option<ProbeResult>,
+}
+
+#[derive(Serialize)]
+struct ProbeResult {
+    ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// 源探测超时时间
+const PROBE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(15);
+
+/// 探测源是否可连接
+async fn probe_source(url: String, input_options: InputOptions) -> ProbeResult {
+    let error = audit::probe(url, input_options, PROBE_TIMEOUT).await;
+    ProbeResult { ok: error.is_none(), error }
+}
+
+#[derive(Deserialize)]
+struct ProbeRequest {
+    /// 拉流地址，与 `name` 二选一
+    #[serde(default)]
+    url: Option<String>,
+    /// 配置中的流名称，使用其主源地址、凭据与输入输出参数
+    #[serde(default)]
+    name: Option<String>,
+    /// 输入参数（传输方式等），只用于 `url`
+    #[serde(default)]
+    input: InputOptions,
+}
+
+#[derive(Serialize)]
+struct ProbeResponse {
+    /// 探测的地址（密码已隐藏）
+    url: String,
+    #[serde(flatten)]
+    report: probe::ProbeReport,
+}
+
+/// 源预检接口
+/// 打开拉流地址读取编码、分辨率、帧率与音频声道，判断能否直接转封装为 FLV，不推流
+async fn probe_stream(
+    State(state): State<AppState>,
+    access: StreamAccess, // 验证 Token
+    Json(payload): Json<ProbeRequest>,
+) -> Result<Response, AppError> {
+    let (url, input, output) = match (payload.url.filter(|u| !u.is_empty()), payload.name) {
+        (Some(url), _) => {
+            if access.scope.is_some() {
+                return Err(Forbidden("该 API Key 不能探测自定义地址".to_string()).into());
+            }
+            if !transcoder::is_supported_input(&url) {
+                return Ok((StatusCode::BAD_REQUEST, format!("不支持的流地址: {}", url)).into_response());
+            }
+            if let Some(error) = payload.input.errors().into_iter().next() {
+                return Ok((StatusCode::BAD_REQUEST, error).into_response());
+            }
+            let config = state.config();
+            let input = payload.input.with_global_proxy(config.proxy.as_deref());
+            (url, input, OutputOptions::default().with_global_encoder(&config.encoder))
+        }
+        (None, Some(name)) => {
+            access.check(&state, &name)?;
+            match resolve_configured(&state, &state.registry.canonical(&name), StreamProfile::Main) {
+                Ok((_, urls, input, output)) => (urls[0].clone(), input, output),
+                Err(e) => return Ok((StatusCode::NOT_FOUND, e.to_string()).into_response()),
+            }
+        }
+        (None, None) => return Ok((StatusCode::BAD_REQUEST, "需要 url 或 name").into_response()),
+    };
+
+    let redacted = vault::redact_url(&url);
+    match probe::run(url, input, output).await {
+        Ok(report) => Ok(Json(ProbeResponse { url: redacted, report }).into_response()),
+        Err(e) => {
+            tracing::info!("探测 {} 失败: {:#}", redacted, e);
+            Ok((StatusCode::BAD_GATEWAY, format!("{:#}", e)).into_response())
+        }
+    }
+}
+
+/// 所有配置的流（主码流主源）的巡检目标
+fn audit_targets(state: &AppState) -> Vec<audit::AuditTarget> {
+    state
+        .registry
+        .list()
+        .into_iter()
+        .map(|stream| audit::AuditTarget {
+            source: resolve_configured(state, &stream.name, StreamProfile::Main)
+                .map(|(_, urls, input, _)| (urls[0].clone(), input))
+                .map_err(|e| e.to_string()),
+            name: stream.name,
+        })
+        .collect()
+}
+
+/// 开始连通性巡检接口
+async fn start_audit(State(state): State<AppState>, _: AuthToken) -> Response {
+    if !state.auditor.start(audit_targets(&state), &state.config().audit) {
+        return (StatusCode::CONFLICT, "巡检正在进行中").into_response();
+    }
+    (StatusCode::ACCEPTED, Json(state.auditor.report())).into_response()
+}
+
+/// 查询巡检报告接口
+async fn audit_report(State(state): State<AppState>, _: AuthToken) -> Response {
+    match state.auditor.report() {
+        Some(report) => Json(report).into_response(),
+        None => (StatusCode::NOT_FOUND, "尚未进行巡检").into_response(),
+    }
+}
+
+/// 从流注册表查找流，并按码流类型生成转码任务名称与拉流地址
+fn resolve_configured(state: &AppState, name: &str, profile: StreamProfile) -> anyhow::Result<(String, Vec<String>, InputOptions, OutputOptions)> {
+    let stream_config = state.registry.get(name)
+        .ok_or_else(|| anyhow::anyhow!("未找到名称为 '{}' 的流配置", name))?;
+    let (stream_name, urls) = stream_config.select(profile);
+    let urls = urls
+        .into_iter()
+        .map(|url| state.vault.resolve_url(&stream_config, url))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    if let Some(error) = stream_config.input.errors().into_iter().next() {
+        anyhow::bail!("流 '{}' 的输入参数无效: {}", name, error);
+    }
+    let input = stream_config.input.with_global_proxy(state.config().proxy.as_deref());
+    let output = stream_config.output.with_global_encoder(&state.config().encoder);
+    Ok((stream_name, urls, input, output))
+}
+
+/// 播放流接口
+/// 接收流名称或自定义 URL，调用 SRS 接口，返回播放地址
+///
+/// 携带 `Idempotency-Key` 请求头时，同一个键的重试请求直接返回第一次的结果
+async fn play_stream(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    access: StreamAccess, // 验证 Token
+    headers: HeaderMap,
+    Json(payload): Json<PlayRequest>,
+) -> Result<Response, AppError> {
+    use std::hash::{DefaultHasher, Hash, Hasher};
+
+    access.check(&state, &payload.name)?;
+    if access.scope.is_some() && payload.url.as_deref().is_some_and(|u| !u.is_empty()) {
+        return Err(Forbidden("该 API Key 不能播放自定义地址".to_string()).into());
+    }
+
+    let Some(key) = headers.get("Idempotency-Key") else {
+        return Ok(Json(play_with_session(&state, &client, &access, payload).await?).into_response());
+    };
+    let key = match key.to_str() {
+        Ok(k) if !k.is_empty() && k.len() <= idempotency::MAX_KEY_LEN => k,
+        _ => return Ok((StatusCode::BAD_REQUEST, "无效的 Idempotency-Key").into_response()),
+    };
+
+    // 按调用方 Token 隔离幂等键，请求体指纹用于识别键被误用于不同请求
+    let mut hasher = DefaultHasher::new();
+    headers.get(header::AUTHORIZATION).map(|v| v.as_bytes()).hash(&mut hasher);
+    let cache_key = format!("{:016x}:{}", hasher.finish(), key);
+    let mut hasher = DefaultHasher::new();
+    payload.hash(&mut hasher);
+    let fingerprint = hasher.finish();
+
+    let cell = match state.idempotency.lookup(&cache_key, fingerprint) {
+        idempotency::Lookup::Cell(cell) => cell,
+        idempotency::Lookup::Mismatch => {
+            return Ok((StatusCode::UNPROCESSABLE_ENTITY, "Idempotency-Key 已用于内容不同的请求").into_response());
+        }
+    };
+    let (body, replayed) = idempotency::run(&cell, || async {
+        let response = play_with_session(&state, &client, &access, payload).await?;
+        Ok::<_, AppError>(bytes::Bytes::from(serde_json::to_vec(&response)?))
+    })
+    .await?;
+
+    if replayed {
+        tracing::info!("重放幂等请求: {}", key);
+    }
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/json"),
+            (header::HeaderName::from_static("idempotent-replayed"), if replayed { "true" } else { "false" }),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+/// 流在 SRS 中的推流路径，子码流沿用主码流配置中的租户、分组与路径模板
+fn srs_stream_path(state: &AppState, name: &str) -> anyhow::Result<srs::StreamPath> {
+    let stream = state
+        .registry
+        .get(name)
+        .or_else(|| name.strip_suffix("_sub").and_then(|base| state.registry.get(base)));
+    state.srs().stream_path(name, stream.as_ref())
+}
+
+/// 推流到 SRS 的地址：从配置的 API URL 中提取主机名（IPv6 地址带方括号），默认端口 1935
+fn srs_output_url(state: &AppState, path: &srs::StreamPath) -> anyhow::Result<String> {
+    let api_url = reqwest::Url::parse(&state.config().srs.api_url)
+        .map_err(|e| anyhow::anyhow!("配置的 SRS API URL 无效: {}", e))?;
+    let host = api_url.host().ok_or(anyhow::anyhow!("配置的 SRS API URL 缺少主机名"))?;
+    Ok(format!("rtmp://{}:1935/{}", host, path))
+}
+
+/// 配置中流的运行策略
+fn stream_policy(state: &AppState, name: &str, profile: StreamProfile) -> anyhow::Result<StreamPolicy> {
+    let Some(stream) = state.registry.get(name) else {
+        return Ok(StreamPolicy::default());
+    };
+    let sub_fallback = match (&stream.sub_url, profile) {
+        (Some(sub_url), StreamProfile::Main) if stream.sub_fallback => Some(state.vault.resolve_url(&stream, sub_url)?),
+        _ => None,
+    };
+    Ok(StreamPolicy { protected_hours: stream.protected_hours, sub_fallback, ..Default::default() })
+}
+
+/// 启动阶段等待 SRS 可用期间，播放请求在此排队
+async fn wait_srs_ready(state: &AppState) {
+    let mut ready = state.srs_ready.clone();
+    if !*ready.borrow() {
+        tracing::info!("SRS 尚未就绪，播放请求等待中");
+        let _ = ready.wait_for(|ready| *ready).await;
+    }
+}
+
+/// 按需启动流（RTSP 转发服务、访客分享链接），已在运行的流（包括 `{name}_sub`）直接返回
+async fn play_on_demand(state: &AppState, name: String, ip: IpAddr) -> anyhow::Result<String> {
+    let canonical = state.registry.canonical(&name);
+    if state.stream_manager.is_running(&canonical) {
+        return Ok(canonical);
+    }
+    let client = ClientInfo { ip, scheme: "http".to_string() };
+    let request = PlayRequest {
+        name,
+        url: None,
+        profile: StreamProfile::Main,
+        dry_run: false,
+        probe: false,
+        output_url: None,
+        overrides: None,
+        device: None,
+    };
+    play(state, &client, request).await.map(|played| played.stream).map_err(|e| e.0)
+}
+
+/// 执行播放接口的请求，并为播放端签发观看会话
+async fn play_with_session(state: &AppState, client: &ClientInfo, access: &StreamAccess, mut payload: PlayRequest) -> Result<PlayResponse, AppError> {
+    let device = payload.device.take().filter(|d| !d.is_empty());
+    if device.as_ref().is_some_and(|d| d.len() > sessions::MAX_DEVICE_LEN) {
+        return Err(anyhow::anyhow!("device 不能超过 {} 个字符", sessions::MAX_DEVICE_LEN).into());
+    }
+    let mut response = play(state, client, payload).await?;
+    if response.dry_run.is_none() {
+        response.session = Some(state.sessions.open(&response.stream, device)?);
+        access.subscribe(state, &response.stream);
+    }
+    Ok(response)
+}
+
+/// 执行播放请求
+async fn play(state: &AppState, client: &ClientInfo, payload: PlayRequest) -> Result<PlayResponse, AppError> {
+    play_with_options(state, client, payload, None).await
+}
+
+/// 执行播放请求，`options` 为平滑升级时旧进程交接的输入输出参数，替代按配置解析的参数
+async fn play_with_options(
+    state: &AppState,
+    client: &ClientInfo,
+    payload: PlayRequest,
+    options: Option<(InputOptions, OutputOptions)>,
+) -> Result<PlayResponse, AppError> {
+    let (name, rtsp_urls, mut input_options, mut output_options) = if let Some(custom_url) = &payload.url {
+        if !custom_url.is_empty() {
+             // 1. 如果提供了 URL，直接使用（自定义播放模式）
+            if !transcoder::is_supported_input(custom_url) {
+                 return Err(anyhow::anyhow!("自定义地址必须以 {} 开头", transcoder::SUPPORTED_SCHEMES.join(" / ")).into());
+            }
+            let input = InputOptions::default().with_global_proxy(state.config().proxy.as_deref());
+            let output = OutputOptions::default().with_global_encoder(&state.config().encoder);
+            (state.registry.canonical(&payload.name), vec![custom_url.clone()], input, output)
+        } else {
+             // URL 字段存在但为空字符串，视为查找配置
+            resolve_configured(state, &payload.name, payload.profile)?
+        }
+    } else {
+        // 2. 如果没有提供 URL，从配置中查找
+        resolve_configured(state, &payload.name, payload.profile)?
+    };
+    if let Some(overrides) = &payload.overrides {
+        overrides.apply(&state.config().play_overrides, &mut input_options, &mut output_options)?;
+        if state.stream_manager.is_running(&name) {
+            tracing::warn!("流 '{}' 已在运行，本次请求的覆盖项不生效", name);
+        }
+    }
+    if let Some((input, output)) = options {
+        (input_options, output_options) = (input, output);
+    }
+
+    // 构造推流地址 (RTMP)
+    // 请求或流配置指定了自定义地址时使用该地址，否则从配置的 API URL 中提取主机名，默认端口 1935
+    let custom_output = payload
+        .output_url
+        .as_deref()
+        .filter(|u| !u.is_empty())
+        .or(output_options.url.as_deref())
+        .map(str::to_string);
+    let (rtmp_url, srs_path) = match &custom_output {
+        Some(url) => {
+            state.config().srs.check_output_url(url)?;
+            (url.clone(), None)
+        }
+        // 独立模式不使用 SRS，观众直接从本服务拉取 FLV
+        None if state.config().http_flv.standalone() => (http_flv::LOCAL_OUTPUT.to_string(), None),
+        None => {
+            let path = srs_stream_path(state, &name)?;
+            (srs_output_url(state, &path)?, Some(path))
+        }
+    };
+
+    // 准入控制：已在运行的流不会增加负载
+    let decision = if state.stream_manager.is_running(&name) {
+        None
+    } else {
+        let work = admission::Work {
+            profile: payload.profile,
+            analyze: state.config().content_check.enabled || state.config().preview.enabled,
+            transcode: rtsp_urls[0].to_lowercase().starts_with(transcoder::TEST_SOURCE_SCHEME),
+        };
+        state.config().admission.evaluate(&work, state.stream_manager.committed_cost())
+    };
+
+    if payload.dry_run {
+        for url in &rtsp_urls {
+            srs::SrsClient::validate_url(url)?;
+        }
+        let probe = if payload.probe {
+            Some(probe_source(rtsp_urls[0].clone(), input_options).await)
+        } else {
+            None
+        };
+        let playback_url = match &srs_path {
+            Some(path) => state.srs().playback_url(path, Some(client.ip), &client.scheme),
+            None if rtmp_url == http_flv::LOCAL_OUTPUT => http_flv::playback_path(&state.config().server.base_path(), &name),
+            None => String::new(),
+        };
+        let report = DryRunReport {
+            input_urls: rtsp_urls.iter().map(|u| vault::redact_url(u)).collect(),
+            output_url: rtmp_url,
+            running: state.stream_manager.is_running(&name),
+            probe,
+        };
+        let hls_url = hls_url(state, &name);
+        return Ok(PlayResponse { playback_url, stream: name, dry_run: Some(report), admission: decision, ready: None, session: None, hls_url });
+    }
+    if let Some(decision) = decision.as_ref().filter(|d| d.action == admission::Action::Reject) {
+        tracing::warn!("节点负载过高（{} / {}），拒绝播放流 '{}'", decision.load, decision.capacity, name);
+        return Err(admission::Rejected(decision.clone()).into());
+    }
+
+    // 1. 获取 SRS 播放地址 (用于返回给前端)
+    // 注意：这里我们仍然调用 srs.play_stream 主要是为了利用它的 URL 生成逻辑
+    // 实际上 SRS 的 API 调用可能是不必要的，但保留也没坏处
+    tracing::info!("客户端 {} 请求播放流 '{}'", client.ip, name);
+    // 推到自定义地址时播放地址由目标服务决定，不调用本地 SRS
+    let mut srs_error = None;
+    let playback_url = match &srs_path {
+        Some(path) => {
+            wait_srs_ready(state).await;
+            match state.srs().play_stream(&name, path, &rtsp_urls[0], Some(client.ip), &client.scheme).await {
+                Ok(url) => url,
+                // strict 时播放请求失败（502），degraded 时继续播放并标记流状态
+                Err(e) => match e.downcast::<srs::ApiError>() {
+                    Ok(e) if state.config().srs.error_policy == SrsErrorPolicy::Degraded => {
+                        tracing::warn!("SRS 接口调用失败，继续播放流 '{}': {}", name, e);
+                        srs_error = Some(e.0);
+                        state.srs().playback_url(path, Some(client.ip), &client.scheme)
+                    }
+                    Ok(e) => return Err(e.into()),
+                    Err(e) => return Err(e.into()),
+                },
+            }
+        }
+        None => {
+            srs::SrsClient::validate_url(&rtsp_urls[0])?;
+            if rtmp_url == http_flv::LOCAL_OUTPUT {
+                http_flv::playback_path(&state.config().server.base_path(), &name)
+            } else {
+                String::new()
+            }
+        }
+    };
+
+    // 2. 启动转码任务
+    // 这里我们启动本地的 FFmpeg 转码任务，将 RTSP 流推送到 SRS
+    // SRS 接收 RTMP 推流后，会分发 HTTP-FLV 供前端播放
+    // 保护时段、子码流降级只对配置中的流生效
+    let mut policy = match payload.url.as_deref() {
+        Some(url) if !url.is_empty() => StreamPolicy::default(),
+        _ => stream_policy(state, &payload.name, payload.profile)?,
+    };
+    if let Some(decision) = &decision {
+        policy.remux_only = decision.action == admission::Action::Downgrade;
+        policy.cost = decision.cost;
+        if policy.remux_only {
+            tracing::warn!("节点负载较高（{} / {}），流 '{}' 降级为只转封装", decision.load, decision.capacity, name);
+        }
+    }
+    state.stream_manager.start_stream(name.clone(), rtsp_urls, input_options, rtmp_url, output_options, policy)?;
+    if srs_path.is_some() {
+        state.stream_manager.set_srs_error(&name, srs_error);
+    }
+
+    // 3. 等待流推到 SRS，避免返回的播放地址在最初几秒内 404
+    let ready = match &srs_path {
+        Some(path) => state.srs().verify_playback(&name, path, &playback_url).await,
+        None => None,
+    };
+
+    let hls_url = hls_url(state, &name);
+    Ok(PlayResponse { playback_url, stream: name, dry_run: None, admission: decision, ready, session: None, hls_url })
+}
+
+/// 开启 HLS 输出时流的播放列表地址
+fn hls_url(state: &AppState, name: &str) -> Option<String> {
+    let config = state.config();
+    config.hls.enabled.then(|| hls::playlist_path(&config.server.base_path(), name))
+}
+
+#[derive(Deserialize)]
+struct StopRequest {
+    name: String,
+    /// 播放接口返回的观看会话，携带时只在没有其他会话时停止转码
+    #[serde(default)]
+    session: Option<String>,
+}
+
+/// 停止流接口
+/// 观众关闭播放器时调用，立即停止转码，不必等待心跳超时
+async fn stop_stream(
+    State(state): State<AppState>,
+    access: StreamAccess, // 验证 Token
+    Json(payload): Json<StopRequest>,
+) -> StatusCode {
+    if !access.allows(&state, &payload.name) {
+        return StatusCode::FORBIDDEN;
+    }
+    let name = state.registry.canonical(&payload.name);
+    if let Some(session) = &payload.session {
+        state.sessions.close(session);
+        let viewers = state.sessions.count(&name);
+        if viewers > 0 && state.stream_manager.is_running(&name) {
+            tracing::info!("流 '{}' 还有 {} 个观看会话，不停止转码", name, viewers);
+            return StatusCode::OK;
+        }
+    }
+    if state.stream_manager.stop_stream(&name, "手动停止", std::time::Duration::from_secs(10)).await {
+        StatusCode::OK
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
+
+#[derive(Deserialize, Default)]
+struct TransferRequest {
+    /// 接收会话的设备名称
+    #[serde(default)]
+    device: Option<String>,
+}
+
+#[derive(Serialize)]
+struct TransferResponse {
+    /// 新的观看会话，原会话失效
+    session: String,
+    stream: String,
+    playback_url: String,
+}
+
+/// 观看会话转移接口
+/// 从一台设备切换到另一台设备继续观看：签发新会话并作废原会话，转码任务保持运行，不会中断其他观众
+async fn transfer_session(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    access: StreamAccess, // 验证 Token
+    Path(id): Path<String>,
+    payload: Option<Json<TransferRequest>>,
+) -> Result<Response, AppError> {
+    let device = payload.map(|Json(p)| p).unwrap_or_default().device.filter(|d| !d.is_empty());
+    if device.as_ref().is_some_and(|d| d.len() > sessions::MAX_DEVICE_LEN) {
+        return Ok((StatusCode::BAD_REQUEST, format!("device 不能超过 {} 个字符", sessions::MAX_DEVICE_LEN)).into_response());
+    }
+    if let Some(stream) = state.sessions.touch(&id) {
+        access.check(&state, &stream)?;
+    }
+    let Some(session) = state.sessions.transfer(&id, device)? else {
+        return Ok((StatusCode::NOT_FOUND, "观看会话不存在或已过期").into_response());
+    };
+    // 续期后再返回，新设备开始播放前转码任务不会因无心跳停止
+    if !state.stream_manager.heartbeat(&session.stream) {
+        state.sessions.close(&session.id);
+        return Ok((StatusCode::NOT_FOUND, "流已停止，需要重新调用播放接口").into_response());
+    }
+    // 按新设备的地址选择播放节点
+    let path = srs_stream_path(&state, &session.stream)?;
+    let playback_url = state.srs().playback_url(&path, Some(client.ip), &client.scheme);
+    tracing::info!("流 '{}' 的观看会话已转移到 {:?}", session.stream, session.device);
+    Ok(Json(TransferResponse { session: session.id, stream: session.stream, playback_url }).into_response())
+}
+
+#[derive(Serialize, Deserialize)]
+struct WebhookBody {
+    url: String,
+}
+
+/// 网页登录会话没有 API Key，不能登记回调
+fn webhook_requires_key() -> Response {
+    (StatusCode::BAD_REQUEST, "只有 API Key 可以登记停止通知回调").into_response()
+}
+
+/// 查询当前 API Key 登记的停止通知回调地址
+async fn get_webhook(State(state): State<AppState>, access: StreamAccess) -> Result<Response, AppError> {
+    let Some(key_id) = access.key_id.as_deref() else {
+        return Ok(webhook_requires_key());
+    };
+    Ok(match state.webhooks.url(key_id)? {
+        Some(url) => Json(WebhookBody { url }).into_response(),
+        None => (StatusCode::NOT_FOUND, "未登记停止通知回调").into_response(),
+    })
+}
+
+/// 登记停止通知回调
+/// 以当前 API Key 播放的流停止时（无观众超时、重启次数用尽、手动停止等）向该地址 POST 停止原因
+async fn put_webhook(
+    State(state): State<AppState>,
+    access: StreamAccess, // 验证 Token
+    Json(payload): Json<WebhookBody>,
+) -> Result<Response, AppError> {
+    let Some(key_id) = access.key_id.as_deref() else {
+        return Ok(webhook_requires_key());
+    };
+    if let Err(e) = state.webhooks.register(key_id, &payload.url) {
+        return Ok((StatusCode::BAD_REQUEST, e.to_string()).into_response());
+    }
+    tracing::info!("API Key {} 登记了停止通知回调", key_id);
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+/// 删除当前 API Key 的停止通知回调
+async fn delete_webhook(State(state): State<AppState>, access: StreamAccess) -> Result<Response, AppError> {
+    let Some(key_id) = access.key_id.as_deref() else {
+        return Ok(webhook_requires_key());
+    };
+    state.webhooks.unregister(key_id)?;
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+/// 后台任务查询接口
+/// 按状态（pending / running / done / failed）查看停止通知回调、SRS 清理等后台任务，按 ID 倒序返回
+async fn list_jobs(
+    State(state): State<AppState>,
+    _: AuthToken, // 验证 Token
+    Query(query): Query<jobs::JobsQuery>,
+) -> Result<Response, AppError> {
+    Ok(Json(state.jobs.list(&query)?).into_response())
+}
+
+/// 故障注入：当前生效的进程级故障
+#[cfg(feature = "chaos")]
+async fn chaos_status(_: AuthToken) -> Response {
+    Json(chaos::status()).into_response()
+}
+
+/// 故障注入：清除全部进程级故障
+#[cfg(feature = "chaos")]
+async fn clear_chaos(_: AuthToken) -> Response {
+    chaos::clear();
+    tracing::warn!("故障注入：已清除");
+    StatusCode::NO_CONTENT.into_response()
+}
+
+/// 故障注入：让流的转码线程以错误退出，验证重启策略与主备切换
+#[cfg(feature = "chaos")]
+async fn chaos_kill(State(state): State<AppState>, _: AuthToken, Path(name): Path<String>) -> Response {
+    let name = state.registry.canonical(&name);
+    if !state.stream_manager.inject_kill(&name) {
+        return (StatusCode::NOT_FOUND, "流未运行").into_response();
+    }
+    tracing::warn!("故障注入：终止流 '{}' 的转码线程", name);
+    StatusCode::ACCEPTED.into_response()
+}
+
+/// 故障注入：延迟 SRS 接口响应
+#[cfg(feature = "chaos")]
+async fn chaos_srs_delay(_: AuthToken, Json(payload): Json<chaos::SrsDelayRequest>) -> Response {
+    chaos::set_srs_delay(&payload);
+    tracing::warn!("故障注入：SRS 接口延迟 {} ms", payload.delay_ms);
+    Json(chaos::status()).into_response()
+}
+
+/// 故障注入：丢弃心跳，验证无观众超时与报警
+#[cfg(feature = "chaos")]
+async fn chaos_drop_heartbeats(
+    State(state): State<AppState>,
+    _: AuthToken,
+    payload: Option<Json<chaos::DropHeartbeatsRequest>>,
+) -> Response {
+    let Json(mut payload) = payload.unwrap_or_default();
+    payload.stream = payload.stream.map(|s| state.registry.canonical(&s));
+    tracing::warn!("故障注入：丢弃心跳 ({})", payload.stream.as_deref().unwrap_or("全部流"));
+    chaos::set_drop_heartbeats(payload);
+    Json(chaos::status()).into_response()
+}
+
+/// 重新加载配置文件接口
+/// 配置文件修改后会自动重新加载，无法监视文件修改时（如挂载的网络文件系统）可手动调用
+async fn reload_config(
+    State(state): State<AppState>,
+    _: AuthToken, // 验证 Token
+) -> Response {
+    match state.live.reload(&state.registry) {
+        Ok(reloaded) => Json(reloaded).into_response(),
+        Err(e) => (StatusCode::UNPROCESSABLE_ENTITY, format!("重新加载配置失败: {:#}", e)).into_response(),
+    }
+}
+
+/// HTTP 推流接口
+/// 推流端以分块传输持续发送 FLV / MPEG-TS 数据，转封装后推到 SRS；请求结束即停止推流
+async fn ingest_stream(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    _: AuthToken, // 验证 Token
+    Path(name): Path<String>,
+    body: axum::body::Body,
+) -> Result<Response, AppError> {
+    let name = state.registry.canonical(&name);
+    if state.stream_manager.is_running(&name) {
+        return Ok((StatusCode::CONFLICT, "流正在运行，不能同时推流").into_response());
+    }
+    let path = srs_stream_path(&state, &name)?;
+    let output_url = srs_output_url(&state, &path)?;
+    wait_srs_ready(&state).await;
+
+    let push = ingest::Push::bind().await?;
+    tracing::info!("客户端 {} 开始推流 '{}'", client.ip, name);
+    // 推流数据已是编码后的音视频，只转封装
+    let policy = StreamPolicy { remux_only: true, ..Default::default() };
+    state.stream_manager.start_stream(
+        name.clone(),
+        vec![push.url()],
+        InputOptions::default(),
+        output_url,
+        OutputOptions::default(),
+        policy,
+    )?;
+
+    let result = match push.accept().await {
+        Ok(mut conn) => {
+            let result = ingest::forward(body, &mut conn, || {
+                state.stream_manager.heartbeat(&name);
+            })
+            .await;
+            // 先标记停止再断开连接，转码器读到结尾时不记为异常结束
+            tokio::join!(
+                biased;
+                state.stream_manager.stop_stream(&name, "推流结束", std::time::Duration::from_secs(10)),
+                async move { drop(conn) },
+            );
+            result
+        }
+        Err(e) => {
+            state.stream_manager.stop_stream(&name, "推流结束", std::time::Duration::from_secs(10)).await;
+            Err(e)
+        }
+    };
+    match result {
+        Ok(bytes) => {
+            tracing::info!("流 '{}' 推流结束，共 {} 字节", name, bytes);
+            Ok(Json(serde_json::json!({ "stream": name, "bytes": bytes })).into_response())
+        }
+        Err(e) => {
+            tracing::warn!("流 '{}' 推流中断: {}", name, e);
+            Ok((StatusCode::BAD_GATEWAY, e.to_string()).into_response())
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct HeartbeatRequest {
+    name: String,
+    /// 播放接口返回的观看会话，同时为会话续期
+    #[serde(default)]
+    session: Option<String>,
+}
+
+#[derive(Serialize)]
+struct HeartbeatResponse {
+    /// 观看会话，之后的心跳携带该会话
+    session: String,
+    /// 流当前的观看会话数
+    viewers: usize,
+}
+
+/// 心跳接口
+/// 为流续期；未携带观看会话（或会话已过期）时签发新会话，流在最后一个会话过期后才因无观众停止
+async fn heartbeat(
+    State(state): State<AppState>,
+    access: StreamAccess, // 验证 Token
+    Json(payload): Json<HeartbeatRequest>,
+) -> Result<Response, AppError> {
+    if !access.allows(&state, &payload.name) {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+    let name = state.registry.canonical(&payload.name);
+    if !state.stream_manager.heartbeat(&name) {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    }
+    let renewed = payload.session.as_deref().filter(|id| state.sessions.touch(id).is_some_and(|stream| stream == name));
+    let session = match renewed {
+        Some(id) => id.to_string(),
+        None => state.sessions.open(&name, None)?,
+    };
+    let viewers = state.sessions.count(&name);
+    Ok(Json(HeartbeatResponse { session, viewers }).into_response())
+}
+
+#[derive(Deserialize)]
+struct HeartbeatBatchRequest {
+    heartbeats: Vec<HeartbeatRequest>,
+}
+
+#[derive(Serialize)]
+struct HeartbeatResult {
+    name: String,
+    ok: bool,
+}
+
+/// 批量心跳接口
+/// 视频墙等一次播放多路流的客户端可以用一个请求为所有流续期
+async fn heartbeat_batch(
+    State(state): State<AppState>,
+    access: StreamAccess, // 验证 Token
+    Json(payload): Json<HeartbeatBatchRequest>,
+) -> Json<Vec<HeartbeatResult>> {
+    // 无权访问的流不续期，结果为 false
+    let allowed: Vec<bool> = payload.heartbeats.iter().map(|h| access.allows(&state, &h.name)).collect();
+    let canonical: Vec<String> = payload
+        .heartbeats
+        .iter()
+        .zip(&allowed)
+        .filter(|(_, allowed)| **allowed)
+        .map(|(h, _)| state.registry.canonical(&h.name))
+        .collect();
+    let names: Vec<&str> = canonical.iter().map(String::as_str).collect();
+    let mut renewed = state.stream_manager.heartbeat_many(&names).into_iter();
+    let results: Vec<bool> = allowed.iter().map(|allowed| *allowed && renewed.next().unwrap_or(false)).collect();
+    for session in payload.heartbeats.iter().zip(&allowed).filter(|(_, a)| **a).filter_map(|(h, _)| h.session.as_deref()) {
+        state.sessions.touch(session);
+    }
+
+    Json(
+        payload
+            .heartbeats
+            .into_iter()
+            .zip(results)
+            .map(|(h, ok)| HeartbeatResult { name: h.name, ok })
+            .collect(),
+    )
+}
+
+#[derive(Deserialize)]
+struct LongPollQuery {
+    name: String,
+    session: String,
+    /// 最长等待秒数
+    wait: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct LongPollResponse {
+    name: String,
+    /// 与该会话上次收到的状态不同
+    changed: bool,
+    /// 当前状态，流已停止时为 null，需要重新调用播放接口
+    state: Option<longpoll::WatchedState>,
+}
+
+/// 长轮询心跳接口
+/// 代理或浏览器限制后台定时器与 WebSocket 时使用：连接保持期间持续为流续期，
+/// 流状态相对该会话上次收到的状态发生变化或等待超时后返回，客户端收到响应后立即发起下一次请求。
+async fn heartbeat_longpoll(
+    State(state): State<AppState>,
+    access: StreamAccess, // 验证 Token
+    Query(query): Query<LongPollQuery>,
+) -> Response {
+    if !access.allows(&state, &query.name) {
+        return StatusCode::FORBIDDEN.into_response();
+    }
+    if query.session.is_empty() || query.session.len() > longpoll::MAX_SESSION_LEN {
+        return (StatusCode::BAD_REQUEST, "session 不能为空且不超过 128 个字符").into_response();
+    }
+    let name = state.registry.canonical(&query.name);
+    let seen = state.longpoll.last_seen(&query.session, &name);
+    // 流已停止且会话已收到过停止状态时不再等待
+    if !state.stream_manager.heartbeat(&name) && seen.as_ref().is_none_or(Option::is_some) {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+
+    let wait = query.wait.map_or(longpoll::DEFAULT_WAIT, std::time::Duration::from_secs).min(longpoll::MAX_WAIT);
+    let deadline = tokio::time::Instant::now() + wait;
+    let mut renewed = tokio::time::Instant::now();
+    let mut current = state.stream_manager.status_of(&name).map(|s| longpoll::WatchedState::from(&s));
+    // 首次轮询立即返回当前状态
+    if let Some(seen) = &seen {
+        while current == *seen && current.is_some() && tokio::time::Instant::now() < deadline {
+            tokio::time::sleep(longpoll::POLL_INTERVAL).await;
+            if renewed.elapsed() >= longpoll::RENEW_INTERVAL {
+                state.stream_manager.heartbeat(&name);
+                renewed = tokio::time::Instant::now();
+            }
+            current = state.stream_manager.status_of(&name).map(|s| longpoll::WatchedState::from(&s));
+        }
+    }
+
+    state.longpoll.remember(&query.session, &name, current.clone());
+    let changed = seen.is_some_and(|seen| seen != current);
+    Json(LongPollResponse { name, changed, state: current }).into_response()
 }
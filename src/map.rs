@@ -0,0 +1,123 @@
+use serde::Serialize;
+use crate::config::StreamConfig;
+use crate::dashboard::StreamOverview;
+
+/// 元数据中的纬度键
+const LATITUDE_KEY: &str = "lat";
+
+/// 元数据中的经度键，也接受 `lon`
+const LONGITUDE_KEYS: &[&str] = &["lng", "lon"];
+
+/// 流在地图上的健康状态
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Health {
+    /// 运行中且没有异常
+    Healthy,
+    /// 运行中，但正在使用备用源、发生过重启、画面异常或推流受阻（与管理面板的 degraded 相同）
+    Degraded,
+    /// 重启次数已用尽，不再自动重启
+    Failed,
+    /// 未运行（无人观看的按需流）
+    Idle,
+}
+
+impl Health {
+    fn of(overview: &StreamOverview) -> Self {
+        if overview.restarts_exhausted {
+            Health::Failed
+        } else if !overview.running {
+            Health::Idle
+        } else if overview.degraded() {
+            Health::Degraded
+        } else {
+            Health::Healthy
+        }
+    }
+}
+
+/// GeoJSON 要素集合
+#[derive(Serialize)]
+pub struct FeatureCollection {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    /// Unix 时间戳（秒）
+    generated_at: u64,
+    features: Vec<Feature>,
+}
+
+#[derive(Serialize)]
+struct Feature {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    id: String,
+    geometry: Point,
+    properties: Properties,
+}
+
+#[derive(Serialize)]
+struct Point {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    /// GeoJSON 坐标顺序为 [经度, 纬度]
+    coordinates: [f64; 2],
+}
+
+#[derive(Serialize)]
+struct Properties {
+    health: Health,
+    /// 缩略图地址，未启用定时缩略图时为空
+    thumbnail: Option<String>,
+    #[serde(flatten)]
+    overview: StreamOverview,
+}
+
+/// 流元数据中的经纬度 (纬度, 经度)；未配置时返回 None，配置了但无效时返回错误
+pub fn coordinates(stream: &StreamConfig) -> Option<Result<(f64, f64), String>> {
+    let latitude = stream.metadata.get(LATITUDE_KEY);
+    let longitude = LONGITUDE_KEYS.iter().find_map(|key| stream.metadata.get(*key));
+    if latitude.is_none() && longitude.is_none() {
+        return None;
+    }
+    let parse = |value: Option<&String>, range: f64| {
+        value.and_then(|v| v.trim().parse::<f64>().ok()).filter(|v| v.abs() <= range)
+    };
+    Some(match (parse(latitude, 90.0), parse(longitude, 180.0)) {
+        (Some(latitude), Some(longitude)) => Ok((latitude, longitude)),
+        _ => Err(format!(
+            "元数据中的经纬度无效（lat: {}, lng: {}），应为 -90 到 90 与 -180 到 180 之间的小数",
+            latitude.map_or("未配置", String::as_str),
+            longitude.map_or("未配置", String::as_str)
+        )),
+    })
+}
+
+/// 由流配置与管理面板的流概览生成地图，只包含配置了有效经纬度的流
+///
+/// `thumbnail` 根据流名称返回缩略图地址。
+pub fn collection(
+    configured: &[StreamConfig],
+    overviews: Vec<StreamOverview>,
+    generated_at: u64,
+    thumbnail: impl Fn(&str) -> Option<String>,
+) -> FeatureCollection {
+    let features = overviews
+        .into_iter()
+        .filter(|overview| overview.configured)
+        .filter_map(|overview| {
+            let stream = configured.iter().find(|s| s.name == overview.name)?;
+            let (latitude, longitude) = coordinates(stream)?.ok()?;
+            Some(Feature {
+                kind: "Feature",
+                id: overview.name.clone(),
+                geometry: Point { kind: "Point", coordinates: [longitude, latitude] },
+                properties: Properties {
+                    health: Health::of(&overview),
+                    thumbnail: thumbnail(&overview.name),
+                    overview,
+                },
+            })
+        })
+        .collect();
+    FeatureCollection { kind: "FeatureCollection", generated_at, features }
+}
@@ -0,0 +1,187 @@
+use anyhow::{Result, anyhow};
+use config::{Config, File};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+/// 当前配置文件结构版本
+pub const CURRENT_VERSION: u32 = 2;
+
+/// 已知的顶层配置项，用于发现拼写错误或已废弃的配置
+const KNOWN_TOP_LEVEL_KEYS: &[&str] = &["version", "server", "srs", "streams", "api_keys", "proxy", "credentials", "data_dir", "history", "gop_cache", "gb28181", "auth", "content_check", "clock_skew", "clip", "audit", "rtsp_server", "play_overrides", "admission", "preview", "vendor_presets", "soak_restart", "event_clips", "http_flv", "async_remux", "hls", "jobs", "encoder", "share", "thumbnails", "recording"];
+
+/// 单步迁移：在原始文本上修改以尽量保留注释，返回迁移说明
+type Migration = fn(text: &mut String, value: &Value, format: Format, notes: &mut Vec<String>);
+
+/// 迁移步骤表，下标 i 为从版本 i+1 升级到 i+2
+const MIGRATIONS: &[Migration] = &[migrate_v1_to_v2];
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Yaml,
+    Toml,
+}
+
+/// `rtsp2flv migrate-config [--config <路径>] [--dry-run]`
+///
+/// 将旧版本配置文件升级到当前结构，原文件备份为 `<文件名>.bak`。
+pub fn run(args: &[String]) -> Result<()> {
+    let mut path: Option<PathBuf> = None;
+    let mut dry_run = false;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--config" => path = iter.next().map(PathBuf::from),
+            "--dry-run" => dry_run = true,
+            other => return Err(anyhow!("未知参数: {}", other)),
+        }
+    }
+
+    let path = match path {
+        Some(p) => p,
+        None => find_config_file().ok_or(anyhow!("当前目录下未找到配置文件 (config.yaml / config.yml / config.toml)"))?,
+    };
+    let format = match path.extension().and_then(|e| e.to_str()) {
+        Some("yaml") | Some("yml") => Format::Yaml,
+        Some("toml") => Format::Toml,
+        _ => return Err(anyhow!("仅支持 YAML / TOML 配置文件: {}", path.display())),
+    };
+
+    let original = std::fs::read_to_string(&path)?;
+    let mut text = original.clone();
+    let mut notes = Vec::new();
+
+    let mut version = parse(&path, &text)?
+        .get("version")
+        .and_then(Value::as_u64)
+        .unwrap_or(1) as u32;
+    println!("配置文件: {} (版本 {})", path.display(), version);
+
+    if version > CURRENT_VERSION {
+        return Err(anyhow!("配置文件版本 {} 高于当前程序支持的版本 {}", version, CURRENT_VERSION));
+    }
+
+    while version < CURRENT_VERSION {
+        let value = parse(&path, &text)?;
+        MIGRATIONS[(version - 1) as usize](&mut text, &value, format, &mut notes);
+        version += 1;
+        notes.push(format!("已升级到版本 {}", version));
+    }
+
+    // 迁移结果必须仍然可以被解析
+    let value = parse(&path, &text)?;
+    check_ambiguities(&value, &mut notes);
+
+    for note in &notes {
+        println!("  - {}", note);
+    }
+
+    if text == original {
+        println!("配置文件已是最新版本，无需修改。");
+        return Ok(());
+    }
+
+    if dry_run {
+        println!("--- 迁移后的配置 (dry-run，未写入) ---\n{}", text);
+        return Ok(());
+    }
+
+    let mut backup = path.clone().into_os_string();
+    backup.push(".bak");
+    std::fs::copy(&path, &backup)?;
+    std::fs::write(&path, text)?;
+    println!("迁移完成，原文件已备份到 {}", PathBuf::from(backup).display());
+    Ok(())
+}
+
+/// 检查配置文件是否落后于当前版本，用于启动时提示；全部配置由环境变量提供时不提示
+pub fn outdated_version(version: u32) -> Option<String> {
+    (version < CURRENT_VERSION && find_config_file().is_some()).then(|| {
+        format!(
+            "配置文件版本为 {}，当前版本为 {}，建议执行 `rtsp2flv migrate-config` 升级",
+            version, CURRENT_VERSION
+        )
+    })
+}
+
+/// 检查当前目录下的配置文件，返回需要人工确认的问题（用于 `rtsp2flv doctor`）
+pub fn review() -> Result<Vec<String>> {
+    let path = find_config_file().ok_or(anyhow!("当前目录下未找到配置文件 (config.yaml / config.yml / config.toml)"))?;
+    let value = parse(&path, &std::fs::read_to_string(&path)?)?;
+    let version = value.get("version").and_then(Value::as_u64).unwrap_or(1) as u32;
+    let mut notes: Vec<String> = outdated_version(version).into_iter().collect();
+    check_ambiguities(&value, &mut notes);
+    Ok(notes.into_iter().map(|n| n.trim_start_matches("[需确认] ").to_string()).collect())
+}
+
+fn find_config_file() -> Option<PathBuf> {
+    ["config.yaml", "config.yml", "config.toml"]
+        .iter()
+        .map(PathBuf::from)
+        .find(|p| p.exists())
+}
+
+fn parse(path: &Path, text: &str) -> Result<Value> {
+    let format = match path.extension().and_then(|e| e.to_str()) {
+        Some("toml") => config::FileFormat::Toml,
+        _ => config::FileFormat::Yaml,
+    };
+    let value = Config::builder()
+        .add_source(File::from_str(text, format))
+        .build()?
+        .try_deserialize::<Value>()?;
+    Ok(value)
+}
+
+/// v1 -> v2：增加显式的 version 字段，已写明 `version: 1` 时改为 2
+fn migrate_v1_to_v2(text: &mut String, _value: &Value, format: Format, notes: &mut Vec<String>) {
+    let (line, separator) = match format {
+        Format::Yaml => ("version: 2", ':'),
+        Format::Toml => ("version = 2", '='),
+    };
+    // 只匹配顶层的 version（YAML 无缩进，TOML 在第一个表之前）
+    let mut offset = 0;
+    let mut existing = None;
+    for raw in text.split_inclusive('\n') {
+        let content = raw.trim_end_matches(['\r', '\n']);
+        if format == Format::Toml && content.starts_with('[') {
+            break;
+        }
+        if content.split_once(separator).is_some_and(|(key, _)| key.trim_end() == "version") {
+            existing = Some(offset..offset + content.len());
+            break;
+        }
+        offset += raw.len();
+    }
+    match existing {
+        Some(range) => {
+            text.replace_range(range, line);
+            notes.push("version 字段改为 2".to_string());
+        }
+        None => {
+            text.insert_str(0, &format!("{}\n", line));
+            notes.push("增加 version 字段".to_string());
+        }
+    }
+}
+
+/// 报告无法自动处理、需要人工确认的配置
+fn check_ambiguities(value: &Value, notes: &mut Vec<String>) {
+    if let Some(map) = value.as_object() {
+        for key in map.keys() {
+            if !KNOWN_TOP_LEVEL_KEYS.contains(&key.as_str()) {
+                notes.push(format!("[需确认] 未知的顶层配置项 '{}'，可能是拼写错误或已废弃", key));
+            }
+        }
+    }
+
+    let names: Vec<&str> = value
+        .get("streams")
+        .and_then(Value::as_array)
+        .map(|streams| streams.iter().filter_map(|s| s.get("name").and_then(Value::as_str)).collect())
+        .unwrap_or_default();
+    for (i, name) in names.iter().enumerate() {
+        if names[..i].contains(name) {
+            notes.push(format!("[需确认] 流名称 '{}' 重复，只有第一个会生效", name));
+        }
+    }
+}
@@ -0,0 +1,196 @@
+use anyhow::Result;
+use ffmpeg_next as ffmpeg;
+use serde::Serialize;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+use crate::history::now_secs;
+
+/// 单次跟踪的最长时长
+pub const MAX_TRACE_DURATION: Duration = Duration::from_secs(600);
+
+/// 单次跟踪文件大小上限
+const MAX_TRACE_BYTES: u64 = 256 * 1024 * 1024;
+
+/// 跟踪状态
+#[derive(Debug, Clone, Serialize, Default)]
+pub struct TraceStatus {
+    pub active: bool,
+    #[serde(skip)]
+    pub path: Option<PathBuf>,
+    pub started_at: Option<u64>,
+    pub finished_at: Option<u64>,
+    pub packets: u64,
+    pub bytes: u64,
+    pub error: Option<String>,
+}
+
+struct Session {
+    writer: BufWriter<File>,
+    started: Instant,
+    until: Instant,
+    packets: u64,
+    bytes: u64,
+}
+
+/// 单个流的数据包跟踪
+///
+/// 开启后在限定时长内把每个数据包的时间戳（修正前后）、大小与关键帧标志逐行写入独立文件，
+/// 只影响这一个流，不需要把全局日志级别调到 trace。未开启时转码线程只读取一个原子标志。
+#[derive(Default)]
+pub struct TraceControl {
+    active: AtomicBool,
+    session: Mutex<Option<Session>>,
+    status: Mutex<TraceStatus>,
+}
+
+impl TraceControl {
+    /// 开始跟踪并创建文件，已有跟踪进行中时返回 false
+    pub fn start(&self, stream: &str, path: PathBuf, duration: Duration) -> Result<bool> {
+        let mut session = self.session.lock().unwrap();
+        if session.is_some() {
+            return Ok(false);
+        }
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut writer = BufWriter::new(File::create(&path)?);
+        let duration = duration.min(MAX_TRACE_DURATION);
+        writeln!(writer, "# 流 {} 数据包跟踪，开始于 {}，时长 {} 秒", stream, now_secs(), duration.as_secs())?;
+        writeln!(writer, "# 相对时间(秒) 方向 流序号 关键帧 大小 pts dts 时间基；out 行为时间戳修正前->修正后，* 表示被修正")?;
+        info!("流 '{}' 开始数据包跟踪: {}", stream, path.display());
+
+        let now = Instant::now();
+        *session = Some(Session { writer, started: now, until: now + duration, packets: 0, bytes: 0 });
+        *self.status.lock().unwrap() = TraceStatus {
+            active: true,
+            path: Some(path),
+            started_at: Some(now_secs()),
+            ..Default::default()
+        };
+        self.active.store(true, Ordering::Release);
+        Ok(true)
+    }
+
+    /// 提前结束跟踪，没有进行中的跟踪时返回 false
+    pub fn stop(&self) -> bool {
+        let mut session = self.session.lock().unwrap();
+        let stopped = session.is_some();
+        self.finish(&mut session, None);
+        stopped
+    }
+
+    pub fn status(&self) -> TraceStatus {
+        // 流没有数据包时不会触发写入，查询时检查是否已到时长
+        let mut session = self.session.lock().unwrap();
+        if session.as_ref().is_some_and(|s| Instant::now() >= s.until) {
+            self.finish(&mut session, None);
+        }
+        drop(session);
+        self.status.lock().unwrap().clone()
+    }
+
+    /// 记录一个原始输入数据包
+    pub fn input(&self, stream: usize, packet: &ffmpeg::Packet, time_base: ffmpeg::Rational) {
+        if !self.active.load(Ordering::Relaxed) {
+            return;
+        }
+        self.write(|elapsed| {
+            format!(
+                "{:>10.3} in  #{} {} {:>7} pts={} dts={} tb={}/{}",
+                elapsed,
+                stream,
+                key_flag(packet),
+                packet.size(),
+                ts(packet.pts()),
+                ts(packet.dts()),
+                time_base.numerator(),
+                time_base.denominator(),
+            )
+        });
+    }
+
+    /// 记录一个推流数据包，`raw` 为时间戳修正前的 (pts, dts)
+    pub fn output(&self, stream: usize, raw: (Option<i64>, Option<i64>), packet: &ffmpeg::Packet, time_base: ffmpeg::Rational) {
+        if !self.active.load(Ordering::Relaxed) {
+            return;
+        }
+        let fixed = (packet.pts(), packet.dts());
+        self.write(|elapsed| {
+            format!(
+                "{:>10.3} out #{} {} {:>7} pts={}->{} dts={}->{} tb={}/{}{}",
+                elapsed,
+                stream,
+                key_flag(packet),
+                packet.size(),
+                ts(raw.0),
+                ts(fixed.0),
+                ts(raw.1),
+                ts(fixed.1),
+                time_base.numerator(),
+                time_base.denominator(),
+                if raw == fixed { "" } else { " *" },
+            )
+        });
+    }
+
+    fn write(&self, line: impl FnOnce(f64) -> String) {
+        let mut guard = self.session.lock().unwrap();
+        let Some(session) = guard.as_mut() else {
+            return;
+        };
+        let now = Instant::now();
+        if now >= session.until {
+            self.finish(&mut guard, None);
+            return;
+        }
+        let line = line(now.duration_since(session.started).as_secs_f64());
+        if let Err(e) = writeln!(session.writer, "{}", line) {
+            self.finish(&mut guard, Some(e.to_string()));
+            return;
+        }
+        session.packets += 1;
+        session.bytes += line.len() as u64 + 1;
+        if session.bytes >= MAX_TRACE_BYTES {
+            self.finish(&mut guard, Some("跟踪文件超出大小上限".to_string()));
+            return;
+        }
+        if session.packets.is_multiple_of(100) {
+            let (packets, bytes) = (session.packets, session.bytes);
+            let mut status = self.status.lock().unwrap();
+            status.packets = packets;
+            status.bytes = bytes;
+        }
+    }
+
+    /// 关闭文件并更新状态
+    fn finish(&self, session: &mut Option<Session>, error: Option<String>) {
+        let Some(mut finished) = session.take() else {
+            return;
+        };
+        self.active.store(false, Ordering::Release);
+        let flushed = finished.writer.flush();
+        info!("数据包跟踪结束: {} 个数据包, {} 字节", finished.packets, finished.bytes);
+        let mut status = self.status.lock().unwrap();
+        status.active = false;
+        status.packets = finished.packets;
+        status.bytes = finished.bytes;
+        status.finished_at = Some(now_secs());
+        if let Some(e) = error.or(flushed.err().map(|e| e.to_string())) {
+            warn!("数据包跟踪异常结束: {}", e);
+            status.error = Some(e);
+        }
+    }
+}
+
+fn key_flag(packet: &ffmpeg::Packet) -> &'static str {
+    if packet.is_key() { "K" } else { "-" }
+}
+
+fn ts(value: Option<i64>) -> String {
+    value.map_or_else(|| "NOPTS".to_string(), |v| v.to_string())
+}
@@ -0,0 +1,179 @@
+use anyhow::{Result, anyhow};
+use ffmpeg_next as ffmpeg;
+use serde::Deserialize;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use crate::history::now_secs;
+
+/// 预览墙最多拼接的流数量
+pub const MAX_WALL_STREAMS: usize = 64;
+
+/// 预览缩略图配置
+#[derive(Debug, Deserialize, Clone)]
+pub struct PreviewConfig {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// 抽帧间隔（秒）
+    #[serde(default = "default_interval_secs")]
+    pub interval_secs: u64,
+    /// 缩略图宽度（像素）
+    #[serde(default = "default_width")]
+    pub width: u32,
+    /// 缩略图高度（像素）
+    #[serde(default = "default_height")]
+    pub height: u32,
+    /// 缩略图超过该秒数未更新（流已停止或源断开）时在预览墙上显示为黑色
+    #[serde(default = "default_stale_secs")]
+    pub stale_secs: u64,
+    /// 预览墙 JPEG 质量，2（最好）到 31（最差）
+    #[serde(default = "default_quality")]
+    pub quality: u32,
+}
+
+impl Default for PreviewConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_enabled(),
+            interval_secs: default_interval_secs(),
+            width: default_width(),
+            height: default_height(),
+            stale_secs: default_stale_secs(),
+            quality: default_quality(),
+        }
+    }
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_interval_secs() -> u64 {
+    5
+}
+
+fn default_width() -> u32 {
+    320
+}
+
+fn default_height() -> u32 {
+    180
+}
+
+fn default_stale_secs() -> u64 {
+    60
+}
+
+fn default_quality() -> u32 {
+    5
+}
+
+impl PreviewConfig {
+    /// 抽帧间隔
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval_secs.max(1))
+    }
+
+    /// 缩略图尺寸，YUV420 要求宽高为偶数
+    pub fn size(&self) -> (u32, u32) {
+        (self.width.max(16) & !1, self.height.max(16) & !1)
+    }
+}
+
+/// 缩略图（YUVJ420P，各平面按行紧密排列）
+pub struct Preview {
+    pub width: u32,
+    pub height: u32,
+    planes: [Vec<u8>; 3],
+    /// 抽帧时间（Unix 秒）
+    pub captured_at: u64,
+}
+
+impl Preview {
+    /// 从抽帧器缩放后的画面复制，去掉行尾的对齐填充
+    fn from_frame(frame: &ffmpeg::frame::Video) -> Self {
+        let planes = [0, 1, 2].map(|i| {
+            let (width, height) = (frame.plane_width(i) as usize, frame.plane_height(i) as usize);
+            let mut data = Vec::with_capacity(width * height);
+            for row in frame.data(i).chunks(frame.stride(i)).take(height) {
+                data.extend_from_slice(&row[..width]);
+            }
+            data
+        });
+        Self {
+            width: frame.width(),
+            height: frame.height(),
+            planes,
+            captured_at: now_secs(),
+        }
+    }
+}
+
+/// 单个流最近一次的缩略图，流重启后保留，预览墙在重新抽帧前继续显示旧画面
+#[derive(Default)]
+pub struct PreviewCache {
+    latest: Mutex<Option<Arc<Preview>>>,
+}
+
+impl PreviewCache {
+    pub fn store(&self, frame: &ffmpeg::frame::Video) {
+        *self.latest.lock().unwrap() = Some(Arc::new(Preview::from_frame(frame)));
+    }
+
+    pub fn latest(&self) -> Option<Arc<Preview>> {
+        self.latest.lock().unwrap().clone()
+    }
+}
+
+/// 把缩略图按 `cols` 列拼接为一张 JPEG，缺少缩略图或尺寸不符的格子填充黑色
+pub fn mosaic(tiles: &[Option<Arc<Preview>>], cols: usize, config: &PreviewConfig) -> Result<Vec<u8>> {
+    let (width, height) = config.size();
+    let cols = cols.clamp(1, tiles.len().max(1));
+    let rows = tiles.len().div_ceil(cols).max(1);
+
+    let mut frame = ffmpeg::frame::Video::new(ffmpeg::format::Pixel::YUVJ420P, width * cols as u32, height * rows as u32);
+    // 全范围 YUV 的黑色
+    for (plane, value) in [(0, 0u8), (1, 128), (2, 128)] {
+        frame.data_mut(plane).fill(value);
+    }
+    for (index, tile) in tiles.iter().enumerate() {
+        let Some(tile) = tile.as_ref().filter(|t| t.width == width && t.height == height) else {
+            continue;
+        };
+        let (col, row) = (index % cols, index / cols);
+        for plane in 0..3 {
+            // 色度平面宽高各为亮度的一半
+            let shift = usize::from(plane > 0);
+            let (tile_width, tile_height) = (width as usize >> shift, height as usize >> shift);
+            let stride = frame.stride(plane);
+            let data = frame.data_mut(plane);
+            for (y, line) in tile.planes[plane].chunks(tile_width).take(tile_height).enumerate() {
+                let start = (row * tile_height + y) * stride + col * tile_width;
+                data[start..start + tile_width].copy_from_slice(line);
+            }
+        }
+    }
+    encode_jpeg(&mut frame, config.quality)
+}
+
+/// 用 FFmpeg 的 MJPEG 编码器把单帧编码为 JPEG
+pub fn encode_jpeg(frame: &mut ffmpeg::frame::Video, quality: u32) -> Result<Vec<u8>> {
+    let codec = ffmpeg::encoder::find(ffmpeg::codec::Id::MJPEG).ok_or(anyhow!("FFmpeg 未编译 MJPEG 编码器"))?;
+    let mut encoder = ffmpeg::codec::context::Context::new_with_codec(codec).encoder().video()?;
+    encoder.set_width(frame.width());
+    encoder.set_height(frame.height());
+    encoder.set_format(ffmpeg::format::Pixel::YUVJ420P);
+    encoder.set_time_base((1, 25));
+    // 固定量化参数，编码器从帧上读取 lambda = qscale * FF_QP2LAMBDA
+    let lambda = quality.clamp(2, 31) * 118;
+    encoder.set_flags(ffmpeg::codec::Flags::QSCALE);
+    encoder.set_quality(lambda as usize);
+    let mut encoder = encoder.open_as(codec)?;
+    // SAFETY: 帧由调用方独占，只修改质量字段
+    unsafe { (*frame.as_mut_ptr()).quality = lambda as i32 };
+
+    encoder.send_frame(frame)?;
+    encoder.send_eof()?;
+    let mut packet = ffmpeg::Packet::empty();
+    encoder.receive_packet(&mut packet)?;
+    packet.data().map(<[u8]>::to_vec).ok_or(anyhow!("JPEG 编码结果为空"))
+}
@@ -0,0 +1,158 @@
+use anyhow::{Result, anyhow};
+use ffmpeg_next as ffmpeg;
+use serde::Serialize;
+use std::time::{Duration, Instant};
+use crate::config::{InputOptions, OutputOptions};
+use crate::transcoder::{self, Transcoder};
+
+/// 探测超时时间，RTSP 连接本身另有 socket 超时（`input.timeout_secs`，缺省 5 秒）
+pub const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 输入中单个流的处理方式
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Handling {
+    /// 直接转封装为 FLV
+    Copy,
+    /// 重新编码（非 AAC 音频转为 AAC，原始视频与按配置的 H.265 转为 H.264）
+    Transcode,
+    /// FLV 不支持该编码，推流会失败
+    Unsupported,
+    /// 不推送（字幕、数据流，或被 `audio_only` / `no_audio` 排除）
+    Ignore,
+}
+
+/// 输入中的单个流
+#[derive(Debug, Serialize)]
+pub struct StreamInfo {
+    pub index: usize,
+    /// video / audio / data / subtitle 等
+    pub kind: String,
+    pub codec: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pixel_format: Option<String>,
+    /// 平均帧率
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frame_rate: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sample_rate: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channels: Option<u16>,
+    pub handling: Handling,
+}
+
+/// 探测结果
+#[derive(Debug, Serialize)]
+pub struct ProbeReport {
+    /// 输入封装格式，如 `rtsp`
+    pub format: String,
+    /// 打开输入并读取流信息的耗时（毫秒）
+    pub elapsed_ms: u64,
+    pub streams: Vec<StreamInfo>,
+    /// 所有推送的流都不需要重新编码，可以直接转封装为标准 FLV（H.264 + AAC / MP3）
+    pub remux: bool,
+    /// 需要注意的问题
+    pub warnings: Vec<String>,
+}
+
+/// 打开输入源并读取编码信息，判断能否直接转封装为 FLV；`output` 为推流时使用的输出参数
+pub async fn run(url: String, input: InputOptions, output: OutputOptions) -> Result<ProbeReport> {
+    let task = tokio::task::spawn_blocking(move || inspect(&url, &input, &output));
+    match tokio::time::timeout(PROBE_TIMEOUT, task).await {
+        Ok(result) => result?,
+        // 阻塞线程在 socket 超时后自行结束
+        Err(_) => Err(anyhow!("{} 秒内未能读取流信息", PROBE_TIMEOUT.as_secs())),
+    }
+}
+
+fn inspect(url: &str, input: &InputOptions, output: &OutputOptions) -> Result<ProbeReport> {
+    ffmpeg::init()?;
+    let started = Instant::now();
+    let (ictx, _guard) = Transcoder::open_input(url, input)?;
+    let elapsed_ms = started.elapsed().as_millis() as u64;
+
+    let mut warnings = Vec::new();
+    let streams: Vec<StreamInfo> = ictx.streams().map(|stream| describe(&stream, output, &mut warnings)).collect();
+    let pushed: Vec<&StreamInfo> = streams.iter().filter(|s| s.handling != Handling::Ignore).collect();
+    if pushed.is_empty() {
+        warnings.push("输入中没有可推送的音视频流".to_string());
+    }
+    let remux = !pushed.is_empty()
+        && pushed
+            .iter()
+            .all(|s| s.handling == Handling::Copy && ["h264", "aac", "mp3"].contains(&s.codec.as_str()));
+    Ok(ProbeReport { format: ictx.format().name().to_string(), elapsed_ms, streams, remux, warnings })
+}
+
+fn describe(stream: &ffmpeg::Stream, output: &OutputOptions, warnings: &mut Vec<String>) -> StreamInfo {
+    let params = stream.parameters();
+    let codec = params.id().name().to_string();
+    let mut info = StreamInfo {
+        index: stream.index(),
+        kind: format!("{:?}", params.medium()).to_lowercase(),
+        codec: codec.clone(),
+        width: None,
+        height: None,
+        pixel_format: None,
+        frame_rate: None,
+        sample_rate: None,
+        channels: None,
+        handling: Handling::Ignore,
+    };
+    // 没有对应解码器时只返回编码名称
+    let decoder = ffmpeg::codec::context::Context::from_parameters(params.clone()).map(|c| c.decoder());
+    match params.medium() {
+        ffmpeg::media::Type::Video => {
+            if let Ok(video) = decoder.and_then(|d| d.video()) {
+                info.width = Some(video.width());
+                info.height = Some(video.height());
+                info.pixel_format = Some(format!("{:?}", video.format()).to_lowercase());
+            }
+            let rate = stream.avg_frame_rate();
+            if rate.numerator() > 0 && rate.denominator() > 0 {
+                info.frame_rate = Some((f64::from(rate) * 100.0).round() / 100.0);
+            }
+            info.handling = if output.audio_only {
+                Handling::Ignore
+            } else if transcoder::requires_reencode(&params, output) {
+                Handling::Transcode
+            } else {
+                match params.id() {
+                    ffmpeg::codec::Id::H264 => Handling::Copy,
+                    ffmpeg::codec::Id::HEVC => {
+                        warnings.push(
+                            "H.265 不受标准 FLV 支持，需要 SRS 与播放器支持增强 FLV，或配置 output.hevc_to_h264 转码为 H.264".to_string(),
+                        );
+                        Handling::Copy
+                    }
+                    _ => {
+                        warnings.push(format!("FLV 不支持视频编码 {}，推流会失败", codec));
+                        Handling::Unsupported
+                    }
+                }
+            };
+        }
+        ffmpeg::media::Type::Audio => {
+            if let Ok(audio) = decoder.and_then(|d| d.audio()) {
+                info.sample_rate = Some(audio.rate());
+                info.channels = Some(audio.channels());
+            }
+            info.handling = if output.no_audio {
+                Handling::Ignore
+            } else if transcoder::requires_reencode(&params, output) {
+                Handling::Transcode
+            } else {
+                Handling::Copy
+            };
+            if info.handling == Handling::Copy && matches!(params.id(), ffmpeg::codec::Id::PCM_ALAW | ffmpeg::codec::Id::PCM_MULAW) {
+                warnings.push("G.711 音频原样转发（copy_g711），需要 SRS 与播放器支持".to_string());
+            }
+        }
+        _ => {}
+    }
+    info
+}
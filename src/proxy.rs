@@ -0,0 +1,198 @@
+use anyhow::{Context, Result, anyhow, bail};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use std::net::SocketAddr;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+/// 经代理建立连接的本地隧道
+///
+/// FFmpeg 的 RTSP(TCP) 不支持 SOCKS / HTTP 代理，这里在本机监听一个端口，
+/// 把每个连接通过代理转发到摄像头，转码器改为连接本地端口。隧道随本对象销毁而关闭。
+pub struct Tunnel {
+    task: JoinHandle<()>,
+}
+
+impl Drop for Tunnel {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// 代理地址，支持 `socks5://[user:pass@]host:port` 与 `http://[user:pass@]host:port`
+#[derive(Clone)]
+struct Proxy {
+    kind: ProxyKind,
+    addr: String,
+    auth: Option<(String, String)>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ProxyKind {
+    Socks5,
+    Http,
+}
+
+impl Proxy {
+    fn parse(proxy: &str) -> Result<Self> {
+        let url = url::Url::parse(proxy).map_err(|e| anyhow!("代理地址无效 '{}': {}", proxy, e))?;
+        let kind = match url.scheme() {
+            "socks5" | "socks5h" => ProxyKind::Socks5,
+            "http" => ProxyKind::Http,
+            other => bail!("不支持的代理协议: {}", other),
+        };
+        let host = url.host_str().ok_or_else(|| anyhow!("代理地址缺少主机名: {}", proxy))?;
+        let port = url.port().unwrap_or(if kind == ProxyKind::Socks5 { 1080 } else { 8080 });
+        let auth = (!url.username().is_empty()).then(|| {
+            let decode = |s: &str| percent_encoding::percent_decode_str(s).decode_utf8_lossy().to_string();
+            (decode(url.username()), decode(url.password().unwrap_or_default()))
+        });
+        Ok(Self { kind, addr: format!("{}:{}", host, port), auth })
+    }
+
+    /// 经代理连接目标地址
+    async fn connect(&self, host: &str, port: u16) -> Result<TcpStream> {
+        let mut stream = TcpStream::connect(&self.addr)
+            .await
+            .with_context(|| format!("连接代理 {} 失败", self.addr))?;
+        match self.kind {
+            ProxyKind::Socks5 => self.socks5_handshake(&mut stream, host, port).await?,
+            ProxyKind::Http => self.http_connect(&mut stream, host, port).await?,
+        }
+        Ok(stream)
+    }
+
+    /// SOCKS5 握手 (RFC 1928 / RFC 1929)，目标主机名交由代理解析
+    async fn socks5_handshake(&self, stream: &mut TcpStream, host: &str, port: u16) -> Result<()> {
+        let method = if self.auth.is_some() { 0x02 } else { 0x00 };
+        stream.write_all(&[0x05, 0x01, method]).await?;
+        let mut reply = [0u8; 2];
+        stream.read_exact(&mut reply).await?;
+        if reply[0] != 0x05 || reply[1] != method {
+            bail!("SOCKS5 代理不接受认证方式");
+        }
+
+        if let Some((user, pass)) = &self.auth {
+            let mut req = vec![0x01, user.len() as u8];
+            req.extend_from_slice(user.as_bytes());
+            req.push(pass.len() as u8);
+            req.extend_from_slice(pass.as_bytes());
+            stream.write_all(&req).await?;
+            stream.read_exact(&mut reply).await?;
+            if reply[1] != 0x00 {
+                bail!("SOCKS5 代理认证失败");
+            }
+        }
+
+        let mut req = vec![0x05, 0x01, 0x00, 0x03, host.len() as u8];
+        req.extend_from_slice(host.as_bytes());
+        req.extend_from_slice(&port.to_be_bytes());
+        stream.write_all(&req).await?;
+
+        let mut head = [0u8; 4];
+        stream.read_exact(&mut head).await?;
+        if head[1] != 0x00 {
+            bail!("SOCKS5 代理连接 {}:{} 失败 (错误码 {})", host, port, head[1]);
+        }
+        // 跳过代理返回的绑定地址
+        let addr_len = match head[3] {
+            0x01 => 4,
+            0x04 => 16,
+            0x03 => stream.read_u8().await? as usize,
+            t => bail!("SOCKS5 代理返回未知地址类型 {}", t),
+        };
+        let mut skip = vec![0u8; addr_len + 2];
+        stream.read_exact(&mut skip).await?;
+        Ok(())
+    }
+
+    /// HTTP CONNECT 隧道
+    async fn http_connect(&self, stream: &mut TcpStream, host: &str, port: u16) -> Result<()> {
+        let mut req = format!("CONNECT {host}:{port} HTTP/1.1\r\nHost: {host}:{port}\r\n");
+        if let Some((user, pass)) = &self.auth {
+            req.push_str(&format!(
+                "Proxy-Authorization: Basic {}\r\n",
+                BASE64.encode(format!("{}:{}", user, pass))
+            ));
+        }
+        req.push_str("\r\n");
+        stream.write_all(req.as_bytes()).await?;
+
+        // 逐字节读取响应头，避免读走隧道中的数据
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            head.push(stream.read_u8().await?);
+            if head.len() > 8192 {
+                bail!("HTTP 代理响应头过长");
+            }
+        }
+        let status = String::from_utf8_lossy(&head);
+        let status = status.lines().next().unwrap_or_default();
+        if status.split_whitespace().nth(1) != Some("200") {
+            bail!("HTTP 代理拒绝连接 {}:{}: {}", host, port, status);
+        }
+        Ok(())
+    }
+}
+
+/// 按协议推断默认端口
+fn default_port(scheme: &str) -> Option<u16> {
+    match scheme {
+        "rtsp" => Some(554),
+        "rtmp" => Some(1935),
+        "http" => Some(80),
+        "https" => Some(443),
+        _ => None,
+    }
+}
+
+/// 为目标地址建立本地隧道，返回隧道与改写为本地端口的地址
+///
+/// 必须在 tokio 运行时（包括 spawn_blocking 线程）中调用。
+pub fn open(proxy: &str, target_url: &str) -> Result<(Tunnel, String)> {
+    let proxy = Proxy::parse(proxy)?;
+    let mut url = url::Url::parse(target_url).map_err(|e| anyhow!("地址无效: {}", e))?;
+    let host = url.host_str().ok_or_else(|| anyhow!("地址缺少主机名"))?.to_string();
+    let port = url
+        .port()
+        .or_else(|| default_port(url.scheme()))
+        .ok_or_else(|| anyhow!("协议 {} 不支持经代理连接", url.scheme()))?;
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    listener.set_nonblocking(true)?;
+    let local: SocketAddr = listener.local_addr()?;
+
+    let handle = tokio::runtime::Handle::try_current().map_err(|_| anyhow!("代理隧道需要在 tokio 运行时中创建"))?;
+    let target = format!("{}:{}", host, port);
+    info!("经代理 {} 连接 {}，本地隧道 {}", proxy.addr, target, local);
+
+    let task = handle.spawn(async move {
+        let listener = match TcpListener::from_std(listener) {
+            Ok(l) => l,
+            Err(e) => {
+                warn!("代理隧道监听失败: {}", e);
+                return;
+            }
+        };
+        loop {
+            let Ok((mut inbound, _)) = listener.accept().await else { continue };
+            let proxy = proxy.clone();
+            let host = host.clone();
+            tokio::spawn(async move {
+                match proxy.connect(&host, port).await {
+                    Ok(mut outbound) => {
+                        let _ = tokio::io::copy_bidirectional(&mut inbound, &mut outbound).await;
+                        debug!("代理隧道连接关闭: {}:{}", host, port);
+                    }
+                    Err(e) => warn!("代理隧道连接 {}:{} 失败: {:#}", host, port, e),
+                }
+            });
+        }
+    });
+
+    url.set_ip_host(local.ip()).map_err(|_| anyhow!("无法改写地址主机"))?;
+    url.set_port(Some(local.port())).map_err(|_| anyhow!("无法改写地址端口"))?;
+    Ok((Tunnel { task }, url.to_string()))
+}
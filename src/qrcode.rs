@@ -0,0 +1,592 @@
+use anyhow::{Result, anyhow};
+use ffmpeg_next as ffmpeg;
+
+/// 二维码四周的空白宽度（模块数），规范要求至少 4
+const QUIET_ZONE: u32 = 4;
+
+/// 图片边长上限（像素）
+pub const MAX_IMAGE_SIZE: u32 = 4096;
+
+/// 各版本（1 ~ 40）M 级纠错每块的纠错码字数
+const ECC_CODEWORDS_PER_BLOCK: [usize; 40] = [
+    10, 16, 26, 18, 24, 16, 18, 22, 22, 26, 30, 22, 22, 24, 24, 28, 28, 26, 26, 26, 26, 28, 28, 28, 28, 28, 28, 28, 28,
+    28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28,
+];
+
+/// 各版本（1 ~ 40）M 级纠错的分块数
+const NUM_ERROR_CORRECTION_BLOCKS: [usize; 40] = [
+    1, 1, 1, 2, 2, 4, 4, 4, 5, 5, 5, 8, 9, 9, 10, 10, 11, 13, 14, 16, 17, 17, 18, 20, 21, 23, 25, 26, 28, 29, 31, 33, 35,
+    37, 38, 40, 43, 45, 47, 49,
+];
+
+/// 二维码
+///
+/// 只实现播放地址需要的部分：字节模式、M 级纠错（约 15% 的污损可恢复，适合对着屏幕扫描），
+/// 按内容长度选择最小的版本，并按规范的扣分规则选择掩码。
+pub struct QrCode {
+    /// 边长（模块数）
+    size: usize,
+    modules: Vec<bool>,
+    /// 功能图形（定位、对齐、时序与格式信息），不写入数据也不加掩码
+    function: Vec<bool>,
+}
+
+impl QrCode {
+    /// 编码一段文本
+    pub fn encode(text: &str) -> Result<Self> {
+        let data = text.as_bytes();
+        let version = (1..=40)
+            .find(|&v| 4 + count_bits(v) + data.len() * 8 <= num_data_codewords(v) * 8)
+            .ok_or(anyhow!("内容过长，无法生成二维码"))?;
+
+        // 模式指示符（字节模式）、字符数与数据
+        let mut bits = BitBuffer::default();
+        bits.append(0b0100, 4);
+        bits.append(data.len() as u32, count_bits(version));
+        for &b in data {
+            bits.append(b as u32, 8);
+        }
+        // 结束符、补齐到整字节，再交替填充 0xEC / 0x11
+        let capacity = num_data_codewords(version) * 8;
+        bits.append(0, (capacity - bits.len()).min(4));
+        bits.append(0, (8 - bits.len() % 8) % 8);
+        for pad in [0xEC, 0x11].into_iter().cycle() {
+            if bits.len() >= capacity {
+                break;
+            }
+            bits.append(pad, 8);
+        }
+
+        let size = version * 4 + 17;
+        let mut qr = Self { size, modules: vec![false; size * size], function: vec![false; size * size] };
+        qr.draw_function_patterns(version);
+        qr.draw_codewords(&add_ecc_and_interleave(&bits.into_bytes(), version));
+
+        // 选择扣分最少的掩码，再次应用同一掩码即可撤销
+        let mut best = (0, usize::MAX);
+        for mask in 0..8 {
+            qr.apply_mask(mask);
+            qr.draw_format_bits(mask);
+            let penalty = qr.penalty();
+            if penalty < best.1 {
+                best = (mask, penalty);
+            }
+            qr.apply_mask(mask);
+        }
+        qr.apply_mask(best.0);
+        qr.draw_format_bits(best.0);
+        Ok(qr)
+    }
+
+    fn get(&self, x: usize, y: usize) -> bool {
+        self.modules[y * self.size + x]
+    }
+
+    fn set_function(&mut self, x: usize, y: usize, dark: bool) {
+        self.modules[y * self.size + x] = dark;
+        self.function[y * self.size + x] = true;
+    }
+
+    fn draw_function_patterns(&mut self, version: usize) {
+        let size = self.size;
+        // 时序图形
+        for i in 0..size {
+            self.set_function(6, i, i % 2 == 0);
+            self.set_function(i, 6, i % 2 == 0);
+        }
+        // 三个角的定位图形（含分隔符）
+        for (x, y) in [(3, 3), (size - 4, 3), (3, size - 4)] {
+            self.draw_finder(x, y);
+        }
+        // 对齐图形，跳过与定位图形重叠的三个角
+        let positions = alignment_positions(version, size);
+        let last = positions.len().saturating_sub(1);
+        for (i, &x) in positions.iter().enumerate() {
+            for (j, &y) in positions.iter().enumerate() {
+                if [(0, 0), (0, last), (last, 0)].contains(&(i, j)) {
+                    continue;
+                }
+                self.draw_alignment(x, y);
+            }
+        }
+        // 先占位格式信息，确定掩码后再写入
+        self.draw_format_bits(0);
+        self.draw_version(version);
+    }
+
+    fn draw_finder(&mut self, x: usize, y: usize) {
+        for dy in -4i32..=4 {
+            for dx in -4i32..=4 {
+                let (xx, yy) = (x as i32 + dx, y as i32 + dy);
+                if (0..self.size as i32).contains(&xx) && (0..self.size as i32).contains(&yy) {
+                    let distance = dx.abs().max(dy.abs());
+                    self.set_function(xx as usize, yy as usize, distance != 2 && distance != 4);
+                }
+            }
+        }
+    }
+
+    fn draw_alignment(&mut self, x: usize, y: usize) {
+        for dy in -2i32..=2 {
+            for dx in -2i32..=2 {
+                self.set_function((x as i32 + dx) as usize, (y as i32 + dy) as usize, dx.abs().max(dy.abs()) != 1);
+            }
+        }
+    }
+
+    /// 写入两份格式信息（M 级纠错与掩码编号，BCH 编码）
+    fn draw_format_bits(&mut self, mask: u32) {
+        // M 级纠错的格式位为 00
+        let data = mask;
+        let mut rem = data;
+        for _ in 0..10 {
+            rem = (rem << 1) ^ ((rem >> 9) * 0x537);
+        }
+        let bits = ((data << 10) | rem) ^ 0x5412;
+        let bit = |i: usize| (bits >> i) & 1 != 0;
+
+        // 左上角
+        for i in 0..6 {
+            self.set_function(8, i, bit(i));
+        }
+        self.set_function(8, 7, bit(6));
+        self.set_function(8, 8, bit(7));
+        self.set_function(7, 8, bit(8));
+        for i in 9..15 {
+            self.set_function(14 - i, 8, bit(i));
+        }
+        // 右上角与左下角
+        let size = self.size;
+        for i in 0..8 {
+            self.set_function(size - 1 - i, 8, bit(i));
+        }
+        for i in 8..15 {
+            self.set_function(8, size - 15 + i, bit(i));
+        }
+        // 固定的深色模块
+        self.set_function(8, size - 8, true);
+    }
+
+    /// 版本 7 及以上写入两份版本信息
+    fn draw_version(&mut self, version: usize) {
+        if version < 7 {
+            return;
+        }
+        let mut rem = version as u32;
+        for _ in 0..12 {
+            rem = (rem << 1) ^ ((rem >> 11) * 0x1F25);
+        }
+        let bits = ((version as u32) << 12) | rem;
+        for i in 0..18 {
+            let dark = (bits >> i) & 1 != 0;
+            let (a, b) = (self.size - 11 + i % 3, i / 3);
+            self.set_function(a, b, dark);
+            self.set_function(b, a, dark);
+        }
+    }
+
+    /// 从右下角开始按两列一组蛇形写入码字，跳过功能图形
+    fn draw_codewords(&mut self, data: &[u8]) {
+        let size = self.size;
+        let total = data.len() * 8;
+        let mut i = 0;
+        let mut right = size as i32 - 1;
+        while right >= 1 {
+            // 跳过竖直时序图形所在的列
+            if right == 6 {
+                right = 5;
+            }
+            let upward = (right + 1) & 2 == 0;
+            for vert in 0..size {
+                for j in 0..2 {
+                    let x = right as usize - j;
+                    let y = if upward { size - 1 - vert } else { vert };
+                    if !self.function[y * size + x] && i < total {
+                        self.modules[y * size + x] = (data[i >> 3] >> (7 - (i & 7))) & 1 != 0;
+                        i += 1;
+                    }
+                }
+            }
+            right -= 2;
+        }
+    }
+
+    fn apply_mask(&mut self, mask: u32) {
+        let size = self.size;
+        for y in 0..size {
+            for x in 0..size {
+                let invert = match mask {
+                    0 => (x + y) % 2 == 0,
+                    1 => y % 2 == 0,
+                    2 => x % 3 == 0,
+                    3 => (x + y) % 3 == 0,
+                    4 => (x / 3 + y / 2) % 2 == 0,
+                    5 => x * y % 2 + x * y % 3 == 0,
+                    6 => (x * y % 2 + x * y % 3) % 2 == 0,
+                    _ => ((x + y) % 2 + x * y % 3) % 2 == 0,
+                };
+                let index = y * size + x;
+                self.modules[index] ^= invert && !self.function[index];
+            }
+        }
+    }
+
+    /// 按规范计算掩码扣分：连续同色、2x2 同色块、类似定位图形的序列与深浅比例
+    fn penalty(&self) -> usize {
+        let size = self.size;
+        let mut score = 0;
+        for horizontal in [true, false] {
+            for a in 0..size {
+                let line: Vec<bool> = (0..size).map(|b| if horizontal { self.get(b, a) } else { self.get(a, b) }).collect();
+                let mut run = 1;
+                for b in 1..=size {
+                    if b < size && line[b] == line[b - 1] {
+                        run += 1;
+                        continue;
+                    }
+                    if run >= 5 {
+                        score += run - 2;
+                    }
+                    run = 1;
+                }
+                const FINDER_LIKE: [bool; 7] = [true, false, true, true, true, false, true];
+                for b in 0..size.saturating_sub(6) {
+                    // 任一侧有 4 个浅色模块（超出边界视为浅色）
+                    if line[b..b + 7] == FINDER_LIKE
+                        && ((b.saturating_sub(4)..b).all(|i| !line[i]) || (b + 7..b + 11).all(|i| i >= size || !line[i]))
+                    {
+                        score += 40;
+                    }
+                }
+            }
+        }
+        for y in 0..size - 1 {
+            for x in 0..size - 1 {
+                let color = self.get(x, y);
+                if color == self.get(x + 1, y) && color == self.get(x, y + 1) && color == self.get(x + 1, y + 1) {
+                    score += 3;
+                }
+            }
+        }
+        let dark = self.modules.iter().filter(|&&m| m).count();
+        let percent = dark * 100 / (size * size);
+        score + percent.abs_diff(50) / 5 * 10
+    }
+
+    /// 渲染为灰度 PNG，`scale` 为每个模块的像素数
+    pub fn to_png(&self, scale: u32) -> Result<Vec<u8>> {
+        let modules = self.size as u32 + QUIET_ZONE * 2;
+        let side = modules * scale;
+        if scale == 0 || side > MAX_IMAGE_SIZE {
+            return Err(anyhow!("scale 须在 1 ~ {} 之间", MAX_IMAGE_SIZE / modules));
+        }
+        let mut frame = ffmpeg::frame::Video::new(ffmpeg::format::Pixel::GRAY8, side, side);
+        let stride = frame.stride(0);
+        let data = frame.data_mut(0);
+        for y in 0..side {
+            for x in 0..side {
+                let (mx, my) = ((x / scale) as i64 - QUIET_ZONE as i64, (y / scale) as i64 - QUIET_ZONE as i64);
+                let inside = (0..self.size as i64).contains(&mx) && (0..self.size as i64).contains(&my);
+                let dark = inside && self.get(mx as usize, my as usize);
+                data[y as usize * stride + x as usize] = if dark { 0 } else { 255 };
+            }
+        }
+        encode_png(&frame)
+    }
+}
+
+/// 字符数字段的位数（字节模式）
+fn count_bits(version: usize) -> usize {
+    if version <= 9 { 8 } else { 16 }
+}
+
+/// 版本可容纳的数据与纠错模块数（不含功能图形与格式、版本信息）
+fn num_raw_data_modules(version: usize) -> usize {
+    let mut result = (16 * version + 128) * version + 64;
+    if version >= 2 {
+        let num_align = version / 7 + 2;
+        result -= (25 * num_align - 10) * num_align - 55;
+        if version >= 7 {
+            result -= 36;
+        }
+    }
+    result
+}
+
+/// M 级纠错下版本可容纳的数据码字数
+fn num_data_codewords(version: usize) -> usize {
+    num_raw_data_modules(version) / 8 - ECC_CODEWORDS_PER_BLOCK[version - 1] * NUM_ERROR_CORRECTION_BLOCKS[version - 1]
+}
+
+/// 对齐图形中心的坐标（横纵相同）
+fn alignment_positions(version: usize, size: usize) -> Vec<usize> {
+    if version == 1 {
+        return Vec::new();
+    }
+    let num_align = version / 7 + 2;
+    let step = (version * 8 + num_align * 3 + 5) / (num_align * 4 - 4) * 2;
+    let mut positions: Vec<usize> = (0..num_align - 1).map(|i| size - 7 - i * step).collect();
+    positions.push(6);
+    positions.reverse();
+    positions
+}
+
+/// 数据分块计算 Reed-Solomon 纠错码，再按列交错
+fn add_ecc_and_interleave(data: &[u8], version: usize) -> Vec<u8> {
+    let num_blocks = NUM_ERROR_CORRECTION_BLOCKS[version - 1];
+    let ecc_len = ECC_CODEWORDS_PER_BLOCK[version - 1];
+    let raw_codewords = num_raw_data_modules(version) / 8;
+    let num_short_blocks = num_blocks - raw_codewords % num_blocks;
+    let short_block_len = raw_codewords / num_blocks;
+
+    let divisor = reed_solomon_divisor(ecc_len);
+    let mut blocks = Vec::with_capacity(num_blocks);
+    let mut offset = 0;
+    for i in 0..num_blocks {
+        let len = short_block_len - ecc_len + usize::from(i >= num_short_blocks);
+        let mut block = data[offset..offset + len].to_vec();
+        offset += len;
+        let ecc = reed_solomon_remainder(&block, &divisor);
+        // 短块补一个占位字节，交错时跳过
+        if i < num_short_blocks {
+            block.push(0);
+        }
+        block.extend(ecc);
+        blocks.push(block);
+    }
+
+    let mut result = Vec::with_capacity(raw_codewords);
+    for i in 0..blocks[0].len() {
+        for (j, block) in blocks.iter().enumerate() {
+            if i != short_block_len - ecc_len || j >= num_short_blocks {
+                result.push(block[i]);
+            }
+        }
+    }
+    result
+}
+
+fn reed_solomon_divisor(degree: usize) -> Vec<u8> {
+    let mut result = vec![0u8; degree];
+    result[degree - 1] = 1;
+    let mut root = 1u8;
+    for _ in 0..degree {
+        for j in 0..degree {
+            result[j] = gf_multiply(result[j], root);
+            if j + 1 < degree {
+                result[j] ^= result[j + 1];
+            }
+        }
+        root = gf_multiply(root, 0x02);
+    }
+    result
+}
+
+fn reed_solomon_remainder(data: &[u8], divisor: &[u8]) -> Vec<u8> {
+    let mut result = vec![0u8; divisor.len()];
+    for &b in data {
+        let factor = b ^ result.remove(0);
+        result.push(0);
+        for (r, &d) in result.iter_mut().zip(divisor) {
+            *r ^= gf_multiply(d, factor);
+        }
+    }
+    result
+}
+
+/// GF(2^8) 乘法，模多项式 0x11D
+fn gf_multiply(x: u8, y: u8) -> u8 {
+    let mut z: u32 = 0;
+    for i in (0..8).rev() {
+        z = (z << 1) ^ ((z >> 7) * 0x11D);
+        z ^= ((y as u32 >> i) & 1) * x as u32;
+    }
+    z as u8
+}
+
+#[derive(Default)]
+struct BitBuffer {
+    bits: Vec<bool>,
+}
+
+impl BitBuffer {
+    fn len(&self) -> usize {
+        self.bits.len()
+    }
+
+    fn append(&mut self, value: u32, len: usize) {
+        self.bits.extend((0..len).rev().map(|i| (value >> i) & 1 != 0));
+    }
+
+    fn into_bytes(self) -> Vec<u8> {
+        self.bits.chunks(8).map(|chunk| chunk.iter().fold(0u8, |acc, &b| (acc << 1) | b as u8)).collect()
+    }
+}
+
+/// 用 FFmpeg 的 PNG 编码器编码单帧
+fn encode_png(frame: &ffmpeg::frame::Video) -> Result<Vec<u8>> {
+    let codec = ffmpeg::encoder::find(ffmpeg::codec::Id::PNG).ok_or(anyhow!("FFmpeg 未编译 PNG 编码器"))?;
+    let mut encoder = ffmpeg::codec::context::Context::new_with_codec(codec).encoder().video()?;
+    encoder.set_width(frame.width());
+    encoder.set_height(frame.height());
+    encoder.set_format(ffmpeg::format::Pixel::GRAY8);
+    encoder.set_time_base((1, 25));
+    let mut encoder = encoder.open_as(codec)?;
+
+    encoder.send_frame(frame)?;
+    encoder.send_eof()?;
+    let mut packet = ffmpeg::Packet::empty();
+    encoder.receive_packet(&mut packet)?;
+    packet.data().map(<[u8]>::to_vec).ok_or(anyhow!("PNG 编码结果为空"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 参考矩阵由独立实现（Kazuhiko Arase 的 QRCode 库）生成：字节模式、M 级纠错、同一掩码。
+    // 覆盖单块（版本 1、3）与多块交错、版本信息和多个对齐图形（版本 7）
+
+    const HELLO: [&str; 21] = [
+        "#######.#..#..#######",
+        "#.....#.####..#.....#",
+        "#.###.#...#.#.#.###.#",
+        "#.###.#.#.#.#.#.###.#",
+        "#.###.#....#..#.###.#",
+        "#.....#....##.#.....#",
+        "#######.#.#.#.#######",
+        "........#..##........",
+        "#.##.###.#.##.#..#.##",
+        ".##.##.#.######..##..",
+        "#...#.#..#.#.......##",
+        "#.##...#...#..####.#.",
+        ".#.######...#..#..#.#",
+        "........####..#...#.#",
+        "#######.#..##..#.....",
+        "#.....#.#.#....#####.",
+        "#.###.#.....######.##",
+        "#.###.#.#.##..#.####.",
+        "#.###.#.##..#.##..#..",
+        "#.....#...#..#.##...#",
+        "#######.#.#..#.#.....",
+    ];
+
+    const VERSION_3: [&str; 29] = [
+        "#######..#...#..#..#..#######",
+        "#.....#...##..##..#...#.....#",
+        "#.###.#.#.#.....#.#...#.###.#",
+        "#.###.#.########.##.#.#.###.#",
+        "#.###.#.#..#..#.#..#..#.###.#",
+        "#.....#.#....###..#.#.#.....#",
+        "#######.#.#.#.#.#.#.#.#######",
+        "........#.......#.##.........",
+        "#.#####..#.#.....##.#.#####..",
+        ".###...#...#.#...###..#.#...#",
+        "..######...#.#####.....##....",
+        "#.#.##...###.#.#...#.##.#..#.",
+        "##.#..##...##.####.###.#.##..",
+        "#..#...####.#....###..#.#.#.#",
+        "..#...#..#.#...##....##...#..",
+        "...#...##.#.#.###.#..#.#...#.",
+        "..###.##...#.....#..##....#..",
+        "#..##...#.##.##.####.##.###.#",
+        "#..#.##.##..#####.#.#.#..##..",
+        "#..#....######.#.....##.#..#.",
+        "#...###.....#.##.#..#####.###",
+        "........#...#...#..##...#####",
+        "#######..####..###.##.#.###..",
+        "#.....#.###...#...#.#...#..##",
+        "#.###.#.##..#..#.#..#######..",
+        "#.###.#.#####.#.#.#.##.#...##",
+        "#.###.#.#...#..##..##.#.##.#.",
+        "#.....#....#.#..#...##.##..#.",
+        "#######.###.#....##......##..",
+    ];
+
+    const VERSION_7: [&str; 45] = [
+        "#######..##...#.#..#.......##.##....#.#######",
+        "#.....#...#...#....#...####....#...#..#.....#",
+        "#.###.#.#.#..####.#.##.#.#..##.###.#..#.###.#",
+        "#.###.#.###.####..#.#.##.#.#.#...#.##.#.###.#",
+        "#.###.#.##.#....##..######.#.##...###.#.###.#",
+        "#.....#.#..######.#.#...#.##....#.....#.....#",
+        "#######.#.#.#.#.#.#.#.#.#.#.#.#.#.#.#.#######",
+        "........#..#.##.#####...#...#.#.###.#........",
+        "#.#####......###..#.########.###.#.#..#####..",
+        "#......######..#####....#..#####....#..###.##",
+        "....#.#...###..###...#.#.####...###.#.######.",
+        "#.##...##...###..#.###.....##...#..####.###..",
+        "##..######..#.#####.#.##.#...###.##..#.#....#",
+        "#...#..#....#.#....###.#...#.##....##..####.#",
+        ".#########.#.#.##.###...###....#.##..##...##.",
+        "##.#........#..#.##..#.#...###..#..#....###.#",
+        "#.#.####.###.##.#####...#......#...#.#.#.....",
+        ".##........##.##...#..##.....##..#.##..#..#.#",
+        "...#..#..##.#...#..###..###....##.######.#.#.",
+        ".##.....#.###.#..##.....##..#..##..##..######",
+        "...########..#..###.#####.##.#...#.######....",
+        ".#..#...#####.#.....#...#....#####.##...###.#",
+        "#####.#.#.#.#..###..#.#.#...#..#..#.#.#.#.##.",
+        ".#..#...#..##.#....##...#...#.####..#...####.",
+        "..########.#..#..#..#####..#........######...",
+        "..#.......#.###.#.#######....##....##..#.##.#",
+        ".##..###..#.#..#..#......##.#...###......###.",
+        ".####....#..#..###.####.#..##.###.######.###.",
+        "#####.#.#.##..##.##.##..#.#..###.#...##.##...",
+        "#.#.##.#.....####.#...#.#...#####..#####.##.#",
+        ".#######.#..#.##..#.#..#####...##.#.#..#.###.",
+        "##.##...#.....##..#.....#..##.#.#..##.##.###.",
+        "#.###.####...##..#..##.###...#.#.##....##....",
+        ".......##.#...##.###..#.#..#.##..#..#....##.#",
+        "....#.##..#.#.#.#.#...##.##.......#.#.....##.",
+        ".####..##.#.#.##...####.###.#####..#.##..##.#",
+        "#..##.##.#.#..#..##.#####.##.##.....#####....",
+        "........#.#.#.###.###...#######.##.##...###.#",
+        "#######.....##.#.####.#.#...#..#..###.#.#.##.",
+        "#.....#.#...#..###..#...#..##.###.###...#####",
+        "#.###.#.#..###...##.#####....#.....#######...",
+        "#.###.#.##.###.##..##.#.....#####..#....#.###",
+        "#.###.#.#.#####..#.#.#..###.#..#..#.#.##...#.",
+        "#.....#....#.##.#.######...##.###..##..####..",
+        "#######.#.###.#..###....###......##.###....#.",
+    ];
+
+    fn assert_matrix(text: &str, expected: &[&str]) {
+        let qr = QrCode::encode(text).unwrap();
+        assert_eq!(qr.size, expected.len());
+        for (y, row) in expected.iter().enumerate() {
+            let actual: String = (0..qr.size).map(|x| if qr.get(x, y) { '#' } else { '.' }).collect();
+            assert_eq!(actual, *row, "第 {} 行不一致", y);
+        }
+    }
+
+    #[test]
+    fn matches_reference_version_1() {
+        assert_matrix("HELLO", &HELLO);
+    }
+
+    #[test]
+    fn matches_reference_version_3() {
+        assert_matrix("http://192.168.1.10:3000/live/cam1.flv", &VERSION_3);
+    }
+
+    #[test]
+    fn matches_reference_version_7() {
+        let text = format!("http://example.com:3000/live/camera-01.flv?token={}", "0123456789abcdef".repeat(4));
+        assert_matrix(&text, &VERSION_7);
+    }
+
+    #[test]
+    fn alignment_positions_match_spec() {
+        assert_eq!(alignment_positions(2, 25), [6, 18]);
+        assert_eq!(alignment_positions(7, 45), [6, 22, 38]);
+        assert_eq!(alignment_positions(32, 145), [6, 34, 60, 86, 112, 138]);
+        assert_eq!(alignment_positions(40, 177), [6, 30, 58, 86, 114, 142, 170]);
+    }
+
+    #[test]
+    fn rejects_oversized_content() {
+        assert!(QrCode::encode(&"a".repeat(2331)).is_ok());
+        assert!(QrCode::encode(&"a".repeat(2332)).is_err());
+    }
+}
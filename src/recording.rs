@@ -0,0 +1,384 @@
+use anyhow::Result;
+use ffmpeg_next as ffmpeg;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant, UNIX_EPOCH};
+use tracing::{info, warn};
+use crate::history::now_secs;
+use crate::usage::{date_of, days_from_civil};
+
+/// 清理过期录制文件的间隔
+pub const CLEANUP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// 录制文件格式
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RecordFormat {
+    /// 分片 MP4（fMP4），进程异常退出时已写入的部分仍可播放
+    #[default]
+    Mp4,
+    Mkv,
+}
+
+impl RecordFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            RecordFormat::Mp4 => "mp4",
+            RecordFormat::Mkv => "mkv",
+        }
+    }
+
+    fn muxer(self) -> &'static str {
+        match self {
+            RecordFormat::Mp4 => "mp4",
+            RecordFormat::Mkv => "matroska",
+        }
+    }
+}
+
+/// 录制配置
+#[derive(Debug, Deserialize, Clone)]
+pub struct RecordingConfig {
+    /// 录制目录，缺省为 `<data_dir>/recordings`
+    #[serde(default)]
+    pub dir: Option<String>,
+    #[serde(default)]
+    pub format: RecordFormat,
+    /// 单个文件的时长（秒），到达后在下一个关键帧处切换新文件
+    #[serde(default = "default_segment_secs")]
+    pub segment_secs: u64,
+    /// 单个文件的大小上限（MB），0 为不限
+    #[serde(default)]
+    pub max_segment_mb: u64,
+    /// 保留天数，0 为不按时间删除
+    #[serde(default)]
+    pub retention_days: u64,
+    /// 所有流的录制文件总大小上限（MB），超出后从最早的文件开始删除，0 为不限
+    #[serde(default)]
+    pub max_total_mb: u64,
+}
+
+impl Default for RecordingConfig {
+    fn default() -> Self {
+        Self {
+            dir: None,
+            format: RecordFormat::default(),
+            segment_secs: default_segment_secs(),
+            max_segment_mb: 0,
+            retention_days: 0,
+            max_total_mb: 0,
+        }
+    }
+}
+
+fn default_segment_secs() -> u64 {
+    600
+}
+
+impl RecordingConfig {
+    /// 所有流的录制目录
+    pub fn root(&self, data_dir: &str) -> PathBuf {
+        match &self.dir {
+            Some(dir) => PathBuf::from(dir),
+            None => PathBuf::from(data_dir).join("recordings"),
+        }
+    }
+
+    /// 是否配置了自动清理
+    pub fn retention_enabled(&self) -> bool {
+        self.retention_days > 0 || self.max_total_mb > 0
+    }
+}
+
+/// 录制状态
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct RecordStatus {
+    pub recording: bool,
+    /// 正在写入的文件
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    /// 本次录制已完成的文件数
+    pub segments: u64,
+    /// 开始录制的时间（Unix 秒）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub started_at: Option<u64>,
+    /// 最近一次写入失败的原因，失败后在下一个关键帧处重新打开文件
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// 单个流的录制开关
+///
+/// 由接口开启或关闭，转码线程在处理数据包时检查；转码器断线重连与自动重启后继续录制，
+/// 流被停止时随转码任务一起释放。录制中的流不会因无观众停止。
+#[derive(Default)]
+pub struct RecordControl {
+    enabled: AtomicBool,
+    status: Mutex<RecordStatus>,
+}
+
+impl RecordControl {
+    /// 开始录制，已在录制时返回 false
+    pub fn start(&self) -> bool {
+        if self.enabled.swap(true, Ordering::Relaxed) {
+            return false;
+        }
+        *self.status.lock().unwrap() = RecordStatus { recording: true, started_at: Some(now_secs()), ..Default::default() };
+        true
+    }
+
+    /// 停止录制，当前文件在转码线程处理下一个数据包时关闭；未在录制时返回 false
+    pub fn stop(&self) -> bool {
+        let stopped = self.enabled.swap(false, Ordering::Relaxed);
+        if stopped {
+            self.status.lock().unwrap().recording = false;
+        }
+        stopped
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn status(&self) -> RecordStatus {
+        self.status.lock().unwrap().clone()
+    }
+}
+
+/// 转码器内的录制输出
+///
+/// 将推流数据额外写入流的录制目录，按时长或大小在视频关键帧处切换文件，文件名为开始时间（UTC），
+/// 同一秒内开始的文件加 `-1`、`-2` 等序号。
+pub struct Recorder {
+    dir: PathBuf,
+    config: RecordingConfig,
+    streams: Vec<ffmpeg::codec::Parameters>,
+    /// 视频输出流，切换文件只在其关键帧处进行；只有音频时在任意数据包处切换
+    video: Option<usize>,
+    segment: Option<Segment>,
+}
+
+struct Segment {
+    octx: ffmpeg::format::context::Output,
+    path: PathBuf,
+    opened_at: Instant,
+    bytes: u64,
+}
+
+impl Recorder {
+    pub fn new(dir: PathBuf, config: RecordingConfig, streams: &[ffmpeg::codec::Parameters]) -> Self {
+        let video = streams.iter().position(|p| p.medium() == ffmpeg::media::Type::Video);
+        Self { dir, config, streams: streams.to_vec(), video, segment: None }
+    }
+
+    /// 写入一个数据包，`time_base` 为数据包当前的时间基；录制关闭后关闭当前文件
+    pub fn write(&mut self, control: &RecordControl, index: usize, packet: &ffmpeg::Packet, time_base: ffmpeg::Rational) {
+        if !control.enabled() {
+            self.close(control);
+            return;
+        }
+        let boundary = match self.video {
+            Some(video) => index == video && packet.is_key(),
+            None => true,
+        };
+        if boundary && self.segment.as_ref().is_none_or(|s| self.full(s)) {
+            self.close(control);
+            match self.open() {
+                Ok(segment) => {
+                    let mut status = control.status.lock().unwrap();
+                    status.file = Some(segment.path.to_string_lossy().into_owned());
+                    status.error = None;
+                    self.segment = Some(segment);
+                }
+                Err(e) => {
+                    warn!("创建录制文件失败: {}", e);
+                    control.status.lock().unwrap().error = Some(format!("创建录制文件失败: {}", e));
+                }
+            }
+        }
+        let Some(segment) = self.segment.as_mut() else {
+            return;
+        };
+        if let Err(e) = segment.write(index, packet, time_base) {
+            warn!("写入录制文件 {} 失败: {}", segment.path.display(), e);
+            control.status.lock().unwrap().error = Some(format!("写入录制文件失败: {}", e));
+            // 丢弃当前文件，下一个关键帧处重新打开
+            self.segment = None;
+        }
+    }
+
+    /// 当前文件是否已达到时长或大小上限
+    fn full(&self, segment: &Segment) -> bool {
+        segment.opened_at.elapsed() >= Duration::from_secs(self.config.segment_secs.max(1))
+            || (self.config.max_segment_mb > 0 && segment.bytes >= self.config.max_segment_mb * 1024 * 1024)
+    }
+
+    fn open(&self) -> Result<Segment> {
+        std::fs::create_dir_all(&self.dir)?;
+        // 同一秒内切换文件或重新开始录制时加序号，不覆盖已有文件
+        let stem = file_name(now_secs());
+        let extension = self.config.format.extension();
+        let path = (0..)
+            .map(|seq| match seq {
+                0 => self.dir.join(format!("{}.{}", stem, extension)),
+                _ => self.dir.join(format!("{}-{}.{}", stem, seq, extension)),
+            })
+            .find(|path| !path.exists())
+            .expect("序号足够");
+        let mut octx = ffmpeg::format::output_as(&path, self.config.format.muxer())?;
+        for params in &self.streams {
+            let mut params = params.clone();
+            // FLV 的 codec_tag 与 MP4 / MKV 不兼容，清空后由封装器重新选择
+            unsafe {
+                (*params.as_mut_ptr()).codec_tag = 0;
+            }
+            let mut ostream = octx.add_stream(ffmpeg::encoder::find(ffmpeg::codec::Id::None))?;
+            ostream.set_parameters(params);
+        }
+        let mut options = ffmpeg::Dictionary::new();
+        // 每个文件的时间戳从 0 开始
+        options.set("avoid_negative_ts", "make_zero");
+        if self.config.format == RecordFormat::Mp4 {
+            options.set("movflags", "+frag_keyframe+empty_moov+default_base_moof");
+        }
+        octx.write_header_with(options)?;
+        info!("开始写入录制文件 {}", path.display());
+        Ok(Segment { octx, path, opened_at: Instant::now(), bytes: 0 })
+    }
+
+    fn close(&mut self, control: &RecordControl) {
+        let Some(segment) = self.segment.take() else {
+            return;
+        };
+        info!("录制文件 {} 已完成", segment.path.display());
+        drop(segment);
+        let mut status = control.status.lock().unwrap();
+        status.segments += 1;
+        status.file = None;
+    }
+}
+
+impl Segment {
+    fn write(&mut self, index: usize, packet: &ffmpeg::Packet, time_base: ffmpeg::Rational) -> Result<()> {
+        let Some(ostream) = self.octx.stream(index) else {
+            return Ok(());
+        };
+        let mut packet = packet.clone();
+        packet.rescale_ts(time_base, ostream.time_base());
+        packet.set_stream(index);
+        packet.set_position(-1);
+        self.bytes += packet.size() as u64;
+        packet.write_interleaved(&mut self.octx)?;
+        Ok(())
+    }
+}
+
+impl Drop for Segment {
+    fn drop(&mut self) {
+        // 切换文件、停止录制与转码结束（包括断线重连）时补写文件尾
+        let _ = self.octx.write_trailer();
+    }
+}
+
+/// 录制文件名：开始时间（UTC），如 `20261015-083000`
+pub fn file_name(secs: u64) -> String {
+    let (year, month, day) = date_of(secs, 0);
+    let time = secs % 86400;
+    format!("{:04}{:02}{:02}-{:02}{:02}{:02}", year, month, day, time / 3600, time % 3600 / 60, time % 60)
+}
+
+/// 解析录制文件名（同一秒内的后续文件带 `-序号` 后缀），返回开始时间（Unix 秒）；不是录制文件时返回 None
+pub fn parse_file_name(file: &str) -> Option<u64> {
+    let (stem, extension) = file.rsplit_once('.')?;
+    if ![RecordFormat::Mp4, RecordFormat::Mkv].iter().any(|f| f.extension() == extension) {
+        return None;
+    }
+    let mut parts = stem.splitn(3, '-');
+    let (date, time) = (parts.next()?, parts.next()?);
+    let seq = parts.next();
+    if date.len() != 8 || time.len() != 6 || seq.is_some_and(|s| s.is_empty() || s.starts_with('0'))
+        || !stem.bytes().all(|b| b.is_ascii_digit() || b == b'-')
+    {
+        return None;
+    }
+    let number = |s: &str| s.parse::<u32>().ok();
+    let (year, month, day) = (number(&date[..4])?, number(&date[4..6])?, number(&date[6..])?);
+    let (hour, minute, second) = (number(&time[..2])?, number(&time[2..4])?, number(&time[4..])?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+    let days = days_from_civil(year as i64, month, day);
+    u64::try_from(days * 86400 + (hour * 3600 + minute * 60 + second) as i64).ok()
+}
+
+/// 已保存的录制文件
+#[derive(Debug, Serialize, Clone)]
+pub struct RecordingFile {
+    #[serde(skip)]
+    pub path: PathBuf,
+    /// 文件名
+    pub file: String,
+    /// 开始时间（Unix 秒），来自文件名
+    pub start: u64,
+    /// 最后写入的时间（Unix 秒）
+    pub end: u64,
+    pub bytes: u64,
+}
+
+/// 列出目录中的录制文件，按开始时间排序；目录不存在时为空
+pub fn list(dir: &Path) -> Vec<RecordingFile> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut files: Vec<RecordingFile> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let file = entry.file_name().into_string().ok()?;
+            let start = parse_file_name(&file)?;
+            let metadata = entry.metadata().ok().filter(|m| m.is_file())?;
+            let end = metadata
+                .modified()
+                .ok()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map_or(start, |d| d.as_secs().max(start));
+            Some(RecordingFile { path: entry.path(), file, start, end, bytes: metadata.len() })
+        })
+        .collect();
+    files.sort_by(|a, b| a.start.cmp(&b.start).then_with(|| a.file.cmp(&b.file)));
+    files
+}
+
+/// 按保留天数与总大小上限清理录制文件，正在写入的文件不删除；返回删除的文件数与字节数
+pub fn cleanup(root: &Path, config: &RecordingConfig, active: &[PathBuf]) -> (usize, u64) {
+    let Ok(entries) = std::fs::read_dir(root) else {
+        return (0, 0);
+    };
+    let mut files: Vec<RecordingFile> = entries.flatten().flat_map(|entry| list(&entry.path())).collect();
+    // 所有流一起按时间排序，容量不足时先删除最早的文件
+    files.sort_by_key(|f| f.start);
+    let cutoff = now_secs().saturating_sub(config.retention_days * 86400);
+    let limit = config.max_total_mb.saturating_mul(1024 * 1024);
+    let mut total: u64 = files.iter().map(|f| f.bytes).sum();
+    let (mut removed, mut freed) = (0, 0);
+    for file in &files {
+        let expired = config.retention_days > 0 && file.end < cutoff;
+        let over_quota = config.max_total_mb > 0 && total > limit;
+        if !expired && !over_quota {
+            continue;
+        }
+        if active.contains(&file.path) {
+            continue;
+        }
+        match std::fs::remove_file(&file.path) {
+            Ok(()) => {
+                total -= file.bytes;
+                removed += 1;
+                freed += file.bytes;
+            }
+            Err(e) => warn!("删除录制文件 {} 失败: {}", file.path.display(), e),
+        }
+    }
+    (removed, freed)
+}
@@ -0,0 +1,306 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+use tracing::{error, info};
+use crate::config::StreamConfig;
+use crate::store::Store;
+use crate::vendor::VendorPresets;
+
+/// 流注册表
+///
+/// 由配置文件中的流（静态）与运行时导入/添加的流（动态）组成，
+/// 动态流持久化在本地数据库中，同名时动态流覆盖静态流。
+pub struct StreamRegistry {
+    static_streams: RwLock<Vec<StreamConfig>>,
+    dynamic_streams: RwLock<Vec<StreamConfig>>,
+    store: Arc<Store>,
+    changes: Mutex<ChangeLog>,
+    presets: VendorPresets,
+}
+
+/// 流变更记录，用于增量同步
+///
+/// 每次新增 / 修改 / 删除流时版本号加一，并记录每个流最后变更的版本。版本号只在进程内有效，
+/// 游标中带有进程启动时间，服务重启后旧游标失效，客户端需要重新全量同步。
+struct ChangeLog {
+    epoch: u64,
+    version: u64,
+    /// 流名称 -> (新增时的版本, 最后修改的版本)，启动时已有的流不在其中，视为版本 0
+    streams: HashMap<String, (u64, u64)>,
+    /// 已删除的流名称 -> 删除时的版本
+    removed: HashMap<String, u64>,
+}
+
+impl ChangeLog {
+    fn cursor(&self) -> String {
+        format!("{}-{}", self.epoch, self.version)
+    }
+
+    /// 解析本进程签发的游标，返回版本号
+    fn parse(&self, cursor: &str) -> Option<u64> {
+        let (epoch, version) = cursor.split_once('-')?;
+        let version = version.parse().ok()?;
+        (epoch.parse() == Ok(self.epoch) && version <= self.version).then_some(version)
+    }
+}
+
+/// 删除动态流的结果
+#[derive(Debug, PartialEq)]
+pub enum Removal {
+    /// 已删除
+    Removed,
+    /// 已删除运行时的修改，恢复为配置文件中的同名流
+    Reverted,
+    /// 配置文件中的流，只能修改配置文件删除
+    Static,
+    NotFound,
+}
+
+/// 增量同步结果
+#[derive(Debug, Serialize)]
+pub struct Changes {
+    /// 下次请求携带的游标
+    pub cursor: String,
+    /// 游标缺失、无效或已过期（如服务重启），`added` 为全部流，客户端应替换整个列表
+    pub reset: bool,
+    pub added: Vec<StreamConfig>,
+    pub modified: Vec<StreamConfig>,
+    pub removed: Vec<String>,
+}
+
+impl StreamRegistry {
+    /// 读取动态流失败时返回错误，避免之后保存时删除数据库中的动态流
+    pub fn new(mut static_streams: Vec<StreamConfig>, store: Arc<Store>, presets: VendorPresets) -> Result<Self> {
+        let mut dynamic_streams = store.streams().context("读取动态流配置失败")?;
+        if !dynamic_streams.is_empty() {
+            info!("已加载 {} 个动态流配置", dynamic_streams.len());
+        }
+
+        // 按厂商预设生成地址，失败时保留原地址，播放时报告不支持的地址
+        for stream in static_streams.iter_mut().chain(dynamic_streams.iter_mut()) {
+            if let Err(e) = presets.expand(stream) {
+                error!("{:#}", e);
+            }
+        }
+
+        let epoch = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis() as u64);
+        Ok(Self {
+            static_streams: RwLock::new(static_streams),
+            dynamic_streams: RwLock::new(dynamic_streams),
+            store,
+            changes: Mutex::new(ChangeLog { epoch, version: 0, streams: HashMap::new(), removed: HashMap::new() }),
+            presets,
+        })
+    }
+
+    /// 按厂商预设生成流的地址，导入时在校验前调用
+    pub fn expand(&self, stream: &mut StreamConfig) -> Result<()> {
+        self.presets.expand(stream)
+    }
+
+    /// 所有流（静态 + 动态，同名以动态为准）
+    pub fn list(&self) -> Vec<StreamConfig> {
+        self.merged(&self.dynamic_streams.read().unwrap())
+    }
+
+    fn merged(&self, dynamic: &[StreamConfig]) -> Vec<StreamConfig> {
+        let mut streams: Vec<StreamConfig> = self
+            .static_streams
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|s| !dynamic.iter().any(|d| d.name == s.name))
+            .cloned()
+            .collect();
+        streams.extend(dynamic.iter().cloned());
+        streams
+    }
+
+    /// 游标之后新增、修改、删除的流；游标缺失或无效时返回全部流
+    pub fn changes(&self, since: Option<&str>) -> Changes {
+        // 持有读锁，保证流列表与变更记录一致
+        let dynamic = self.dynamic_streams.read().unwrap();
+        let streams = self.merged(&dynamic);
+        let log = self.changes.lock().unwrap();
+        let cursor = log.cursor();
+        let Some(since) = since.and_then(|c| log.parse(c)) else {
+            return Changes { cursor, reset: true, added: streams, modified: Vec::new(), removed: Vec::new() };
+        };
+
+        let mut changes = Changes { cursor, reset: false, added: Vec::new(), modified: Vec::new(), removed: Vec::new() };
+        for stream in streams {
+            match log.streams.get(&stream.name) {
+                Some((added, _)) if *added > since => changes.added.push(stream),
+                Some((_, modified)) if *modified > since => changes.modified.push(stream),
+                _ => {}
+            }
+        }
+        let mut removed: Vec<String> = log
+            .removed
+            .iter()
+            .filter(|(_, version)| **version > since)
+            .map(|(name, _)| name.clone())
+            .collect();
+        removed.sort();
+        changes.removed = removed;
+        changes
+    }
+
+    /// 按名称查找流，名称不存在时按别名查找
+    pub fn get(&self, name: &str) -> Option<StreamConfig> {
+        let found = {
+            let dynamic = self.dynamic_streams.read().unwrap();
+            let static_streams = self.static_streams.read().unwrap();
+            dynamic
+                .iter()
+                .find(|s| s.name == name)
+                .or_else(|| static_streams.iter().find(|s| s.name == name))
+                .cloned()
+        };
+        found.or_else(|| self.list().into_iter().find(|s| s.aliases.iter().any(|a| a == name)))
+    }
+
+    /// 是否为运行时导入 / 添加的流（覆盖了配置文件中的同名流时也为 true）
+    pub fn is_dynamic(&self, name: &str) -> bool {
+        self.dynamic_streams.read().unwrap().iter().any(|s| s.name == name)
+    }
+
+    /// 把别名解析为流的正式名称（`{别名}_sub` 解析为 `{正式名称}_sub`），不是别名时原样返回
+    ///
+    /// 别名与正式名称共用同一个转码任务，运行状态相关的接口都按正式名称查找。
+    pub fn canonical(&self, name: &str) -> String {
+        let streams = self.list();
+        let alias_of = |alias: &str| {
+            if streams.iter().any(|s| s.name == alias) {
+                return None;
+            }
+            streams.iter().find(|s| s.aliases.iter().any(|a| a == alias)).map(|s| s.name.clone())
+        };
+        if let Some(canonical) = alias_of(name) {
+            return canonical;
+        }
+        if let Some(base) = name.strip_suffix("_sub")
+            && let Some(canonical) = alias_of(base)
+        {
+            return format!("{}_sub", canonical);
+        }
+        name.to_string()
+    }
+
+    /// 与其他流的名称或别名重复的别名
+    pub fn alias_conflicts(&self) -> Vec<String> {
+        let streams = self.list();
+        let mut conflicts: Vec<String> = streams
+            .iter()
+            .flat_map(|s| s.aliases.iter().map(move |a| (s, a)))
+            .filter(|(owner, alias)| {
+                streams
+                    .iter()
+                    .any(|s| s.name == **alias || (s.name != owner.name && s.aliases.contains(alias)))
+            })
+            .map(|(_, alias)| alias.clone())
+            .collect();
+        conflicts.sort();
+        conflicts.dedup();
+        conflicts
+    }
+
+    /// 批量新增或覆盖动态流，并持久化
+    pub fn upsert_many(&self, streams: Vec<StreamConfig>) -> Result<()> {
+        let mut dynamic = self.dynamic_streams.write().unwrap();
+        let static_streams = self.static_streams.read().unwrap();
+        let mut log = self.changes.lock().unwrap();
+        for stream in streams {
+            let previous = dynamic
+                .iter()
+                .chain(static_streams.iter())
+                .find(|s| s.name == stream.name)
+                .map(|s| serde_json::to_value(s).ok());
+            // 内容未变化的覆盖不产生变更
+            if previous.is_none() || previous != Some(serde_json::to_value(&stream).ok()) {
+                log.version += 1;
+                let version = log.version;
+                log.removed.remove(&stream.name);
+                let added = if previous.is_some() { 0 } else { version };
+                log.streams.entry(stream.name.clone()).or_insert((added, version)).1 = version;
+            }
+            match dynamic.iter_mut().find(|s| s.name == stream.name) {
+                Some(existing) => *existing = stream,
+                None => dynamic.push(stream),
+            }
+        }
+        self.persist(&dynamic)
+    }
+
+    /// 删除动态流，并持久化
+    pub fn remove(&self, name: &str) -> Result<Removal> {
+        let mut dynamic = self.dynamic_streams.write().unwrap();
+        let static_streams = self.static_streams.read().unwrap();
+        let Some(index) = dynamic.iter().position(|s| s.name == name) else {
+            let exists = static_streams.iter().any(|s| s.name == name);
+            return Ok(if exists { Removal::Static } else { Removal::NotFound });
+        };
+        let removed = dynamic.remove(index);
+        self.persist(&dynamic)?;
+
+        let mut log = self.changes.lock().unwrap();
+        log.version += 1;
+        let version = log.version;
+        match static_streams.iter().find(|s| s.name == name) {
+            Some(original) => {
+                // 恢复为静态配置，内容相同时不产生变更
+                if serde_json::to_value(original).ok() != serde_json::to_value(&removed).ok() {
+                    log.streams.entry(name.to_string()).or_insert((0, version)).1 = version;
+                }
+                Ok(Removal::Reverted)
+            }
+            None => {
+                log.streams.remove(name);
+                log.removed.insert(name.to_string(), version);
+                Ok(Removal::Removed)
+            }
+        }
+    }
+
+    /// 重新加载配置文件中的流，返回有变化的流数量（被动态流覆盖的不计）
+    pub fn reload_static(&self, mut streams: Vec<StreamConfig>) -> usize {
+        for stream in &mut streams {
+            if let Err(e) = self.presets.expand(stream) {
+                error!("{:#}", e);
+            }
+        }
+        let dynamic = self.dynamic_streams.read().unwrap();
+        let mut static_streams = self.static_streams.write().unwrap();
+        let mut log = self.changes.lock().unwrap();
+        let overridden = |name: &str| dynamic.iter().any(|d| d.name == name);
+        let mut changed = 0;
+        for stream in streams.iter().filter(|s| !overridden(&s.name)) {
+            let previous = static_streams.iter().find(|s| s.name == stream.name);
+            if previous.is_some_and(|p| serde_json::to_value(p).ok() == serde_json::to_value(stream).ok()) {
+                continue;
+            }
+            log.version += 1;
+            let version = log.version;
+            log.removed.remove(&stream.name);
+            let added = if previous.is_some() { 0 } else { version };
+            log.streams.entry(stream.name.clone()).or_insert((added, version)).1 = version;
+            changed += 1;
+        }
+        for old in static_streams.iter().filter(|s| !overridden(&s.name) && !streams.iter().any(|n| n.name == s.name)) {
+            log.version += 1;
+            let version = log.version;
+            log.streams.remove(&old.name);
+            log.removed.insert(old.name.clone(), version);
+            changed += 1;
+        }
+        *static_streams = streams;
+        changed
+    }
+
+    fn persist(&self, dynamic: &[StreamConfig]) -> Result<()> {
+        self.store.save_streams(dynamic)
+    }
+}
@@ -0,0 +1,174 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use tracing::{error, info, warn};
+use crate::config::AppConfig;
+use crate::registry::StreamRegistry;
+use crate::srs::{self, SrsClient};
+
+/// 检查配置文件是否修改的间隔
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// 修改后立即生效的顶层配置项（与 [`hot_update`] 中复制的字段一致），其余配置项修改后需要重启服务
+const HOT_KEYS: &[&str] = &["version", "streams", "api_keys", "srs", "proxy", "play_overrides", "admission", "audit"];
+
+/// config crate 按 `File::with_name("config")` 查找配置文件时尝试的扩展名
+const EXTENSIONS: &[&str] = &["toml", "json", "yaml", "yml", "ini", "ron", "json5"];
+
+/// 重新加载的结果
+#[derive(Serialize)]
+pub struct Reloaded {
+    /// 已生效的配置项
+    pub changed: Vec<String>,
+    /// 已修改但需要重启服务才能生效的配置项
+    pub restart_required: Vec<String>,
+}
+
+/// 某一时刻的配置，与按其创建的 SRS 客户端一起替换
+struct Snapshot {
+    config: Arc<AppConfig>,
+    srs: Arc<SrsClient>,
+    /// 原始配置内容，用于比较哪些配置项发生了变化
+    raw: Value,
+}
+
+/// 可热加载的配置
+///
+/// 配置文件修改后重新加载并替换可热加载的配置项，请求处理中通过 `config()` / `srs()` 取当前值；
+/// 已在运行的转码任务继续使用启动时的参数，下次启动时生效。
+pub struct LiveConfig {
+    current: RwLock<Snapshot>,
+}
+
+impl LiveConfig {
+    pub fn new(config: Arc<AppConfig>, raw: Value) -> Self {
+        let srs = Arc::new(SrsClient::new(&config.srs, srs::http_client(&config.srs.http)));
+        Self {
+            current: RwLock::new(Snapshot { config, srs, raw }),
+        }
+    }
+
+    pub fn config(&self) -> Arc<AppConfig> {
+        self.current.read().unwrap().config.clone()
+    }
+
+    pub fn srs(&self) -> Arc<SrsClient> {
+        self.current.read().unwrap().srs.clone()
+    }
+
+    /// 重新加载配置文件，加载失败时保留当前配置
+    ///
+    /// 只替换 [`HOT_KEYS`] 中的配置项，其余配置项保持当前值直到重启，避免请求处理读到只生效一半的修改
+    /// （如 `/hls/` 已返回 404 而 HLS 输出仍在写入）。
+    pub fn reload(&self, registry: &StreamRegistry) -> anyhow::Result<Reloaded> {
+        let (config, mut raw) = AppConfig::load()?;
+        let mut current = self.current.write().unwrap();
+        let (changed, restart_required): (Vec<String>, Vec<String>) =
+            changed_keys(&current.raw, &raw).into_iter().partition(|k| HOT_KEYS.contains(&k.as_str()));
+
+        // 需要重启的配置项保留原值，之后的重新加载仍按当前生效的配置比较
+        if let (Some(new), Some(old)) = (raw.as_object_mut(), current.raw.as_object()) {
+            for key in &restart_required {
+                warn!("配置项 '{}' 已修改，需要重启服务才能生效", key);
+                match old.get(key) {
+                    Some(value) => new.insert(key.clone(), value.clone()),
+                    None => new.remove(key),
+                };
+            }
+        }
+        if changed.is_empty() {
+            current.raw = raw;
+            return Ok(Reloaded { changed, restart_required });
+        }
+
+        let srs = if changed.iter().any(|k| k == "srs") {
+            Arc::new(SrsClient::new(&config.srs, srs::http_client(&config.srs.http)))
+        } else {
+            current.srs.clone()
+        };
+        if changed.iter().any(|k| k == "streams") {
+            let count = registry.reload_static(config.streams.clone());
+            info!("配置文件中的流已重新加载，{} 个流有变化", count);
+        }
+        let config = hot_update(&current.config, config);
+        *current = Snapshot { config: Arc::new(config), srs, raw };
+        Ok(Reloaded { changed, restart_required })
+    }
+
+    /// 在后台监视配置文件，修改后自动重新加载
+    pub fn watch(self: &Arc<Self>, registry: Arc<StreamRegistry>) {
+        let Some(path) = config_path() else {
+            warn!("未找到配置文件，不监视配置修改");
+            return;
+        };
+        info!("监视配置文件 {} 的修改", path.display());
+        let live = self.clone();
+        tokio::spawn(async move {
+            let mut last = modified(&path);
+            let mut interval = tokio::time::interval(POLL_INTERVAL);
+            loop {
+                interval.tick().await;
+                let current = modified(&path);
+                if current == last {
+                    continue;
+                }
+                // 等待编辑器写完，下一次检查时修改时间不再变化再加载
+                tokio::time::sleep(POLL_INTERVAL / 4).await;
+                if modified(&path) != current {
+                    continue;
+                }
+                last = current;
+                match live.reload(&registry) {
+                    Ok(reloaded) if reloaded.changed.is_empty() => {}
+                    Ok(reloaded) => info!("配置文件已重新加载，修改的配置项: {:?}", reloaded.changed),
+                    Err(e) => error!("重新加载配置文件失败，继续使用当前配置: {:#}", e),
+                }
+            }
+        });
+    }
+}
+
+/// 以当前配置为基础，只取新配置中 [`HOT_KEYS`] 对应的字段
+fn hot_update(current: &AppConfig, new: AppConfig) -> AppConfig {
+    AppConfig {
+        version: new.version,
+        streams: new.streams,
+        api_keys: new.api_keys,
+        srs: new.srs,
+        proxy: new.proxy,
+        play_overrides: new.play_overrides,
+        admission: new.admission,
+        audit: new.audit,
+        ..current.clone()
+    }
+}
+
+/// 当前目录下的配置文件
+fn config_path() -> Option<PathBuf> {
+    EXTENSIONS
+        .iter()
+        .map(|ext| PathBuf::from(format!("config.{}", ext)))
+        .find(|path| path.is_file())
+}
+
+fn modified(path: &PathBuf) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// 两份原始配置中取值不同的顶层配置项
+fn changed_keys(old: &Value, new: &Value) -> Vec<String> {
+    let empty = serde_json::Map::new();
+    let old = old.as_object().unwrap_or(&empty);
+    let new = new.as_object().unwrap_or(&empty);
+    let mut keys: Vec<String> = old
+        .keys()
+        .chain(new.keys())
+        .filter(|k| old.get(*k) != new.get(*k))
+        .cloned()
+        .collect();
+    keys.sort();
+    keys.dedup();
+    keys
+}
@@ -0,0 +1,709 @@
+use anyhow::{Context, Result, anyhow, bail};
+use bytes::Bytes;
+use ffmpeg_next as ffmpeg;
+use ipnet::IpNet;
+use percent_encoding::{NON_ALPHANUMERIC, percent_decode_str, utf8_percent_encode};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::tcp::OwnedWriteHalf;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
+use crate::stream_manager::StreamManager;
+
+/// 播放期间为流续期心跳的间隔
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// 单个播放端排队的最大 RTP 包数，超出时断开该播放端
+const PLAYER_QUEUE: usize = 4096;
+
+/// 请求头与 SDP 的长度上限
+const MAX_HEADER_BYTES: usize = 16 * 1024;
+const MAX_BODY_BYTES: usize = 64 * 1024;
+
+const PUBLIC_METHODS: &str = "OPTIONS, DESCRIBE, SETUP, PLAY, TEARDOWN, GET_PARAMETER";
+
+/// RTSP 转发服务配置
+#[derive(Debug, Deserialize, Clone)]
+pub struct RtspServerConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// 监听地址
+    #[serde(default = "default_listen")]
+    pub listen: String,
+    /// 允许播放的客户端网段，为空时只接受本机连接
+    #[serde(default)]
+    pub allowed_networks: Vec<IpNet>,
+    /// 按需启动流后等待出流的最长时间（秒）
+    #[serde(default = "default_start_timeout_secs")]
+    pub start_timeout_secs: u64,
+}
+
+impl Default for RtspServerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            listen: default_listen(),
+            allowed_networks: Vec::new(),
+            start_timeout_secs: default_start_timeout_secs(),
+        }
+    }
+}
+
+fn default_listen() -> String {
+    "0.0.0.0:8554".to_string()
+}
+
+fn default_start_timeout_secs() -> u64 {
+    15
+}
+
+/// 按需启动流，参数为请求的流名称与客户端地址，返回实际运行的流名称
+pub type StartFn = Box<dyn Fn(String, IpAddr) -> Pin<Box<dyn Future<Output = Result<String>> + Send>> + Send + Sync>;
+
+/// 转发的 RTP / RTCP 包
+#[derive(Clone)]
+struct Frame {
+    track: usize,
+    rtcp: bool,
+    data: Bytes,
+}
+
+/// 转码器推来的一路流
+struct Publication {
+    /// 改写后供播放端使用的 SDP
+    sdp: String,
+    tracks: usize,
+    players: Mutex<Vec<mpsc::Sender<Frame>>>,
+}
+
+impl Publication {
+    fn broadcast(&self, frame: Frame) {
+        let mut players = self.players.lock().unwrap();
+        players.retain(|tx| match tx.try_send(frame.clone()) {
+            Ok(()) => true,
+            Err(mpsc::error::TrySendError::Full(_)) => {
+                warn!("RTSP 播放端接收过慢，断开连接");
+                false
+            }
+            Err(mpsc::error::TrySendError::Closed(_)) => false,
+        });
+    }
+}
+
+struct Server {
+    config: RtspServerConfig,
+    /// 转码器推流使用的本机地址前缀
+    publish_base: String,
+    local_ip: IpAddr,
+    stream_manager: Arc<StreamManager>,
+    start: StartFn,
+    publications: Mutex<HashMap<String, Arc<Publication>>>,
+}
+
+static SERVER: OnceLock<Arc<Server>> = OnceLock::new();
+
+/// 启动 RTSP 转发服务
+///
+/// 下游 NVR 等只能拉 RTSP 的设备通过 `rtsp://<服务地址>:8554/<流名称>` 播放。转码器在推流给 SRS 的同时
+/// 经 FFmpeg RTSP 封装器（TCP 交织）把同一份数据推给本服务，本服务再分发给所有播放端，不重复拉摄像头。
+pub async fn start(config: RtspServerConfig, stream_manager: Arc<StreamManager>, start: StartFn) -> Result<()> {
+    let listener = TcpListener::bind(&config.listen)
+        .await
+        .with_context(|| format!("RTSP 转发服务监听 {} 失败", config.listen))?;
+    let local = listener.local_addr()?;
+    // 监听所有地址时转码器经回环地址推流
+    let local_ip = match local.ip() {
+        IpAddr::V4(ip) if ip.is_unspecified() => IpAddr::V4(Ipv4Addr::LOCALHOST),
+        IpAddr::V6(ip) if ip.is_unspecified() => IpAddr::V6(Ipv6Addr::LOCALHOST),
+        ip => ip,
+    };
+    info!("RTSP 转发服务监听 {}", local);
+    // 播放不经过 API Key 认证，未配置网段时不对外开放
+    if config.allowed_networks.is_empty() {
+        warn!("rtsp_server.allowed_networks 未配置，RTSP 转发服务只接受本机连接");
+    }
+
+    let server = Arc::new(Server {
+        config,
+        publish_base: format!("rtsp://{}/", SocketAddr::new(local_ip, local.port())),
+        local_ip,
+        stream_manager,
+        start,
+        publications: Mutex::new(HashMap::new()),
+    });
+    SERVER.set(server.clone()).map_err(|_| anyhow!("RTSP 转发服务已启动"))?;
+    tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, peer)) => {
+                    if !server.is_local(peer.ip()) && !server.allowed(peer.ip()) {
+                        debug!("拒绝 RTSP 客户端 {}: 不在 allowed_networks 中", peer);
+                        continue;
+                    }
+                    tokio::spawn(server.clone().serve(stream, peer));
+                }
+                Err(e) => {
+                    warn!("接受 RTSP 连接失败: {}", e);
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                }
+            }
+        }
+    });
+    Ok(())
+}
+
+/// 转码器推流到本服务的地址，未启用时返回 None
+pub fn publish_url(name: &str) -> Option<String> {
+    SERVER
+        .get()
+        .map(|server| format!("{}{}", server.publish_base, utf8_percent_encode(name, NON_ALPHANUMERIC)))
+}
+
+impl Server {
+    fn is_local(&self, ip: IpAddr) -> bool {
+        ip.is_loopback() || ip == self.local_ip
+    }
+
+    fn allowed(&self, ip: IpAddr) -> bool {
+        self.config.allowed_networks.iter().any(|net| net.contains(&ip))
+    }
+
+    /// 等待转码器开始推流
+    async fn wait_publication(&self, name: &str) -> Option<Arc<Publication>> {
+        let deadline = Instant::now() + Duration::from_secs(self.config.start_timeout_secs.max(1));
+        loop {
+            if let Some(publication) = self.publications.lock().unwrap().get(name) {
+                return Some(publication.clone());
+            }
+            if Instant::now() >= deadline {
+                return None;
+            }
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        }
+    }
+
+    async fn serve(self: Arc<Self>, stream: TcpStream, peer: SocketAddr) {
+        let _ = stream.set_nodelay(true);
+        let (read_half, writer) = stream.into_split();
+        // 读取放在单独的任务中，避免发送数据时取消读到一半的请求
+        let (tx, mut incoming) = mpsc::channel(64);
+        let reader = tokio::spawn(async move {
+            let mut reader = BufReader::new(read_half);
+            loop {
+                match read_message(&mut reader).await {
+                    Ok(Some(message)) => {
+                        if tx.send(message).await.is_err() {
+                            break;
+                        }
+                    }
+                    Ok(None) => break,
+                    Err(e) => {
+                        debug!("读取 RTSP 客户端 {} 的请求失败: {}", peer, e);
+                        break;
+                    }
+                }
+            }
+        });
+
+        let mut conn = Connection::new(self.clone(), peer, writer);
+        let mut heartbeat = tokio::time::interval(HEARTBEAT_INTERVAL);
+        loop {
+            tokio::select! {
+                message = incoming.recv() => {
+                    let Some(message) = message else { break };
+                    match conn.handle(message).await {
+                        Ok(true) => {}
+                        Ok(false) => break,
+                        Err(e) => {
+                            debug!("RTSP 客户端 {} 连接中断: {}", peer, e);
+                            break;
+                        }
+                    }
+                }
+                frame = next_frame(&mut conn.frames) => {
+                    let Some(frame) = frame else {
+                        info!("流 '{}' 的 RTSP 转发已结束，断开客户端 {}", conn.name.as_deref().unwrap_or_default(), peer);
+                        break;
+                    };
+                    if let Err(e) = conn.send_frame(frame).await {
+                        debug!("向 RTSP 客户端 {} 发送数据失败: {}", peer, e);
+                        break;
+                    }
+                }
+                _ = heartbeat.tick(), if conn.frames.is_some() => {
+                    if let Some(name) = &conn.name {
+                        self.stream_manager.heartbeat(name);
+                    }
+                }
+            }
+        }
+        reader.abort();
+        conn.close();
+    }
+}
+
+async fn next_frame(frames: &mut Option<mpsc::Receiver<Frame>>) -> Option<Frame> {
+    match frames {
+        Some(rx) => rx.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// RTSP 请求
+struct Request {
+    method: String,
+    uri: String,
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+impl Request {
+    fn parse(head: &str) -> Result<Self> {
+        let mut lines = head.lines();
+        let start_line = lines.next().unwrap_or_default();
+        let mut parts = start_line.split_whitespace();
+        let (Some(method), Some(uri), Some(version)) = (parts.next(), parts.next(), parts.next()) else {
+            bail!("无效的请求行: {}", start_line);
+        };
+        if !version.starts_with("RTSP/") {
+            bail!("不支持的协议: {}", version);
+        }
+        let headers = lines
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+            .collect();
+        Ok(Self {
+            method: method.to_uppercase(),
+            uri: uri.to_string(),
+            headers,
+            body: String::new(),
+        })
+    }
+
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+enum Incoming {
+    Request(Request),
+    /// 交织的 RTP / RTCP 数据（通道, 数据）
+    Data(u8, Bytes),
+}
+
+/// 读取一个请求或一个交织数据包，连接关闭时返回 None
+async fn read_message<R: AsyncBufRead + Unpin>(reader: &mut R) -> Result<Option<Incoming>> {
+    let Some(&first) = reader.fill_buf().await?.first() else {
+        return Ok(None);
+    };
+    if first == b'$' {
+        let mut header = [0u8; 4];
+        reader.read_exact(&mut header).await?;
+        let mut data = vec![0u8; u16::from_be_bytes([header[2], header[3]]) as usize];
+        reader.read_exact(&mut data).await?;
+        return Ok(Some(Incoming::Data(header[1], Bytes::from(data))));
+    }
+
+    let mut head = String::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).await? == 0 {
+            return Ok(None);
+        }
+        if line.trim().is_empty() {
+            if head.is_empty() {
+                continue;
+            }
+            break;
+        }
+        head.push_str(&line);
+        if head.len() > MAX_HEADER_BYTES {
+            bail!("请求头过长");
+        }
+    }
+    let mut request = Request::parse(&head)?;
+    let length: usize = request.header("Content-Length").and_then(|v| v.parse().ok()).unwrap_or(0);
+    if length > MAX_BODY_BYTES {
+        bail!("请求体过长: {} 字节", length);
+    }
+    let mut body = vec![0u8; length];
+    reader.read_exact(&mut body).await?;
+    request.body = String::from_utf8_lossy(&body).into_owned();
+    Ok(Some(Incoming::Request(request)))
+}
+
+/// RTSP 响应
+struct Reply {
+    status: u16,
+    headers: Vec<(&'static str, String)>,
+    body: String,
+}
+
+impl Reply {
+    fn status(status: u16) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: String::new(),
+        }
+    }
+
+    fn ok() -> Self {
+        Self::status(200)
+    }
+
+    fn header(mut self, name: &'static str, value: impl Into<String>) -> Self {
+        self.headers.push((name, value.into()));
+        self
+    }
+
+    fn body(mut self, body: String) -> Self {
+        self.body = body;
+        self
+    }
+
+    fn reason(&self) -> &'static str {
+        match self.status {
+            200 => "OK",
+            400 => "Bad Request",
+            403 => "Forbidden",
+            404 => "Not Found",
+            455 => "Method Not Valid in This State",
+            461 => "Unsupported Transport",
+            501 => "Not Implemented",
+            503 => "Service Unavailable",
+            _ => "Error",
+        }
+    }
+}
+
+/// 推流端 ANNOUNCE 的内容
+struct Announce {
+    name: String,
+    sdp: String,
+}
+
+/// 单个 RTSP 连接，可能是播放端，也可能是本机转码器的推流
+struct Connection {
+    server: Arc<Server>,
+    peer: SocketAddr,
+    writer: OwnedWriteHalf,
+    session: String,
+    /// 播放的流名称
+    name: Option<String>,
+    source: Option<Arc<Publication>>,
+    /// 播放端: 轨道序号 -> RTP 交织通道（RTCP 为其后一个）
+    channels: HashMap<usize, u8>,
+    frames: Option<mpsc::Receiver<Frame>>,
+    /// 推流端: 交织通道 -> (轨道序号, 是否 RTCP)
+    announce: Option<Announce>,
+    publish_channels: HashMap<u8, (usize, bool)>,
+    publishing: Option<(String, Arc<Publication>)>,
+}
+
+impl Connection {
+    fn new(server: Arc<Server>, peer: SocketAddr, writer: OwnedWriteHalf) -> Self {
+        let mut id = [0u8; 8];
+        let _ = getrandom::getrandom(&mut id);
+        Self {
+            server,
+            peer,
+            writer,
+            session: id.iter().map(|b| format!("{:02X}", b)).collect(),
+            name: None,
+            source: None,
+            channels: HashMap::new(),
+            frames: None,
+            announce: None,
+            publish_channels: HashMap::new(),
+            publishing: None,
+        }
+    }
+
+    /// 处理一个请求，需要关闭连接时返回 false
+    async fn handle(&mut self, message: Incoming) -> Result<bool> {
+        let request = match message {
+            Incoming::Data(channel, data) => {
+                // 播放端发来的 RTCP 接收报告直接忽略
+                if let Some((_, publication)) = &self.publishing
+                    && let Some(&(track, rtcp)) = self.publish_channels.get(&channel)
+                {
+                    publication.broadcast(Frame { track, rtcp, data });
+                }
+                return Ok(true);
+            }
+            Incoming::Request(request) => request,
+        };
+
+        let reply = match request.method.as_str() {
+            "OPTIONS" => Reply::ok().header("Public", PUBLIC_METHODS),
+            "DESCRIBE" => self.describe(&request).await,
+            "ANNOUNCE" => self.announce(&request),
+            "SETUP" => self.setup(&request),
+            "PLAY" => self.play(),
+            "RECORD" => self.record(),
+            "GET_PARAMETER" | "SET_PARAMETER" => Reply::ok(),
+            "TEARDOWN" => {
+                self.reply(&request, Reply::ok()).await?;
+                return Ok(false);
+            }
+            _ => Reply::status(501),
+        };
+        self.reply(&request, reply).await?;
+        Ok(true)
+    }
+
+    async fn reply(&mut self, request: &Request, reply: Reply) -> Result<()> {
+        let mut text = format!(
+            "RTSP/1.0 {} {}\r\nCSeq: {}\r\nServer: rtsp2flv\r\n",
+            reply.status,
+            reply.reason(),
+            request.header("CSeq").unwrap_or("0")
+        );
+        if request.header("Session").is_some() || request.method == "SETUP" {
+            text.push_str(&format!("Session: {};timeout=60\r\n", self.session));
+        }
+        for (name, value) in &reply.headers {
+            text.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        text.push_str(&format!("Content-Length: {}\r\n\r\n", reply.body.len()));
+        text.push_str(&reply.body);
+        self.writer.write_all(text.as_bytes()).await?;
+        Ok(())
+    }
+
+    async fn describe(&mut self, request: &Request) -> Reply {
+        let Some(name) = stream_name(&request.uri) else {
+            return Reply::status(400);
+        };
+        let name = match (self.server.start)(name.clone(), self.peer.ip()).await {
+            Ok(name) => name,
+            Err(e) => {
+                warn!("RTSP 客户端 {} 请求流 '{}' 失败: {:#}", self.peer, name, e);
+                return Reply::status(404);
+            }
+        };
+        let Some(publication) = self.server.wait_publication(&name).await else {
+            warn!("流 '{}' 在 {} 秒内未推流到 RTSP 转发服务", name, self.server.config.start_timeout_secs);
+            return Reply::status(503);
+        };
+        let sdp = publication.sdp.clone();
+        self.name = Some(name);
+        self.source = Some(publication);
+        Reply::ok()
+            .header("Content-Base", format!("{}/", request.uri.trim_end_matches('/')))
+            .header("Content-Type", "application/sdp")
+            .body(sdp)
+    }
+
+    fn announce(&mut self, request: &Request) -> Reply {
+        if !self.server.is_local(self.peer.ip()) {
+            return Reply::status(403);
+        }
+        let Some(name) = stream_name(&request.uri) else {
+            return Reply::status(400);
+        };
+        self.announce = Some(Announce { name, sdp: request.body.clone() });
+        Reply::ok()
+    }
+
+    fn setup(&mut self, request: &Request) -> Reply {
+        let transport = request.header("Transport").unwrap_or_default();
+        let interleaved = interleaved_channel(transport);
+
+        if self.announce.is_some() {
+            let track = track_index(&request.uri).unwrap_or(self.publish_channels.len() / 2);
+            let channel = interleaved.unwrap_or((track * 2) as u8);
+            self.publish_channels.insert(channel, (track, false));
+            self.publish_channels.insert(channel.wrapping_add(1), (track, true));
+            return Reply::ok().header("Transport", transport);
+        }
+
+        let Some(source) = &self.source else {
+            return Reply::status(455);
+        };
+        // 只支持 TCP 交织传输，NVR 一般可在通道设置中选择 TCP
+        if !transport.to_uppercase().contains("RTP/AVP/TCP") {
+            return Reply::status(461);
+        }
+        let track = match track_index(&request.uri) {
+            Some(track) if track < source.tracks => track,
+            None if source.tracks == 1 => 0,
+            _ => return Reply::status(404),
+        };
+        let channel = interleaved.unwrap_or((track * 2) as u8);
+        self.channels.insert(track, channel);
+        Reply::ok().header("Transport", format!("RTP/AVP/TCP;unicast;interleaved={}-{}", channel, channel.wrapping_add(1)))
+    }
+
+    fn play(&mut self) -> Reply {
+        let (Some(name), Some(source)) = (&self.name, &self.source) else {
+            return Reply::status(455);
+        };
+        if self.channels.is_empty() {
+            return Reply::status(455);
+        }
+        if self.frames.is_none() {
+            let (tx, rx) = mpsc::channel(PLAYER_QUEUE);
+            source.players.lock().unwrap().push(tx);
+            self.frames = Some(rx);
+            info!("RTSP 客户端 {} 开始播放流 '{}'", self.peer, name);
+        }
+        Reply::ok().header("Range", "npt=0.000-")
+    }
+
+    fn record(&mut self) -> Reply {
+        let Some(announce) = self.announce.take() else {
+            return Reply::status(455);
+        };
+        let (sdp, tracks) = player_sdp(&announce.sdp);
+        let publication = Arc::new(Publication {
+            sdp,
+            tracks,
+            players: Mutex::new(Vec::new()),
+        });
+        // 转码器重连后替换旧的发布，旧的播放端随之断开
+        self.server
+            .publications
+            .lock()
+            .unwrap()
+            .insert(announce.name.clone(), publication.clone());
+        debug!("流 '{}' 开始推流到 RTSP 转发服务", announce.name);
+        self.publishing = Some((announce.name, publication));
+        Reply::ok()
+    }
+
+    async fn send_frame(&mut self, frame: Frame) -> Result<()> {
+        let Some(&channel) = self.channels.get(&frame.track) else {
+            return Ok(());
+        };
+        let channel = if frame.rtcp { channel.wrapping_add(1) } else { channel };
+        let mut buf = Vec::with_capacity(4 + frame.data.len());
+        buf.push(b'$');
+        buf.push(channel);
+        buf.extend_from_slice(&(frame.data.len() as u16).to_be_bytes());
+        buf.extend_from_slice(&frame.data);
+        self.writer.write_all(&buf).await?;
+        Ok(())
+    }
+
+    fn close(&mut self) {
+        if let Some((name, publication)) = self.publishing.take() {
+            let mut publications = self.server.publications.lock().unwrap();
+            if publications.get(&name).is_some_and(|p| Arc::ptr_eq(p, &publication)) {
+                publications.remove(&name);
+            }
+            debug!("流 '{}' 停止推流到 RTSP 转发服务", name);
+        }
+        if self.frames.is_some()
+            && let Some(name) = &self.name
+        {
+            info!("RTSP 客户端 {} 停止播放流 '{}'", self.peer, name);
+        }
+    }
+}
+
+/// 从请求地址中取出流名称（第一段路径）
+fn stream_name(uri: &str) -> Option<String> {
+    let url = url::Url::parse(uri).ok()?;
+    let segment = url.path().trim_start_matches('/').split('/').next()?;
+    let name = percent_decode_str(segment).decode_utf8().ok()?;
+    (!name.is_empty()).then(|| name.into_owned())
+}
+
+/// 控制地址中的轨道序号：播放端为 `trackID=N`，FFmpeg 推流端为 `streamid=N`
+fn track_index(uri: &str) -> Option<usize> {
+    let (_, index) = uri.rsplit_once("trackID=").or_else(|| uri.rsplit_once("streamid="))?;
+    index.trim_end_matches('/').parse().ok()
+}
+
+/// Transport 头中 `interleaved=a-b` 的起始通道
+fn interleaved_channel(transport: &str) -> Option<u8> {
+    transport
+        .split(';')
+        .find_map(|part| part.trim().strip_prefix("interleaved="))
+        .and_then(|range| range.split('-').next())
+        .and_then(|channel| channel.trim().parse().ok())
+}
+
+/// 把推流端的 SDP 改写为播放端使用的 SDP：连接地址置为 0.0.0.0，每个媒体的控制地址改为 `trackID=N`
+fn player_sdp(sdp: &str) -> (String, usize) {
+    let mut out = String::new();
+    let mut tracks = 0;
+    for line in sdp.lines().map(str::trim_end).filter(|l| !l.is_empty()) {
+        if line.starts_with("a=control:") {
+            continue;
+        }
+        if line.starts_with("m=") {
+            if tracks > 0 {
+                out.push_str(&format!("a=control:trackID={}\r\n", tracks - 1));
+            }
+            tracks += 1;
+        }
+        if line.starts_with("c=") {
+            out.push_str("c=IN IP4 0.0.0.0\r\n");
+        } else {
+            out.push_str(line);
+            out.push_str("\r\n");
+        }
+    }
+    if tracks > 0 {
+        out.push_str(&format!("a=control:trackID={}\r\n", tracks - 1));
+    }
+    (out, tracks)
+}
+
+/// 转码器内的 RTSP 转发输出
+///
+/// 将推流数据额外经 FFmpeg RTSP 封装器（TCP 交织）推给本机的 RTSP 转发服务。
+pub struct RelayOutput {
+    octx: ffmpeg::format::context::Output,
+}
+
+impl RelayOutput {
+    pub fn open(url: &str, streams: &[ffmpeg::codec::Parameters]) -> Result<Self> {
+        let mut octx = ffmpeg::format::output_as(&url, "rtsp")?;
+        for params in streams {
+            let mut params = params.clone();
+            // FLV 的 codec_tag 对 RTP 封装无意义，清空后由封装器重新选择
+            unsafe {
+                (*params.as_mut_ptr()).codec_tag = 0;
+            }
+            let mut ostream = octx.add_stream(ffmpeg::encoder::find(ffmpeg::codec::Id::None))?;
+            ostream.set_parameters(params);
+        }
+        let mut options = ffmpeg::Dictionary::new();
+        options.set("rtsp_transport", "tcp");
+        octx.write_header_with(options)?;
+        Ok(Self { octx })
+    }
+
+    /// 写入一个数据包，`time_base` 为数据包当前的时间基
+    pub fn write(&mut self, index: usize, packet: &ffmpeg::Packet, time_base: ffmpeg::Rational) -> Result<()> {
+        let Some(ostream) = self.octx.stream(index) else {
+            return Ok(());
+        };
+        let mut packet = packet.clone();
+        packet.rescale_ts(time_base, ostream.time_base());
+        packet.set_stream(index);
+        packet.set_position(-1);
+        packet.write_interleaved(&mut self.octx)?;
+        Ok(())
+    }
+}
+
+impl Drop for RelayOutput {
+    fn drop(&mut self) {
+        let _ = self.octx.write_trailer();
+    }
+}
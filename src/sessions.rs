@@ -0,0 +1,100 @@
+use anyhow::{Result, anyhow};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// 观看会话超过该时长没有心跳时失效，与无观众超时一致
+pub const SESSION_TTL: Duration = Duration::from_secs(120);
+
+/// 设备名称最长长度
+pub const MAX_DEVICE_LEN: usize = 64;
+
+/// 一个播放端的观看会话
+#[derive(Debug, Clone, Serialize)]
+pub struct ViewerSession {
+    pub id: String,
+    pub stream: String,
+    /// 客户端自报的设备名称
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+    #[serde(skip)]
+    last_seen: Instant,
+}
+
+/// 观看会话
+///
+/// 播放接口为每个播放端签发会话（未经播放接口的播放端在第一次心跳时签发），心跳与停止请求携带会话后，
+/// 停止流时只在没有其他播放端时才真正停止转码，无观众超时也以最后一个会话过期为准；
+/// 会话可以转移到另一台设备，转码任务保持运行，切换设备不会中断其他观众。
+#[derive(Default)]
+pub struct ViewerSessions {
+    sessions: Mutex<HashMap<String, ViewerSession>>,
+}
+
+impl ViewerSessions {
+    /// 为流签发新会话
+    pub fn open(&self, stream: &str, device: Option<String>) -> Result<String> {
+        let id = new_id()?;
+        let mut sessions = self.sessions.lock().unwrap();
+        prune(&mut sessions);
+        sessions.insert(id.clone(), ViewerSession { id: id.clone(), stream: stream.to_string(), device, last_seen: Instant::now() });
+        Ok(id)
+    }
+
+    /// 会话续期，返回会话所属的流；会话不存在或已过期时返回 None
+    pub fn touch(&self, id: &str) -> Option<String> {
+        let mut sessions = self.sessions.lock().unwrap();
+        prune(&mut sessions);
+        let session = sessions.get_mut(id)?;
+        session.last_seen = Instant::now();
+        Some(session.stream.clone())
+    }
+
+    /// 结束会话
+    pub fn close(&self, id: &str) -> Option<ViewerSession> {
+        self.sessions.lock().unwrap().remove(id)
+    }
+
+    /// 把会话转移到另一台设备：原会话失效，签发同一个流的新会话
+    pub fn transfer(&self, id: &str, device: Option<String>) -> Result<Option<ViewerSession>> {
+        let new_id = new_id()?;
+        let mut sessions = self.sessions.lock().unwrap();
+        prune(&mut sessions);
+        let Some(old) = sessions.remove(id) else {
+            return Ok(None);
+        };
+        let session = ViewerSession { id: new_id.clone(), stream: old.stream, device, last_seen: Instant::now() };
+        sessions.insert(new_id, session.clone());
+        Ok(Some(session))
+    }
+
+    /// 各流的有效会话数，没有会话的流不在结果中
+    pub fn counts(&self) -> HashMap<String, usize> {
+        let mut sessions = self.sessions.lock().unwrap();
+        prune(&mut sessions);
+        let mut counts = HashMap::new();
+        for session in sessions.values() {
+            *counts.entry(session.stream.clone()).or_default() += 1;
+        }
+        counts
+    }
+
+    /// 流当前的有效会话数
+    pub fn count(&self, stream: &str) -> usize {
+        let mut sessions = self.sessions.lock().unwrap();
+        prune(&mut sessions);
+        sessions.values().filter(|s| s.stream == stream).count()
+    }
+}
+
+fn prune(sessions: &mut HashMap<String, ViewerSession>) {
+    let now = Instant::now();
+    sessions.retain(|_, s| now.duration_since(s.last_seen) < SESSION_TTL);
+}
+
+fn new_id() -> Result<String> {
+    let mut buf = [0u8; 16];
+    getrandom::getrandom(&mut buf).map_err(|e| anyhow!("生成会话标识失败: {}", e))?;
+    Ok(buf.iter().map(|b| format!("{:02x}", b)).collect())
+}
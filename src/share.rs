@@ -0,0 +1,292 @@
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tracing::{info, warn};
+use crate::history::now_secs;
+use crate::store::{ShareRow, Store};
+
+/// 签名密钥文件名（位于数据目录）
+const KEY_FILE: &str = "share.key";
+
+/// 过期超过该时长（秒）的链接从数据库删除
+const KEEP_EXPIRED_SECS: u64 = 7 * 86400;
+
+/// 备注最长长度
+pub const MAX_NOTE_LEN: usize = 200;
+
+/// 分享链接配置
+#[derive(Debug, Deserialize, Clone)]
+pub struct ShareConfig {
+    /// 所有分享链接同时观看的访客总数上限，与 API Key / 登录用户的播放分开计算
+    #[serde(default = "default_max_guests")]
+    pub max_guests: usize,
+    /// 未指定时链接的有效期（秒）
+    #[serde(default = "default_ttl_secs")]
+    pub default_ttl_secs: u64,
+    /// 链接有效期上限（秒）
+    #[serde(default = "default_max_ttl_secs")]
+    pub max_ttl_secs: u64,
+}
+
+impl Default for ShareConfig {
+    fn default() -> Self {
+        Self {
+            max_guests: default_max_guests(),
+            default_ttl_secs: default_ttl_secs(),
+            max_ttl_secs: default_max_ttl_secs(),
+        }
+    }
+}
+
+fn default_max_guests() -> usize {
+    20
+}
+
+fn default_ttl_secs() -> u64 {
+    3600
+}
+
+fn default_max_ttl_secs() -> u64 {
+    86400
+}
+
+/// 创建分享链接的请求
+#[derive(Debug, Deserialize, Default)]
+pub struct ShareRequest {
+    /// 有效期（秒），缺省 `share.default_ttl_secs`
+    #[serde(default)]
+    pub ttl_secs: Option<u64>,
+    /// 同时观看人数上限，缺省 1
+    #[serde(default)]
+    pub max_viewers: Option<u32>,
+    /// 备注，如分享对象与用途
+    #[serde(default)]
+    pub note: Option<String>,
+}
+
+/// 分享链接信息
+#[derive(Debug, Serialize)]
+pub struct ShareInfo {
+    pub id: String,
+    pub stream: String,
+    /// 访客播放地址（相对于服务根路径），只在创建时返回
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    pub expires_at: u64,
+    pub max_viewers: u32,
+    /// 当前观看人数
+    pub viewers: u32,
+    pub revoked: bool,
+    pub expired: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    pub created_at: u64,
+}
+
+/// 访客令牌校验失败的原因
+#[derive(Debug)]
+pub enum ShareError {
+    /// 令牌无效或链接不存在
+    Invalid,
+    /// 链接已过期或被撤销
+    Gone,
+    /// 观看人数已满（`true` 为全部访客名额已满）
+    Full(bool),
+    Internal(anyhow::Error),
+}
+
+impl From<anyhow::Error> for ShareError {
+    fn from(e: anyhow::Error) -> Self {
+        ShareError::Internal(e)
+    }
+}
+
+/// 访客分享链接
+///
+/// 为运行中的流生成限时、限人数的播放地址 `/share/{token}.flv`，访客无需账号即可观看；令牌由链接 ID
+/// 与服务端密钥（`<data_dir>/share.key`）的签名组成，数据库中只保存链接 ID，泄露数据库不会泄露可用的链接。
+/// 访客观看人数单独计算，不占用 API Key 与登录用户的名额；链接过期或被撤销后正在观看的访客最迟 20 秒断开。
+pub struct ShareLinks {
+    store: Arc<Store>,
+    key: [u8; 32],
+    /// 各链接当前的观看人数
+    viewers: Arc<Mutex<HashMap<String, u32>>>,
+}
+
+impl ShareLinks {
+    pub fn new(store: Arc<Store>, data_dir: &str) -> Result<Self> {
+        if let Err(e) = store.prune_shares(now_secs().saturating_sub(KEEP_EXPIRED_SECS)) {
+            warn!("清理过期分享链接失败: {:#}", e);
+        }
+        Ok(Self { store, key: load_key(&Path::new(data_dir).join(KEY_FILE))?, viewers: Default::default() })
+    }
+
+    /// 创建分享链接，返回包含访客播放地址的链接信息
+    pub fn create(&self, stream: &str, request: ShareRequest, config: &ShareConfig, base_path: &str) -> Result<ShareInfo> {
+        let ttl = request.ttl_secs.unwrap_or(config.default_ttl_secs);
+        if ttl == 0 || ttl > config.max_ttl_secs {
+            return Err(anyhow!("ttl_secs 须在 1 ~ {} 之间", config.max_ttl_secs));
+        }
+        let max_viewers = request.max_viewers.unwrap_or(1);
+        if max_viewers == 0 || max_viewers as usize > config.max_guests {
+            return Err(anyhow!("max_viewers 须在 1 ~ {} 之间", config.max_guests));
+        }
+        let note = request.note.filter(|n| !n.is_empty());
+        if note.as_ref().is_some_and(|n| n.chars().count() > MAX_NOTE_LEN) {
+            return Err(anyhow!("note 不能超过 {} 个字符", MAX_NOTE_LEN));
+        }
+        let now = now_secs();
+        let row = ShareRow {
+            id: random_hex(8)?,
+            stream: stream.to_string(),
+            expires_at: now + ttl,
+            max_viewers,
+            note,
+            revoked: false,
+            created_at: now,
+        };
+        self.store.add_share(&row)?;
+        info!("创建流 '{}' 的分享链接 {}，有效期 {} 秒，最多 {} 人观看", stream, row.id, ttl, max_viewers);
+        let mut share = self.info(row, now);
+        let token = format!("{}.{}", share.id, self.sign(&share.id, &share.stream, share.expires_at));
+        share.url = Some(format!("{}/share/{}.flv", base_path, token));
+        Ok(share)
+    }
+
+    /// 全部分享链接（不含播放地址）
+    pub fn list(&self) -> Result<Vec<ShareInfo>> {
+        let now = now_secs();
+        Ok(self.store.shares()?.into_iter().map(|row| self.info(row, now)).collect())
+    }
+
+    /// 撤销分享链接，链接不存在时返回 false
+    pub fn revoke(&self, id: &str) -> Result<bool> {
+        let revoked = self.store.revoke_share(id)?;
+        if revoked {
+            info!("分享链接 {} 已撤销", id);
+        }
+        Ok(revoked)
+    }
+
+    /// 校验访客令牌并占用一个观看名额，名额在返回的 [`GuestPass`] 释放时归还
+    pub fn admit(&self, token: &str, config: &ShareConfig) -> Result<GuestPass, ShareError> {
+        let (id, signature) = token.split_once('.').ok_or(ShareError::Invalid)?;
+        let row = self.store.share(id)?.ok_or(ShareError::Invalid)?;
+        if !constant_time_eq(signature.as_bytes(), self.sign(&row.id, &row.stream, row.expires_at).as_bytes()) {
+            return Err(ShareError::Invalid);
+        }
+        if row.revoked || row.expires_at <= now_secs() {
+            return Err(ShareError::Gone);
+        }
+        let mut viewers = self.viewers.lock().unwrap();
+        if viewers.values().sum::<u32>() as usize >= config.max_guests {
+            return Err(ShareError::Full(true));
+        }
+        let count = viewers.entry(row.id.clone()).or_default();
+        if *count >= row.max_viewers {
+            return Err(ShareError::Full(false));
+        }
+        *count += 1;
+        Ok(GuestPass { id: row.id, stream: row.stream, store: self.store.clone(), viewers: self.viewers.clone() })
+    }
+
+    fn info(&self, row: ShareRow, now: u64) -> ShareInfo {
+        let viewers = self.viewers.lock().unwrap().get(&row.id).copied().unwrap_or_default();
+        ShareInfo {
+            id: row.id,
+            stream: row.stream,
+            url: None,
+            expired: row.expires_at <= now,
+            expires_at: row.expires_at,
+            max_viewers: row.max_viewers,
+            viewers,
+            revoked: row.revoked,
+            note: row.note,
+            created_at: row.created_at,
+        }
+    }
+
+    /// 令牌签名：HMAC-SHA256(密钥, "id:stream:expires_at") 的前 16 字节
+    fn sign(&self, id: &str, stream: &str, expires_at: u64) -> String {
+        let mac = hmac_sha256(&self.key, format!("{}:{}:{}", id, stream, expires_at).as_bytes());
+        mac[..16].iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+/// 一个访客的观看名额
+pub struct GuestPass {
+    id: String,
+    pub stream: String,
+    store: Arc<Store>,
+    viewers: Arc<Mutex<HashMap<String, u32>>>,
+}
+
+impl GuestPass {
+    /// 链接是否仍然有效（未过期、未撤销）
+    pub fn valid(&self) -> bool {
+        matches!(self.store.share(&self.id), Ok(Some(row)) if !row.revoked && row.expires_at > now_secs())
+    }
+}
+
+impl Drop for GuestPass {
+    fn drop(&mut self) {
+        let mut viewers = self.viewers.lock().unwrap();
+        if let Some(count) = viewers.get_mut(&self.id) {
+            *count = count.saturating_sub(1);
+            if *count == 0 {
+                viewers.remove(&self.id);
+            }
+        }
+    }
+}
+
+/// 读取签名密钥，不存在时生成
+fn load_key(path: &Path) -> Result<[u8; 32]> {
+    match fs::read_to_string(path) {
+        Ok(hex) => {
+            let hex = hex.trim();
+            let bytes: Vec<u8> = (0..hex.len())
+                .step_by(2)
+                .filter_map(|i| hex.get(i..i + 2).and_then(|b| u8::from_str_radix(b, 16).ok()))
+                .collect();
+            return bytes.try_into().map_err(|_| anyhow!("分享链接密钥文件 {} 已损坏", path.display()));
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(anyhow!("读取分享链接密钥 {} 失败: {}", path.display(), e)),
+    }
+    let mut key = [0u8; 32];
+    getrandom::getrandom(&mut key).map_err(|e| anyhow!("生成分享链接密钥失败: {}", e))?;
+    let hex: String = key.iter().map(|b| format!("{:02x}", b)).collect();
+    fs::write(path, hex).with_context(|| format!("写入分享链接密钥 {} 失败", path.display()))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    }
+    Ok(key)
+}
+
+fn random_hex(len: usize) -> Result<String> {
+    let mut buf = vec![0u8; len];
+    getrandom::getrandom(&mut buf).map_err(|e| anyhow!("生成分享链接 ID 失败: {}", e))?;
+    Ok(buf.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+fn hmac_sha256(key: &[u8; 32], message: &[u8]) -> [u8; 32] {
+    let mut ipad = [0x36u8; 64];
+    let mut opad = [0x5cu8; 64];
+    for (i, b) in key.iter().enumerate() {
+        ipad[i] ^= b;
+        opad[i] ^= b;
+    }
+    let inner = Sha256::new().chain_update(ipad).chain_update(message).finalize();
+    Sha256::new().chain_update(opad).chain_update(inner).finalize().into()
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
@@ -0,0 +1,130 @@
+use anyhow::{Result, anyhow};
+use ffmpeg_next as ffmpeg;
+use lazy_static::lazy_static;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::{Semaphore, oneshot};
+use tracing::debug;
+use crate::config::InputOptions;
+use crate::transcoder::Transcoder;
+
+/// 等待关键帧的超时时间
+pub const SNAPSHOT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 同时为未运行的流临时拉流截图的数量上限，避免仪表盘批量刷新时同时连接大量摄像头
+const MAX_CAPTURES: usize = 4;
+
+lazy_static! {
+    static ref CAPTURES: Semaphore = Semaphore::new(MAX_CAPTURES);
+}
+
+type Waiter = oneshot::Sender<Result<ffmpeg::frame::Video, String>>;
+
+/// 运行中转码任务的截图请求
+///
+/// 转码只做转封装，不解码视频；有请求时转码线程为下一个视频关键帧临时创建解码器，
+/// 解码出原始分辨率的画面交给所有等待的请求，没有请求时不产生任何开销。
+#[derive(Default)]
+pub struct SnapshotRequests {
+    pending: AtomicBool,
+    waiters: Mutex<Vec<Waiter>>,
+}
+
+impl SnapshotRequests {
+    /// 请求下一个关键帧的画面
+    pub fn request(&self) -> oneshot::Receiver<Result<ffmpeg::frame::Video, String>> {
+        let (tx, rx) = oneshot::channel();
+        self.waiters.lock().unwrap().push(tx);
+        self.pending.store(true, Ordering::Relaxed);
+        rx
+    }
+
+    /// 是否有等待中的请求
+    pub fn pending(&self) -> bool {
+        self.pending.load(Ordering::Relaxed)
+    }
+
+    /// 输入中没有视频流时直接拒绝等待中的请求
+    pub fn reject(&self, reason: &str) {
+        self.fulfill(Err(reason.to_string()));
+    }
+
+    /// 送入一个输入包，有请求且为视频关键帧时解码并交给等待的请求
+    pub fn feed(&self, video: &(usize, ffmpeg::codec::Parameters), stream_index: usize, packet: &ffmpeg::Packet) {
+        if !self.pending() || stream_index != video.0 || !packet.is_key() {
+            return;
+        }
+        self.fulfill(decode_key_frame(video.1.clone(), packet).map_err(|e| format!("解码关键帧失败: {}", e)));
+    }
+
+    fn fulfill(&self, result: Result<ffmpeg::frame::Video, String>) {
+        let waiters = std::mem::take(&mut *self.waiters.lock().unwrap());
+        self.pending.store(false, Ordering::Relaxed);
+        for waiter in waiters {
+            // 请求方已超时时忽略
+            let _ = waiter.send(result.clone());
+        }
+    }
+}
+
+/// 单独解码一个关键帧，送入后立即冲刷解码器，不必等待后续数据包
+fn decode_key_frame(parameters: ffmpeg::codec::Parameters, packet: &ffmpeg::Packet) -> Result<ffmpeg::frame::Video> {
+    let mut decoder = ffmpeg::codec::context::Context::from_parameters(parameters)?.decoder().video()?;
+    decoder.send_packet(packet)?;
+    decoder.send_eof()?;
+    let mut frame = ffmpeg::frame::Video::empty();
+    decoder.receive_frame(&mut frame)?;
+    Ok(frame)
+}
+
+/// 临时打开输入源截取一帧，用于未运行的流；阻塞调用，返回原始分辨率的画面
+///
+/// 未能占用截图名额时返回 None，调用方应稍后重试。
+pub fn capture(url: &str, options: &InputOptions) -> Option<Result<ffmpeg::frame::Video>> {
+    let _permit = CAPTURES.try_acquire().ok()?;
+    Some(capture_frame(url, options))
+}
+
+fn capture_frame(url: &str, options: &InputOptions) -> Result<ffmpeg::frame::Video> {
+    let deadline = Instant::now() + SNAPSHOT_TIMEOUT;
+    let (mut ictx, _guard) = Transcoder::open_input(url, options)?;
+    let (index, parameters) = ictx
+        .streams()
+        .best(ffmpeg::media::Type::Video)
+        .map(|s| (s.index(), s.parameters()))
+        .ok_or(anyhow!("输入中没有视频流"))?;
+    for (stream, packet) in ictx.packets() {
+        if Instant::now() > deadline {
+            break;
+        }
+        if stream.index() != index || !packet.is_key() {
+            continue;
+        }
+        match decode_key_frame(parameters.clone(), &packet) {
+            Ok(frame) => return Ok(frame),
+            // 部分摄像头的第一个关键帧缺少参数集，继续等下一个
+            Err(e) => debug!("截图解码失败: {}", e),
+        }
+    }
+    Err(anyhow!("{} 秒内未收到可解码的关键帧", SNAPSHOT_TIMEOUT.as_secs()))
+}
+
+/// 把画面按指定宽度（缺省原始宽度，保持宽高比）缩放并编码为 JPEG
+pub fn encode(frame: &ffmpeg::frame::Video, width: Option<u32>, quality: u32) -> Result<Vec<u8>> {
+    // YUV420 要求宽高为偶数
+    let width = width.unwrap_or(frame.width()).clamp(16, frame.width().max(16)) & !1;
+    let height = ((frame.height() as u64 * width as u64 / frame.width().max(1) as u64) as u32).max(16) & !1;
+    let mut scaler = ffmpeg::software::scaling::Context::get(
+        frame.format(),
+        frame.width(),
+        frame.height(),
+        ffmpeg::format::Pixel::YUVJ420P,
+        width,
+        height,
+        ffmpeg::software::scaling::Flags::BILINEAR,
+    )?;
+    let mut scaled = ffmpeg::frame::Video::empty();
+    scaler.run(frame, &mut scaled)?;
+    crate::preview::encode_jpeg(&mut scaled, quality)
+}
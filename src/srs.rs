@@ -1,14 +1,48 @@
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
-use std::time::Duration;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
 use anyhow::{Result, anyhow};
-use tracing::{info, error};
+use tracing::{info, error, warn};
+use crate::config::{EdgeConfig, StreamConfig, HttpClientConfig, SrsConfig, StreamKeyConfig, TeardownConfig, VerifyMethod, VerifyPlaybackConfig};
 
 #[derive(Clone)]
 pub struct SrsClient {
     client: Client,
     api_url: String,
     playback_url_template: String,
+    edges: Vec<EdgeConfig>,
+    stream_key: StreamKeyConfig,
+    output_name_template: String,
+    /// 播放路径上请求的超时
+    play_timeout: Duration,
+    teardown: TeardownConfig,
+    verify_playback: VerifyPlaybackConfig,
+}
+
+/// 流在 SRS 中的位置，推流地址为 `rtmp://<SRS>:1935/{app}/{stream}`
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamPath {
+    /// 应用名，可以包含多级路径，如 `acme/lobby`
+    pub app: String,
+    /// SRS 流名称
+    pub stream: String,
+}
+
+impl std::fmt::Display for StreamPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.app, self.stream)
+    }
+}
+
+/// SRS 服务健康状态
+#[derive(Debug, Serialize)]
+pub struct SrsHealth {
+    pub reachable: bool,
+    pub latency_ms: Option<u64>,
+    pub version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -17,6 +51,18 @@ struct SrsRequest {
     stream_name: String,
 }
 
+/// 播放时调用 SRS 接口失败，按 `srs.error_policy` 决定是否继续播放
+#[derive(Debug)]
+pub struct ApiError(pub String);
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for ApiError {}
+
 #[derive(Deserialize, Debug)]
 pub struct SrsResponse {
     pub code: i32,
@@ -26,34 +72,97 @@ pub struct SrsResponse {
 
 impl SrsClient {
     /// 创建新的 SRS 客户端实例
-    /// 
-    /// # 参数
-    /// * `api_url` - SRS 服务器的 API 地址
-    /// * `playback_url_template` - 播放地址模板
-    pub fn new(api_url: String, playback_url_template: String) -> Self {
+    ///
+    /// `client` 由 [`http_client`] 创建，在各组件间共享连接池。
+    pub fn new(config: &SrsConfig, client: Client) -> Self {
         Self {
-            client: Client::builder()
-                .timeout(Duration::from_secs(5))
-                .build()
-                .unwrap_or_default(),
-            api_url,
-            playback_url_template,
+            client,
+            api_url: config.api_url.clone(),
+            playback_url_template: config.playback_url_template.clone(),
+            edges: config.edges.clone(),
+            stream_key: config.stream_key.clone(),
+            output_name_template: config.output_name_template.clone(),
+            play_timeout: config.http.play_timeout(),
+            teardown: config.teardown.clone(),
+            verify_playback: config.verify_playback.clone(),
+        }
+    }
+
+    /// SRS 中的流名称
+    pub fn stream_key(&self, name: &str) -> String {
+        self.stream_key.stream_key(name)
+    }
+
+    /// 按推流路径模板生成流在 SRS 中的位置，`stream` 为流配置（自定义地址播放时为 None）
+    ///
+    /// 模板中的流名称、租户、分组按 `stream_key` 规则转换，不会引入额外的路径层级。
+    pub fn stream_path(&self, name: &str, stream: Option<&StreamConfig>) -> Result<StreamPath> {
+        let template = stream
+            .and_then(|s| s.output.name_template.as_deref())
+            .unwrap_or(&self.output_name_template);
+        let field = |field: &str, value: Option<&String>| -> Result<String> {
+            if !template.contains(&format!("{{{}}}", field)) {
+                return Ok(String::new());
+            }
+            value
+                .map(|v| self.stream_key(v))
+                .filter(|k| !k.is_empty())
+                .ok_or_else(|| anyhow!("流 '{}' 未配置 {}，推流路径模板 '{}' 需要该字段", name, field, template))
+        };
+        let tenant = field("tenant", stream.and_then(|s| s.tenant.as_ref()))?;
+        let group = field("group", stream.and_then(|s| s.group.as_ref()))?;
+        let path = render(template, &[("stream_name", &self.stream_key(name)), ("tenant", &tenant), ("group", &group)]);
+
+        let (app, key) = path
+            .rsplit_once('/')
+            .ok_or_else(|| anyhow!("推流路径 '{}' 缺少应用名，格式应为 应用/流名称", path))?;
+        if key.is_empty() || app.split('/').any(str::is_empty) {
+            return Err(anyhow!("流 '{}' 的推流路径 '{}' 无效，请检查名称或 srs.stream_key 配置", name, path));
         }
+        Ok(StreamPath { app: app.to_string(), stream: key.to_string() })
+    }
+
+    /// 找出推流路径相同（或无法生成）的流，返回 (推流路径, 流名称列表)，无法生成的路径为空字符串
+    pub fn key_collisions<'a>(&self, streams: impl IntoIterator<Item = &'a StreamConfig>) -> Vec<(String, Vec<String>)> {
+        let mut keys: std::collections::BTreeMap<String, Vec<String>> = Default::default();
+        for stream in streams {
+            let path = self.stream_path(&stream.name, Some(stream)).map(|p| p.to_string()).unwrap_or_default();
+            keys.entry(path).or_default().push(stream.name.clone());
+        }
+        keys.into_iter().filter(|(key, names)| key.is_empty() || names.len() > 1).collect()
+    }
+
+    /// 根据客户端 IP 选择播放地址模板
+    ///
+    /// 在所有边缘节点的网段中取前缀最长（最精确）的匹配，未匹配时使用默认模板。
+    fn select_template(&self, client_ip: Option<IpAddr>) -> &str {
+        let Some(ip) = client_ip else {
+            return &self.playback_url_template;
+        };
+
+        self.edges
+            .iter()
+            .flat_map(|edge| edge.cidrs.iter().map(move |net| (edge, net)))
+            .filter(|(_, net)| net.contains(&ip))
+            .max_by_key(|(_, net)| net.prefix_len())
+            .map(|(edge, _)| {
+                info!("客户端 {} 匹配边缘节点 '{}'", ip, edge.name);
+                edge.playback_url_template.as_str()
+            })
+            .unwrap_or(&self.playback_url_template)
     }
 
     /// 请求播放流
     /// 
     /// 负责验证 RTSP 地址，发送请求到 SRS，并返回播放地址。
-    pub async fn play_stream(&self, name: &str, rtsp_url: &str) -> Result<String> {
+    /// `client_ip` 用于选择离客户端最近的边缘节点，`scheme` 用于替换模板中的 `{scheme}`。
+    ///
+    /// SRS 接口返回错误或无法连接时返回 [`ApiError`]，由调用方决定是否继续播放。
+    pub async fn play_stream(&self, name: &str, path: &StreamPath, rtsp_url: &str, client_ip: Option<IpAddr>, scheme: &str) -> Result<String> {
         // 1. 校验 RTSP 地址
-        if rtsp_url.trim().is_empty() {
-            return Err(anyhow!("RTSP 地址不能为空"));
-        }
-        if !rtsp_url.to_lowercase().starts_with("rtsp://") {
-            return Err(anyhow!("非法的 RTSP 地址格式: {}", rtsp_url));
-        }
+        Self::validate_url(rtsp_url)?;
 
-        info!("请求 SRS 播放流: {} -> {}", name, rtsp_url);
+        info!("请求 SRS 播放流: {} -> {}", name, crate::vault::redact_url(rtsp_url));
 
         // 2. 构造请求体
         let payload = SrsRequest {
@@ -63,7 +172,11 @@ impl SrsClient {
 
         // 3. 发送请求到 SRS (如果不是本地测试环境)
         if !self.api_url.contains("localhost") {
+             if let Err(e) = injected_delay(self.play_timeout).await {
+                 return Err(ApiError(e.to_string()).into());
+             }
              let res = self.client.post(&self.api_url)
+                .timeout(self.play_timeout)
                 .json(&payload)
                 .send()
                 .await;
@@ -75,23 +188,247 @@ impl SrsClient {
                          // 这里可以添加解析 SRS 返回 JSON 的逻辑，如果 SRS 返回了具体播放地址
                      } else {
                          error!("SRS API 调用失败: 状态码 {}", response.status());
-                         // 根据需求，这里可以选择报错，或者降级处理
-                         // return Err(anyhow!("SRS 服务器返回错误: {}", response.status()));
+                         return Err(ApiError(format!("SRS 服务器返回错误: {}", response.status())).into());
                      }
                  },
                  Err(e) => {
                      error!("连接 SRS API 失败: {}", e);
-                     // return Err(anyhow!("无法连接到 SRS 服务器: {}", e));
+                     return Err(ApiError(format!("无法连接到 SRS 服务器: {}", e)).into());
                  }
              }
         }
 
         // 4. 生成播放地址
-        // 使用配置中的模板进行替换
-        // 对流名称进行简单的 URL 安全处理（替换空格）
-        let safe_name = name.replace(" ", "_").to_lowercase();
-        let playback_url = self.playback_url_template.replace("{stream_name}", &safe_name);
-        
-        Ok(playback_url)
+        Ok(self.playback_url(path, client_ip, scheme))
+    }
+
+    /// 校验拉流地址
+    pub fn validate_url(rtsp_url: &str) -> Result<()> {
+        if rtsp_url.trim().is_empty() {
+            return Err(anyhow!("RTSP 地址不能为空"));
+        }
+        if !crate::transcoder::is_supported_input(rtsp_url) {
+            return Err(anyhow!("非法的 RTSP 地址格式: {}", crate::vault::redact_url(rtsp_url)));
+        }
+        Ok(())
+    }
+
+    /// 生成播放地址（不调用 SRS 接口）
+    ///
+    /// 使用配置中的模板进行替换（按客户端网段选择边缘节点）
+    pub fn playback_url(&self, path: &StreamPath, client_ip: Option<IpAddr>, scheme: &str) -> String {
+        render(self.select_template(client_ip), &[("stream_name", &path.stream), ("app", &path.app), ("scheme", scheme)])
+    }
+
+    /// 等待流在 SRS 上可用，返回是否可用；未开启确认时返回 None
+    pub async fn verify_playback(&self, name: &str, path: &StreamPath, playback_url: &str) -> Option<bool> {
+        let verify = &self.verify_playback;
+        if !verify.enabled {
+            return None;
+        }
+        let started = Instant::now();
+        let deadline = started + Duration::from_millis(verify.max_wait_ms);
+        let interval = Duration::from_millis(verify.interval_ms.max(50));
+        loop {
+            let available = match verify.method {
+                VerifyMethod::Api => self.is_published(path).await,
+                VerifyMethod::Http => self.is_playable(playback_url).await,
+            };
+            match available {
+                Ok(true) => {
+                    info!("流 '{}' 已在 SRS 上可用（等待 {} ms）", name, started.elapsed().as_millis());
+                    return Some(true);
+                }
+                Ok(false) => {}
+                Err(e) => warn!("确认流 '{}' 是否可用失败: {}", name, e),
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                warn!("流 '{}' 在 {} ms 内未在 SRS 上可用", name, verify.max_wait_ms);
+                return Some(false);
+            }
+            tokio::time::sleep(interval.min(remaining)).await;
+        }
+    }
+
+    /// SRS 上指定流是否有活跃的推流端
+    async fn is_published(&self, path: &StreamPath) -> Result<bool> {
+        injected_delay(self.play_timeout).await?;
+        let base = reqwest::Url::parse(&self.api_url)?;
+        let streams: serde_json::Value = self
+            .client
+            .get(base.join("/api/v1/streams/?count=1000")?)
+            .timeout(self.play_timeout)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let published = streams["streams"]
+            .as_array()
+            .and_then(|streams| streams.iter().find(|s| s["name"] == path.stream.as_str() && s["app"] == path.app.as_str()))
+            // 旧版本 SRS 没有 publish 字段，流存在即视为已推流
+            .is_some_and(|s| s.pointer("/publish/active").and_then(|a| a.as_bool()).unwrap_or(true));
+        Ok(published)
+    }
+
+    /// 请求播放地址，只读取响应头
+    async fn is_playable(&self, playback_url: &str) -> Result<bool> {
+        let response = self.client.get(playback_url).timeout(self.play_timeout).send().await?;
+        Ok(response.status().is_success())
+    }
+
+    /// 停止推流后的清理：按配置踢掉 SRS 上该流的客户端并调用通知地址，失败时由后台任务队列重试
+    ///
+    /// SRS 在推流端断开后会让播放端继续等待重新推流，播放器会转圈数十秒，踢掉后播放器立即报错或重连。
+    pub async fn teardown(&self, name: &str, path: &StreamPath) -> Result<()> {
+        injected_delay(Duration::MAX).await?;
+        if self.teardown.kick_clients {
+            let count = self.kick_clients(path).await.map_err(|e| anyhow!("断开 SRS 客户端失败: {}", e))?;
+            if count > 0 {
+                info!("流 '{}' 已停止推流，已断开 SRS 上的 {} 个客户端", name, count);
+            }
+        }
+        if let Some(hook_url) = &self.teardown.hook_url {
+            let body = serde_json::json!({ "action": "on_unpublish", "stream": name, "srs_app": path.app, "srs_stream": path.stream });
+            self.client
+                .post(hook_url)
+                .json(&body)
+                .send()
+                .await
+                .and_then(|r| r.error_for_status())
+                .map_err(|e| anyhow!("停止推流通知失败: {}", e))?;
+        }
+        Ok(())
+    }
+
+    /// 踢掉 SRS 上指定流的全部客户端，返回断开的数量
+    async fn kick_clients(&self, path: &StreamPath) -> Result<usize> {
+        let base = reqwest::Url::parse(&self.api_url)?;
+        let streams: serde_json::Value = self
+            .client
+            .get(base.join("/api/v1/streams/?count=1000")?)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let stream_id = streams["streams"]
+            .as_array()
+            .and_then(|streams| streams.iter().find(|s| s["name"] == path.stream.as_str() && s["app"] == path.app.as_str()))
+            .and_then(|s| s["id"].as_str().map(str::to_string));
+        let Some(stream_id) = stream_id else {
+            return Ok(0);
+        };
+
+        let clients: serde_json::Value = self
+            .client
+            .get(base.join("/api/v1/clients/?count=1000")?)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let ids: Vec<String> = clients["clients"]
+            .as_array()
+            .map(|clients| {
+                clients
+                    .iter()
+                    .filter(|c| c["stream"] == stream_id.as_str())
+                    .filter_map(|c| c["id"].as_str().map(str::to_string))
+                    .collect()
+            })
+            .unwrap_or_default();
+        for id in &ids {
+            self.client
+                .delete(base.join(&format!("/api/v1/clients/{}", id))?)
+                .send()
+                .await?
+                .error_for_status()?;
+        }
+        Ok(ids.len())
+    }
+
+    /// 等待 SRS HTTP API 可用，重试间隔从 1 秒起逐次加倍（最长 10 秒），超过 `max_wait` 放弃，返回是否可用
+    pub async fn wait_ready(&self, max_wait: Duration) -> bool {
+        let deadline = Instant::now() + max_wait;
+        let mut delay = Duration::from_secs(1);
+        loop {
+            let health = self.health().await;
+            if health.reachable {
+                return true;
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return false;
+            }
+            info!("SRS 暂不可用（{}），{} 秒后重试", health.error.unwrap_or_default(), delay.min(remaining).as_secs());
+            tokio::time::sleep(delay.min(remaining)).await;
+            delay = (delay * 2).min(Duration::from_secs(10));
+        }
+    }
+
+    /// 检查 SRS HTTP API 是否可用（请求版本接口）
+    pub async fn health(&self) -> SrsHealth {
+        let url = match reqwest::Url::parse(&self.api_url).and_then(|u| u.join("/api/v1/versions")) {
+            Ok(url) => url,
+            Err(e) => {
+                return SrsHealth { reachable: false, latency_ms: None, version: None, error: Some(format!("SRS API 地址无效: {}", e)) };
+            }
+        };
+        let started = std::time::Instant::now();
+        let result = match injected_delay(Duration::from_secs(2)).await {
+            Ok(()) => self.client.get(url).timeout(Duration::from_secs(2)).send().await.map_err(|e| e.to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+        let latency_ms = Some(started.elapsed().as_millis() as u64);
+        match result {
+            Ok(response) if response.status().is_success() => {
+                let body: serde_json::Value = response.json().await.unwrap_or_default();
+                SrsHealth {
+                    reachable: true,
+                    latency_ms,
+                    version: body.pointer("/data/version").and_then(|v| v.as_str()).map(str::to_string),
+                    error: None,
+                }
+            }
+            Ok(response) => SrsHealth {
+                reachable: false,
+                latency_ms,
+                version: None,
+                error: Some(format!("状态码 {}", response.status())),
+            },
+            Err(e) => SrsHealth { reachable: false, latency_ms: None, version: None, error: Some(e.to_string()) },
+        }
+    }
+}
+
+/// 创建共享的 HTTP 客户端
+pub fn http_client(config: &HttpClientConfig) -> Client {
+    Client::builder()
+        .pool_max_idle_per_host(config.pool_max_idle_per_host)
+        .pool_idle_timeout(Duration::from_secs(config.pool_idle_timeout_secs))
+        .connect_timeout(Duration::from_millis(config.connect_timeout_ms))
+        .timeout(Duration::from_millis(config.request_timeout_ms))
+        .build()
+        .unwrap_or_default()
+}
+
+/// 替换模板中的 `{变量}`，播放地址与推流路径共用
+fn render(template: &str, vars: &[(&str, &str)]) -> String {
+    vars.iter().fold(template.to_string(), |out, (name, value)| out.replace(&format!("{{{}}}", name), value))
+}
+
+/// 故障注入（chaos 特性）：模拟 SRS 接口响应缓慢，延迟达到请求超时时返回超时错误
+async fn injected_delay(timeout: Duration) -> Result<()> {
+    #[cfg(feature = "chaos")]
+    if let Some(delay) = crate::chaos::srs_delay() {
+        tokio::time::sleep(delay.min(timeout)).await;
+        if delay >= timeout {
+            return Err(anyhow!("SRS 接口请求超时（故障注入）"));
+        }
     }
+    #[cfg(not(feature = "chaos"))]
+    let _ = timeout;
+    Ok(())
 }
@@ -0,0 +1,159 @@
+use ffmpeg_next as ffmpeg;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{info, warn};
+use crate::config::InputOptions;
+use crate::transcoder::{InputGuard, Transcoder};
+
+/// 待机连接断开后的重连间隔
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// 转码器等待待机线程交出输入的最长时间
+const HANDOFF_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// 待机期间缓存的最大字节数，超出时丢弃直到下一个关键帧
+const MAX_BUFFER_BYTES: usize = 16 * 1024 * 1024;
+
+/// 已连接的输入，由待机线程交给转码器
+pub struct PreparedInput {
+    pub ictx: ffmpeg::format::context::Input,
+    pub guard: Option<InputGuard>,
+    /// 最近一个视频关键帧开始的数据包（输入流序号, 数据包），转码器先发送这些包
+    pub packets: Vec<(usize, ffmpeg::Packet)>,
+}
+
+/// 预连接（热备）
+///
+/// 对配置了 `preconnect` 的流，在无人观看时保持与摄像头的连接并持续读取数据（不推流），
+/// 缓存最近一个 GOP。播放请求到来时转码器直接接管该连接，省去 RTSP 握手与等待关键帧的时间。
+pub struct Standby {
+    name: String,
+    url: String,
+    options: InputOptions,
+    /// 转码器正在使用该流，待机线程不再连接
+    paused: AtomicBool,
+    /// 待机线程是否在运行
+    active: AtomicBool,
+    /// 转码器请求接管时放入的发送端
+    handoff: Mutex<Option<mpsc::Sender<PreparedInput>>>,
+    /// 创建时所在的 tokio 运行时，待机线程进入该运行时，代理隧道与 GB28181 点播需要它
+    runtime: Option<tokio::runtime::Handle>,
+}
+
+impl Standby {
+    pub fn new(name: String, url: String, options: InputOptions) -> Self {
+        Self {
+            name,
+            url,
+            options,
+            paused: AtomicBool::new(false),
+            active: AtomicBool::new(false),
+            handoff: Mutex::new(None),
+            runtime: tokio::runtime::Handle::try_current().ok(),
+        }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// 流未在播放时恢复待机连接（已在运行时不做任何事）
+    pub fn ensure_running(self: &Arc<Self>) {
+        self.paused.store(false, Ordering::Relaxed);
+        if self.active.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        let standby = self.clone();
+        std::thread::spawn(move || {
+            let _runtime = standby.runtime.as_ref().map(|handle| handle.enter());
+            standby.run();
+            // 线程退出时丢弃发送端，正在等待的转码器立即返回
+            standby.handoff.lock().unwrap().take();
+            standby.active.store(false, Ordering::Release);
+        });
+    }
+
+    /// 关闭待机连接（流被删除时）
+    pub fn stop(&self) {
+        self.paused.store(true, Ordering::Relaxed);
+    }
+
+    /// 转码器接管连接，待机未连接时返回 None；之后待机暂停，直到再次调用 `ensure_running`
+    pub fn take(&self) -> Option<PreparedInput> {
+        self.paused.store(true, Ordering::Relaxed);
+        if !self.active.load(Ordering::Acquire) {
+            return None;
+        }
+        let (tx, rx) = mpsc::channel();
+        *self.handoff.lock().unwrap() = Some(tx);
+        rx.recv_timeout(HANDOFF_TIMEOUT).ok()
+    }
+
+    fn run(&self) {
+        if let Err(e) = ffmpeg::init() {
+            warn!("流 '{}' 预连接初始化 FFmpeg 失败: {}", self.name, e);
+            return;
+        }
+        while !self.paused.load(Ordering::Relaxed) {
+            match Transcoder::open_input(&self.url, &self.options) {
+                Ok((ictx, guard)) => {
+                    info!("流 '{}' 已预连接，等待播放", self.name);
+                    if self.hold(ictx, guard) {
+                        return;
+                    }
+                    warn!("流 '{}' 预连接中断，{} 秒后重连", self.name, RECONNECT_INTERVAL.as_secs());
+                }
+                Err(e) => warn!("流 '{}' 预连接失败: {}，{} 秒后重试", self.name, e, RECONNECT_INTERVAL.as_secs()),
+            }
+            // 分段等待，暂停时及时退出
+            for _ in 0..RECONNECT_INTERVAL.as_millis() / 100 {
+                if self.paused.load(Ordering::Relaxed) {
+                    return;
+                }
+                std::thread::sleep(Duration::from_millis(100));
+            }
+        }
+    }
+
+    /// 保持连接并缓存最近的 GOP，交给转码器或被暂停时返回 true，连接中断时返回 false
+    fn hold(&self, mut ictx: ffmpeg::format::context::Input, guard: Option<InputGuard>) -> bool {
+        let video = ictx.streams().best(ffmpeg::media::Type::Video).map(|s| s.index());
+        let mut packets: Vec<(usize, ffmpeg::Packet)> = Vec::new();
+        let mut bytes = 0;
+        loop {
+            if let Some(tx) = self.handoff.lock().unwrap().take() {
+                info!("流 '{}' 的预连接交给转码器（缓存 {} 个数据包）", self.name, packets.len());
+                // 接收端已超时放弃时直接关闭连接
+                let _ = tx.send(PreparedInput { ictx, guard, packets });
+                return true;
+            }
+            if self.paused.load(Ordering::Relaxed) {
+                return true;
+            }
+
+            let Some((stream, packet)) = ictx.packets().next() else {
+                return false;
+            };
+            let index = stream.index();
+            // 没有视频时不需要缓存，音频可以从任意位置开始
+            let Some(video) = video else {
+                continue;
+            };
+            if index == video && packet.is_key() {
+                packets.clear();
+                bytes = 0;
+            }
+            if packets.is_empty() && !(index == video && packet.is_key()) {
+                continue;
+            }
+            bytes += packet.size();
+            packets.push((index, packet));
+            if bytes > MAX_BUFFER_BYTES {
+                packets.clear();
+                bytes = 0;
+            }
+        }
+    }
+}
@@ -0,0 +1,405 @@
+use ffmpeg_next as ffmpeg;
+use serde::Serialize;
+use std::fmt::Write;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use crate::clock_skew::ClockMonitor;
+use crate::stream_manager::StreamStatus;
+
+/// 摄像头时钟偏差的采样间隔
+const CLOCK_SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 时间戳修正汇总日志的间隔
+const TIMESTAMP_SUMMARY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// 输入统计
+///
+/// FFmpeg 不对外提供 RTSP/RTP 接收端的 RTCP 统计，这里根据解复用后的数据包推算：
+/// 损坏包（RTP 丢包导致帧不完整时 FFmpeg 会标记）、乱序包、按视频时间戳间隔估算的丢帧数，
+/// 以及 RFC 3550 方式计算的到达抖动。
+pub struct InputStats {
+    packets: AtomicU64,
+    bytes: AtomicU64,
+    corrupt_packets: AtomicU64,
+    reordered_packets: AtomicU64,
+    lost_frames: AtomicU64,
+    /// 到达抖动（微秒）
+    jitter_us: AtomicU64,
+    /// 最近一次收到数据包的时间（Unix 毫秒），0 表示尚未收到
+    last_packet_ms: AtomicU64,
+    /// 摄像头时钟偏差（毫秒），`i64::MIN` 表示源未提供时钟信息
+    clock_skew_ms: AtomicI64,
+}
+
+impl Default for InputStats {
+    fn default() -> Self {
+        Self {
+            packets: AtomicU64::default(),
+            bytes: AtomicU64::default(),
+            corrupt_packets: AtomicU64::default(),
+            reordered_packets: AtomicU64::default(),
+            lost_frames: AtomicU64::default(),
+            jitter_us: AtomicU64::default(),
+            last_packet_ms: AtomicU64::default(),
+            clock_skew_ms: AtomicI64::new(i64::MIN),
+        }
+    }
+}
+
+impl InputStats {
+    /// 距最近一次收到源数据包的秒数，尚未收到时为 None
+    pub fn idle_secs(&self) -> Option<u64> {
+        idle_secs(&self.last_packet_ms)
+    }
+}
+
+/// 输入统计快照
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct InputSnapshot {
+    pub packets: u64,
+    pub bytes: u64,
+    /// 标记为损坏的包（通常由 RTP 丢包引起）
+    pub corrupt_packets: u64,
+    /// DTS 倒退的包
+    pub reordered_packets: u64,
+    /// 根据视频时间戳间隔估算的丢帧数
+    pub lost_frames: u64,
+    /// 视频包到达抖动（毫秒）
+    pub jitter_ms: f64,
+    /// 摄像头时钟偏差（毫秒）：到达时间减去摄像头时间戳对应的时间，正值表示摄像头时钟落后；
+    /// 源未通过 RTCP SR 提供时钟信息时为空
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clock_skew_ms: Option<i64>,
+}
+
+/// 推流输出统计
+#[derive(Default)]
+pub struct OutputStats {
+    packets: AtomicU64,
+    bytes: AtomicU64,
+    /// 推流中断后重新连接成功的次数
+    pub reconnects: AtomicU64,
+    /// 正在重试推流连接（SRS 不可用）
+    pub degraded: AtomicBool,
+    /// 最近一次成功写出数据包的时间（Unix 毫秒），0 表示尚未写出
+    last_packet_ms: AtomicU64,
+}
+
+impl OutputStats {
+    /// 记录一个推给 SRS 的数据包
+    pub fn record(&self, size: usize) {
+        self.packets.fetch_add(1, Ordering::Relaxed);
+        self.bytes.fetch_add(size as u64, Ordering::Relaxed);
+        self.last_packet_ms.store(now_millis(), Ordering::Relaxed);
+    }
+
+    /// 记录 ffmpeg 子进程推出的字节数（子进程只报告累计字节数，不统计包数）
+    #[cfg(feature = "async-remux")]
+    pub fn record_bytes(&self, bytes: u64) {
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+        self.last_packet_ms.store(now_millis(), Ordering::Relaxed);
+    }
+
+    /// 距最近一次成功写出数据包的秒数，尚未写出时为 None
+    pub fn idle_secs(&self) -> Option<u64> {
+        idle_secs(&self.last_packet_ms)
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or_default()
+}
+
+fn idle_secs(last_ms: &AtomicU64) -> Option<u64> {
+    match last_ms.load(Ordering::Relaxed) {
+        0 => None,
+        at => Some(now_millis().saturating_sub(at) / 1000),
+    }
+}
+
+/// 推流输出统计快照
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct OutputSnapshot {
+    pub packets: u64,
+    pub bytes: u64,
+    pub reconnects: u64,
+}
+
+/// 推流前的时间戳修正次数，按修正类别计数
+#[derive(Default)]
+pub struct TimestampStats {
+    /// 缺失 DTS
+    pub missing_dts: AtomicU64,
+    /// 缺失 PTS
+    pub missing_pts: AtomicU64,
+    /// PTS 小于 DTS
+    pub pts_before_dts: AtomicU64,
+    /// DTS 未递增
+    pub non_monotonic: AtomicU64,
+}
+
+impl TimestampStats {
+    pub fn snapshot(&self) -> TimestampSnapshot {
+        TimestampSnapshot {
+            missing_dts: self.missing_dts.load(Ordering::Relaxed),
+            missing_pts: self.missing_pts.load(Ordering::Relaxed),
+            pts_before_dts: self.pts_before_dts.load(Ordering::Relaxed),
+            non_monotonic: self.non_monotonic.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// 时间戳修正统计快照
+#[derive(Debug, Serialize, Clone, Copy, Default, PartialEq)]
+pub struct TimestampSnapshot {
+    pub missing_dts: u64,
+    pub missing_pts: u64,
+    pub pts_before_dts: u64,
+    pub non_monotonic: u64,
+}
+
+/// 按间隔汇总时间戳修正次数，代替逐包打印日志
+pub struct TimestampSummary {
+    last: TimestampSnapshot,
+    last_at: Instant,
+}
+
+impl TimestampSummary {
+    pub fn new(stats: &TimestampStats) -> Self {
+        Self { last: stats.snapshot(), last_at: Instant::now() }
+    }
+
+    /// 到达汇总间隔且期间有修正时返回期间的修正次数
+    pub fn due(&mut self, stats: &TimestampStats) -> Option<TimestampSnapshot> {
+        if self.last_at.elapsed() < TIMESTAMP_SUMMARY_INTERVAL {
+            return None;
+        }
+        let current = stats.snapshot();
+        let delta = TimestampSnapshot {
+            missing_dts: current.missing_dts - self.last.missing_dts,
+            missing_pts: current.missing_pts - self.last.missing_pts,
+            pts_before_dts: current.pts_before_dts - self.last.pts_before_dts,
+            non_monotonic: current.non_monotonic - self.last.non_monotonic,
+        };
+        self.last = current;
+        self.last_at = Instant::now();
+        (delta != TimestampSnapshot::default()).then_some(delta)
+    }
+}
+
+/// 单个流的统计，流启动后累计，断线重连不清零
+#[derive(Default)]
+pub struct StreamStats {
+    pub input: InputStats,
+    pub output: OutputStats,
+    pub timestamps: TimestampStats,
+    /// 异常后自动重启的次数（切换到备用源不计入）
+    pub restarts: AtomicU64,
+}
+
+/// 流统计快照
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct StatsSnapshot {
+    pub input: InputSnapshot,
+    pub output: OutputSnapshot,
+    pub timestamps: TimestampSnapshot,
+    pub restarts: u64,
+}
+
+impl StreamStats {
+    pub fn snapshot(&self) -> StatsSnapshot {
+        let input = &self.input;
+        StatsSnapshot {
+            input: InputSnapshot {
+                packets: input.packets.load(Ordering::Relaxed),
+                bytes: input.bytes.load(Ordering::Relaxed),
+                corrupt_packets: input.corrupt_packets.load(Ordering::Relaxed),
+                reordered_packets: input.reordered_packets.load(Ordering::Relaxed),
+                lost_frames: input.lost_frames.load(Ordering::Relaxed),
+                jitter_ms: input.jitter_us.load(Ordering::Relaxed) as f64 / 1000.0,
+                clock_skew_ms: Some(input.clock_skew_ms.load(Ordering::Relaxed)).filter(|v| *v != i64::MIN),
+            },
+            output: OutputSnapshot {
+                packets: self.output.packets.load(Ordering::Relaxed),
+                bytes: self.output.bytes.load(Ordering::Relaxed),
+                reconnects: self.output.reconnects.load(Ordering::Relaxed),
+            },
+            timestamps: self.timestamps.snapshot(),
+            restarts: self.restarts.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct TrackState {
+    last_dts: Option<i64>,
+}
+
+/// 输入统计采集器，每次连接源时创建
+pub struct InputMeter {
+    tracks: Vec<TrackState>,
+    video: Option<usize>,
+    /// 视频帧间隔的平滑估计（秒）
+    frame_interval: Option<f64>,
+    /// 上一个视频包的到达时间与时间戳（秒）
+    last_arrival: Option<(Instant, f64)>,
+    jitter: f64,
+    /// 输入上下文，用于读取 RTCP SR 给出的起始时间（收到第一个 SR 后才有值）
+    context: *const ffmpeg::ffi::AVFormatContext,
+    clock: Option<Arc<ClockMonitor>>,
+    /// 时钟偏差的平滑估计（秒）及最近一次采样时间
+    clock_skew: Option<f64>,
+    clock_sampled: Option<Instant>,
+}
+
+impl InputMeter {
+    /// `ictx` 必须在采集器使用期间保持有效
+    pub fn new(ictx: &ffmpeg::format::context::Input, clock: Option<Arc<ClockMonitor>>) -> Self {
+        Self {
+            tracks: vec![TrackState { last_dts: None }; ictx.nb_streams() as usize],
+            video: ictx.streams().best(ffmpeg::media::Type::Video).map(|s| s.index()),
+            frame_interval: None,
+            last_arrival: None,
+            jitter: 0.0,
+            context: unsafe { ictx.as_ptr() },
+            clock,
+            clock_skew: None,
+            clock_sampled: None,
+        }
+    }
+
+    /// 记录一个输入包（输入流时间基）
+    pub fn record(&mut self, stats: &InputStats, stream_index: usize, packet: &ffmpeg::Packet, time_base: ffmpeg::Rational) {
+        stats.packets.fetch_add(1, Ordering::Relaxed);
+        stats.bytes.fetch_add(packet.size() as u64, Ordering::Relaxed);
+        stats.last_packet_ms.store(now_millis(), Ordering::Relaxed);
+        if packet.is_corrupt() {
+            stats.corrupt_packets.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let Some(track) = self.tracks.get_mut(stream_index) else {
+            return;
+        };
+        let Some(dts) = packet.dts() else {
+            return;
+        };
+        let previous = track.last_dts.replace(dts);
+        if previous.is_some_and(|last| dts < last) {
+            stats.reordered_packets.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        if Some(stream_index) != self.video {
+            return;
+        }
+
+        let seconds = dts as f64 * f64::from(time_base);
+        let now = Instant::now();
+        if let Some(pts) = packet.pts()
+            && self.clock_sampled.is_none_or(|at| now.duration_since(at) >= CLOCK_SAMPLE_INTERVAL)
+        {
+            self.clock_sampled = Some(now);
+            self.sample_clock(stats, pts as f64 * f64::from(time_base));
+        }
+        if let Some((last_at, last_seconds)) = self.last_arrival {
+            let delta = seconds - last_seconds;
+            // 时间戳间隔明显大于平时的帧间隔，视为中间的帧丢失
+            match self.frame_interval {
+                Some(interval) if interval > 0.0 && delta > interval * 2.5 && delta < 10.0 => {
+                    let lost = (delta / interval).round() as u64 - 1;
+                    stats.lost_frames.fetch_add(lost, Ordering::Relaxed);
+                }
+                Some(interval) if delta > 0.0 => self.frame_interval = Some(interval * 0.9 + delta * 0.1),
+                None if delta > 0.0 => self.frame_interval = Some(delta),
+                _ => {}
+            }
+            // RFC 3550: J += (|D| - J) / 16，D 为到达间隔与时间戳间隔之差
+            let d = now.duration_since(last_at).as_secs_f64() - delta;
+            self.jitter += (d.abs() - self.jitter) / 16.0;
+            stats.jitter_us.store((self.jitter * 1_000_000.0) as u64, Ordering::Relaxed);
+        }
+        self.last_arrival = Some((now, seconds));
+    }
+
+    /// 比较到达时间与摄像头时间戳对应的时间，得到摄像头时钟偏差
+    fn sample_clock(&mut self, stats: &InputStats, pts_seconds: f64) {
+        // RTSP 在收到第一个 RTCP SR 后把其中的 NTP 时间作为起始时间，时间戳从该时刻起算
+        let start_us = unsafe { (*self.context).start_time_realtime };
+        if start_us == ffmpeg::ffi::AV_NOPTS_VALUE || start_us <= 0 {
+            return;
+        }
+        let camera = start_us as f64 / 1_000_000.0 + pts_seconds;
+        let arrival = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs_f64()).unwrap_or_default();
+        // 平滑网络延迟与缓冲带来的波动
+        let skew = match self.clock_skew {
+            Some(smoothed) => smoothed * 0.8 + (arrival - camera) * 0.2,
+            None => arrival - camera,
+        };
+        self.clock_skew = Some(skew);
+        stats.clock_skew_ms.store((skew * 1000.0) as i64, Ordering::Relaxed);
+        if let Some(clock) = &self.clock {
+            clock.observe(skew);
+        }
+    }
+}
+
+/// 以 Prometheus 文本格式输出指标
+pub fn prometheus(streams: &[(StreamStatus, StatsSnapshot)]) -> String {
+    let mut out = String::new();
+    let mut family = |name: &str, kind: &str, help: &str, value: &dyn Fn(&StreamStatus, &StatsSnapshot) -> f64| {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} {}", name, kind);
+        for (status, stats) in streams {
+            let _ = writeln!(out, "{}{{stream=\"{}\"}} {}", name, escape_label(&status.name), value(status, stats));
+        }
+    };
+
+    family("rtsp2flv_stream_up", "gauge", "转码器是否运行中", &|s, _| if s.running { 1.0 } else { 0.0 });
+    family("rtsp2flv_stream_restarts_total", "counter", "转码器重启次数", &|s, _| s.restart_count as f64);
+    family("rtsp2flv_stream_protected", "gauge", "是否处于保护时段", &|s, _| if s.protected { 1.0 } else { 0.0 });
+    family("rtsp2flv_stream_downgraded", "gauge", "是否已降级为子码流", &|s, _| if s.downgraded { 1.0 } else { 0.0 });
+    family("rtsp2flv_stream_active_source", "gauge", "当前使用的源序号，0 为主源", &|s, _| s.active_source as f64);
+    family("rtsp2flv_input_packets_total", "counter", "从源接收的数据包数", &|_, t| t.input.packets as f64);
+    family("rtsp2flv_input_bytes_total", "counter", "从源接收的字节数", &|_, t| t.input.bytes as f64);
+    family("rtsp2flv_input_corrupt_packets_total", "counter", "被标记为损坏的数据包数", &|_, t| {
+        t.input.corrupt_packets as f64
+    });
+    family("rtsp2flv_input_reordered_packets_total", "counter", "DTS 倒退的数据包数", &|_, t| {
+        t.input.reordered_packets as f64
+    });
+    family("rtsp2flv_input_lost_frames_total", "counter", "按时间戳间隔估算的视频丢帧数", &|_, t| {
+        t.input.lost_frames as f64
+    });
+    family("rtsp2flv_timestamp_missing_dts_total", "counter", "补齐缺失 DTS 的数据包数", &|_, t| {
+        t.timestamps.missing_dts as f64
+    });
+    family("rtsp2flv_timestamp_missing_pts_total", "counter", "补齐缺失 PTS 的数据包数", &|_, t| {
+        t.timestamps.missing_pts as f64
+    });
+    family("rtsp2flv_timestamp_pts_before_dts_total", "counter", "修正 PTS 小于 DTS 的数据包数", &|_, t| {
+        t.timestamps.pts_before_dts as f64
+    });
+    family("rtsp2flv_timestamp_non_monotonic_total", "counter", "修正 DTS 未递增的数据包数", &|_, t| {
+        t.timestamps.non_monotonic as f64
+    });
+    family("rtsp2flv_stream_publish_degraded", "gauge", "是否正在重试推流连接", &|s, _| if s.publish_degraded { 1.0 } else { 0.0 });
+    family("rtsp2flv_output_bytes_total", "counter", "推给 SRS 的字节数", &|_, t| t.output.bytes as f64);
+    family("rtsp2flv_output_reconnects_total", "counter", "推流中断后重新连接的次数", &|_, t| t.output.reconnects as f64);
+    family("rtsp2flv_input_jitter_seconds", "gauge", "视频包到达抖动", &|_, t| t.input.jitter_ms / 1000.0);
+    // 尚未收到或写出过数据包、源未提供时钟信息时为 NaN
+    family("rtsp2flv_input_clock_skew_seconds", "gauge", "摄像头时钟偏差，正值表示摄像头时钟落后", &|_, t| {
+        t.input.clock_skew_ms.map_or(f64::NAN, |v| v as f64 / 1000.0)
+    });
+    family("rtsp2flv_input_idle_seconds", "gauge", "距最近一次从源收到数据包的秒数", &|s, _| {
+        s.input_idle_secs.map_or(f64::NAN, |v| v as f64)
+    });
+    family("rtsp2flv_output_idle_seconds", "gauge", "距最近一次成功推给 SRS 的秒数", &|s, _| {
+        s.output_idle_secs.map_or(f64::NAN, |v| v as f64)
+    });
+    out
+}
+
+/// 转义 Prometheus 标签值
+pub fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
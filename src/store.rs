@@ -0,0 +1,431 @@
+use anyhow::{Context, Result};
+use rusqlite::{Connection, params};
+use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+use tracing::{error, info};
+use crate::config::StreamConfig;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS streams (
+    name TEXT PRIMARY KEY,
+    position INTEGER NOT NULL,
+    config TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS users (
+    username TEXT PRIMARY KEY,
+    data TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS usage (
+    hour INTEGER NOT NULL,
+    stream TEXT NOT NULL,
+    bytes INTEGER NOT NULL,
+    restarts INTEGER NOT NULL,
+    PRIMARY KEY (hour, stream)
+);
+CREATE TABLE IF NOT EXISTS webhooks (
+    key_id TEXT PRIMARY KEY,
+    url TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS jobs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    payload TEXT NOT NULL,
+    state TEXT NOT NULL,
+    attempts INTEGER NOT NULL,
+    next_at INTEGER NOT NULL,
+    last_error TEXT,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS jobs_due ON jobs (state, next_at);
+CREATE TABLE IF NOT EXISTS shares (
+    id TEXT PRIMARY KEY,
+    stream TEXT NOT NULL,
+    expires_at INTEGER NOT NULL,
+    max_viewers INTEGER NOT NULL,
+    note TEXT,
+    revoked INTEGER NOT NULL DEFAULT 0,
+    created_at INTEGER NOT NULL
+);
+";
+
+/// 以 JSON 保存的表，按键逐行写入
+#[derive(Clone, Copy)]
+enum JsonTable {
+    Streams,
+    Users,
+}
+
+impl JsonTable {
+    fn file_name(self) -> &'static str {
+        match self {
+            Self::Streams => "streams.json",
+            Self::Users => "users.json",
+        }
+    }
+}
+
+/// 单个流一小时内的用量
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct HourUsage {
+    pub bytes: u64,
+    pub restarts: u64,
+}
+
+/// 后台任务记录
+#[derive(Debug, Clone)]
+pub struct JobRow {
+    pub id: i64,
+    /// 任务内容（JSON）
+    pub payload: String,
+    pub state: String,
+    pub attempts: u32,
+    /// 下次执行时间（Unix 秒）
+    pub next_at: u64,
+    pub last_error: Option<String>,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+/// 分享链接记录（不含令牌签名）
+#[derive(Debug, Clone)]
+pub struct ShareRow {
+    pub id: String,
+    pub stream: String,
+    /// 过期时间（Unix 秒）
+    pub expires_at: u64,
+    pub max_viewers: u32,
+    pub note: Option<String>,
+    pub revoked: bool,
+    pub created_at: u64,
+}
+
+/// 本地数据库
+///
+/// 运行时添加的流、登录用户、用量台账、停止通知回调、后台任务与分享链接保存在 `<data_dir>/rtsp2flv.db`（SQLite），配置文件只提供初始配置。
+/// 首次启动时导入旧版本的 `streams.json` / `users.json` / `usage.json`，导入后重命名为 `*.migrated`。
+pub struct Store {
+    conn: Mutex<Connection>,
+}
+
+impl Store {
+    pub fn open(data_dir: &str) -> Result<Self> {
+        let dir = PathBuf::from(data_dir);
+        fs::create_dir_all(&dir)?;
+        let path = dir.join("rtsp2flv.db");
+        let conn = Connection::open(&path).with_context(|| format!("打开数据库 {} 失败", path.display()))?;
+        // add-user 等子命令可能与服务同时访问数据库
+        conn.busy_timeout(Duration::from_secs(5))?;
+        conn.query_row("PRAGMA journal_mode = WAL", [], |_| Ok(()))?;
+        conn.execute_batch(SCHEMA)?;
+        let store = Self { conn: Mutex::new(conn) };
+        store.import_legacy(&dir);
+        Ok(store)
+    }
+
+    /// 动态流，按添加顺序
+    pub fn streams(&self) -> Result<Vec<StreamConfig>> {
+        self.load_json("SELECT name, config FROM streams ORDER BY position")
+    }
+
+    /// 保存动态流：逐个写入，并删除已移除的流
+    pub fn save_streams(&self, streams: &[StreamConfig]) -> Result<()> {
+        let rows = streams.iter().map(|s| (s.name.as_str(), serde_json::to_string(s))).collect();
+        self.save_json(JsonTable::Streams, rows, true)
+    }
+
+    /// 登录用户
+    pub fn users<T: DeserializeOwned>(&self) -> Result<Vec<T>> {
+        self.load_json("SELECT username, data FROM users ORDER BY username")
+    }
+
+    /// 写入单个登录用户（不存在时插入）
+    pub fn save_user<T: Serialize>(&self, username: &str, user: &T) -> Result<()> {
+        self.save_json(JsonTable::Users, vec![(username, serde_json::to_string(user))], false)
+    }
+
+    /// 全部用量记录：小时序号（Unix 秒 / 3600） -> 流名称 -> 用量
+    pub fn usage(&self) -> Result<BTreeMap<u64, BTreeMap<String, HourUsage>>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare("SELECT hour, stream, bytes, restarts FROM usage")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?, row.get::<_, i64>(2)?, row.get::<_, i64>(3)?))
+        })?;
+        let mut hours: BTreeMap<u64, BTreeMap<String, HourUsage>> = BTreeMap::new();
+        for row in rows {
+            let (hour, stream, bytes, restarts) = row?;
+            hours.entry(hour as u64).or_default().insert(stream, HourUsage { bytes: bytes as u64, restarts: restarts as u64 });
+        }
+        Ok(hours)
+    }
+
+    /// 累加某一小时的用量，并删除 `cutoff` 小时之前的记录
+    pub fn add_usage(&self, hour: u64, deltas: &[(String, u64, u64)], cutoff: u64) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        for (stream, bytes, restarts) in deltas {
+            tx.execute(
+                "INSERT INTO usage (hour, stream, bytes, restarts) VALUES (?1, ?2, ?3, ?4)
+                 ON CONFLICT (hour, stream) DO UPDATE SET bytes = bytes + excluded.bytes, restarts = restarts + excluded.restarts",
+                params![hour as i64, stream, *bytes as i64, *restarts as i64],
+            )?;
+        }
+        tx.execute("DELETE FROM usage WHERE hour < ?1", params![cutoff as i64])?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// API Key（按指纹）登记的停止通知回调地址
+    pub fn webhook(&self, key_id: &str) -> Result<Option<String>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare("SELECT url FROM webhooks WHERE key_id = ?1")?;
+        let mut rows = stmt.query_map(params![key_id], |row| row.get::<_, String>(0))?;
+        Ok(rows.next().transpose()?)
+    }
+
+    /// 登记或删除（`url` 为 None）停止通知回调地址
+    pub fn set_webhook(&self, key_id: &str, url: Option<&str>) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        match url {
+            Some(url) => conn.execute(
+                "INSERT INTO webhooks (key_id, url) VALUES (?1, ?2) ON CONFLICT (key_id) DO UPDATE SET url = excluded.url",
+                params![key_id, url],
+            )?,
+            None => conn.execute("DELETE FROM webhooks WHERE key_id = ?1", params![key_id])?,
+        };
+        Ok(())
+    }
+
+    /// 加入待执行的后台任务，返回任务 ID
+    pub fn add_job(&self, payload: &str, now: u64) -> Result<i64> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO jobs (payload, state, attempts, next_at, created_at, updated_at) VALUES (?1, 'pending', 0, ?2, ?2, ?2)",
+            params![payload, now as i64],
+        )?;
+        Ok(conn.last_insert_rowid())
+    }
+
+    /// 到期的待执行任务，按加入顺序
+    pub fn due_jobs(&self, now: u64, limit: usize) -> Result<Vec<JobRow>> {
+        self.query_jobs(
+            "SELECT * FROM jobs WHERE state = 'pending' AND next_at <= ?1 ORDER BY id LIMIT ?2",
+            params![now as i64, limit as i64],
+        )
+    }
+
+    /// 最近的任务（按 ID 倒序），`state` 为空时返回全部状态
+    pub fn jobs(&self, state: Option<&str>, limit: usize) -> Result<Vec<JobRow>> {
+        match state {
+            Some(state) => self.query_jobs(
+                "SELECT * FROM jobs WHERE state = ?1 ORDER BY id DESC LIMIT ?2",
+                params![state, limit as i64],
+            ),
+            None => self.query_jobs("SELECT * FROM jobs ORDER BY id DESC LIMIT ?1", params![limit as i64]),
+        }
+    }
+
+    /// 更新任务执行结果
+    pub fn update_job(&self, id: i64, state: &str, attempts: u32, next_at: u64, last_error: Option<&str>, now: u64) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE jobs SET state = ?2, attempts = ?3, next_at = ?4, last_error = ?5, updated_at = ?6 WHERE id = ?1",
+            params![id, state, attempts, next_at as i64, last_error, now as i64],
+        )?;
+        Ok(())
+    }
+
+    /// 只保留最近 `keep` 个已结束（成功或失败）的任务
+    pub fn prune_jobs(&self, keep: usize) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "DELETE FROM jobs WHERE state != 'pending' AND id NOT IN
+             (SELECT id FROM jobs WHERE state != 'pending' ORDER BY id DESC LIMIT ?1)",
+            params![keep as i64],
+        )?;
+        Ok(())
+    }
+
+    /// 保存新的分享链接
+    pub fn add_share(&self, share: &ShareRow) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO shares (id, stream, expires_at, max_viewers, note, revoked, created_at) VALUES (?1, ?2, ?3, ?4, ?5, 0, ?6)",
+            params![share.id, share.stream, share.expires_at as i64, share.max_viewers, share.note, share.created_at as i64],
+        )?;
+        Ok(())
+    }
+
+    pub fn share(&self, id: &str) -> Result<Option<ShareRow>> {
+        Ok(self.query_shares("SELECT * FROM shares WHERE id = ?1", params![id])?.into_iter().next())
+    }
+
+    /// 全部分享链接，按创建时间倒序
+    pub fn shares(&self) -> Result<Vec<ShareRow>> {
+        self.query_shares("SELECT * FROM shares ORDER BY created_at DESC", [])
+    }
+
+    /// 撤销分享链接，链接不存在时返回 false
+    pub fn revoke_share(&self, id: &str) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        Ok(conn.execute("UPDATE shares SET revoked = 1 WHERE id = ?1", params![id])? > 0)
+    }
+
+    /// 删除在 `before` 之前过期的分享链接
+    pub fn prune_shares(&self, before: u64) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM shares WHERE expires_at < ?1", params![before as i64])?;
+        Ok(())
+    }
+
+    fn query_shares(&self, sql: &str, params: impl rusqlite::Params) -> Result<Vec<ShareRow>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(sql)?;
+        let rows = stmt.query_map(params, |row| {
+            Ok(ShareRow {
+                id: row.get("id")?,
+                stream: row.get("stream")?,
+                expires_at: row.get::<_, i64>("expires_at")? as u64,
+                max_viewers: row.get("max_viewers")?,
+                note: row.get("note")?,
+                revoked: row.get::<_, i64>("revoked")? != 0,
+                created_at: row.get::<_, i64>("created_at")? as u64,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    fn query_jobs(&self, sql: &str, params: impl rusqlite::Params) -> Result<Vec<JobRow>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(sql)?;
+        let rows = stmt.query_map(params, |row| {
+            Ok(JobRow {
+                id: row.get("id")?,
+                payload: row.get("payload")?,
+                state: row.get("state")?,
+                attempts: row.get("attempts")?,
+                next_at: row.get::<_, i64>("next_at")? as u64,
+                last_error: row.get("last_error")?,
+                created_at: row.get::<_, i64>("created_at")? as u64,
+                updated_at: row.get::<_, i64>("updated_at")? as u64,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// 读取 (键, JSON) 两列，任一记录无法解析时返回错误
+    ///
+    /// 不能跳过无法解析的记录：保存时会删除内存中没有的行，跳过的记录会随之丢失。
+    fn load_json<T: DeserializeOwned>(&self, sql: &str) -> Result<Vec<T>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(sql)?;
+        let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?;
+        let mut items = Vec::new();
+        for row in rows {
+            let (key, json) = row?;
+            let item = serde_json::from_str(&json).with_context(|| format!("解析数据库记录 '{}' 失败", key))?;
+            items.push(item);
+        }
+        Ok(items)
+    }
+
+    /// 逐行写入（按键插入或更新），不影响其他行；`prune` 为 true 时删除 `rows` 中没有的行
+    fn save_json(&self, table: JsonTable, rows: Vec<(&str, serde_json::Result<String>)>, prune: bool) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let (select, delete) = match table {
+            JsonTable::Streams => ("SELECT name FROM streams", "DELETE FROM streams WHERE name = ?1"),
+            JsonTable::Users => ("SELECT username FROM users", "DELETE FROM users WHERE username = ?1"),
+        };
+        if prune {
+            let existing: Vec<String> = tx.prepare(select)?.query_map([], |row| row.get(0))?.collect::<rusqlite::Result<_>>()?;
+            for key in existing.iter().filter(|key| !rows.iter().any(|(k, _)| k == key)) {
+                tx.execute(delete, params![key])?;
+            }
+        }
+        for (position, (key, json)) in rows.into_iter().enumerate() {
+            match table {
+                JsonTable::Streams => tx.execute(
+                    "INSERT INTO streams (name, position, config) VALUES (?1, ?2, ?3)
+                     ON CONFLICT (name) DO UPDATE SET position = excluded.position, config = excluded.config",
+                    params![key, position as i64, json?],
+                )?,
+                JsonTable::Users => tx.execute(
+                    "INSERT INTO users (username, data) VALUES (?1, ?2) ON CONFLICT (username) DO UPDATE SET data = excluded.data",
+                    params![key, json?],
+                )?,
+            };
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// 导入旧版本的 JSON 文件，数据库中已有数据时不导入
+    fn import_legacy(&self, dir: &Path) {
+        for table in [JsonTable::Streams, JsonTable::Users] {
+            let path = dir.join(table.file_name());
+            let result = read_legacy::<Vec<serde_json::Value>>(&path).and_then(|items| {
+                let Some(items) = items else {
+                    return Ok(false);
+                };
+                let (existing, key) = match table {
+                    JsonTable::Streams => (self.streams()?.len(), "name"),
+                    JsonTable::Users => (self.users::<serde_json::Value>()?.len(), "username"),
+                };
+                if existing == 0 {
+                    let rows = items
+                        .iter()
+                        .map(|item| (item.get(key).and_then(|k| k.as_str()).unwrap_or_default(), serde_json::to_string(item)))
+                        .collect();
+                    self.save_json(table, rows, false)?;
+                    info!("已将 {} 导入数据库（{} 条）", path.display(), items.len());
+                }
+                Ok(true)
+            });
+            finish_legacy(&path, result);
+        }
+
+        let path = dir.join("usage.json");
+        let result = read_legacy::<BTreeMap<u64, BTreeMap<String, HourUsage>>>(&path).and_then(|hours| {
+            let Some(hours) = hours else {
+                return Ok(false);
+            };
+            if self.usage()?.is_empty() {
+                for (hour, streams) in &hours {
+                    let deltas: Vec<_> = streams.iter().map(|(s, u)| (s.clone(), u.bytes, u.restarts)).collect();
+                    self.add_usage(*hour, &deltas, 0)?;
+                }
+                info!("已将 {} 导入数据库（{} 小时）", path.display(), hours.len());
+            }
+            Ok(true)
+        });
+        finish_legacy(&path, result);
+    }
+}
+
+/// 读取旧版本的 JSON 文件，文件不存在时返回 None
+fn read_legacy<T: DeserializeOwned>(path: &Path) -> Result<Option<T>> {
+    match fs::read_to_string(path) {
+        Ok(content) => Ok(Some(serde_json::from_str(&content)?)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// 导入完成后重命名旧文件，避免下次启动重复导入；导入失败时保留原文件
+fn finish_legacy(path: &Path, result: Result<bool>) {
+    match result {
+        Ok(false) => {}
+        Ok(true) => {
+            let migrated = path.with_extension("json.migrated");
+            if let Err(e) = fs::rename(path, &migrated) {
+                error!("重命名 {} 失败: {}", path.display(), e);
+            }
+        }
+        Err(e) => error!("导入 {} 失败: {:#}", path.display(), e),
+    }
+}
@@ -0,0 +1,383 @@
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use crate::config::StreamConfig;
+use crate::registry::StreamRegistry;
+use crate::transcoder;
+use crate::vault::CredentialVault;
+use crate::vendor::DeviceConfig;
+
+/// 导入/导出格式
+#[derive(Debug, Clone, Copy, Deserialize, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    #[default]
+    Json,
+    Csv,
+}
+
+/// 导入时与已有流同名的处理策略
+#[derive(Debug, Clone, Copy, Deserialize, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictPolicy {
+    /// 保留已有配置，跳过导入项
+    #[default]
+    Skip,
+    /// 使用导入项覆盖已有配置
+    Overwrite,
+    /// 存在任何冲突时整体拒绝导入
+    Fail,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportError {
+    /// 导入数据中的序号（从 1 开始，CSV 不含表头）
+    pub index: usize,
+    pub name: String,
+    pub message: String,
+}
+
+/// 导入结果报告
+#[derive(Debug, Serialize, Default)]
+pub struct ImportReport {
+    pub dry_run: bool,
+    pub added: Vec<String>,
+    pub updated: Vec<String>,
+    pub skipped: Vec<String>,
+    pub errors: Vec<ImportError>,
+}
+
+/// CSV 中多个备用源地址的分隔符
+const BACKUP_URL_SEPARATOR: &str = "|";
+
+/// CSV 列顺序
+const CSV_COLUMNS: &[&str] = &["name", "url", "backup_urls", "sub_url", "credential", "aliases", "tenant", "group"];
+
+/// CSV 中多个别名的分隔符
+const ALIAS_SEPARATOR: &str = "|";
+
+/// CSV 中元数据列名的前缀，每个元数据键一列，如 `meta.location`
+const METADATA_COLUMN_PREFIX: &str = "meta.";
+
+/// 导出流配置
+pub fn export(streams: &[StreamConfig], format: Format) -> Result<String> {
+    match format {
+        Format::Json => Ok(serde_json::to_string_pretty(streams)?),
+        Format::Csv => {
+            let keys: BTreeSet<&str> = streams.iter().flat_map(|s| s.metadata.keys().map(String::as_str)).collect();
+            let mut out = CSV_COLUMNS.join(",");
+            for key in &keys {
+                out.push_str(&format!(",{}{}", METADATA_COLUMN_PREFIX, key));
+            }
+            out.push('\n');
+            for s in streams {
+                out.push_str(&format!(
+                    "{},{},{},{},{},{},{},{}",
+                    csv_escape(&s.name),
+                    csv_escape(&s.url),
+                    csv_escape(&s.backup_urls.join(BACKUP_URL_SEPARATOR)),
+                    csv_escape(s.sub_url.as_deref().unwrap_or_default()),
+                    csv_escape(s.credential.as_deref().unwrap_or_default()),
+                    csv_escape(&s.aliases.join(ALIAS_SEPARATOR)),
+                    csv_escape(s.tenant.as_deref().unwrap_or_default()),
+                    csv_escape(s.group.as_deref().unwrap_or_default())
+                ));
+                for key in &keys {
+                    out.push(',');
+                    out.push_str(&csv_escape(s.metadata.get(*key).map_or("", String::as_str)));
+                }
+                out.push('\n');
+            }
+            Ok(out)
+        }
+    }
+}
+
+/// 导入流配置
+///
+/// 所有条目先校验，存在错误或 `Fail` 策略下存在冲突时不会写入任何数据；
+/// `dry_run` 时只返回报告。
+pub fn import(
+    registry: &StreamRegistry,
+    vault: &CredentialVault,
+    body: &str,
+    format: Format,
+    policy: ConflictPolicy,
+    dry_run: bool,
+) -> Result<ImportReport> {
+    let entries = match format {
+        Format::Json => serde_json::from_str::<Vec<StreamConfig>>(body)
+            .map_err(|e| anyhow!("JSON 格式错误: {}", e))?,
+        Format::Csv => parse_csv(body)?,
+    };
+    import_entries(registry, vault, entries, policy, dry_run)
+}
+
+/// 校验并写入已解析的流配置，规则同 [`import`]
+pub fn import_entries(
+    registry: &StreamRegistry,
+    vault: &CredentialVault,
+    entries: Vec<StreamConfig>,
+    policy: ConflictPolicy,
+    dry_run: bool,
+) -> Result<ImportReport> {
+    let mut report = ImportReport { dry_run, ..Default::default() };
+    let mut accepted = Vec::new();
+    let existing = registry.list();
+
+    for (i, mut entry) in entries.into_iter().enumerate() {
+        let index = i + 1;
+        let expanded = registry.expand(&mut entry);
+        let fail = |message: String| ImportError { index, name: entry.name.clone(), message };
+
+        if entry.name.trim().is_empty() {
+            report.errors.push(fail("流名称不能为空".to_string()));
+            continue;
+        }
+        if let Err(e) = expanded {
+            report.errors.push(fail(format!("{:#}", e)));
+            continue;
+        }
+        if !transcoder::is_supported_input(&entry.url) {
+            report.errors.push(fail(format!("不支持的流地址: {}", entry.url)));
+            continue;
+        }
+        if let Some(backup) = entry.backup_urls.iter().find(|u| !transcoder::is_supported_input(u)) {
+            report.errors.push(fail(format!("不支持的备用源地址: {}", backup)));
+            continue;
+        }
+        if let Some(sub_url) = &entry.sub_url
+            && !transcoder::is_supported_input(sub_url)
+        {
+            report.errors.push(fail(format!("不支持的子码流地址: {}", sub_url)));
+            continue;
+        }
+        if let Some(credential) = &entry.credential
+            && !vault.contains(credential)
+        {
+            report.errors.push(fail(format!("引用的凭据 '{}' 不存在", credential)));
+            continue;
+        }
+        if let Some(owner) = existing.iter().find(|s| s.name != entry.name && s.aliases.contains(&entry.name)) {
+            report.errors.push(fail(format!("名称与流 '{}' 的别名重复", owner.name)));
+            continue;
+        }
+        if let Some(alias) = entry.aliases.iter().find(|a| {
+            **a == entry.name
+                || existing
+                    .iter()
+                    .chain(accepted.iter())
+                    .any(|s| s.name != entry.name && (s.name == **a || s.aliases.contains(a)))
+        }) {
+            report.errors.push(fail(format!("别名 '{}' 与其他流的名称或别名重复", alias)));
+            continue;
+        }
+        if let Some(error) = entry
+            .protected_hours
+            .as_ref()
+            .and_then(|p| p.errors().into_iter().next())
+            .or_else(|| entry.input.errors().into_iter().next())
+            .or_else(|| entry.output.errors().into_iter().next())
+            .or_else(|| entry.metadata_errors().into_iter().next())
+        {
+            report.errors.push(fail(error));
+            continue;
+        }
+        if accepted.iter().any(|s: &StreamConfig| s.name == entry.name) {
+            report.errors.push(fail("导入数据中名称重复".to_string()));
+            continue;
+        }
+
+        if registry.get(&entry.name).is_some() {
+            match policy {
+                ConflictPolicy::Skip => {
+                    report.skipped.push(entry.name);
+                    continue;
+                }
+                ConflictPolicy::Overwrite => report.updated.push(entry.name.clone()),
+                ConflictPolicy::Fail => {
+                    report.errors.push(fail("已存在同名流".to_string()));
+                    continue;
+                }
+            }
+        } else {
+            report.added.push(entry.name.clone());
+        }
+        accepted.push(entry);
+    }
+
+    if !dry_run && report.errors.is_empty() && !accepted.is_empty() {
+        registry.upsert_many(accepted)?;
+    }
+    Ok(report)
+}
+
+pub fn csv_escape(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// 解析 CSV 记录，支持双引号包裹、"" 转义与引号内的换行，跳过空行
+fn parse_csv_records(body: &str) -> Result<Vec<Vec<String>>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = body.chars().peekable();
+
+    let mut finish = |record: Vec<String>| {
+        if !(record.len() == 1 && record[0].trim().is_empty()) {
+            records.push(record);
+        }
+    };
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if in_quotes && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => in_quotes = !in_quotes,
+            ',' if !in_quotes => record.push(std::mem::take(&mut field)),
+            '\r' if !in_quotes && chars.peek() == Some(&'\n') => {}
+            '\n' if !in_quotes => {
+                record.push(std::mem::take(&mut field));
+                finish(std::mem::take(&mut record));
+            }
+            _ => field.push(c),
+        }
+    }
+    if in_quotes {
+        return Err(anyhow!("CSV 格式错误: 引号未闭合"));
+    }
+    record.push(field);
+    finish(record);
+    Ok(records)
+}
+
+/// 按表头解析 CSV，列顺序不限，未知列忽略
+fn parse_csv(body: &str) -> Result<Vec<StreamConfig>> {
+    let mut records = parse_csv_records(body)?.into_iter();
+    let header: Vec<String> = records
+        .next()
+        .ok_or(anyhow!("CSV 内容为空"))?
+        .into_iter()
+        .map(|h| h.trim().to_lowercase())
+        .collect();
+
+    let column = |name: &str| header.iter().position(|h| h == name);
+    let name_col = column("name").ok_or(anyhow!("CSV 缺少 name 列"))?;
+    let url_col = column("url");
+    // 按厂商预设生成地址时可以没有 url 列
+    let vendor_col = column("vendor");
+    if url_col.is_none() && vendor_col.is_none() {
+        return Err(anyhow!("CSV 缺少 url 列（或 vendor、ip 列）"));
+    }
+    let ip_col = column("ip");
+    let channel_col = column("channel");
+    let subtype_col = column("subtype");
+    let backup_urls_col = column("backup_urls");
+    let sub_url_col = column("sub_url");
+    let credential_col = column("credential");
+    let aliases_col = column("aliases");
+    let tenant_col = column("tenant");
+    let group_col = column("group");
+    let metadata_cols: Vec<(usize, String)> = header
+        .iter()
+        .enumerate()
+        .filter_map(|(i, h)| Some((i, h.strip_prefix(METADATA_COLUMN_PREFIX)?.to_string())))
+        .collect();
+
+    records
+        .enumerate()
+        .map(|(i, fields)| {
+            let get = |i: usize| fields.get(i).map(|f| f.trim().to_string()).unwrap_or_default();
+            let number = |col: Option<usize>, default: u32| {
+                let value = col.map(get).unwrap_or_default();
+                if value.is_empty() {
+                    return Ok(default);
+                }
+                value.parse().map_err(|_| anyhow!("第 {} 行: 无效的数字 '{}'", i + 1, value))
+            };
+            let device = match vendor_col.map(get).filter(|v| !v.is_empty()) {
+                Some(vendor) => Some(DeviceConfig {
+                    vendor,
+                    ip: ip_col.map(get).unwrap_or_default(),
+                    port: None,
+                    channel: number(channel_col, 1)?,
+                    subtype: number(subtype_col, 0)?,
+                }),
+                None => None,
+            };
+            Ok(StreamConfig {
+                name: get(name_col),
+                url: url_col.map(get).unwrap_or_default(),
+                device,
+                backup_urls: backup_urls_col
+                    .map(|i| {
+                        get(i)
+                            .split(BACKUP_URL_SEPARATOR)
+                            .map(|u| u.trim().to_string())
+                            .filter(|u| !u.is_empty())
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+                sub_url: sub_url_col.map(get).filter(|u| !u.is_empty()),
+                credential: credential_col.map(get).filter(|c| !c.is_empty()),
+                input: Default::default(),
+                output: Default::default(),
+                preconnect: false,
+                aliases: aliases_col
+                    .map(|i| {
+                        get(i)
+                            .split(ALIAS_SEPARATOR)
+                            .map(|a| a.trim().to_string())
+                            .filter(|a| !a.is_empty())
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+                sub_fallback: false,
+                protected_hours: None,
+                tenant: tenant_col.map(get).filter(|t| !t.is_empty()),
+                group: group_col.map(get).filter(|g| !g.is_empty()),
+                // 空单元格表示该流没有这项元数据
+                metadata: metadata_cols
+                    .iter()
+                    .map(|(i, key)| (key.clone(), get(*i)))
+                    .filter(|(_, value)| !value.is_empty())
+                    .collect(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_export_round_trips() {
+        let streams: Vec<StreamConfig> = serde_json::from_value(serde_json::json!([
+            {
+                "name": "Lobby, east",
+                "url": "rtsp://10.0.0.1/stream?a=1,b=\"2\"",
+                "backup_urls": ["rtsp://10.0.0.2/a", "rtsp://10.0.0.3/b"],
+                "aliases": ["L1", "L2"],
+                "tenant": "acme",
+                "metadata": { "location": "一楼\n东侧大厅", "note": "line1\r\nline2" }
+            },
+            { "name": "Gate", "url": "rtsp://10.0.0.4/", "credential": "ops", "group": "outdoor" }
+        ]))
+        .unwrap();
+        let csv = export(&streams, Format::Csv).unwrap();
+        let imported = parse_csv(&csv).unwrap();
+        assert_eq!(serde_json::to_value(&imported).unwrap(), serde_json::to_value(&streams).unwrap());
+    }
+
+    #[test]
+    fn csv_rejects_unclosed_quote() {
+        assert!(parse_csv("name,url\n\"cam,rtsp://a/\n").is_err());
+    }
+}
@@ -1,13 +1,230 @@
-use std::collections::HashMap;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex, atomic::{AtomicBool, Ordering}};
 use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
 use tokio::task::JoinHandle;
 use tracing::{info, error, warn};
-use crate::transcoder::Transcoder;
+#[cfg(feature = "async-remux")]
+use crate::async_remux::{self, AsyncRemuxConfig};
+use crate::clip::{ClipBuffer, ClipConfig};
+use crate::config::{InputOptions, OutputOptions, ProtectedHours, PublishRetryConfig, SoakRestartConfig};
+use crate::clock_skew::{ClockMonitor, ClockSkewConfig};
+use crate::content_check::{ContentCheckConfig, ContentMonitor, ContentProblem};
+use crate::dump::DumpControl;
+use crate::gop_cache::{GopCache, GopCacheConfig};
+use crate::history::{HealthHistory, now_secs};
+use crate::hls::{self, HlsConfig};
+use crate::packet_trace::TraceControl;
+use crate::preview::{Preview, PreviewCache, PreviewConfig};
+use crate::recording::{self, RecordControl, RecordingConfig};
+use crate::rtsp_server;
+use crate::sessions::ViewerSessions;
+use crate::snapshot::SnapshotRequests;
+use crate::standby::Standby;
+use crate::stats::{StatsSnapshot, StreamStats};
+use crate::thumbnail::{self, ThumbnailConfig};
+use crate::transcoder::{JobHooks, Transcoder};
+use crate::vault;
+
+/// 心跳合并窗口：同一流在窗口内的重复心跳只更新一次状态
+const HEARTBEAT_COALESCE_WINDOW: Duration = Duration::from_secs(1);
+
+/// 当前源连续失败多少次后切换到下一个源
+const FAILOVER_THRESHOLD: u32 = 2;
+
+/// 使用备用源期间探测主源是否恢复的间隔
+const PRIMARY_PROBE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// 降级为子码流后探测主码流是否恢复的间隔
+const SUB_RESTORE_INTERVAL: Duration = Duration::from_secs(300);
+
+/// 单个源允许的最大连续重启次数
+const MAX_RESTARTS_PER_SOURCE: u32 = 5;
+
+/// 未被订阅者及时取走的停止事件最多保留条数
+const STOPPED_EVENT_CAPACITY: usize = 256;
+
+/// 流停止的原因
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StopReason {
+    /// 无观众超时
+    Timeout,
+    /// 手动停止、推流结束或流配置被删除
+    Stopped,
+    /// 重启次数用尽，不再自动重启（流保留到手动重置或无观众超时）
+    Exhausted,
+}
+
+/// 流停止事件
+#[derive(Debug, Clone, Serialize)]
+pub struct StoppedEvent {
+    pub stream: String,
+    pub reason: StopReason,
+    /// 停止说明，与健康历史中的记录一致
+    pub message: String,
+}
+
+impl StoppedEvent {
+    fn new(stream: &str, reason: StopReason, message: &str) -> Self {
+        Self { stream: stream.to_string(), reason, message: message.to_string() }
+    }
+}
+
+/// 流运行状态快照
+#[derive(Debug, Serialize)]
+pub struct StreamStatus {
+    pub name: String,
+    pub running: bool,
+    /// 当前使用的源序号，0 为主源
+    pub active_source: usize,
+    pub restart_count: u32,
+    /// 距最近一次心跳的秒数
+    pub last_heartbeat_secs: u64,
+    /// 有效的观看会话数（2 分钟内有心跳的播放端）
+    pub viewers: usize,
+    /// 画面问题（冻结 / 黑屏）
+    pub content_problem: Option<ContentProblem>,
+    /// 当前处于保护时段，无观众也不会停止
+    pub protected: bool,
+    /// 正在录制，无观众也不会停止
+    pub recording: bool,
+    /// 主码流故障，已降级为子码流
+    pub downgraded: bool,
+    /// 最近一次画面明显变化的时间（Unix 秒）
+    pub view_changed_at: Option<u64>,
+    /// 摄像头时钟偏差超过阈值
+    pub clock_skewed: bool,
+    /// 剩余的自动重启次数
+    pub restart_budget: u32,
+    /// 重启次数已用尽，不再自动重启，可通过 reset-failures 接口重新启用
+    pub restarts_exhausted: bool,
+    /// SRS 不可用，正在重试推流连接
+    pub publish_degraded: bool,
+    /// 距最近一次从源收到数据包的秒数，尚未收到时为空；持续增长说明摄像头侧中断
+    pub input_idle_secs: Option<u64>,
+    /// 距最近一次成功推给 SRS 的秒数，尚未推出时为空；输入正常而该值增长说明 SRS 侧中断
+    pub output_idle_secs: Option<u64>,
+    /// 播放时调用 SRS 接口失败的原因（`srs.error_policy: degraded` 时继续播放），为空表示正常
+    pub srs_error: Option<String>,
+}
+
+/// 转码任务的运行阶段
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RunPhase {
+    /// 转码线程运行中
+    Running,
+    /// 已请求停止（主备切换、计划重启），等待转码线程退出
+    Stopping,
+    /// 转码线程已退出，正在启动新实例
+    Restarting,
+    /// 转码线程异常退出，等待自动重启（或重启次数已用尽）
+    Crashed,
+}
+
+/// 单个流的运行详情
+#[derive(Debug, Serialize)]
+pub struct StreamDetail {
+    #[serde(flatten)]
+    pub status: StreamStatus,
+    pub phase: RunPhase,
+    /// 当前转码实例已运行的秒数
+    pub uptime_secs: u64,
+    /// 当前使用的拉流地址（密码已隐藏）
+    pub input_url: String,
+    /// 推流地址（密码已隐藏）
+    pub output_url: String,
+    pub stats: StatsSnapshot,
+    /// 流配置中的自定义元数据，子码流使用所属流的元数据
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: BTreeMap<String, String>,
+}
+
+/// 运行中转码任务实际使用的参数
+#[derive(Debug, Serialize)]
+pub struct RunningConfig {
+    /// 主源在前，降级用的子码流在最后（密码已隐藏）
+    pub input_urls: Vec<String>,
+    pub active_source: usize,
+    pub input: InputOptions,
+    pub output_url: String,
+    pub output: OutputOptions,
+    pub protected_hours: Option<ProtectedHours>,
+}
+
+/// 运行中的转码任务，升级时交给新进程
+pub struct RunningJob {
+    pub name: String,
+    /// 主源地址
+    pub url: String,
+    pub output_url: String,
+    /// 实际使用的输入输出参数（含播放请求中的临时覆盖项）
+    pub input: InputOptions,
+    pub output: OutputOptions,
+}
+
+/// 服务停止或平滑升级期间拒绝启动新的流，客户端重试时由新进程处理
+#[derive(Debug)]
+pub struct Closing;
+
+impl std::fmt::Display for Closing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("服务正在停止或升级，请稍后重试")
+    }
+}
+
+impl std::error::Error for Closing {}
+
+/// 流的运行策略（来自流配置，自定义地址播放时为默认值）
+#[derive(Debug, Clone, Default)]
+pub struct StreamPolicy {
+    /// 保护时段
+    pub protected_hours: Option<ProtectedHours>,
+    /// 主源与备用源都连续失败后降级使用的子码流地址
+    pub sub_fallback: Option<String>,
+    /// 准入控制降级：只转封装，不做画面检测、预览缩略图等解码处理
+    pub remux_only: bool,
+    /// 准入控制的预估负载
+    pub cost: u32,
+}
 
 pub struct StreamManager {
     // 映射: 流名称 -> 流状态
     streams: Arc<Mutex<HashMap<String, StreamState>>>,
+    // 最近一次实际写入心跳的时间，用于合并重复心跳，避免频繁争用 streams 锁
+    recent_heartbeats: Arc<Mutex<HashMap<String, Instant>>>,
+    /// 已开始停止所有流（服务停止或平滑升级），不再启动新的流
+    closing: AtomicBool,
+    history: Arc<HealthHistory>,
+    gop_cache: GopCacheConfig,
+    content_check: ContentCheckConfig,
+    clock_skew: ClockSkewConfig,
+    clip: ClipConfig,
+    publish_retry: PublishRetryConfig,
+    preview: PreviewConfig,
+    soak_restart: SoakRestartConfig,
+    /// HLS 输出：所有流的分片目录与配置
+    hls: Option<(PathBuf, HlsConfig)>,
+    /// 录制：所有流的录制目录与配置
+    recording: (PathBuf, RecordingConfig),
+    /// 预连接的流
+    standbys: Arc<Mutex<HashMap<String, Arc<Standby>>>>,
+    /// 画面检测，流重启后沿用以保留画面哈希基准
+    content_monitors: Mutex<HashMap<String, Arc<ContentMonitor>>>,
+    /// 时钟偏差检测，流重启后沿用以免重复报警
+    clock_monitors: Mutex<HashMap<String, Arc<ClockMonitor>>>,
+    /// 预览缩略图，流重启后沿用以保留最近的画面
+    previews: Mutex<HashMap<String, Arc<PreviewCache>>>,
+    /// 流停止事件
+    stopped_tx: broadcast::Sender<StoppedEvent>,
+    /// 观看会话，还有有效会话的流不会因无观众超时停止
+    sessions: Arc<ViewerSessions>,
+    /// 以 ffmpeg 子进程只转封装
+    #[cfg(feature = "async-remux")]
+    async_remux: AsyncRemuxConfig,
 }
 
 struct StreamState {
@@ -15,35 +232,374 @@ struct StreamState {
     last_heartbeat: Instant,
     // 保留句柄以便等待或分离
     handle: JoinHandle<()>,
-    // 存储 URL 用于自动重启
-    input_url: String,
-    output_url: String,
+    // 存储 URL 用于自动重启与主备切换
+    sources: Failover,
+    job: TranscodeJob,
     // 重启计数器
     restart_count: u32,
     // 上次尝试重启的时间
     last_restart_attempt: Instant,
+    // 当前转码实例的启动时间，用于长时间运行后的定期重启
+    running_since: Instant,
+    // 准入控制的预估负载
+    cost: u32,
+    // 重启次数已用尽，保留状态直到无观众超时或手动重置
+    exhausted: bool,
+    // 重启任务进行中，监控循环跳过该流
+    restarting: bool,
+    // 最近一次播放时调用 SRS 接口失败的原因
+    srs_error: Option<String>,
+}
+
+/// 监控循环决定重启、在锁外执行的任务
+struct PendingRestart {
+    name: String,
+    job: TranscodeJob,
+    input_url: String,
+    /// 计入重启次数（计划内的主备切换不计入）
+    counts_as_restart: bool,
+}
+
+impl StreamState {
+    /// 允许的最大连续重启次数，每个源各有一份额度
+    fn max_restarts(&self) -> u32 {
+        MAX_RESTARTS_PER_SOURCE * self.sources.urls.len() as u32
+    }
+}
+
+/// 转码任务参数，自动重启时复用
+#[derive(Clone)]
+struct TranscodeJob {
+    name: String,
+    input_options: InputOptions,
+    output_url: String,
+    output_options: OutputOptions,
+    publish_retry: PublishRetryConfig,
+    protected_hours: Option<ProtectedHours>,
+    hooks: JobHooks,
+    /// 以 ffmpeg 子进程只转封装时使用的可执行文件
+    #[cfg(feature = "async-remux")]
+    async_remux: Option<String>,
+}
+
+/// 主备源切换状态
+///
+/// `urls[0]` 为主源，其余为备用源，配置了子码流降级时子码流排在最后。当前源连续失败达到阈值后切换到下一个源，
+/// 使用备用源期间定期探测主源，恢复后切回。
+struct Failover {
+    urls: Vec<String>,
+    /// 子码流在 `urls` 中的序号
+    fallback: Option<usize>,
+    active: usize,
+    // 当前源的连续失败次数
+    failures: u32,
+    // 计划切换到的源：等待当前转码线程退出后再启动，避免同时推流到同一地址
+    pending: Option<usize>,
+    primary_ok: Arc<AtomicBool>,
+    probe: Option<JoinHandle<()>>,
+    last_probe: Instant,
+}
+
+impl Failover {
+    fn new(mut urls: Vec<String>, sub_fallback: Option<String>) -> Self {
+        let fallback = sub_fallback.map(|url| {
+            urls.push(url);
+            urls.len() - 1
+        });
+        Self {
+            urls,
+            fallback,
+            active: 0,
+            failures: 0,
+            pending: None,
+            primary_ok: Arc::new(AtomicBool::new(false)),
+            probe: None,
+            last_probe: Instant::now(),
+        }
+    }
+
+    fn active_url(&self) -> &str {
+        &self.urls[self.active]
+    }
+
+    /// 是否为降级使用的子码流
+    fn is_fallback(&self, index: usize) -> bool {
+        self.fallback == Some(index)
+    }
+
+    /// 记录一次失败，达到阈值且存在其他源时切换，返回切换后的源序号
+    fn record_failure(&mut self) -> Option<usize> {
+        self.failures += 1;
+        if self.urls.len() < 2 || self.failures < FAILOVER_THRESHOLD {
+            return None;
+        }
+        self.active = (self.active + 1) % self.urls.len();
+        self.failures = 0;
+        Some(self.active)
+    }
+
+    /// 使用备用源期间按间隔在后台探测主源
+    fn poll_primary(&mut self, now: Instant, input_options: &InputOptions) {
+        let probing = self.probe.as_ref().is_some_and(|h| !h.is_finished());
+        // 降级后主码流多为带宽或解码问题，间隔更长，避免频繁切换
+        let interval = if self.is_fallback(self.active) { SUB_RESTORE_INTERVAL } else { PRIMARY_PROBE_INTERVAL };
+        if self.active == 0 || probing || now.duration_since(self.last_probe) < interval {
+            return;
+        }
+        self.last_probe = now;
+        let url = self.urls[0].clone();
+        let input_options = input_options.clone();
+        let primary_ok = self.primary_ok.clone();
+        self.probe = Some(tokio::task::spawn_blocking(move || {
+            if Transcoder::probe(&url, &input_options).is_ok() {
+                primary_ok.store(true, Ordering::Relaxed);
+            }
+        }));
+    }
 }
 
 impl StreamManager {
-    pub fn new() -> Self {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        history: Arc<HealthHistory>,
+        gop_cache: GopCacheConfig,
+        content_check: ContentCheckConfig,
+        clock_skew: ClockSkewConfig,
+        clip: ClipConfig,
+        publish_retry: PublishRetryConfig,
+        preview: PreviewConfig,
+        soak_restart: SoakRestartConfig,
+        hls: Option<(PathBuf, HlsConfig)>,
+        recording: (PathBuf, RecordingConfig),
+    ) -> Self {
         let manager = Self {
             streams: Arc::new(Mutex::new(HashMap::new())),
+            recent_heartbeats: Arc::new(Mutex::new(HashMap::new())),
+            closing: AtomicBool::new(false),
+            history,
+            gop_cache,
+            content_check,
+            clock_skew,
+            clip,
+            publish_retry,
+            preview,
+            soak_restart,
+            hls,
+            recording,
+            standbys: Arc::new(Mutex::new(HashMap::new())),
+            content_monitors: Mutex::new(HashMap::new()),
+            clock_monitors: Mutex::new(HashMap::new()),
+            previews: Mutex::new(HashMap::new()),
+            stopped_tx: broadcast::channel(STOPPED_EVENT_CAPACITY).0,
+            sessions: Arc::new(ViewerSessions::default()),
+            #[cfg(feature = "async-remux")]
+            async_remux: AsyncRemuxConfig::default(),
         };
         
         // 启动后台监控任务
         let streams_clone = manager.streams.clone();
+        let history_clone = manager.history.clone();
+        let standbys = manager.standbys.clone();
+        let stopped_tx = manager.stopped_tx.clone();
+        let sessions = manager.sessions.clone();
+        let soak_restart = manager.soak_restart.clone();
+        let recent_heartbeats = manager.recent_heartbeats.clone();
         tokio::spawn(async move {
+            let mut last_soak = None;
             loop {
                 tokio::time::sleep(Duration::from_secs(5)).await; // 每 5 秒检查一次
-                Self::monitor_streams(streams_clone.clone());
+                let (stopped, restarts) = Self::monitor_streams(streams_clone.clone(), &history_clone, &sessions, &soak_restart, &mut last_soak);
+                // 每个流的重启在独立任务中进行，不阻塞本轮对其他流的检查
+                for restart in restarts {
+                    tokio::spawn(Self::restart(streams_clone.clone(), history_clone.clone(), restart));
+                }
+                for event in stopped {
+                    // 已移除的流不能再由合并窗口内的心跳报告为存在
+                    recent_heartbeats.lock().unwrap().remove(&event.stream);
+                    let _ = stopped_tx.send(event);
+                }
+                Self::resume_standbys(&streams_clone, &standbys);
             }
         });
 
         manager
     }
 
-    pub fn start_stream(&self, name: String, input_url: String, output_url: String) {
+    /// 启用以 ffmpeg 子进程只转封装
+    #[cfg(feature = "async-remux")]
+    pub fn with_async_remux(mut self, config: AsyncRemuxConfig) -> Self {
+        self.async_remux = config;
+        self
+    }
+
+    /// 启动定时缩略图任务，按间隔为运行中的流截图写入 `root`
+    pub fn with_thumbnails(self, config: ThumbnailConfig, root: PathBuf) -> Self {
+        if let Err(e) = std::fs::create_dir_all(&root) {
+            warn!("创建缩略图目录 {} 失败，不生成缩略图: {}", root.display(), e);
+            return self;
+        }
+        info!("每 {} 秒为运行中的流生成缩略图: {}", config.interval().as_secs(), root.display());
+        let streams = self.streams.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(config.interval());
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                ticker.tick().await;
+                let targets: Vec<_> = {
+                    let streams = streams.lock().unwrap();
+                    streams
+                        .iter()
+                        .filter(|(_, state)| !state.handle.is_finished())
+                        .filter_map(|(name, state)| Some((name.clone(), state.job.hooks.snapshot.clone()?)))
+                        .collect()
+                };
+                if !targets.is_empty() {
+                    thumbnail::capture_all(targets, root.clone(), config.clone()).await;
+                }
+            }
+        });
+        self
+    }
+
+    /// 启动录制文件清理任务，按保留天数与总大小上限定期删除最早的文件
+    pub fn with_recording_retention(self) -> Self {
+        let (root, config) = self.recording.clone();
+        if !config.retention_enabled() {
+            return self;
+        }
+        let streams = self.streams.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(recording::CLEANUP_INTERVAL);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                ticker.tick().await;
+                let active = Self::active_recordings(&streams.lock().unwrap());
+                let (root, config) = (root.clone(), config.clone());
+                match tokio::task::spawn_blocking(move || recording::cleanup(&root, &config, &active)).await {
+                    Ok((0, _)) => {}
+                    Ok((removed, freed)) => info!("已清理 {} 个录制文件，释放 {} MB", removed, freed / 1024 / 1024),
+                    Err(e) => error!("清理录制文件失败: {}", e),
+                }
+            }
+        });
+        self
+    }
+
+    /// 正在写入的录制文件
+    fn active_recordings(streams: &HashMap<String, StreamState>) -> Vec<PathBuf> {
+        streams
+            .values()
+            .filter_map(|state| state.job.hooks.record.as_ref()?.0.status().file)
+            .map(PathBuf::from)
+            .collect()
+    }
+
+    /// 流的录制目录
+    pub fn recording_dir(&self, name: &str) -> PathBuf {
+        hls::stream_dir(&self.recording.0, name)
+    }
+
+    /// 流的录制文件，按开始时间排序；第二项标记是否正在写入
+    pub fn recordings(&self, name: &str) -> Vec<(recording::RecordingFile, bool)> {
+        let active = Self::active_recordings(&self.streams.lock().unwrap());
+        recording::list(&self.recording_dir(name))
+            .into_iter()
+            .map(|file| {
+                let writing = active.contains(&file.path);
+                (file, writing)
+            })
+            .collect()
+    }
+
+    /// 观看会话
+    pub fn sessions(&self) -> Arc<ViewerSessions> {
+        self.sessions.clone()
+    }
+
+    /// 订阅流停止事件：流停止推流并被移除，或重启次数用尽不再自动重启
+    pub fn stopped_events(&self) -> broadcast::Receiver<StoppedEvent> {
+        self.stopped_tx.subscribe()
+    }
+
+    /// 在阻塞线程中启动转码任务，并记录健康状态变化；以子进程只转封装的流改为启动异步任务
+    fn spawn_transcoder(
+        job: TranscodeJob,
+        input_url: String,
+        running: Arc<AtomicBool>,
+        history: Arc<HealthHistory>,
+    ) -> JoinHandle<()> {
+        #[cfg(feature = "async-remux")]
+        if let Some(ffmpeg) = job.async_remux.clone()
+            && async_remux::supports(&input_url, &job.input_options, &job.output_url, &job.output_options)
+        {
+            return tokio::spawn(async move {
+                let name = job.name;
+                let result = async_remux::run(
+                    &ffmpeg,
+                    &input_url,
+                    &job.input_options,
+                    &job.output_url,
+                    &job.output_options,
+                    &running,
+                    &job.hooks.stats,
+                    || history.record(&name, true, "开始推流"),
+                )
+                .await;
+                Self::record_result(&history, &name, &running, result);
+            });
+        }
+        tokio::task::spawn_blocking(move || {
+            let name = job.name;
+            let transcoder = Transcoder::new(
+                input_url,
+                job.input_options,
+                job.output_url,
+                job.output_options,
+                job.publish_retry,
+                running.clone(),
+                job.hooks,
+            );
+            let result = transcoder.run(|| history.record(&name, true, "开始推流"));
+            Self::record_result(&history, &name, &running, result);
+        })
+    }
+
+    /// 记录转码任务的结束原因，主动停止的原因由管理器记录，这里只记录异常结束
+    fn record_result(history: &HealthHistory, name: &str, running: &AtomicBool, result: anyhow::Result<()>) {
+        let stopped = !running.load(Ordering::Relaxed);
+        match result {
+            Ok(_) => {
+                info!("流 '{}' 已成功结束。", name);
+                if !stopped {
+                    history.record(name, false, "输入流结束");
+                }
+            }
+            Err(e) => {
+                error!("流 '{}' 失败: {}", name, e);
+                if !stopped {
+                    history.record(name, false, &format!("转码失败: {}", e));
+                }
+            }
+        }
+    }
+
+    /// 启动流，`input_urls` 第一个为主源，其余为按顺序切换的备用源
+    ///
+    /// 已开始停止所有流时返回 [`Closing`]，避免启动的转码任务随进程退出而丢失。
+    pub fn start_stream(
+        &self,
+        name: String,
+        input_urls: Vec<String>,
+        input_options: InputOptions,
+        output_url: String,
+        output_options: OutputOptions,
+        policy: StreamPolicy,
+    ) -> Result<(), Closing> {
         let mut streams = self.streams.lock().unwrap();
+        if self.closing.load(Ordering::Relaxed) {
+            warn!("服务正在停止，不再启动流 '{}'", name);
+            return Err(Closing);
+        }
 
         if let Some(state) = streams.get_mut(&name) {
             // 检查现有流是否确实存活
@@ -51,7 +607,7 @@ impl StreamManager {
                 // 流正在运行且健康，仅更新心跳
                 state.last_heartbeat = Instant::now();
                 info!("流 '{}' 正在运行，已更新心跳。", name);
-                return;
+                return Ok(());
             } else {
                 // 流存在但线程已结束（僵尸状态）
                 warn!("流 '{}' 处于僵尸状态（线程已结束）。正在重启...", name);
@@ -60,45 +616,481 @@ impl StreamManager {
         }
 
         info!("启动新流: {}", name);
+        let sources = Failover::new(input_urls, policy.sub_fallback);
+        // 以子进程只转封装时，需要在本进程处理数据包的功能均不可用
+        #[cfg(feature = "async-remux")]
+        let async_remux = (self.async_remux.applies(policy.remux_only)
+            && async_remux::supports(sources.active_url(), &input_options, &output_url, &output_options))
+        .then(|| self.async_remux.ffmpeg.clone());
+        #[cfg(feature = "async-remux")]
+        let in_process = async_remux.is_none();
+        #[cfg(not(feature = "async-remux"))]
+        let in_process = true;
+        let job = TranscodeJob {
+            name: name.clone(),
+            input_options,
+            output_url,
+            output_options,
+            publish_retry: self.publish_retry.clone(),
+            protected_hours: policy.protected_hours,
+            hooks: JobHooks {
+                dump: Arc::new(DumpControl::default()),
+                trace: Arc::new(TraceControl::default()),
+                gop_cache: (self.gop_cache.enabled && in_process).then(|| Arc::new(GopCache::new(self.gop_cache.max_bytes))),
+                content: (self.content_check.enabled && !policy.remux_only && in_process).then(|| {
+                    self.content_monitors
+                        .lock()
+                        .unwrap()
+                        .entry(name.clone())
+                        .or_insert_with(|| Arc::new(ContentMonitor::new(&name, self.content_check.clone(), self.history.clone())))
+                        .clone()
+                }),
+                clock: (self.clock_skew.enabled && in_process).then(|| {
+                    self.clock_monitors
+                        .lock()
+                        .unwrap()
+                        .entry(name.clone())
+                        .or_insert_with(|| Arc::new(ClockMonitor::new(&name, self.clock_skew.clone(), self.history.clone())))
+                        .clone()
+                }),
+                preview: (self.preview.enabled && !policy.remux_only && in_process).then(|| {
+                    let cache = self.previews.lock().unwrap().entry(name.clone()).or_default().clone();
+                    (cache, self.preview.clone())
+                }),
+                snapshot: in_process.then(Arc::default),
+                hls: self
+                    .hls
+                    .as_ref()
+                    .filter(|_| in_process)
+                    .map(|(root, config)| (hls::stream_dir(root, &name), config.clone())),
+                clip: (self.clip.enabled && in_process).then(|| Arc::new(ClipBuffer::new(self.clip.clone()))),
+                record: in_process.then(|| {
+                    let (root, config) = &self.recording;
+                    (Arc::default(), hls::stream_dir(root, &name), config.clone())
+                }),
+                stats: Arc::new(StreamStats::default()),
+                standby: self.standbys.lock().unwrap().get(&name).cloned().filter(|_| in_process),
+                relay: rtsp_server::publish_url(&name).filter(|_| in_process),
+                #[cfg(feature = "chaos")]
+                fault: Arc::default(),
+            },
+            #[cfg(feature = "async-remux")]
+            async_remux,
+        };
         let running = Arc::new(AtomicBool::new(true));
-        let running_clone = running.clone();
-        let name_clone = name.clone();
-        let input_clone = input_url.clone();
-        let output_clone = output_url.clone();
-
-        let handle = tokio::task::spawn_blocking(move || {
-            let transcoder = Transcoder::new(input_clone, output_clone, running_clone);
-            match transcoder.run() {
-                Ok(_) => info!("流 '{}' 已成功结束。", name_clone),
-                Err(e) => error!("流 '{}' 失败: {}", name_clone, e),
-            }
-        });
+        let handle = Self::spawn_transcoder(
+            job.clone(),
+            sources.active_url().to_string(),
+            running.clone(),
+            self.history.clone(),
+        );
 
         streams.insert(name, StreamState {
             running,
             last_heartbeat: Instant::now(),
             handle,
-            input_url,
-            output_url,
+            sources,
+            job,
             restart_count: 0,
             last_restart_attempt: Instant::now(),
+            running_since: Instant::now(),
+            cost: policy.cost,
+            exhausted: false,
+            restarting: false,
+            srs_error: None,
         });
+        Ok(())
     }
 
-    pub fn heartbeat(&self, name: &str) -> bool {
+    /// 为流建立预连接，无人观看时保持与摄像头的连接，播放时立即开始推流
+    pub fn add_standby(&self, name: String, input_url: String, input_options: InputOptions) {
+        let standby = Arc::new(Standby::new(name.clone(), input_url, input_options));
+        if !self.streams.lock().unwrap().contains_key(&name) {
+            standby.ensure_running();
+        }
+        self.standbys.lock().unwrap().insert(name, standby);
+    }
+
+    /// 移除流的预连接（流被删除时）
+    pub fn remove_standby(&self, name: &str) {
+        if let Some(standby) = self.standbys.lock().unwrap().remove(name) {
+            standby.stop();
+        }
+    }
+
+    /// 流停止后恢复其预连接
+    fn resume_standbys(streams: &Mutex<HashMap<String, StreamState>>, standbys: &Mutex<HashMap<String, Arc<Standby>>>) {
+        let streams = streams.lock().unwrap();
+        for (name, standby) in standbys.lock().unwrap().iter() {
+            if !streams.contains_key(name) {
+                standby.ensure_running();
+            }
+        }
+    }
+
+    /// 流是否正在运行
+    pub fn is_running(&self, name: &str) -> bool {
+        let streams = self.streams.lock().unwrap();
+        streams.get(name).is_some_and(|state| !state.handle.is_finished())
+    }
+
+    /// 获取运行中流的抓包控制
+    pub fn dump_control(&self, name: &str) -> Option<Arc<DumpControl>> {
+        let streams = self.streams.lock().unwrap();
+        streams.get(name).map(|state| state.job.hooks.dump.clone())
+    }
+
+    /// 获取运行中流的数据包跟踪控制
+    pub fn trace_control(&self, name: &str) -> Option<Arc<TraceControl>> {
+        let streams = self.streams.lock().unwrap();
+        streams.get(name).map(|state| state.job.hooks.trace.clone())
+    }
+
+    /// 获取运行中流的 GOP 缓存
+    pub fn gop_cache(&self, name: &str) -> Option<Arc<GopCache>> {
+        let streams = self.streams.lock().unwrap();
+        streams.get(name).and_then(|state| state.job.hooks.gop_cache.clone())
+    }
+
+    /// 故障注入：让流的转码线程以错误退出，之后按重启策略重启；流不存在时返回 false
+    #[cfg(feature = "chaos")]
+    pub fn inject_kill(&self, name: &str) -> bool {
+        let streams = self.streams.lock().unwrap();
+        streams.get(name).map(|state| state.job.hooks.fault.kill()).is_some()
+    }
+
+    /// 获取运行中流实际使用的参数
+    pub fn running_config(&self, name: &str) -> Option<RunningConfig> {
+        let streams = self.streams.lock().unwrap();
+        streams.get(name).map(|state| {
+            let mut input = state.job.input_options.clone();
+            input.proxy = input.proxy.as_deref().map(vault::redact_url);
+            RunningConfig {
+                input_urls: state.sources.urls.iter().map(|u| vault::redact_url(u)).collect(),
+                active_source: state.sources.active,
+                input,
+                output_url: vault::redact_url(&state.job.output_url),
+                output: state.job.output_options.clone(),
+                protected_hours: state.job.protected_hours.clone(),
+            }
+        })
+    }
+
+    /// 获取运行中流的片段缓冲
+    pub fn clip_buffer(&self, name: &str) -> Option<Arc<ClipBuffer>> {
+        let streams = self.streams.lock().unwrap();
+        streams.get(name).and_then(|state| state.job.hooks.clip.clone())
+    }
+
+    /// 获取运行中流的录制开关，由子进程转封装的流没有
+    pub fn record_control(&self, name: &str) -> Option<Arc<RecordControl>> {
+        let streams = self.streams.lock().unwrap();
+        streams.get(name).and_then(|state| state.job.hooks.record.as_ref().map(|(control, _, _)| control.clone()))
+    }
+
+    /// 获取运行中流的截图请求，由子进程转封装的流没有
+    pub fn snapshot_requests(&self, name: &str) -> Option<Arc<SnapshotRequests>> {
+        let streams = self.streams.lock().unwrap();
+        streams.get(name).filter(|state| !state.handle.is_finished()).and_then(|state| state.job.hooks.snapshot.clone())
+    }
+
+    /// 流最近一次的预览缩略图，超过 `stale_secs` 未更新时视为没有
+    pub fn preview(&self, name: &str) -> Option<Arc<Preview>> {
+        let previews = self.previews.lock().unwrap();
+        let preview = previews.get(name)?.latest()?;
+        (now_secs().saturating_sub(preview.captured_at) <= self.preview.stale_secs).then_some(preview)
+    }
+
+    /// 运行中流的预估负载之和
+    pub fn committed_cost(&self) -> u32 {
+        let streams = self.streams.lock().unwrap();
+        streams.values().map(|state| state.cost).sum()
+    }
+
+    /// 获取运行中流的统计
+    pub fn stats(&self, name: &str) -> Option<StatsSnapshot> {
+        let streams = self.streams.lock().unwrap();
+        streams.get(name).map(|state| state.job.hooks.stats.snapshot())
+    }
+
+    /// 所有已启动流的状态
+    pub fn snapshot(&self) -> Vec<StreamStatus> {
+        let now = Instant::now();
+        let viewers = self.sessions.counts();
+        let streams = self.streams.lock().unwrap();
+        streams.iter().map(|(name, state)| Self::status(name, state, now, &viewers)).collect()
+    }
+
+    /// 单个流的状态，流未启动时返回 None
+    pub fn status_of(&self, name: &str) -> Option<StreamStatus> {
+        let viewers = self.sessions.counts();
+        let streams = self.streams.lock().unwrap();
+        streams.get(name).map(|state| Self::status(name, state, Instant::now(), &viewers))
+    }
+
+    /// 单个流的运行详情，流未启动时返回 None
+    pub fn detail(&self, name: &str) -> Option<StreamDetail> {
+        let now = Instant::now();
+        let viewers = self.sessions.counts();
+        let streams = self.streams.lock().unwrap();
+        let state = streams.get(name)?;
+        let phase = if state.restarting {
+            RunPhase::Restarting
+        } else if state.handle.is_finished() {
+            RunPhase::Crashed
+        } else if !state.running.load(Ordering::Relaxed) {
+            RunPhase::Stopping
+        } else {
+            RunPhase::Running
+        };
+        Some(StreamDetail {
+            status: Self::status(name, state, now, &viewers),
+            phase,
+            uptime_secs: now.duration_since(state.running_since).as_secs(),
+            input_url: vault::redact_url(state.sources.active_url()),
+            output_url: vault::redact_url(&state.job.output_url),
+            stats: state.job.hooks.stats.snapshot(),
+            metadata: BTreeMap::new(),
+        })
+    }
+
+    /// 所有已启动流的状态与统计
+    pub fn snapshot_with_stats(&self) -> Vec<(StreamStatus, StatsSnapshot)> {
+        let now = Instant::now();
+        let viewers = self.sessions.counts();
+        let streams = self.streams.lock().unwrap();
+        let mut all: Vec<_> = streams
+            .iter()
+            .map(|(name, state)| (Self::status(name, state, now, &viewers), state.job.hooks.stats.snapshot()))
+            .collect();
+        all.sort_by(|a, b| a.0.name.cmp(&b.0.name));
+        all
+    }
+
+    fn status(name: &str, state: &StreamState, now: Instant, viewers: &HashMap<String, usize>) -> StreamStatus {
+        StreamStatus {
+            name: name.to_string(),
+            running: !state.handle.is_finished(),
+            active_source: state.sources.active,
+            restart_count: state.restart_count,
+            last_heartbeat_secs: now.duration_since(state.last_heartbeat).as_secs(),
+            viewers: viewers.get(name).copied().unwrap_or(0),
+            content_problem: state.job.hooks.content.as_ref().and_then(|c| c.problem()),
+            protected: state.job.protected_hours.as_ref().is_some_and(|p| p.contains(now_secs())),
+            recording: state.job.hooks.record.as_ref().is_some_and(|(control, _, _)| control.enabled()),
+            downgraded: state.sources.is_fallback(state.sources.active),
+            view_changed_at: state.job.hooks.content.as_ref().and_then(|c| c.changed_at()),
+            clock_skewed: state.job.hooks.clock.as_ref().is_some_and(|c| c.skewed()),
+            restart_budget: state.max_restarts().saturating_sub(state.restart_count),
+            restarts_exhausted: state.exhausted,
+            publish_degraded: !state.handle.is_finished() && state.job.hooks.stats.output.degraded.load(Ordering::Relaxed),
+            input_idle_secs: state.job.hooks.stats.input.idle_secs(),
+            output_idle_secs: state.job.hooks.stats.output.idle_secs(),
+            srs_error: state.srs_error.clone(),
+        }
+    }
+
+    /// 清零流的重启计数，重新启用自动重启；因重启次数用尽而停止的流立即重新启动。流不存在时返回 false
+    pub fn reset_failures(&self, name: &str) -> bool {
         let mut streams = self.streams.lock().unwrap();
-        if let Some(state) = streams.get_mut(name) {
+        let Some(state) = streams.get_mut(name) else {
+            return false;
+        };
+        state.restart_count = 0;
+        state.sources.failures = 0;
+        if state.exhausted {
+            info!("流 '{}' 已重置重启次数，重新启动", name);
+            state.exhausted = false;
+            let running = Arc::new(AtomicBool::new(true));
+            state.handle = Self::spawn_transcoder(
+                state.job.clone(),
+                state.sources.active_url().to_string(),
+                running.clone(),
+                self.history.clone(),
+            );
+            state.running = running;
             state.last_heartbeat = Instant::now();
-            true
-        } else {
-            false
+            state.last_restart_attempt = Instant::now();
+            state.running_since = Instant::now();
+        }
+        true
+    }
+
+    /// 记录播放时调用 SRS 接口的结果，状态变化时写入健康历史；流不存在时忽略
+    pub fn set_srs_error(&self, name: &str, error: Option<String>) {
+        {
+            let mut streams = self.streams.lock().unwrap();
+            let Some(state) = streams.get_mut(name) else {
+                return;
+            };
+            if state.srs_error == error {
+                return;
+            }
+            state.srs_error = error.clone();
+        }
+        match &error {
+            Some(e) => self.history.record_degraded(name, false, &format!("SRS 接口调用失败: {}", e)),
+            None => self.history.record_degraded(name, true, "SRS 接口恢复"),
         }
     }
 
-    fn monitor_streams(streams: Arc<Mutex<HashMap<String, StreamState>>>) {
+    pub fn heartbeat(&self, name: &str) -> bool {
+        self.heartbeat_many(&[name])[0]
+    }
+
+    /// 批量处理心跳，返回每个流是否存在
+    ///
+    /// 合并窗口内已经写入过的心跳直接返回，其余的在一次加锁内统一更新。
+    pub fn heartbeat_many(&self, names: &[&str]) -> Vec<bool> {
+        let now = Instant::now();
+        let mut results = vec![false; names.len()];
+        let mut pending = Vec::new();
+
+        {
+            let recent = self.recent_heartbeats.lock().unwrap();
+            for (i, name) in names.iter().enumerate() {
+                match recent.get(*name) {
+                    Some(at) if now.duration_since(*at) < HEARTBEAT_COALESCE_WINDOW => results[i] = true,
+                    _ => pending.push(i),
+                }
+            }
+        }
+
+        if pending.is_empty() {
+            return results;
+        }
+
+        let mut updated = Vec::new();
+        let mut streams = self.streams.lock().unwrap();
+        for i in pending {
+            #[cfg(feature = "chaos")]
+            if crate::chaos::drops_heartbeat(names[i]) {
+                results[i] = streams.contains_key(names[i]);
+                continue;
+            }
+            if let Some(state) = streams.get_mut(names[i]) {
+                state.last_heartbeat = now;
+                results[i] = true;
+                updated.push(names[i]);
+            }
+        }
+
+        // 持有 streams 锁时写入，避免与停止流时的清除交错，把刚移除的流重新记为存在
+        let mut recent = self.recent_heartbeats.lock().unwrap();
+        recent.retain(|_, at| now.duration_since(*at) < HEARTBEAT_COALESCE_WINDOW);
+        for name in updated {
+            recent.insert(name.to_string(), now);
+        }
+        drop(streams);
+
+        results
+    }
+
+    /// 运行中的流数量
+    pub fn running_count(&self) -> usize {
+        self.streams.lock().unwrap().len()
+    }
+
+    /// 停止流并等待转码线程退出（最长等待 `timeout`），流不存在时返回 false
+    ///
+    /// 观众主动关闭播放器时立即释放摄像头连接与 CPU，不必等待心跳超时；停止后同样发送停止推流通知。
+    pub async fn stop_stream(&self, name: &str, reason: &str, timeout: Duration) -> bool {
+        let Some(state) = self.streams.lock().unwrap().remove(name) else {
+            return false;
+        };
+        self.recent_heartbeats.lock().unwrap().remove(name);
+        info!("停止流: {}", name);
+        if state.running.swap(false, Ordering::Relaxed) {
+            self.history.record(name, false, reason);
+        }
+        if tokio::time::timeout(timeout, state.handle).await.is_err() {
+            warn!("等待流 '{}' 的转码线程退出超时", name);
+        }
+        let _ = self.stopped_tx.send(StoppedEvent::new(name, StopReason::Stopped, reason));
+        true
+    }
+
+    /// 停止所有流并等待转码线程退出，最长等待 `timeout`，返回停止的转码任务
+    ///
+    /// 直接从列表中移除，不触发停止推流通知，之后不再启动新的流。`handover` 为 true 时流由新进程接管（平滑升级），
+    /// 不在健康历史中记录中断，避免计入可用率。
+    pub async fn stop_all(&self, reason: &str, handover: bool, timeout: Duration) -> Vec<RunningJob> {
+        let stopped: Vec<StreamState> = {
+            let mut streams = self.streams.lock().unwrap();
+            self.closing.store(true, Ordering::Relaxed);
+            streams.drain().map(|(_, state)| state).collect()
+        };
+        self.recent_heartbeats.lock().unwrap().clear();
+        for state in &stopped {
+            if state.running.swap(false, Ordering::Relaxed) && !handover {
+                self.history.record(&state.job.name, false, reason);
+            }
+        }
+        if handover {
+            info!("{}，停止 {} 个流", reason, stopped.len());
+        }
+        let jobs = stopped
+            .iter()
+            .map(|state| RunningJob {
+                name: state.job.name.clone(),
+                url: state.sources.urls[0].clone(),
+                output_url: state.job.output_url.clone(),
+                input: state.job.input_options.clone(),
+                output: state.job.output_options.clone(),
+            })
+            .collect();
+        let deadline = tokio::time::Instant::now() + timeout;
+        for state in stopped {
+            if tokio::time::timeout_at(deadline, state.handle).await.is_err() {
+                warn!("等待流 '{}' 的转码线程退出超时", state.job.name);
+            }
+        }
+        jobs
+    }
+
+    /// 启动新的转码实例并写回流状态；期间流已被移除或被新的播放请求替换时停止新实例
+    async fn restart(streams: Arc<Mutex<HashMap<String, StreamState>>>, history: Arc<HealthHistory>, restart: PendingRestart) {
+        let running = Arc::new(AtomicBool::new(true));
+        let handle = Self::spawn_transcoder(restart.job, restart.input_url, running.clone(), history);
+
+        let mut streams = streams.lock().unwrap();
+        let Some(state) = streams.get_mut(&restart.name).filter(|state| state.restarting) else {
+            info!("流 '{}' 在重启期间已被移除或替换，停止新实例", restart.name);
+            running.store(false, Ordering::Relaxed);
+            return;
+        };
+        state.restarting = false;
+        state.running = running;
+        state.handle = handle;
+        state.last_heartbeat = Instant::now(); // 重启时重置心跳
+        if restart.counts_as_restart {
+            state.restart_count += 1;
+            state.job.hooks.stats.restarts.fetch_add(1, Ordering::Relaxed);
+        }
+        state.last_restart_attempt = Instant::now();
+        state.running_since = Instant::now();
+    }
+
+    /// 检查所有流，返回本轮移除的流与需要重启的流
+    ///
+    /// 持锁期间只做判断与状态标记，启动转码实例由调用方在锁外并发执行。
+    /// `last_soak` 为上一次长时间运行重启的时间，每轮最多计划一次，且没有其他流在重启或切换时才计划。
+    fn monitor_streams(
+        streams: Arc<Mutex<HashMap<String, StreamState>>>,
+        history: &Arc<HealthHistory>,
+        sessions: &ViewerSessions,
+        soak_restart: &SoakRestartConfig,
+        last_soak: &mut Option<Instant>,
+    ) -> (Vec<StoppedEvent>, Vec<PendingRestart>) {
+        let mut stopped = Vec::new();
+        let mut restarts = Vec::new();
+        let viewers = sessions.counts();
         let mut streams = streams.lock().unwrap();
         let now = Instant::now();
         let timeout = Duration::from_secs(120); // 120秒超时，避免过早关闭
+        let now_secs = now_secs();
+        let mut soak_ready = soak_restart.ready(now_secs, last_soak.map(|at| now.duration_since(at)))
+            && !streams.values().any(|s| s.restarting || s.sources.pending.is_some());
 
         // 识别需要处理的流
         let keys: Vec<String> = streams.keys().cloned().collect();
@@ -106,11 +1098,20 @@ impl StreamManager {
         for key in keys {
             let should_remove;
             let mut restart_needed = false;
+            let mut counts_as_restart = true;
             
             {
                 let state = streams.get_mut(&key).unwrap();
+                if state.restarting {
+                    continue;
+                }
                 let elapsed = now.duration_since(state.last_heartbeat);
-                let is_timeout = elapsed > timeout;
+                // 保护时段内不因无观众停止
+                let protected = state.job.protected_hours.as_ref().is_some_and(|p| p.contains(now_secs));
+                // 录制中的流同样不停止
+                let recording = state.job.hooks.record.as_ref().is_some_and(|(control, _, _)| control.enabled());
+                // 最后一个观看会话过期后才算无观众；不使用会话的旧客户端只按心跳时间判断
+                let is_timeout = elapsed > timeout && !protected && !recording && !viewers.contains_key(&key);
                 let is_crashed = state.handle.is_finished();
 
                 // 如果流运行稳定超过 60 秒，重置重启计数
@@ -118,64 +1119,96 @@ impl StreamManager {
                     if state.restart_count > 0 {
                         state.restart_count = 0;
                     }
+                    state.sources.failures = 0;
                 }
 
                 if is_timeout {
                     info!("流 '{}' 超时（{:?} 无观众）。正在停止...", key, elapsed);
-                    state.running.store(false, Ordering::Relaxed);
+                    if state.running.swap(false, Ordering::Relaxed) {
+                        history.record(&key, false, "无观众超时停止");
+                    }
                     should_remove = true;
+                } else if is_crashed && let Some(target) = state.sources.pending.take() {
+                    // 计划内的切换，旧线程已退出，立即在目标源上启动
+                    state.sources.active = target;
+                    state.sources.failures = 0;
+                    should_remove = false;
+                    restart_needed = true;
+                    counts_as_restart = false;
+                } else if is_crashed && state.exhausted {
+                    // 重启次数已用尽，等待手动重置或无观众超时
+                    should_remove = false;
                 } else if is_crashed {
                     // 流崩溃但仍有观众（心跳活跃）
                     warn!("流 '{}' 已崩溃但有活跃观众。", key);
                     
                     // 检查重启频率
-                    if state.restart_count >= 5 {
+                    if state.restart_count >= state.max_restarts() {
                         error!("流 '{}' 重启次数过多（{} 次），停止自动重启。", key, state.restart_count);
-                        should_remove = true;
+                        history.record(&key, false, "重启次数过多，停止自动重启");
+                        state.exhausted = true;
+                        stopped.push(StoppedEvent::new(&key, StopReason::Exhausted, "重启次数过多，停止自动重启"));
+                        should_remove = false;
                     } else if now.duration_since(state.last_restart_attempt) < Duration::from_secs(10) {
                         warn!("流 '{}' 崩溃过快，等待冷却...", key);
                         should_remove = false; // 暂时保留，下次循环再试
                     } else {
+                        let was_fallback = state.sources.is_fallback(state.sources.active);
+                        if let Some(next) = state.sources.record_failure() {
+                            let downgrade = state.sources.is_fallback(next);
+                            let reason = if downgrade {
+                                "主码流连续失败，降级为子码流".to_string()
+                            } else if next == 0 && was_fallback {
+                                "子码流故障，切换回主码流".to_string()
+                            } else if next == 0 {
+                                "备用源故障，切换回主源".to_string()
+                            } else {
+                                format!("源故障，切换到备用源 #{}", next)
+                            };
+                            warn!("流 '{}' {}", key, reason);
+                            history.record(&key, false, &reason);
+                            if downgrade || was_fallback {
+                                history.record_degraded(&key, !downgrade, &reason);
+                            }
+                        }
                         warn!("尝试自动重启流 '{}' (第 {} 次)...", key, state.restart_count + 1);
                         should_remove = false;
                         restart_needed = true;
                     }
                 } else {
+                    // 使用备用源时，主源恢复后切回
+                    if state.sources.pending.is_none() && state.sources.primary_ok.swap(false, Ordering::Relaxed) {
+                        // 计划内的切换，不在健康历史中记录中断
+                        info!("流 '{}' 主源已恢复，切回主源", key);
+                        if state.sources.is_fallback(state.sources.active) {
+                            history.record_degraded(&key, true, "主码流恢复，取消降级");
+                        }
+                        state.sources.pending = Some(0);
+                        state.running.store(false, Ordering::Relaxed);
+                    } else if soak_ready && now.duration_since(state.running_since) >= soak_restart.max_runtime() {
+                        // 在当前源上平滑重启，不计入重启次数
+                        let hours = now.duration_since(state.running_since).as_secs() / 3600;
+                        info!("流 '{}' 已连续运行 {} 小时，计划重启", key, hours);
+                        history.record(&key, false, &format!("已连续运行 {} 小时，计划重启", hours));
+                        state.sources.pending = Some(state.sources.active);
+                        state.running.store(false, Ordering::Relaxed);
+                        soak_ready = false;
+                        *last_soak = Some(now);
+                    } else {
+                        state.sources.poll_primary(now, &state.job.input_options);
+                    }
                     should_remove = false;
                 }
             }
 
             if restart_needed {
-                // 提取重启所需信息
-                if let Some(old_state) = streams.get(&key) {
-                    let input_url = old_state.input_url.clone();
-                    let output_url = old_state.output_url.clone();
-                    let restart_count = old_state.restart_count + 1;
-                    
-                    // 启动新实例
-                    let running = Arc::new(AtomicBool::new(true));
-                    let running_clone = running.clone();
-                    let name_clone = key.clone();
-                    let input_clone = input_url.clone();
-                    let output_clone = output_url.clone();
-
-                    let handle = tokio::task::spawn_blocking(move || {
-                        let transcoder = Transcoder::new(input_clone, output_clone, running_clone);
-                        match transcoder.run() {
-                            Ok(_) => info!("流 '{}' 已成功结束。", name_clone),
-                            Err(e) => error!("流 '{}' 失败: {}", name_clone, e),
-                        }
-                    });
-
-                    // 更新 Map 中的状态
-                    streams.insert(key.clone(), StreamState {
-                        running,
-                        last_heartbeat: Instant::now(), // 重启时重置心跳
-                        handle,
-                        input_url,
-                        output_url,
-                        restart_count,
-                        last_restart_attempt: Instant::now(),
+                if let Some(state) = streams.get_mut(&key) {
+                    state.restarting = true;
+                    restarts.push(PendingRestart {
+                        name: key.clone(),
+                        job: state.job.clone(),
+                        input_url: state.sources.active_url().to_string(),
+                        counts_as_restart,
                     });
                 }
             } else if should_remove {
@@ -184,9 +1217,11 @@ impl StreamManager {
                      if state.handle.is_finished() {
                          streams.remove(&key);
                          info!("已移除停止的流: {}", key);
+                         stopped.push(StoppedEvent::new(&key, StopReason::Timeout, "无观众超时停止"));
                      }
                 }
             }
         }
+        (stopped, restarts)
     }
 }
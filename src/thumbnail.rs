@@ -0,0 +1,110 @@
+use anyhow::Result;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, warn};
+use crate::clip::file_stem;
+use crate::snapshot::{self, SNAPSHOT_TIMEOUT, SnapshotRequests};
+
+/// 定时缩略图配置
+#[derive(Debug, Deserialize, Clone)]
+pub struct ThumbnailConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// 截图间隔（秒）
+    #[serde(default = "default_interval_secs")]
+    pub interval_secs: u64,
+    /// 缩略图宽度（像素），高度按比例缩放，0 为原始分辨率
+    #[serde(default = "default_width")]
+    pub width: u32,
+    /// JPEG 质量，2（最好）到 31（最差）
+    #[serde(default = "default_quality")]
+    pub quality: u32,
+    /// 缩略图目录，缺省为 `<data_dir>/thumbnails`
+    #[serde(default)]
+    pub dir: Option<String>,
+}
+
+impl Default for ThumbnailConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval_secs: default_interval_secs(),
+            width: default_width(),
+            quality: default_quality(),
+            dir: None,
+        }
+    }
+}
+
+fn default_interval_secs() -> u64 {
+    60
+}
+
+fn default_width() -> u32 {
+    640
+}
+
+fn default_quality() -> u32 {
+    5
+}
+
+impl ThumbnailConfig {
+    /// 缩略图目录
+    pub fn root(&self, data_dir: &str) -> PathBuf {
+        match &self.dir {
+            Some(dir) => PathBuf::from(dir),
+            None => PathBuf::from(data_dir).join("thumbnails"),
+        }
+    }
+
+    /// 截图间隔，不短于等待关键帧的超时时间
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval_secs).max(SNAPSHOT_TIMEOUT)
+    }
+}
+
+/// 流的缩略图路径
+///
+/// 文件名由安全字符与名称哈希组成，避免不同流名称替换字符后重名。
+pub fn path(root: &Path, name: &str) -> PathBuf {
+    let hash: String = Sha256::digest(name.as_bytes())[..4].iter().map(|b| format!("{:02x}", b)).collect();
+    root.join(format!("{}-{}.jpg", file_stem(name), hash))
+}
+
+/// 为一批运行中的流各截取一张缩略图写入目录
+///
+/// 同时向所有流发出截图请求，统一等待关键帧；流停止后保留最后一张缩略图。
+pub async fn capture_all(streams: Vec<(String, Arc<SnapshotRequests>)>, root: PathBuf, config: ThumbnailConfig) {
+    let pending: Vec<_> = streams.into_iter().map(|(name, requests)| (name, requests.request())).collect();
+    let deadline = tokio::time::Instant::now() + SNAPSHOT_TIMEOUT;
+    for (name, rx) in pending {
+        let frame = match tokio::time::timeout_at(deadline, rx).await {
+            Ok(Ok(Ok(frame))) => frame,
+            Ok(Ok(Err(e))) => {
+                debug!("流 '{}' 截取缩略图失败: {}", name, e);
+                continue;
+            }
+            // 流已停止或未在超时前收到关键帧，下一轮再试
+            _ => continue,
+        };
+        let path = path(&root, &name);
+        let config = config.clone();
+        let result = tokio::task::spawn_blocking(move || -> Result<()> {
+            let data = snapshot::encode(&frame, Some(config.width).filter(|w| *w > 0), config.quality)?;
+            // 先写临时文件再改名，读取方不会读到写了一半的图片
+            let tmp = path.with_extension("jpg.tmp");
+            std::fs::write(&tmp, data)?;
+            std::fs::rename(&tmp, &path)?;
+            Ok(())
+        })
+        .await;
+        match result {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => warn!("写入流 '{}' 的缩略图失败: {:#}", name, e),
+            Err(e) => warn!("写入流 '{}' 的缩略图失败: {}", name, e),
+        }
+    }
+}
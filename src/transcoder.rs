@@ -1,7 +1,207 @@
 use anyhow::{Result, anyhow};
 use ffmpeg_next as ffmpeg;
+use ffmpeg::Rescale;
+use std::net::IpAddr;
+use std::path::PathBuf;
 use std::sync::{Arc, atomic::{AtomicBool, Ordering}};
-use tracing::info;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+use crate::clip::ClipBuffer;
+use crate::config::{InputOptions, OutputOptions, PublishRetryConfig, RtspTransport};
+use crate::clock_skew::ClockMonitor;
+use crate::content_check::ContentMonitor;
+use crate::dump::{self, DumpControl, DumpMode, Dumper};
+use crate::frame_tap::FrameTap;
+use crate::gop_cache::{GopCache, LocalFlv, flv_muxer_options};
+use crate::hls::{HlsConfig, HlsOutput};
+use crate::hwaccel::{HwFrames, VideoEncoder};
+use crate::packet_trace::TraceControl;
+use crate::preview::{PreviewCache, PreviewConfig};
+use crate::recording::{RecordControl, Recorder, RecordingConfig};
+use crate::rtsp_server::RelayOutput;
+use crate::snapshot::SnapshotRequests;
+use crate::standby::Standby;
+use crate::stats::{InputMeter, StreamStats, TimestampStats, TimestampSummary};
+use crate::{dns, gb28181, http_flv, ingest, proxy, vault};
+
+/// 测试信号源地址前缀
+///
+/// 形如 `testsrc://` 或 `testsrc://1280x720`，由 lavfi 生成彩条 + 时间码 + 1kHz 音频，
+/// 用于在摄像头安装前验证 SRS / 播放链路。
+pub const TEST_SOURCE_SCHEME: &str = "testsrc://";
+
+/// 转码器支持的输入协议
+///
+/// udp:// 与 rtp:// 用于接收组播分发的 MPEG-TS 流，gb28181:// 由内置 SIP 信令服务点播，
+/// http:// 与 https:// 用于第三方的 HLS / DASH 直播（播放列表地址）。
+pub const SUPPORTED_SCHEMES: &[&str] = &["rtsp://", "udp://", "rtp://", "http://", "https://", gb28181::SCHEME, TEST_SOURCE_SCHEME];
+
+/// HLS / DASH 输入允许的协议，避免播放列表引用本地文件等其他协议
+const HTTP_PROTOCOL_WHITELIST: &str = "http,https,tls,tcp,crypto,httpproxy";
+
+/// 是否为 HTTP(S) 输入（HLS / DASH 播放列表）
+fn is_http_url(url: &str) -> bool {
+    let lower = url.to_lowercase();
+    lower.starts_with("http://") || lower.starts_with("https://")
+}
+
+/// HLS / DASH 输入可用的代理（FFmpeg 的 http_proxy 只支持 HTTP 代理）
+fn is_http_proxy(proxy: &str) -> bool {
+    proxy.to_lowercase().starts_with("http://")
+}
+
+/// 输入连接期间需要保持的资源（代理隧道、GB28181 点播会话），随转码结束释放
+pub type InputGuard = Box<dyn Send>;
+
+/// 判断输入地址是否为转码器支持的源
+pub fn is_supported_input(url: &str) -> bool {
+    let lower = url.to_lowercase();
+    if lower.starts_with(TEST_SOURCE_SCHEME) {
+        return test_source_size(url).is_ok();
+    }
+    SUPPORTED_SCHEMES.iter().any(|scheme| lower.starts_with(scheme))
+}
+
+/// 解析测试信号源地址中的分辨率，只接受空或 `宽x高`（各 1 到 5 位数字）
+///
+/// 分辨率会直接写入 lavfi 滤镜图，其他字符一律拒绝，避免注入额外滤镜。
+fn test_source_size(url: &str) -> Result<&str> {
+    let size = url[TEST_SOURCE_SCHEME.len()..].trim_matches('/');
+    if size.is_empty() {
+        return Ok("1280x720");
+    }
+    let valid = size
+        .split_once('x')
+        .is_some_and(|(w, h)| [w, h].iter().all(|n| (1..=5).contains(&n.len()) && n.bytes().all(|b| b.is_ascii_digit())));
+    if !valid {
+        return Err(anyhow!("测试信号源分辨率无效: {}，应为 宽x高，如 testsrc://1280x720", size));
+    }
+    Ok(size)
+}
+
+/// 判断 udp:// / rtp:// 地址是否为组播地址
+fn is_multicast_url(url: &str) -> bool {
+    url::Url::parse(url)
+        .ok()
+        .and_then(|u| u.host_str().map(|h| h.trim_matches(['[', ']']).to_string()))
+        .and_then(|h| h.parse::<IpAddr>().ok())
+        .is_some_and(|ip| ip.is_multicast())
+}
+
+/// HTTP 隧道方式下补全地址端口
+///
+/// FFmpeg 在地址未写端口时仍按 554 建立隧道连接，这里按隧道类型补为 80 / 443。
+fn tunnel_url(url: &str, options: &InputOptions) -> String {
+    let Some(port) = options.transport.and_then(|t| t.tunnel_port()) else {
+        return url.to_string();
+    };
+    match url::Url::parse(url) {
+        Ok(mut parsed) if parsed.port().is_none() => {
+            let _ = parsed.set_port(Some(port));
+            parsed.to_string()
+        }
+        _ => url.to_string(),
+    }
+}
+
+/// RTSP socket 超时的选项名称
+///
+/// FFmpeg 5（libavformat 59）起 stimeout 改名为 timeout；更早的版本中 timeout 是监听超时（秒），
+/// 设置后 RTSP 会进入监听模式，不能用于拉流。
+fn rtsp_timeout_option() -> &'static str {
+    if ffmpeg::format::version() >> 16 >= 59 { "timeout" } else { "stimeout" }
+}
+
+/// 根据输入地址与流的输入参数生成 FFmpeg 输入选项
+fn input_dictionary(url: &str, options: &InputOptions) -> ffmpeg::Dictionary<'static> {
+    let mut dict = ffmpeg::Dictionary::new();
+    let lower = url.to_lowercase();
+
+    // 读取超时 (单位: 微秒)，超时视为源中断
+    let timeout = |default_secs: u64| options.timeout_secs.unwrap_or(default_secs).saturating_mul(1_000_000).to_string();
+
+    if lower.starts_with("rtsp://") {
+        // 默认强制使用 TCP 传输 RTSP 以避免 UDP 丢包问题
+        let transport = options.transport.unwrap_or(RtspTransport::Tcp);
+        info!("RTSP 输入传输方式: {}", transport.as_str());
+        dict.set("rtsp_transport", transport.as_str());
+        // socket 超时缺省 5 秒以检测网络问题
+        dict.set(rtsp_timeout_option(), &timeout(5));
+        // 以 UDP / 组播接收 RTP 时的接收缓冲区，高码率摄像头丢包时调大
+        if matches!(transport, RtspTransport::Udp | RtspTransport::UdpMulticast)
+            && let Some(buffer_size) = options.buffer_size
+        {
+            dict.set("buffer_size", &buffer_size.to_string());
+        }
+    } else if lower.starts_with("udp://") || lower.starts_with("rtp://") {
+        if is_multicast_url(url) {
+            info!("组播输入: {}", vault::redact_url(url));
+            // 允许同一组播地址被多个进程/流同时接收
+            dict.set("reuse", "1");
+        }
+        // 指定加入组播组 (IGMP) 使用的网卡地址
+        if let Some(interface) = &options.interface {
+            dict.set("localaddr", interface);
+        }
+        let buffer_size = options.buffer_size.unwrap_or(4 * 1024 * 1024);
+        dict.set("buffer_size", &buffer_size.to_string());
+        // 接收线程缓冲溢出时丢包而不是报错退出
+        dict.set("overrun_nonfatal", "1");
+        dict.set("fifo_size", "1000000");
+        // 缺省 5 秒收不到数据视为源中断
+        dict.set("timeout", &timeout(5));
+    } else if is_http_url(url) {
+        info!("HTTP 播放列表输入: {}", vault::redact_url(url));
+        dict.set("protocol_whitelist", HTTP_PROTOCOL_WHITELIST);
+        // 片段请求失败时重连，复用连接减少 TLS 握手
+        dict.set("reconnect", "1");
+        dict.set("reconnect_streamed", "1");
+        dict.set("reconnect_delay_max", "5");
+        dict.set("http_persistent", "1");
+        // 缺省 10 秒读不到数据视为源中断，播放列表刷新间隔通常为数秒
+        dict.set("rw_timeout", &timeout(10));
+        if let Some(proxy) = options.proxy.as_deref().filter(|p| is_http_proxy(p)) {
+            dict.set("http_proxy", proxy);
+        }
+    }
+
+    // 自定义选项最后设置，可覆盖上面的默认值
+    for (key, value) in &options.options {
+        dict.set(key, value);
+    }
+    dict
+}
+
+/// 以 ffmpeg 子进程拉流时使用的输入地址与输入选项
+///
+/// 代理隧道、GB28181、HTTP 推流与测试信号源需要由本进程处理，返回 None。
+#[cfg(feature = "async-remux")]
+pub fn subprocess_input(url: &str, options: &InputOptions) -> Option<(String, Vec<(String, String)>)> {
+    let lower = url.to_lowercase();
+    let url = if is_http_url(url) {
+        // 流单独配置了不支持的代理时交给 open_input 报错
+        if options.proxy.as_deref().is_some_and(|p| !is_http_proxy(p)) && !options.proxy_is_global {
+            return None;
+        }
+        url.to_string()
+    } else if ["rtsp://", "udp://", "rtp://"].iter().any(|scheme| lower.starts_with(scheme)) && options.proxy.is_none() {
+        let url = tunnel_url(url, options);
+        // 与 open_input 相同：HTTPS 隧道需要保留主机名用于 TLS
+        if options.static_ip.is_some() || options.transport != Some(RtspTransport::Https) {
+            dns::refresh(&url, options.static_ip)
+        } else {
+            url
+        }
+    } else {
+        return None;
+    };
+    let args = input_dictionary(&url, options)
+        .iter()
+        // 命令行 ffmpeg 可能比链接的库新，不接受已移除的 stimeout 选项，改用新名称
+        .map(|(key, value)| (if key == "stimeout" { "timeout" } else { key }.to_string(), value.to_string()))
+        .collect();
+    Some((url, args))
+}
 
 #[derive(Clone, Copy)]
 struct StreamState {
@@ -16,6 +216,508 @@ impl StreamState {
             last_pts: i64::MIN,
         }
     }
+
+    /// 健壮的时间戳处理：补齐缺失的 DTS/PTS，并保证 PTS >= DTS 且 DTS 单调递增
+    ///
+    /// 每类修正只计数，由转码循环按分钟汇总打印，避免时间戳混乱的摄像头逐包刷屏。
+    fn fix_timestamps(&mut self, packet: &mut ffmpeg::Packet, stats: &TimestampStats) {
+        let mut dts = packet.dts();
+        let mut pts = packet.pts();
+
+        // 1. 修复缺失的 DTS
+        if dts.is_none() {
+            // 如果有 last_dts，稍微增加它（例如 1 个单位）
+            // 如果是第一个包，从 0 开始
+            let new_dts = if self.last_dts == i64::MIN {
+                0
+            } else {
+                self.last_dts + 1
+            };
+            stats.missing_dts.fetch_add(1, Ordering::Relaxed);
+            dts = Some(new_dts);
+        }
+        let mut dts_val = dts.unwrap();
+
+        // 2. 修复缺失的 PTS
+        if pts.is_none() {
+            // 如果缺失，假设 PTS = DTS
+            stats.missing_pts.fetch_add(1, Ordering::Relaxed);
+            pts = Some(dts_val);
+        }
+        let mut pts_val = pts.unwrap();
+
+        // 3. 确保 PTS >= DTS
+        if pts_val < dts_val {
+            stats.pts_before_dts.fetch_add(1, Ordering::Relaxed);
+            pts_val = dts_val;
+        }
+
+        // 4. 确保单调性 (DTS 必须增加)
+        if self.last_dts != i64::MIN && dts_val <= self.last_dts {
+            stats.non_monotonic.fetch_add(1, Ordering::Relaxed);
+            dts_val = self.last_dts + 1;
+
+            // 如果需要，调整 PTS 以保持 PTS >= DTS
+            if pts_val < dts_val {
+                pts_val = dts_val;
+            }
+        }
+
+        // 更新状态
+        self.last_dts = dts_val;
+        self.last_pts = pts_val;
+
+        // 应用回数据包
+        packet.set_dts(Some(dts_val));
+        packet.set_pts(Some(pts_val));
+    }
+}
+
+/// 重新编码通道
+///
+/// 对 FLV 无法直接封装的原始流（如 lavfi 生成的 rawvideo / PCM）先解码再编码为 H.264 / AAC；
+/// 摄像头常见的 G.711 (PCMA / PCMU) 等非 AAC 音频同样转码为 AAC，否则 SRS 与播放器无法播放声音；
+/// 配置了 `output.hevc_to_h264` 的流将 H.265 视频转码为 H.264。
+enum Reencoder {
+    Video {
+        decoder: ffmpeg::decoder::Video,
+        encoder: ffmpeg::encoder::video::Encoder,
+        frame: ffmpeg::frame::Video,
+        /// 送入编码器的像素格式（VAAPI 为上传显存前的格式）
+        format: ffmpeg::format::Pixel,
+        /// 解码帧像素格式与编码器不同（如 10 位 H.265）时的格式转换，收到第一帧时创建
+        scaler: Option<(ffmpeg::software::scaling::Context, ffmpeg::frame::Video)>,
+        /// VAAPI 编码时的显存帧池
+        hw_frames: Option<HwFrames>,
+    },
+    Audio {
+        decoder: ffmpeg::decoder::Audio,
+        encoder: ffmpeg::encoder::audio::Encoder,
+        frame: ffmpeg::frame::Audio,
+        /// 转换为 AAC 编码器要求的平面浮点格式与声道布局
+        resampler: ffmpeg::software::resampling::Context,
+        /// 解码器输出的声道布局（部分摄像头的 G.711 流未设置布局）
+        layout: ffmpeg::ChannelLayout,
+        /// 输入流时间基，解码帧的时间戳以此为单位
+        time_base: ffmpeg::Rational,
+        fifo: AudioFifo,
+    },
+}
+
+/// 输入流是否需要重新编码才能推送为 FLV
+pub fn requires_reencode(params: &ffmpeg::codec::Parameters, output_options: &OutputOptions) -> bool {
+    Reencoder::required_for(params, output_options)
+}
+
+/// AAC 编码器的采样格式
+const AAC_SAMPLE_FORMAT: ffmpeg::format::Sample = ffmpeg::format::Sample::F32(ffmpeg::format::sample::Type::Planar);
+
+impl Reencoder {
+    /// 判断输入流是否需要重新编码
+    fn required_for(params: &ffmpeg::codec::Parameters, output_options: &OutputOptions) -> bool {
+        match params.medium() {
+            ffmpeg::media::Type::Video => match params.id() {
+                ffmpeg::codec::Id::RAWVIDEO => true,
+                ffmpeg::codec::Id::HEVC => output_options.hevc_to_h264.is_some(),
+                _ => false,
+            },
+            ffmpeg::media::Type::Audio => match params.id() {
+                ffmpeg::codec::Id::AAC | ffmpeg::codec::Id::MP3 => false,
+                ffmpeg::codec::Id::PCM_ALAW | ffmpeg::codec::Id::PCM_MULAW => !output_options.copy_g711,
+                _ => true,
+            },
+            _ => false,
+        }
+    }
+
+    /// 为输入流创建编码通道，并在输出上下文中添加对应的输出流
+    fn new(
+        istream: &ffmpeg::Stream,
+        octx: &mut ffmpeg::format::context::Output,
+        output_options: &OutputOptions,
+    ) -> Result<Self> {
+        let global_header = octx
+            .format()
+            .flags()
+            .contains(ffmpeg::format::Flags::GLOBAL_HEADER);
+        let context = ffmpeg::codec::context::Context::from_parameters(istream.parameters())?;
+
+        match istream.parameters().medium() {
+            ffmpeg::media::Type::Video => {
+                let decoder = context.decoder().video()?;
+                let encoder_config = output_options.encoder.clone().unwrap_or_default();
+                let kind = encoder_config.video;
+                let codec = kind.find()?;
+                let mut ostream = octx.add_stream(codec)?;
+                let mut encoder = ffmpeg::codec::context::Context::new_with_codec(codec)
+                    .encoder()
+                    .video()?;
+                // 编码器只接受 8 位 YUV / NV12，其他格式（如 10 位 H.265）解码后先转换
+                let format = kind.input_format(decoder.format());
+                // 摄像头流的解码器上下文通常没有帧率，使用输入流的平均帧率
+                let frame_rate = decoder
+                    .frame_rate()
+                    .or_else(|| Some(istream.avg_frame_rate()).filter(|r| r.numerator() > 0 && r.denominator() > 0));
+                encoder.set_width(decoder.width());
+                encoder.set_height(decoder.height());
+                encoder.set_aspect_ratio(decoder.aspect_ratio());
+                encoder.set_format(format);
+                encoder.set_frame_rate(frame_rate);
+                encoder.set_time_base(istream.time_base());
+                // 直播场景下每 2 秒一个关键帧，保证新观众能尽快起播
+                let gop = frame_rate.map(|r| (f64::from(r) * 2.0).round() as u32).filter(|g| *g > 0).unwrap_or(50);
+                encoder.set_gop(gop);
+                if global_header {
+                    encoder.set_flags(ffmpeg::codec::Flags::GLOBAL_HEADER);
+                }
+
+                let hw_frames = kind.attach(&mut encoder, encoder_config.device.as_deref())?;
+
+                let transcode = output_options.hevc_to_h264.clone().unwrap_or_default();
+                let mut opts = kind.options(&transcode.preset, encoder_config.device.as_deref());
+                if let Some(kbps) = transcode.bitrate_kbps {
+                    encoder.set_bit_rate(kbps as usize * 1000);
+                    encoder.set_max_bit_rate(kbps as usize * 1000);
+                    opts.set("bufsize", &format!("{}k", kbps * 2));
+                }
+                let encoder = encoder.open_with(opts)?;
+                ostream.set_parameters(&encoder);
+
+                if kind != VideoEncoder::Libx264 {
+                    info!("使用 {} 编码视频", kind.codec_name());
+                }
+
+                Ok(Reencoder::Video {
+                    decoder,
+                    encoder,
+                    frame: ffmpeg::frame::Video::empty(),
+                    format,
+                    scaler: None,
+                    hw_frames,
+                })
+            }
+            ffmpeg::media::Type::Audio => {
+                let decoder = context.decoder().audio()?;
+                let codec = ffmpeg::encoder::find(ffmpeg::codec::Id::AAC)
+                    .ok_or(anyhow!("未找到 AAC 编码器"))?;
+                let layout = match decoder.channel_layout() {
+                    layout if layout.channels() > 0 => layout,
+                    _ => ffmpeg::ChannelLayout::default(decoder.channels().max(1) as i32),
+                };
+                // AAC 只输出单声道或立体声，多声道下混为立体声
+                let output_layout = match layout.channels() {
+                    1 => ffmpeg::ChannelLayout::MONO,
+                    _ => ffmpeg::ChannelLayout::STEREO,
+                };
+                let rate = decoder.rate();
+                let resampler = ffmpeg::software::resampling::Context::get(
+                    decoder.format(),
+                    layout,
+                    rate,
+                    AAC_SAMPLE_FORMAT,
+                    output_layout,
+                    rate,
+                )?;
+
+                let mut ostream = octx.add_stream(codec)?;
+                let mut encoder = ffmpeg::codec::context::Context::new_with_codec(codec)
+                    .encoder()
+                    .audio()?;
+                encoder.set_rate(rate as i32);
+                encoder.set_channel_layout(output_layout);
+                encoder.set_format(AAC_SAMPLE_FORMAT);
+                encoder.set_bit_rate(if rate < 32_000 { 64_000 } else { 128_000 });
+                encoder.set_time_base((1, rate as i32));
+                ostream.set_time_base((1, rate as i32));
+                if global_header {
+                    encoder.set_flags(ffmpeg::codec::Flags::GLOBAL_HEADER);
+                }
+
+                let encoder = encoder.open_as(codec)?;
+                ostream.set_parameters(&encoder);
+                let fifo = AudioFifo::new(output_layout, encoder.frame_size() as usize, rate);
+
+                Ok(Reencoder::Audio {
+                    decoder,
+                    encoder,
+                    frame: ffmpeg::frame::Audio::empty(),
+                    resampler,
+                    layout,
+                    time_base: istream.time_base(),
+                    fifo,
+                })
+            }
+            other => Err(anyhow!("不支持重新编码的流类型: {:?}", other)),
+        }
+    }
+
+    /// 编码器的时间基，编码输出的数据包以此为单位
+    fn time_base(&self) -> ffmpeg::Rational {
+        match self {
+            Reencoder::Video { encoder, .. } => encoder.time_base(),
+            Reencoder::Audio { encoder, .. } => encoder.time_base(),
+        }
+    }
+
+    /// 送入一个输入包，返回编码完成的数据包
+    fn encode(&mut self, packet: &ffmpeg::Packet) -> Result<Vec<ffmpeg::Packet>> {
+        match self {
+            Reencoder::Video { decoder, .. } => decoder.send_packet(packet)?,
+            Reencoder::Audio { decoder, .. } => decoder.send_packet(packet)?,
+        }
+        let mut encoded = Vec::new();
+        self.transcode_frames(&mut encoded)?;
+        Ok(encoded)
+    }
+
+    /// 输入结束时排空解码器、采样缓冲与编码器，返回剩余的数据包
+    fn flush(&mut self) -> Result<Vec<ffmpeg::Packet>> {
+        match self {
+            Reencoder::Video { decoder, .. } => decoder.send_eof()?,
+            Reencoder::Audio { decoder, .. } => decoder.send_eof()?,
+        }
+        let mut encoded = Vec::new();
+        self.transcode_frames(&mut encoded)?;
+        match self {
+            Reencoder::Video { encoder, .. } => {
+                encoder.send_eof()?;
+                drain_encoder(encoder, &mut encoded);
+            }
+            Reencoder::Audio { encoder, fifo, .. } => {
+                // 不足一帧的剩余采样作为最后一帧，由编码器补齐
+                if let Some(chunk) = fifo.pop_remaining() {
+                    encoder.send_frame(&chunk)?;
+                    drain_encoder(encoder, &mut encoded);
+                }
+                encoder.send_eof()?;
+                drain_encoder(encoder, &mut encoded);
+            }
+        }
+        Ok(encoded)
+    }
+
+    /// 取出解码器中已解码的帧并送入编码器
+    fn transcode_frames(&mut self, encoded: &mut Vec<ffmpeg::Packet>) -> Result<()> {
+        match self {
+            Reencoder::Video { decoder, encoder, frame, format, scaler, hw_frames } => {
+                while decoder.receive_frame(frame).is_ok() {
+                    let timestamp = frame.timestamp();
+                    let output = if frame.format() == *format {
+                        &mut *frame
+                    } else {
+                        convert_pixels(scaler, frame, *format, encoder)?
+                    };
+                    let output = match hw_frames {
+                        Some(hw_frames) => hw_frames.upload(output)?,
+                        None => output,
+                    };
+                    output.set_pts(timestamp);
+                    output.set_kind(ffmpeg::picture::Type::None);
+                    encoder.send_frame(output)?;
+                    drain_encoder(encoder, encoded);
+                }
+            }
+            Reencoder::Audio { decoder, encoder, frame, resampler, layout, time_base, fifo } => {
+                while decoder.receive_frame(frame).is_ok() {
+                    frame.set_channel_layout(*layout);
+                    // 输出帧按本帧的采样数分配，每帧重新创建
+                    let mut resampled = ffmpeg::frame::Audio::empty();
+                    resampler.run(frame, &mut resampled)?;
+                    let pts = frame.timestamp().map(|ts| ts.rescale(*time_base, encoder.time_base()));
+                    fifo.push(&resampled, pts);
+                    while let Some(chunk) = fifo.pop() {
+                        encoder.send_frame(&chunk)?;
+                        drain_encoder(encoder, encoded);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 将解码帧转换为送入编码器的像素格式，输入格式或尺寸变化时重新创建转换上下文
+fn convert_pixels<'a>(
+    scaler: &'a mut Option<(ffmpeg::software::scaling::Context, ffmpeg::frame::Video)>,
+    frame: &ffmpeg::frame::Video,
+    format: ffmpeg::format::Pixel,
+    encoder: &ffmpeg::encoder::video::Encoder,
+) -> Result<&'a mut ffmpeg::frame::Video> {
+    let stale = scaler.as_ref().is_none_or(|(context, _)| {
+        let input = context.input();
+        (input.format, input.width, input.height) != (frame.format(), frame.width(), frame.height())
+    });
+    if stale {
+        let context = ffmpeg::software::scaling::Context::get(
+            frame.format(),
+            frame.width(),
+            frame.height(),
+            format,
+            encoder.width(),
+            encoder.height(),
+            ffmpeg::software::scaling::Flags::BILINEAR,
+        )?;
+        *scaler = Some((context, ffmpeg::frame::Video::empty()));
+    }
+    let (context, converted) = scaler.as_mut().ok_or(anyhow!("像素格式转换未初始化"))?;
+    context.run(frame, converted)?;
+    Ok(converted)
+}
+
+/// 音频采样缓冲
+///
+/// AAC 编码器每帧固定 1024 个采样，而 G.711 等输入每包的采样数不定，缓冲后按编码器帧长取出，
+/// 时间戳由输入帧推算并连续递增；输入时间戳跳变（断流、丢包）超过 100 毫秒时重新对齐。
+struct AudioFifo {
+    layout: ffmpeg::ChannelLayout,
+    /// 各声道待编码的采样
+    channels: Vec<Vec<f32>>,
+    frame_size: usize,
+    rate: u32,
+    /// 缓冲中第一个采样的时间戳（编码器时间基，即 1/采样率）
+    next_pts: Option<i64>,
+}
+
+impl AudioFifo {
+    fn new(layout: ffmpeg::ChannelLayout, frame_size: usize, rate: u32) -> Self {
+        Self {
+            layout,
+            channels: vec![Vec::new(); layout.channels().max(1) as usize],
+            // 支持可变帧长的编码器报告 0，此时按 1024 取帧
+            frame_size: if frame_size == 0 { 1024 } else { frame_size },
+            rate,
+            next_pts: None,
+        }
+    }
+
+    /// 追加重采样后的平面浮点帧，`pts` 为该帧第一个采样的时间戳
+    fn push(&mut self, frame: &ffmpeg::frame::Audio, pts: Option<i64>) {
+        let buffered = self.channels[0].len() as i64;
+        if let Some(pts) = pts {
+            let expected = self.next_pts.map(|next| next + buffered);
+            if expected.is_none_or(|expected| (pts - expected).abs() > self.rate as i64 / 10) {
+                self.next_pts = Some(pts - buffered);
+            }
+        }
+        for (i, channel) in self.channels.iter_mut().enumerate() {
+            channel.extend_from_slice(&frame.plane::<f32>(i)[..frame.samples()]);
+        }
+    }
+
+    /// 取出一个编码器帧长的帧，采样不足时返回 None
+    fn pop(&mut self) -> Option<ffmpeg::frame::Audio> {
+        (self.channels[0].len() >= self.frame_size).then(|| self.take(self.frame_size))
+    }
+
+    /// 输入结束时取出不足一帧的剩余采样
+    fn pop_remaining(&mut self) -> Option<ffmpeg::frame::Audio> {
+        let samples = self.channels[0].len().min(self.frame_size);
+        (samples > 0).then(|| self.take(samples))
+    }
+
+    fn take(&mut self, samples: usize) -> ffmpeg::frame::Audio {
+        let mut frame = ffmpeg::frame::Audio::new(AAC_SAMPLE_FORMAT, samples, self.layout);
+        frame.set_rate(self.rate);
+        for (i, channel) in self.channels.iter_mut().enumerate() {
+            frame.plane_mut::<f32>(i).copy_from_slice(&channel[..samples]);
+            channel.drain(..samples);
+        }
+        frame.set_pts(self.next_pts);
+        self.next_pts = self.next_pts.map(|pts| pts + samples as i64);
+        frame
+    }
+}
+
+/// 取出编码器中所有已完成的数据包
+fn drain_encoder(encoder: &mut ffmpeg::encoder::Encoder, out: &mut Vec<ffmpeg::Packet>) {
+    let mut packet = ffmpeg::Packet::empty();
+    while encoder.receive_packet(&mut packet).is_ok() {
+        out.push(packet);
+        packet = ffmpeg::Packet::empty();
+    }
+}
+
+/// 构造测试信号源的 lavfi 滤镜图
+///
+/// `[out0]` 为 SMPTE 彩条叠加时间码，`[out1]` 为 1kHz 正弦音。
+/// realtime/arealtime 让生成速度与实际时间一致，相当于命令行的 `-re`。
+fn test_source_graph(url: &str) -> Result<String> {
+    let size = test_source_size(url)?;
+    Ok(format!(
+        "smptebars=size={size}:rate=25,\
+         drawtext=timecode='00\\:00\\:00\\:00':rate=25:fontsize=48:fontcolor=white:\
+         box=1:boxcolor=black@0.6:x=(w-tw)/2:y=h-th-40,\
+         format=yuv420p,realtime[out0];\
+         sine=frequency=1000:sample_rate=44100:samples_per_frame=1024,\
+         aformat=sample_fmts=fltp:channel_layouts=mono,arealtime[out1]"
+    ))
+}
+
+/// 输出限速器（令牌桶）
+///
+/// 桶容量为 1 秒的码率，令牌不足时阻塞等待，使推流平均码率不超过上限。
+struct Pacer {
+    bytes_per_sec: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl Pacer {
+    fn new(max_kbps: u32) -> Self {
+        let bytes_per_sec = max_kbps as f64 * 1000.0 / 8.0;
+        Self {
+            bytes_per_sec,
+            tokens: bytes_per_sec,
+            last: Instant::now(),
+        }
+    }
+
+    /// 消耗令牌，不足时等待补足
+    fn pace(&mut self, bytes: usize) {
+        let now = Instant::now();
+        let refill = now.duration_since(self.last).as_secs_f64() * self.bytes_per_sec;
+        self.tokens = (self.tokens + refill).min(self.bytes_per_sec);
+        self.last = now;
+
+        self.tokens -= bytes as f64;
+        if self.tokens < 0.0 {
+            std::thread::sleep(Duration::from_secs_f64(-self.tokens / self.bytes_per_sec));
+        }
+    }
+}
+
+/// 转码任务的附加功能，由流管理器创建，断线重连时复用
+#[derive(Clone)]
+pub struct JobHooks {
+    /// 调试抓包
+    pub dump: Arc<DumpControl>,
+    /// 数据包跟踪
+    pub trace: Arc<TraceControl>,
+    /// GOP 缓存
+    pub gop_cache: Option<Arc<GopCache>>,
+    /// 画面检测
+    pub content: Option<Arc<ContentMonitor>>,
+    /// 摄像头时钟偏差检测
+    pub clock: Option<Arc<ClockMonitor>>,
+    /// 预览缩略图
+    pub preview: Option<(Arc<PreviewCache>, PreviewConfig)>,
+    /// 截图请求
+    pub snapshot: Option<Arc<SnapshotRequests>>,
+    /// HLS 输出：流的分片目录与配置
+    pub hls: Option<(PathBuf, HlsConfig)>,
+    /// 片段下载缓冲
+    pub clip: Option<Arc<ClipBuffer>>,
+    /// 录制：开关、流的录制目录与配置
+    pub record: Option<(Arc<RecordControl>, PathBuf, RecordingConfig)>,
+    /// 运行统计
+    pub stats: Arc<StreamStats>,
+    /// 预连接
+    pub standby: Option<Arc<Standby>>,
+    /// RTSP 转发服务的推流地址
+    pub relay: Option<String>,
+    /// 故障注入
+    #[cfg(feature = "chaos")]
+    pub fault: Arc<crate::chaos::StreamFault>,
 }
 
 /// RTSP 转 FLV 转码器
@@ -23,57 +725,160 @@ impl StreamState {
 /// 使用 FFmpeg 将 RTSP 流转码/封装为 FLV 格式。
 pub struct Transcoder {
     input_url: String,
+    input_options: InputOptions,
     output_url: String,
+    output_options: OutputOptions,
+    publish_retry: PublishRetryConfig,
     running: Arc<AtomicBool>,
+    hooks: JobHooks,
 }
 
 impl Transcoder {
     /// 创建新的转码器实例
-    pub fn new(input_url: String, output_url: String, running: Arc<AtomicBool>) -> Self {
+    pub fn new(
+        input_url: String,
+        input_options: InputOptions,
+        output_url: String,
+        output_options: OutputOptions,
+        publish_retry: PublishRetryConfig,
+        running: Arc<AtomicBool>,
+        hooks: JobHooks,
+    ) -> Self {
+        let mut input_options = input_options;
+        if output_options.audio_only {
+            // 让 RTSP 只 SETUP 音频轨道，摄像头不再发送视频数据
+            input_options
+                .options
+                .entry("allowed_media_types".to_string())
+                .or_insert_with(|| "audio".to_string());
+        } else if output_options.no_audio {
+            input_options
+                .options
+                .entry("allowed_media_types".to_string())
+                .or_insert_with(|| "video".to_string());
+        }
         Self {
             input_url,
+            input_options,
             output_url,
+            output_options,
+            publish_retry,
             running,
+            hooks,
+        }
+    }
+
+    /// 按退避间隔重试推流连接，重试期间标记为推流降级；停止转码或重试次数用尽时返回最后一次的错误
+    fn retry_output<T>(&self, mut attempt: impl FnMut() -> Result<T>) -> Result<T> {
+        let output = &self.hooks.stats.output;
+        let mut delay = Duration::from_millis(self.publish_retry.initial_ms);
+        let mut retries = 0;
+        loop {
+            match attempt() {
+                Ok(value) => {
+                    if output.degraded.swap(false, Ordering::Relaxed) {
+                        info!("推流已恢复: {}", vault::redact_url(&self.output_url));
+                    }
+                    return Ok(value);
+                }
+                Err(e) if retries < self.publish_retry.attempts && self.running.load(Ordering::Relaxed) => {
+                    retries += 1;
+                    output.degraded.store(true, Ordering::Relaxed);
+                    warn!("推流到 {} 失败: {}，{} ms 后第 {} 次重试", vault::redact_url(&self.output_url), e, delay.as_millis(), retries);
+                    // 分段等待，停止时及时退出
+                    let until = Instant::now() + delay;
+                    while Instant::now() < until && self.running.load(Ordering::Relaxed) {
+                        std::thread::sleep(Duration::from_millis(100));
+                    }
+                    delay = (delay * 2).min(Duration::from_millis(self.publish_retry.max_ms));
+                }
+                Err(e) => {
+                    output.degraded.store(false, Ordering::Relaxed);
+                    return Err(e);
+                }
+            }
+        }
+    }
+
+    /// 推流目标，独立模式下不推流，观众数据来自 GOP 缓存
+    fn output_target(&self) -> String {
+        if self.output_url == http_flv::LOCAL_OUTPUT {
+            http_flv::DISCARD_OUTPUT.to_string()
+        } else {
+            dns::refresh(&self.output_url, None)
+        }
+    }
+
+    /// 按已确定的流参数重新连接 SRS 并写入文件头（每次都重新解析 SRS 主机名）
+    fn open_output(&self, params: &[ffmpeg::codec::Parameters]) -> Result<ffmpeg::format::context::Output> {
+        let mut octx = ffmpeg::format::output_as(&self.output_target(), "flv")?;
+        for params in params {
+            let mut ostream = octx.add_stream(ffmpeg::encoder::find(ffmpeg::codec::Id::None))?;
+            ostream.set_parameters(params.clone());
         }
+        octx.write_header_with(flv_muxer_options(&self.output_options.flv))?;
+        Ok(octx)
     }
 
     /// 运行转码任务
     /// 
     /// 这是一个阻塞操作，直到流结束或被停止。
-    pub fn run(&self) -> Result<()> {
+    /// `on_started` 在输出头写入成功、开始推流时调用。
+    pub fn run(&self, on_started: impl FnOnce()) -> Result<()> {
         ffmpeg::init()?;
 
-        // 1. 打开输入
-        let mut input_opts = ffmpeg::Dictionary::new();
-        // 强制使用 TCP 传输 RTSP 以避免 UDP 丢包问题
-        if self.input_url.starts_with("rtsp://") {
-            info!("强制使用 TCP 传输 RTSP 输入");
-            input_opts.set("rtsp_transport", "tcp");
-            // 设置 socket 超时为 5 秒 (单位: 微秒) 以检测网络问题
-            input_opts.set("stimeout", "5000000");
-        }
-        
-        let mut ictx = ffmpeg::format::input_with_dictionary(&self.input_url, input_opts)?;
+        // 1. 打开输入，有预连接时直接接管（同时暂停预连接）
+        let prepared = self.hooks.standby.as_ref().and_then(|standby| {
+            let prepared = standby.take()?;
+            // 正在使用备用源时不使用主源的预连接
+            (standby.url() == self.input_url).then_some(prepared)
+        });
+        let (mut ictx, _guard, buffered) = match prepared {
+            Some(prepared) => (prepared.ictx, prepared.guard, prepared.packets),
+            None => {
+                let (ictx, guard) = Self::open_input(&self.input_url, &self.input_options)?;
+                (ictx, guard, Vec::new())
+            }
+        };
         
-        // 2. 打开输出
-        let mut octx = ffmpeg::format::output_as(&self.output_url, "flv")?;
+        // 2. 打开输出（每次重连都重新解析 SRS 主机名），SRS 短暂不可用时按退避间隔重试
+        let mut octx = self.retry_output(|| Ok(ffmpeg::format::output_as(&self.output_target(), "flv")?))?;
 
         // 3. 复制流配置
         // 我们需要收集输入流索引到输出流索引的映射
         let mut stream_mapping = vec![0isize; ictx.nb_streams() as usize];
+        let mut reencoders: Vec<Option<Reencoder>> = (0..ictx.nb_streams()).map(|_| None).collect();
+        // 按输出流顺序记录输入流参数，用于原始输入抓包
+        let mut input_params = Vec::new();
         let mut stream_index = 0;
 
         for (i, istream) in ictx.streams().enumerate() {
             let codec_type = istream.parameters().medium();
             
-            // 我们只关心视频和音频
-            if codec_type == ffmpeg::media::Type::Video || codec_type == ffmpeg::media::Type::Audio {
-                let mut ostream = octx.add_stream(ffmpeg::encoder::find(ffmpeg::codec::Id::None))?;
-                ostream.set_parameters(istream.parameters());
-                
-                // 复制 timebase 重要吗？通常对于重新封装，我们只需要复制参数。
-                // ostream.set_time_base(istream.time_base()); 
-                
+            // 我们只关心视频和音频，纯音频模式跳过视频
+            let wanted = match codec_type {
+                ffmpeg::media::Type::Video => !self.output_options.audio_only,
+                ffmpeg::media::Type::Audio => !self.output_options.no_audio,
+                _ => false,
+            };
+            if wanted {
+                if Reencoder::required_for(&istream.parameters(), &self.output_options) {
+                    // 原始流（如测试信号源）、非 AAC 音频与 H.265 视频（按配置）无法直接封装为 FLV，需要重新编码
+                    match (codec_type, istream.parameters().id()) {
+                        (ffmpeg::media::Type::Audio, id) => info!("音频编码 {:?} 不受 FLV 支持，转码为 AAC", id),
+                        (_, ffmpeg::codec::Id::HEVC) => info!("视频编码为 H.265，转码为 H.264"),
+                        _ => {}
+                    }
+                    reencoders[i] = Some(Reencoder::new(&istream, &mut octx, &self.output_options)?);
+                } else {
+                    let mut ostream = octx.add_stream(ffmpeg::encoder::find(ffmpeg::codec::Id::None))?;
+                    ostream.set_parameters(istream.parameters());
+
+                    // 复制 timebase 重要吗？通常对于重新封装，我们只需要复制参数。
+                    // ostream.set_time_base(istream.time_base()); 
+                }
+
+                input_params.push(istream.parameters());
                 stream_mapping[i] = stream_index;
                 stream_index += 1;
             } else {
@@ -81,98 +886,339 @@ impl Transcoder {
             }
         }
 
-        // 4. 写入文件头
-        octx.write_header()?;
+        if stream_index == 0 {
+            return Err(anyhow!(match (self.output_options.audio_only, self.output_options.no_audio) {
+                (true, _) => "输入中没有音频流",
+                (_, true) => "输入中没有视频流",
+                _ => "输入中没有音视频流",
+            }));
+        }
+
+        // 4. 写入文件头（RTMP 握手与 publish），失败时按相同的流参数重新连接
+        let params: Vec<_> = octx.streams().map(|s| s.parameters()).collect();
+        let mut pending = Some(octx);
+        let mut octx = self.retry_output(|| match pending.take() {
+            Some(mut octx) => {
+                octx.write_header_with(flv_muxer_options(&self.output_options.flv))?;
+                Ok(octx)
+            }
+            None => self.open_output(&params),
+        })?;
 
-        info!("转码器已启动: {} -> {}", self.input_url, self.output_url);
+        info!("转码器已启动: {} -> {}", vault::redact_url(&self.input_url), vault::redact_url(&self.output_url));
+        on_started();
 
         // 初始化输出流的状态
         let mut stream_states = vec![StreamState::new(); octx.nb_streams() as usize];
+        let mut timestamp_summary = TimestampSummary::new(&self.hooks.stats.timestamps);
+        let output_params: Vec<_> = octx.streams().map(|s| s.parameters()).collect();
+        // GOP 缓存的本地 FLV 输出，失败时只影响缓存，不影响推流
+        let mut local_flv = self.hooks.gop_cache.clone().and_then(|cache| {
+            LocalFlv::open(&output_params, cache, &self.output_options.flv)
+                .map_err(|e| warn!("创建 GOP 缓存输出失败: {}", e))
+                .ok()
+        });
+        // RTSP 转发输出，失败时只影响 RTSP 播放，不影响推流
+        let mut relay = self.hooks.relay.as_deref().and_then(|url| {
+            RelayOutput::open(url, &output_params)
+                .map_err(|e| warn!("连接 RTSP 转发服务失败: {}", e))
+                .ok()
+        });
+        // HLS 输出，失败时（如音频编码不能封装为 TS）只影响 HLS 播放，不影响推流
+        let mut hls = self.hooks.hls.as_ref().and_then(|(dir, config)| {
+            HlsOutput::open(dir, config, &output_params)
+                .map_err(|e| warn!("创建 HLS 输出失败: {}", e))
+                .ok()
+        });
+        let mut recorder = self
+            .hooks
+            .record
+            .as_ref()
+            .map(|(_, dir, config)| Recorder::new(dir.clone(), config.clone(), &output_params));
+        if let Some(clip) = &self.hooks.clip {
+            let time_bases: Vec<_> = octx.streams().map(|s| s.time_base()).collect();
+            clip.reset(&output_params, &time_bases);
+        }
+        let mut input_dump: Option<Dumper> = None;
+        let mut output_dump: Option<Dumper> = None;
+        let mut pacer = self.output_options.max_kbps.filter(|k| *k > 0).map(|kbps| {
+            info!("推流限速: {} kbps", kbps);
+            Pacer::new(kbps)
+        });
+        // 画面检测与预览缩略图的抽帧器，失败时只跳过检测和预览
+        if let Some(monitor) = &self.hooks.content {
+            monitor.reset();
+        }
+        let tapped = (self.hooks.content.is_some() || self.hooks.preview.is_some()) && !self.output_options.audio_only;
+        let mut frame_tap = match tapped.then(|| FrameTap::new(&ictx)) {
+            Some(Ok(Some(mut tap))) => {
+                if let Some(monitor) = &self.hooks.content {
+                    tap = tap.with_gray(monitor.interval());
+                }
+                if let Some((_, config)) = &self.hooks.preview {
+                    tap = tap.with_preview(config.size(), config.interval());
+                }
+                Some(tap)
+            }
+            Some(Err(e)) => {
+                warn!("创建抽帧器失败，跳过画面检测和预览: {}", e);
+                None
+            }
+            _ => None,
+        };
+
+        // 截图只解码主视频流的关键帧
+        let snapshot_video = ictx.streams().best(ffmpeg::media::Type::Video).map(|s| (s.index(), s.parameters()));
 
-        // 5. 数据包循环
-        for (stream, mut packet) in ictx.packets() {
+        let mut input_meter = InputMeter::new(&ictx, self.hooks.clock.clone());
+        // 推流重新连接后从视频关键帧开始发送
+        let output_video = output_params.iter().position(|p| p.medium() == ffmpeg::media::Type::Video);
+        let mut awaiting_key = false;
+
+        // 5. 数据包循环，先发送预连接期间缓存的 GOP
+        let input_time_bases: Vec<_> = ictx.streams().map(|s| s.time_base()).collect();
+        let packets = buffered.into_iter().chain(ictx.packets().map(|(stream, packet)| (stream.index(), packet)));
+        for (istream_index, mut packet) in packets {
             // 检查取消信号
             if !self.running.load(Ordering::Relaxed) {
                 info!("收到停止转码请求。");
                 break;
             }
+            #[cfg(feature = "chaos")]
+            if self.hooks.fault.take_kill() {
+                return Err(anyhow!("故障注入：转码线程被终止"));
+            }
 
-            let istream_index = stream.index();
             let ostream_index = stream_mapping[istream_index];
+            let istream_time_base = input_time_bases[istream_index];
+            input_meter.record(&self.hooks.stats.input, istream_index, &packet, istream_time_base);
+
+            if let Some(requests) = &self.hooks.snapshot {
+                match &snapshot_video {
+                    Some(video) => requests.feed(video, istream_index, &packet),
+                    None if requests.pending() => requests.reject("输入中没有视频流"),
+                    None => {}
+                }
+            }
 
             if ostream_index < 0 {
                 continue;
             }
 
+            if let Some(tap) = frame_tap.as_mut()
+                && let Some(sample) = tap.feed(istream_index, &packet)
+            {
+                if let (Some(frame), Some(monitor)) = (sample.gray, &self.hooks.content) {
+                    monitor.analyze(frame);
+                }
+                if let (Some(frame), Some((cache, _))) = (sample.preview, &self.hooks.preview) {
+                    cache.store(&frame);
+                }
+            }
+
+            // 调试抓包：原始输入数据包
+            if let Some((path, duration)) = self.hooks.dump.take(DumpMode::Input) {
+                input_dump = Dumper::start(&self.hooks.dump, DumpMode::Input, path, duration, &input_params);
+            }
+            dump::write_packet(&mut input_dump, ostream_index as usize, &packet, istream_time_base);
+            self.hooks.trace.input(istream_index, &packet, istream_time_base);
+
             // let istream = ictx.stream(istream_index).ok_or(anyhow!("Input stream not found"))?;
             let ostream = octx.stream(ostream_index as usize).ok_or(anyhow!("输出流未找到"))?;
+            let ostream_time_base = ostream.time_base();
 
-            // 重新缩放时间戳
-            packet.rescale_ts(stream.time_base(), ostream.time_base());
-            packet.set_position(-1);
-            packet.set_stream(ostream_index as usize);
+            let packets = match reencoders[istream_index].as_mut() {
+                Some(reencoder) => {
+                    let encoder_time_base = reencoder.time_base();
+                    let mut encoded = reencoder.encode(&packet)?;
+                    for p in encoded.iter_mut() {
+                        p.rescale_ts(encoder_time_base, ostream_time_base);
+                    }
+                    encoded
+                }
+                None => {
+                    // 重新缩放时间戳
+                    packet.rescale_ts(istream_time_base, ostream_time_base);
+                    vec![packet]
+                }
+            };
 
-            // --- 健壮的时间戳处理 ---
-            let state = &mut stream_states[ostream_index as usize];
-            
-            let mut dts = packet.dts();
-            let mut pts = packet.pts();
-
-            // 1. 修复缺失的 DTS
-            if dts.is_none() {
-                // 如果有 last_dts，稍微增加它（例如 1 个单位）
-                // 如果是第一个包，从 0 开始
-                let new_dts = if state.last_dts == i64::MIN {
-                    0
-                } else {
-                    state.last_dts + 1
-                };
-                // warn!("修复缺失的 DTS: {:?} -> {}", dts, new_dts);
-                dts = Some(new_dts);
-            }
-            let mut dts_val = dts.unwrap();
+            for mut packet in packets {
+                packet.set_position(-1);
+                packet.set_stream(ostream_index as usize);
 
-            // 2. 修复缺失的 PTS
-            if pts.is_none() {
-                // 如果缺失，假设 PTS = DTS
-                pts = Some(dts_val);
-            }
-            let mut pts_val = pts.unwrap();
+                // --- 健壮的时间戳处理 ---
+                let raw_ts = (packet.pts(), packet.dts());
+                stream_states[ostream_index as usize].fix_timestamps(&mut packet, &self.hooks.stats.timestamps);
+                if let Some(fixes) = timestamp_summary.due(&self.hooks.stats.timestamps) {
+                    warn!(
+                        "最近 1 分钟修正时间戳 ({}): 缺失 DTS {} 个，缺失 PTS {} 个，PTS < DTS {} 个，DTS 未递增 {} 个",
+                        vault::redact_url(&self.output_url), fixes.missing_dts, fixes.missing_pts, fixes.pts_before_dts, fixes.non_monotonic
+                    );
+                }
+                // ---------------------------------
+                self.hooks.trace.output(ostream_index as usize, raw_ts, &packet, ostream_time_base);
 
-            // 3. 确保 PTS >= DTS
-            if pts_val < dts_val {
-                // warn!("修复 PTS < DTS: pts={} dts={}", pts_val, dts_val);
-                pts_val = dts_val;
+                // 调试抓包：推给 SRS 的数据包
+                if let Some((path, duration)) = self.hooks.dump.take(DumpMode::Output) {
+                    output_dump = Dumper::start(&self.hooks.dump, DumpMode::Output, path, duration, &output_params);
+                }
+                dump::write_packet(&mut output_dump, ostream_index as usize, &packet, ostream_time_base);
+                if let Some(clip) = &self.hooks.clip {
+                    clip.push(ostream_index as usize, &packet);
+                }
+
+                if let Some(flv) = local_flv.as_mut()
+                    && let Err(e) = flv.write(ostream_index as usize, &packet, ostream_time_base)
+                {
+                    warn!("写入 GOP 缓存失败，停止缓存: {}", e);
+                    local_flv = None;
+                }
+                if let Some(output) = relay.as_mut()
+                    && let Err(e) = output.write(ostream_index as usize, &packet, ostream_time_base)
+                {
+                    warn!("写入 RTSP 转发输出失败，停止转发: {}", e);
+                    relay = None;
+                }
+                if let Some(output) = hls.as_mut()
+                    && let Err(e) = output.write(ostream_index as usize, &packet, ostream_time_base)
+                {
+                    warn!("写入 HLS 输出失败，停止 HLS: {}", e);
+                    hls = None;
+                }
+                if let (Some(recorder), Some((control, _, _))) = (recorder.as_mut(), &self.hooks.record) {
+                    recorder.write(control, ostream_index as usize, &packet, ostream_time_base);
+                }
+
+                if awaiting_key {
+                    if output_video == Some(ostream_index as usize) && packet.is_key() {
+                        awaiting_key = false;
+                    } else {
+                        continue;
+                    }
+                }
+                if let Some(pacer) = pacer.as_mut() {
+                    pacer.pace(packet.size());
+                }
+                if let Err(e) = packet.write_interleaved(&mut octx) {
+                    if self.publish_retry.attempts == 0 {
+                        return Err(e.into());
+                    }
+                    warn!("推流中断: {}，重新连接", e);
+                    octx = self.retry_output(|| self.open_output(&output_params))?;
+                    self.hooks.stats.output.reconnects.fetch_add(1, Ordering::Relaxed);
+                    awaiting_key = output_video.is_some();
+                    continue;
+                }
+                self.hooks.stats.output.record(packet.size());
             }
+        }
 
-            // 4. 确保单调性 (DTS 必须增加)
-            if state.last_dts != i64::MIN && dts_val <= state.last_dts {
-                let corrected_dts = state.last_dts + 1;
-                // warn!("修复非单调 DTS: {} -> {}", dts_val, corrected_dts);
-                dts_val = corrected_dts;
-                
-                // 如果需要，调整 PTS 以保持 PTS >= DTS
-                if pts_val < dts_val {
-                    pts_val = dts_val;
+        // 6. 排空重新编码通道中缓存的帧（编码器有帧延迟），再写入文件尾
+        for (istream_index, reencoder) in reencoders.iter_mut().enumerate() {
+            let Some(reencoder) = reencoder else { continue };
+            let ostream_index = stream_mapping[istream_index] as usize;
+            let ostream_time_base = octx.stream(ostream_index).ok_or(anyhow!("输出流未找到"))?.time_base();
+            let encoder_time_base = reencoder.time_base();
+            let flushed = match reencoder.flush() {
+                Ok(flushed) => flushed,
+                Err(e) => {
+                    warn!("排空编码器失败: {}", e);
+                    continue;
+                }
+            };
+            for mut packet in flushed {
+                // 推流重连后尚未等到关键帧时不再发送视频尾帧
+                if awaiting_key && output_video == Some(ostream_index) {
+                    continue;
                 }
+                packet.rescale_ts(encoder_time_base, ostream_time_base);
+                packet.set_position(-1);
+                packet.set_stream(ostream_index);
+                stream_states[ostream_index].fix_timestamps(&mut packet, &self.hooks.stats.timestamps);
+                dump::write_packet(&mut output_dump, ostream_index, &packet, ostream_time_base);
+                packet.write_interleaved(&mut octx)?;
+                self.hooks.stats.output.record(packet.size());
             }
+        }
+        octx.write_trailer()?;
+        info!("转码器已结束。");
 
-            // 更新状态
-            state.last_dts = dts_val;
-            state.last_pts = pts_val;
+        Ok(())
+    }
 
-            // 应用回数据包
-            packet.set_dts(Some(dts_val));
-            packet.set_pts(Some(pts_val));
-            // ---------------------------------
+    /// 探测输入源是否可用（仅打开并读取流信息，不推流）
+    pub fn probe(url: &str, options: &InputOptions) -> Result<()> {
+        ffmpeg::init()?;
+        Self::open_input(url, options)?;
+        Ok(())
+    }
 
-            packet.write_interleaved(&mut octx)?;
+    /// 打开输入源
+    ///
+    /// 配置了代理时同时返回本地隧道，调用方需在输入使用期间持有。
+    pub fn open_input(url: &str, options: &InputOptions) -> Result<(ffmpeg::format::context::Input, Option<InputGuard>)> {
+        if url.to_lowercase().starts_with(TEST_SOURCE_SCHEME) {
+            return Ok((Self::open_test_source(url)?, None));
+        }
+        if url.to_lowercase().starts_with(gb28181::SCHEME) {
+            // 设备推送的 PS 流经本地 TCP 连接交给 FFmpeg 解复用
+            let (session, local_url) = gb28181::open(url)?;
+            let ictx = ffmpeg::format::input_with_dictionary(&local_url, input_dictionary(&local_url, options))?;
+            return Ok((ictx, Some(Box::new(session))));
+        }
+        if url.to_lowercase().starts_with(ingest::SCHEME) {
+            // HTTP 推流接口收到的 FLV / MPEG-TS 数据经本地 TCP 连接交给 FFmpeg 解复用
+            let local_url = ingest::open(url)?;
+            let ictx = ffmpeg::format::input_with_dictionary(&local_url, input_dictionary(&local_url, options))?;
+            return Ok((ictx, None));
+        }
+        if is_http_url(url) {
+            // 保留主机名：CDN 按 Host 与 TLS SNI 分发，代理由 FFmpeg 的 http_proxy 选项处理
+            if options.proxy.as_deref().is_some_and(|p| !is_http_proxy(p)) {
+                // 与 RTSP 相同，全局代理不适用时直连；流单独配置的代理不能忽略
+                if !options.proxy_is_global {
+                    return Err(anyhow!("HTTP 输入只支持 http:// 代理"));
+                }
+                warn!("HTTP 输入只支持 http:// 代理，忽略全局代理: {}", vault::redact_url(url));
+            }
+            let ictx = ffmpeg::format::input_with_dictionary(&url, input_dictionary(url, options))?;
+            return Ok((ictx, None));
         }
+        let url = tunnel_url(url, options);
+        // 经代理时主机名由代理解析；HTTPS 隧道需要保留主机名用于 TLS
+        let url = if options.static_ip.is_some()
+            || (options.proxy.is_none() && options.transport != Some(RtspTransport::Https))
+        {
+            dns::refresh(&url, options.static_ip)
+        } else {
+            url
+        };
 
-        // 6. 写入文件尾
-        octx.write_trailer()?;
-        info!("转码器已结束。");
+        let udp_transport = matches!(options.transport, Some(RtspTransport::Udp | RtspTransport::UdpMulticast));
+        let (url, tunnel) = match &options.proxy {
+            Some(_) if !url.to_lowercase().starts_with("rtsp://") || udp_transport => {
+                warn!("代理仅支持基于 TCP 的 RTSP 输入，忽略代理: {}", vault::redact_url(&url));
+                (url, None)
+            }
+            Some(proxy_url) => {
+                let (tunnel, local_url) = proxy::open(proxy_url, &url)?;
+                (local_url, Some(Box::new(tunnel) as InputGuard))
+            }
+            None => (url, None),
+        };
+        let ictx = ffmpeg::format::input_with_dictionary(&url, input_dictionary(&url, options))?;
+        Ok((ictx, tunnel))
+    }
 
-        Ok(())
+    /// 通过 lavfi 打开测试信号源
+    fn open_test_source(url: &str) -> Result<ffmpeg::format::context::Input> {
+        info!("使用 lavfi 测试信号源: {}", url);
+        let lavfi = ffmpeg::device::input::video()
+            .find(|f| f.name() == "lavfi")
+            .ok_or(anyhow!("当前 FFmpeg 未启用 lavfi 设备，无法生成测试信号"))?;
+        let graph = test_source_graph(url)?;
+        let ctx = ffmpeg::format::open_with(&graph, &lavfi, ffmpeg::Dictionary::new())?;
+        Ok(ctx.input())
     }
 }
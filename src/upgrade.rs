@@ -0,0 +1,158 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::SocketAddr;
+use std::os::fd::{AsRawFd, FromRawFd, RawFd};
+use std::os::unix::process::CommandExt;
+use std::path::PathBuf;
+use std::process::Command;
+use tracing::warn;
+use crate::config::{InputOptions, OutputOptions, StreamProfile};
+
+/// 旧进程传给新进程的监听 socket 文件描述符，逗号分隔
+const LISTEN_FDS_ENV: &str = "RTSP2FLV_LISTEN_FDS";
+
+/// 旧进程写入交接流列表的管道读端
+const HANDOFF_FD_ENV: &str = "RTSP2FLV_HANDOFF_FD";
+
+/// 升级时交给新进程重新启动的流
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HandoffStream {
+    /// 配置中的流名称（子码流为去掉 `_sub` 后的名称）
+    pub name: String,
+    pub profile: StreamProfile,
+    /// 通过自定义地址播放的流的源地址，配置中的流为空，由新进程按当前配置解析
+    pub url: Option<String>,
+    /// 自定义推流地址
+    pub output_url: Option<String>,
+    /// 旧进程中实际使用的输入输出参数（含播放请求中的临时覆盖项），新进程沿用；旧版本交接时为空
+    #[serde(default)]
+    pub input: Option<InputOptions>,
+    #[serde(default)]
+    pub output: Option<OutputOptions>,
+    /// `input.proxy` 来自全局配置（该字段不随输入参数序列化）
+    #[serde(default)]
+    pub proxy_is_global: bool,
+}
+
+/// 已启动的新进程，交接的流写入管道前新进程不接受连接
+pub struct Successor {
+    pub pid: u32,
+    writer: std::io::PipeWriter,
+}
+
+impl Successor {
+    /// 写入需要重新启动的流，新进程读到后开始接受连接
+    pub fn hand_off(self, streams: &[HandoffStream]) -> Result<()> {
+        let content = serde_json::to_vec(streams)?;
+        let mut writer = self.writer;
+        // 写端关闭后新进程读到 EOF；新进程启动失败时写入出错，直接忽略
+        std::thread::spawn(move || {
+            let _ = writer.write_all(&content);
+        });
+        Ok(())
+    }
+}
+
+/// 是否由旧进程升级启动
+pub fn is_successor() -> bool {
+    std::env::var_os(LISTEN_FDS_ENV).is_some()
+}
+
+/// 从旧进程继承的监听 socket，按监听地址索引
+pub fn inherited_listeners() -> HashMap<SocketAddr, std::net::TcpListener> {
+    let Ok(value) = std::env::var(LISTEN_FDS_ENV) else {
+        return HashMap::new();
+    };
+    let mut listeners = HashMap::new();
+    for fd in value.split(',').filter_map(|fd| fd.trim().parse::<RawFd>().ok()) {
+        // SAFETY: 描述符由旧进程在 exec 前显式保留，只在这里接管一次
+        let listener = unsafe { std::net::TcpListener::from_raw_fd(fd) };
+        match listener.local_addr() {
+            Ok(addr) => {
+                listeners.insert(addr, listener);
+            }
+            Err(e) => warn!("继承的描述符 {} 不是有效的监听 socket: {}", fd, e),
+        }
+    }
+    listeners
+}
+
+/// 读取旧进程交接的流，非升级启动时为空
+pub fn take_handoff() -> Vec<HandoffStream> {
+    let Some(fd) = std::env::var(HANDOFF_FD_ENV).ok().and_then(|fd| fd.parse::<RawFd>().ok()) else {
+        return Vec::new();
+    };
+    // SAFETY: 同上，管道读端只在这里接管一次
+    let mut pipe = unsafe { std::fs::File::from_raw_fd(fd) };
+    let mut content = String::new();
+    if let Err(e) = pipe.read_to_string(&mut content) {
+        warn!("读取旧进程交接的流失败: {}", e);
+        return Vec::new();
+    }
+    serde_json::from_str(&content).unwrap_or_else(|e| {
+        warn!("解析旧进程交接的流失败: {}", e);
+        Vec::new()
+    })
+}
+
+/// 以相同参数启动新进程并交出监听 socket，之后通过 [`Successor::hand_off`] 交出需要重新启动的流
+pub fn spawn_successor(listeners: &[RawFd]) -> Result<Successor> {
+    let (reader, writer) = std::io::pipe().context("创建交接管道失败")?;
+    let mut inherit = listeners.to_vec();
+    inherit.push(reader.as_raw_fd());
+    let fds: Vec<String> = listeners.iter().map(|fd| fd.to_string()).collect();
+
+    let mut command = Command::new(executable()?);
+    command
+        .args(std::env::args_os().skip(1))
+        .env(LISTEN_FDS_ENV, fds.join(","))
+        .env(HANDOFF_FD_ENV, reader.as_raw_fd().to_string());
+    // SAFETY: pre_exec 中只调用 async-signal-safe 的 fcntl
+    unsafe {
+        command.pre_exec(move || {
+            // 清除 FD_CLOEXEC，使描述符在 exec 后保留
+            for fd in &inherit {
+                if libc::fcntl(*fd, libc::F_SETFD, 0) == -1 {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+            Ok(())
+        });
+    }
+    let child = command.spawn().context("启动新进程失败")?;
+    drop(reader);
+    Ok(Successor { pid: child.id(), writer })
+}
+
+/// 当前可执行文件路径；升级时旧文件已被替换，去掉内核追加的 " (deleted)" 后缀
+fn executable() -> Result<PathBuf> {
+    let path = std::env::current_exe().context("无法获取可执行文件路径")?;
+    Ok(match path.to_str().and_then(|p| p.strip_suffix(" (deleted)")) {
+        Some(stripped) => PathBuf::from(stripped),
+        None => path,
+    })
+}
+
+/// 在 systemd 下把主进程切换为当前进程（需要 `NotifyAccess=all`），未由 systemd 启动时不做任何事
+pub fn notify_main_pid() {
+    use std::os::linux::net::SocketAddrExt;
+    use std::os::unix::net::{SocketAddr as UnixAddr, UnixDatagram};
+
+    let Some(path) = std::env::var_os("NOTIFY_SOCKET") else {
+        return;
+    };
+    let message = format!("MAINPID={}\nREADY=1", std::process::id());
+    let result = UnixDatagram::unbound().and_then(|socket| {
+        // '@' 开头为抽象命名空间地址
+        let addr = match path.to_str().and_then(|p| p.strip_prefix('@')) {
+            Some(name) => UnixAddr::from_abstract_name(name)?,
+            None => UnixAddr::from_pathname(&path)?,
+        };
+        socket.send_to_addr(message.as_bytes(), &addr)
+    });
+    if let Err(e) = result {
+        warn!("通知 systemd 主进程变更失败: {}", e);
+    }
+}
@@ -0,0 +1,226 @@
+use anyhow::{Result, anyhow};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{error, info};
+use crate::history::{HealthHistory, now_secs};
+use crate::store::{HourUsage, Store};
+use crate::stream_manager::StreamManager;
+use crate::stream_io::csv_escape;
+
+/// 采样间隔
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// 单个流某一天的汇总
+#[derive(Debug, Serialize)]
+pub struct DailyRow {
+    pub date: String,
+    pub stream: String,
+    pub uptime_percent: f64,
+    pub restarts: u64,
+    pub gb_relayed: f64,
+}
+
+/// 流量与重启次数台账
+///
+/// 每分钟对运行中流的累计统计采样，差值按小时累加后持久化到本地数据库，
+/// 导出时再按所需时区汇总为每天的数据。
+pub struct UsageLedger {
+    /// 小时序号（Unix 秒 / 3600） -> 流名称 -> 用量
+    hours: Mutex<BTreeMap<u64, BTreeMap<String, HourUsage>>>,
+    store: Arc<Store>,
+    retention_days: u64,
+}
+
+impl UsageLedger {
+    pub fn new(store: Arc<Store>, retention_days: u64) -> Self {
+        let hours = store.usage().unwrap_or_else(|e| {
+            error!("读取用量台账失败: {:#}", e);
+            BTreeMap::new()
+        });
+        Self {
+            hours: Mutex::new(hours),
+            store,
+            retention_days,
+        }
+    }
+
+    /// 启动后台采样任务
+    pub fn spawn(self: &Arc<Self>, stream_manager: Arc<StreamManager>) {
+        let ledger = self.clone();
+        tokio::spawn(async move {
+            // 流名称 -> 上次采样的 (字节数, 重启次数)
+            let mut last: HashMap<String, (u64, u64)> = HashMap::new();
+            let mut interval = tokio::time::interval(SAMPLE_INTERVAL);
+            loop {
+                interval.tick().await;
+                let mut current = HashMap::new();
+                let mut deltas = Vec::new();
+                for (status, stats) in stream_manager.snapshot_with_stats() {
+                    let sample = (stats.output.bytes, stats.restarts);
+                    // 流停止后重新启动时计数从 0 开始
+                    let (last_bytes, last_restarts) = last.get(&status.name).copied().unwrap_or((0, 0));
+                    let bytes = if sample.0 >= last_bytes { sample.0 - last_bytes } else { sample.0 };
+                    let restarts = if sample.1 >= last_restarts { sample.1 - last_restarts } else { sample.1 };
+                    if bytes > 0 || restarts > 0 {
+                        deltas.push((status.name.clone(), bytes, restarts));
+                    }
+                    current.insert(status.name, sample);
+                }
+                last = current;
+
+                let ledger = ledger.clone();
+                let result = tokio::task::spawn_blocking(move || ledger.add(deltas)).await;
+                if let Ok(Err(e)) = result {
+                    error!("写入用量台账失败: {}", e);
+                }
+            }
+        });
+    }
+
+    fn add(&self, deltas: Vec<(String, u64, u64)>) -> Result<()> {
+        if deltas.is_empty() {
+            return Ok(());
+        }
+        let hour = now_secs() / 3600;
+        let mut hours = self.hours.lock().unwrap();
+        let bucket = hours.entry(hour).or_default();
+        for (stream, bytes, restarts) in &deltas {
+            let usage = bucket.entry(stream.clone()).or_default();
+            usage.bytes += bytes;
+            usage.restarts += restarts;
+        }
+        let cutoff = hour.saturating_sub(self.retention_days * 24);
+        let before = hours.len();
+        hours.retain(|h, _| *h >= cutoff);
+        if hours.len() < before {
+            info!("已清理 {} 小时过期的用量记录", before - hours.len());
+        }
+        self.store.add_usage(hour, &deltas, cutoff)
+    }
+
+    /// 按天汇总 [from, to] 日期范围（含）内的数据，`utc_offset` 为时区偏移（小时）
+    pub fn daily(
+        &self,
+        history: &HealthHistory,
+        streams: &[String],
+        from: (i64, u32, u32),
+        to: (i64, u32, u32),
+        utc_offset: i64,
+    ) -> Result<Vec<DailyRow>> {
+        let first_day = days_from_civil(from.0, from.1, from.2);
+        let last_day = days_from_civil(to.0, to.1, to.2);
+        if last_day < first_day {
+            return Err(anyhow!("结束日期早于开始日期"));
+        }
+        if last_day - first_day > 366 {
+            return Err(anyhow!("日期范围不能超过 366 天"));
+        }
+
+        let offset_secs = utc_offset * 3600;
+        let days: Vec<(i64, u64, u64)> = (first_day..=last_day)
+            .map(|day| {
+                let start = (day * 86400 - offset_secs).max(0) as u64;
+                (day, start, start + 86400)
+            })
+            .collect();
+        let now = now_secs();
+        let windows: Vec<(u64, u64)> = days.iter().map(|(_, start, end)| (*start, (*end).min(now))).collect();
+        let uptime = history.uptime_windows(&windows)?;
+
+        let hours = self.hours.lock().unwrap();
+        let mut rows = Vec::new();
+        for (i, (day, start, end)) in days.iter().enumerate() {
+            if *start >= now {
+                break;
+            }
+            for stream in streams {
+                let usage = hours
+                    .range(start / 3600..end / 3600)
+                    .filter_map(|(_, bucket)| bucket.get(stream))
+                    .fold(HourUsage::default(), |mut acc, u| {
+                        acc.bytes += u.bytes;
+                        acc.restarts += u.restarts;
+                        acc
+                    });
+                let (y, m, d) = civil_from_days(*day);
+                rows.push(DailyRow {
+                    date: format!("{:04}-{:02}-{:02}", y, m, d),
+                    stream: stream.clone(),
+                    uptime_percent: uptime.get(stream).map(|v| v[i]).unwrap_or(0.0),
+                    restarts: usage.restarts,
+                    gb_relayed: (usage.bytes as f64 / 1e9 * 1000.0).round() / 1000.0,
+                });
+            }
+        }
+        Ok(rows)
+    }
+
+    /// 台账中出现过的流名称
+    pub fn streams(&self) -> Vec<String> {
+        let hours = self.hours.lock().unwrap();
+        let mut names: Vec<String> = hours.values().flat_map(|b| b.keys().cloned()).collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+}
+
+/// 导出为 CSV，带 UTF-8 BOM 以便 Excel 正确识别中文
+pub fn to_csv(rows: &[DailyRow]) -> String {
+    let mut out = String::from("\u{feff}date,stream,uptime_percent,restarts,gb_relayed\n");
+    for row in rows {
+        out.push_str(&format!(
+            "{},{},{},{},{}\n",
+            row.date,
+            csv_escape(&row.stream),
+            row.uptime_percent,
+            row.restarts,
+            row.gb_relayed
+        ));
+    }
+    out
+}
+
+/// 解析 `YYYY-MM-DD`
+pub fn parse_date(value: &str) -> Result<(i64, u32, u32)> {
+    let invalid = || anyhow!("无效的日期: {}（格式为 YYYY-MM-DD）", value);
+    let mut parts = value.trim().splitn(3, '-');
+    let year: i64 = parts.next().and_then(|v| v.parse().ok()).ok_or_else(invalid)?;
+    let month: u32 = parts.next().and_then(|v| v.parse().ok()).ok_or_else(invalid)?;
+    let day: u32 = parts.next().and_then(|v| v.parse().ok()).ok_or_else(invalid)?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return Err(invalid());
+    }
+    Ok((year, month, day))
+}
+
+/// 某个时刻在给定时区下的日期
+pub fn date_of(secs: u64, utc_offset: i64) -> (i64, u32, u32) {
+    civil_from_days((secs as i64 + utc_offset * 3600).div_euclid(86400))
+}
+
+/// 公历日期转换为 1970-01-01 起的天数
+pub fn days_from_civil(y: i64, m: u32, d: u32) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (m as i64 + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + d as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// 1970-01-01 起的天数转换为公历日期
+fn civil_from_days(z: i64) -> (i64, u32, u32) {
+    let z = z + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    (if m <= 2 { yoe + era * 400 + 1 } else { yoe + era * 400 }, m, d)
+}
@@ -0,0 +1,325 @@
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+use crate::history::now_secs;
+use crate::store::Store;
+
+/// 登录会话 Cookie 名称
+pub const SESSION_COOKIE: &str = "rtsp2flv_session";
+
+/// 密码最短长度
+pub const MIN_PASSWORD_LEN: usize = 8;
+
+/// 用户登录配置
+#[derive(Debug, Deserialize, Clone)]
+pub struct AuthConfig {
+    /// 登录会话有效期（小时）
+    #[serde(default = "default_session_hours")]
+    pub session_hours: u64,
+    /// 连续登录失败多少次后锁定账号
+    #[serde(default = "default_max_failed_logins")]
+    pub max_failed_logins: u32,
+    /// 账号锁定时长（分钟）
+    #[serde(default = "default_lockout_minutes")]
+    pub lockout_minutes: u64,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self {
+            session_hours: default_session_hours(),
+            max_failed_logins: default_max_failed_logins(),
+            lockout_minutes: default_lockout_minutes(),
+        }
+    }
+}
+
+fn default_session_hours() -> u64 {
+    12
+}
+
+fn default_max_failed_logins() -> u32 {
+    5
+}
+
+fn default_lockout_minutes() -> u64 {
+    15
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct User {
+    username: String,
+    /// bcrypt 哈希
+    password_hash: String,
+    #[serde(default)]
+    failed_logins: u32,
+    /// 锁定截止时间（Unix 秒）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    locked_until: Option<u64>,
+}
+
+struct Session {
+    username: String,
+    expires: Instant,
+}
+
+/// 登录失败原因
+pub enum LoginError {
+    /// 用户名或密码错误
+    Invalid,
+    /// 账号已锁定，附带剩余秒数
+    Locked(u64),
+    Internal(anyhow::Error),
+}
+
+impl From<anyhow::Error> for LoginError {
+    fn from(e: anyhow::Error) -> Self {
+        Self::Internal(e)
+    }
+}
+
+/// 生成会话令牌
+fn new_token() -> Result<String> {
+    let mut buf = [0u8; 32];
+    getrandom::getrandom(&mut buf).map_err(|e| anyhow!("生成会话令牌失败: {}", e))?;
+    Ok(buf.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// 从 Cookie 头中取出会话令牌
+pub fn session_token(cookie_header: &str) -> Option<&str> {
+    cookie_header
+        .split(';')
+        .filter_map(|c| c.trim().split_once('='))
+        .find(|(name, _)| *name == SESSION_COOKIE)
+        .map(|(_, value)| value)
+}
+
+/// 在阻塞线程中校验 bcrypt 密码（单次约数百毫秒）
+async fn verify_password(password: &str, hash: &str) -> Result<bool> {
+    let (password, hash) = (password.to_string(), hash.to_string());
+    tokio::task::spawn_blocking(move || bcrypt::verify(password, &hash))
+        .await?
+        .map_err(|e| anyhow!("校验密码失败: {}", e))
+}
+
+fn hash_password(password: &str) -> Result<String> {
+    if password.chars().count() < MIN_PASSWORD_LEN {
+        return Err(anyhow!("密码长度不能少于 {} 位", MIN_PASSWORD_LEN));
+    }
+    bcrypt::hash(password, bcrypt::DEFAULT_COST).map_err(|e| anyhow!("计算密码哈希失败: {}", e))
+}
+
+/// 用户库
+///
+/// 面向运维人员的账号，持久化在本地数据库中，未创建任何用户时不启用登录。
+/// 登录会话保存在内存中，服务重启后需重新登录。
+pub struct UserStore {
+    users: RwLock<Vec<User>>,
+    sessions: Mutex<HashMap<String, Session>>,
+    store: Arc<Store>,
+    config: AuthConfig,
+}
+
+impl UserStore {
+    /// 读取登录用户失败时返回错误，不能以空用户列表启动（会关闭登录保护）
+    pub fn new(store: Arc<Store>, config: AuthConfig) -> Result<Self> {
+        let users = store.users::<User>().context("读取登录用户失败")?;
+        if !users.is_empty() {
+            info!("已加载 {} 个登录用户", users.len());
+        }
+
+        Ok(Self {
+            users: RwLock::new(users),
+            sessions: Mutex::new(HashMap::new()),
+            store,
+            config,
+        })
+    }
+
+    /// 是否已创建用户（启用登录保护）
+    pub fn is_enabled(&self) -> bool {
+        !self.users.read().unwrap().is_empty()
+    }
+
+    /// 会话有效期
+    pub fn session_ttl(&self) -> Duration {
+        Duration::from_secs(self.config.session_hours * 3600)
+    }
+
+    /// 用户名密码登录，成功返回会话令牌
+    pub async fn login(&self, username: &str, password: &str) -> Result<String, LoginError> {
+        let Some((hash, known)) = self.reserve_attempt(username)? else {
+            return Err(LoginError::Invalid);
+        };
+        let ok = verify_password(password, &hash).await? && known;
+        self.record_attempt(username, ok)?;
+        if !ok {
+            return Err(LoginError::Invalid);
+        }
+
+        let token = new_token()?;
+        let mut sessions = self.sessions.lock().unwrap();
+        let now = Instant::now();
+        sessions.retain(|_, s| s.expires > now);
+        sessions.insert(token.clone(), Session {
+            username: username.to_string(),
+            expires: now + self.session_ttl(),
+        });
+        info!("用户 {} 登录", username);
+        Ok(token)
+    }
+
+    /// 校验密码前先把本次尝试计为失败，并发请求不能绕过失败次数上限
+    ///
+    /// 返回用于校验的哈希与用户是否存在。用户不存在时借用其他用户的哈希校验一次，
+    /// 响应时间与密码错误相同，避免按耗时枚举用户名；没有任何用户时返回 None。
+    fn reserve_attempt(&self, username: &str) -> Result<Option<(String, bool)>, LoginError> {
+        let mut users = self.users.write().unwrap();
+        let Some(user) = users.iter_mut().find(|u| u.username == username) else {
+            return Ok(users.first().map(|u| (u.password_hash.clone(), false)));
+        };
+        let now = now_secs();
+        if let Some(until) = user.locked_until
+            && until > now
+        {
+            return Err(LoginError::Locked(until - now));
+        }
+        // 进行中的尝试已达上限
+        if user.failed_logins >= self.config.max_failed_logins {
+            user.failed_logins = 0;
+            user.locked_until = Some(now + self.config.lockout_minutes * 60);
+            warn!("用户 {} 连续登录失败，锁定 {} 分钟", username, self.config.lockout_minutes);
+            self.persist(user)?;
+            return Err(LoginError::Locked(self.config.lockout_minutes * 60));
+        }
+        user.failed_logins += 1;
+        self.persist(user)?;
+        Ok(Some((user.password_hash.clone(), true)))
+    }
+
+    /// 记录校验结果：成功时清零失败次数，失败次数达到上限时锁定账号
+    ///
+    /// 校验期间账号已被并发的尝试锁定时，即使密码正确也不解除锁定。
+    fn record_attempt(&self, username: &str, ok: bool) -> Result<(), LoginError> {
+        let mut users = self.users.write().unwrap();
+        let Some(user) = users.iter_mut().find(|u| u.username == username) else {
+            return Ok(());
+        };
+        let now = now_secs();
+        if let Some(until) = user.locked_until.filter(|&until| until > now) {
+            return if ok { Err(LoginError::Locked(until - now)) } else { Ok(()) };
+        }
+        if ok {
+            user.failed_logins = 0;
+            user.locked_until = None;
+        } else {
+            warn!("用户 {} 登录失败 ({} 次)", username, user.failed_logins);
+            if user.failed_logins < self.config.max_failed_logins {
+                return Ok(());
+            }
+            user.failed_logins = 0;
+            user.locked_until = Some(now + self.config.lockout_minutes * 60);
+            warn!("用户 {} 连续登录失败，锁定 {} 分钟", username, self.config.lockout_minutes);
+        }
+        Ok(self.persist(user)?)
+    }
+
+    /// 查询会话对应的用户名，会话过期返回 None
+    pub fn session_user(&self, token: &str) -> Option<String> {
+        let sessions = self.sessions.lock().unwrap();
+        sessions
+            .get(token)
+            .filter(|s| s.expires > Instant::now())
+            .map(|s| s.username.clone())
+    }
+
+    pub fn logout(&self, token: &str) {
+        if let Some(session) = self.sessions.lock().unwrap().remove(token) {
+            info!("用户 {} 退出登录", session.username);
+        }
+    }
+
+    /// 修改密码，成功后该用户的其他会话全部失效
+    pub async fn change_password(&self, username: &str, old_password: &str, new_password: &str, keep_token: &str) -> Result<(), LoginError> {
+        let hash = {
+            let users = self.users.read().unwrap();
+            let user = users.iter().find(|u| u.username == username).ok_or(LoginError::Invalid)?;
+            user.password_hash.clone()
+        };
+        if !verify_password(old_password, &hash).await? {
+            return Err(LoginError::Invalid);
+        }
+        let new_password = new_password.to_string();
+        let new_hash = tokio::task::spawn_blocking(move || hash_password(&new_password))
+            .await
+            .map_err(|e| anyhow!(e))??;
+        {
+            let mut users = self.users.write().unwrap();
+            if let Some(user) = users.iter_mut().find(|u| u.username == username) {
+                user.password_hash = new_hash;
+                self.persist(user)?;
+            }
+        }
+        self.sessions
+            .lock()
+            .unwrap()
+            .retain(|token, s| s.username != username || token == keep_token);
+        info!("用户 {} 修改了密码", username);
+        Ok(())
+    }
+
+    /// 创建用户或重置已有用户的密码（同时解除锁定）
+    fn set_password(&self, username: &str, password: &str) -> Result<bool> {
+        let hash = hash_password(password)?;
+        let mut users = self.users.write().unwrap();
+        let created = match users.iter_mut().find(|u| u.username == username) {
+            Some(user) => {
+                user.password_hash = hash;
+                user.failed_logins = 0;
+                user.locked_until = None;
+                self.persist(user)?;
+                false
+            }
+            None => {
+                let user = User {
+                    username: username.to_string(),
+                    password_hash: hash,
+                    failed_logins: 0,
+                    locked_until: None,
+                };
+                self.persist(&user)?;
+                users.push(user);
+                true
+            }
+        };
+        Ok(created)
+    }
+
+    /// 只写入变化的用户，不覆盖其他进程（如服务运行期间执行的 `add-user`）写入的用户
+    fn persist(&self, user: &User) -> Result<()> {
+        self.store.save_user(&user.username, user)
+    }
+}
+
+/// `add-user` 子命令：创建登录用户或重置其密码，密码未在参数中给出时从标准输入读取
+pub fn run_add_user(args: &[String], data_dir: &str) -> Result<()> {
+    let username = args.first().filter(|u| !u.is_empty()).ok_or_else(|| anyhow!("用法: rtsp2flv add-user <用户名> [密码]"))?;
+    let password = match args.get(1) {
+        Some(p) => p.clone(),
+        None => {
+            let mut line = String::new();
+            std::io::stdin().read_line(&mut line)?;
+            line.trim_end_matches(['\r', '\n']).to_string()
+        }
+    };
+    let store = UserStore::new(Arc::new(Store::open(data_dir)?), AuthConfig::default())?;
+    if store.set_password(username, &password)? {
+        println!("已创建用户 {}", username);
+    } else {
+        println!("已重置用户 {} 的密码", username);
+    }
+    Ok(())
+}
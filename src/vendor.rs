@@ -0,0 +1,125 @@
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use crate::config::StreamConfig;
+
+/// 按厂商预设描述的摄像头，拉流地址由预设生成，账号通过流的 `credential` 填入
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct DeviceConfig {
+    /// 厂商预设名称，如 `hikvision`、`dahua`
+    pub vendor: String,
+    /// 摄像头或 NVR 的 IP / 主机名
+    pub ip: String,
+    /// RTSP 端口，缺省使用预设的端口
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+    /// 通道号，从 1 开始
+    #[serde(default = "default_channel")]
+    pub channel: u32,
+    /// 码流类型，0 为主码流，1 为子码流
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub subtype: u32,
+}
+
+fn default_channel() -> u32 {
+    1
+}
+
+fn is_zero(value: &u32) -> bool {
+    *value == 0
+}
+
+/// 厂商的 RTSP 地址规则
+///
+/// `path` 中可使用占位符：`{channel}` 通道号，`{channel02}` 两位补零的通道号，
+/// `{subtype}` 码流类型（0 / 1），`{subtype1}` 码流类型加一（1 / 2），`{profile}` 为 `main` / `sub`。
+#[derive(Debug, Deserialize, Clone)]
+pub struct VendorPreset {
+    pub path: String,
+    #[serde(default = "default_port")]
+    pub port: u16,
+}
+
+fn default_port() -> u16 {
+    554
+}
+
+/// 内置预设 (名称, 路径)
+const BUILTIN_PRESETS: &[(&str, &str)] = &[
+    ("hikvision", "/Streaming/Channels/{channel}0{subtype1}"),
+    ("dahua", "/cam/realmonitor?channel={channel}&subtype={subtype}"),
+    ("uniview", "/unicast/c{channel}/s{subtype}/live"),
+    ("axis", "/axis-media/media.amp?camera={channel}"),
+    ("tplink", "/stream{subtype1}"),
+    ("reolink", "/h264Preview_{channel02}_{profile}"),
+];
+
+/// 厂商预设表：内置预设加配置文件中的 `vendor_presets`（同名时覆盖内置）
+#[derive(Clone)]
+pub struct VendorPresets {
+    presets: HashMap<String, VendorPreset>,
+}
+
+impl VendorPresets {
+    pub fn new(custom: &HashMap<String, VendorPreset>) -> Self {
+        let mut presets: HashMap<String, VendorPreset> = BUILTIN_PRESETS
+            .iter()
+            .map(|(name, path)| (name.to_string(), VendorPreset { path: path.to_string(), port: default_port() }))
+            .collect();
+        presets.extend(custom.iter().map(|(name, preset)| (name.to_lowercase(), preset.clone())));
+        Self { presets }
+    }
+
+    /// 可用的预设名称
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.presets.keys().map(String::as_str).collect();
+        names.sort();
+        names
+    }
+
+    /// 生成指定码流类型的拉流地址
+    pub fn url(&self, device: &DeviceConfig, subtype: u32) -> Result<String> {
+        let preset = self
+            .presets
+            .get(&device.vendor.to_lowercase())
+            .ok_or_else(|| anyhow!("未知的厂商预设 '{}'，可选: {:?}", device.vendor, self.names()))?;
+        if device.channel == 0 {
+            return Err(anyhow!("通道号从 1 开始"));
+        }
+        if device.ip.trim().is_empty() || device.ip.contains(['/', '@', ' ']) {
+            return Err(anyhow!("无效的摄像头地址 '{}'", device.ip));
+        }
+        // IPv6 地址需要加方括号
+        let host = match device.ip.parse::<std::net::Ipv6Addr>() {
+            Ok(_) => format!("[{}]", device.ip),
+            Err(_) => device.ip.clone(),
+        };
+        let path = preset
+            .path
+            .replace("{channel}", &device.channel.to_string())
+            .replace("{channel02}", &format!("{:02}", device.channel))
+            .replace("{subtype}", &subtype.to_string())
+            .replace("{subtype1}", &(subtype + 1).to_string())
+            .replace("{profile}", if subtype == 0 { "main" } else { "sub" });
+        Ok(format!("rtsp://{}:{}{}", host, device.port.unwrap_or(preset.port), path))
+    }
+
+    /// 按 `device` 生成流的地址，覆盖配置中的 `url`
+    ///
+    /// 预设区分码流类型且 `subtype` 为主码流时同时生成 `sub_url`；导出的配置中带有生成的地址，
+    /// 修改 `device` 后重新导入时地址随之更新。
+    pub fn expand(&self, stream: &mut StreamConfig) -> Result<()> {
+        let Some(device) = &stream.device else {
+            return Ok(());
+        };
+        let url = self.url(device, device.subtype).map_err(|e| anyhow!("流 '{}': {}", stream.name, e))?;
+        if device.subtype == 0 {
+            let sub_url = self.url(device, 1)?;
+            if sub_url != url {
+                stream.sub_url = Some(sub_url);
+            }
+        }
+        stream.url = url;
+        Ok(())
+    }
+}
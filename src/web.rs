@@ -0,0 +1,58 @@
+use axum::{
+    http::{header, HeaderMap, StatusCode, Uri},
+    response::{IntoResponse, Response},
+};
+use rust_embed::RustEmbed;
+
+/// 编译进可执行文件的前端页面
+///
+/// Debug 构建时 rust-embed 直接读取磁盘上的 web/ 目录，Release 构建时打包进二进制。
+#[derive(RustEmbed)]
+#[folder = "web/"]
+struct WebAssets;
+
+/// 判断请求的 If-None-Match 是否命中当前 ETag
+pub fn etag_matches(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.split(',').any(|t| t.trim() == etag))
+}
+
+/// 提供内嵌的静态文件
+pub async fn serve_embedded(uri: Uri, headers: HeaderMap) -> Response {
+    let path = uri.path().trim_start_matches('/');
+    let path = if path.is_empty() || path.ends_with('/') {
+        format!("{}index.html", path)
+    } else {
+        path.to_string()
+    };
+
+    let Some(file) = WebAssets::get(&path) else {
+        return (StatusCode::NOT_FOUND, "页面不存在").into_response();
+    };
+
+    // 使用内容哈希作为 ETag，浏览器可通过 304 复用缓存
+    let hash: String = file
+        .metadata
+        .sha256_hash()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    let etag = format!("\"{}\"", hash);
+
+    let cache_headers = [
+        (header::ETAG, etag.clone()),
+        (header::CACHE_CONTROL, "no-cache".to_string()),
+    ];
+    if etag_matches(&headers, &etag) {
+        return (StatusCode::NOT_MODIFIED, cache_headers).into_response();
+    }
+
+    (
+        cache_headers,
+        [(header::CONTENT_TYPE, file.metadata.mimetype().to_string())],
+        file.data,
+    )
+        .into_response()
+}
@@ -0,0 +1,124 @@
+use anyhow::{Result, anyhow};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+use tracing::warn;
+use crate::history::now_secs;
+use crate::jobs::{Job, JobQueue};
+use crate::store::Store;
+use crate::stream_manager::{StopReason, StoppedEvent};
+
+/// 回调地址最长长度
+pub const MAX_URL_LEN: usize = 2048;
+
+/// 回调请求体
+#[derive(Debug, Serialize)]
+struct Notification<'a> {
+    #[serde(flatten)]
+    event: &'a StoppedEvent,
+    /// 停止时间（Unix 秒）
+    at: u64,
+}
+
+/// API Key 的停止通知回调
+///
+/// 调用方通过 `PUT /api/webhook` 为自己的 API Key 登记回调地址，以该 Key 播放过的流停止时
+/// （无观众超时、重启次数用尽、手动停止等）向回调地址 POST 停止原因，便于业务系统更新界面状态。
+/// 回调地址保存在本地数据库，按 Key 的指纹区分，不保存 Key 本身；流与 Key 的对应关系只保存在内存中。
+/// 回调通过后台任务队列发送，失败时自动重试。
+pub struct StopWebhooks {
+    store: Arc<Store>,
+    jobs: Arc<JobQueue>,
+    /// 流名称 -> 播放过该流的 Key 指纹
+    owners: Mutex<HashMap<String, HashSet<String>>>,
+}
+
+impl StopWebhooks {
+    pub fn new(store: Arc<Store>, jobs: Arc<JobQueue>) -> Self {
+        Self {
+            store,
+            jobs,
+            owners: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// API Key 的指纹
+    pub fn key_id(key: &str) -> String {
+        Sha256::digest(key.as_bytes())[..8].iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    /// 已登记的回调地址
+    pub fn url(&self, key_id: &str) -> Result<Option<String>> {
+        self.store.webhook(key_id)
+    }
+
+    /// 登记回调地址，只接受 http / https 地址
+    pub fn register(&self, key_id: &str, url: &str) -> Result<()> {
+        if url.len() > MAX_URL_LEN {
+            return Err(anyhow!("回调地址不能超过 {} 个字符", MAX_URL_LEN));
+        }
+        let parsed = reqwest::Url::parse(url).map_err(|e| anyhow!("无效的回调地址: {}", e))?;
+        if !matches!(parsed.scheme(), "http" | "https") {
+            return Err(anyhow!("回调地址必须以 http:// 或 https:// 开头"));
+        }
+        self.store.set_webhook(key_id, Some(url))
+    }
+
+    /// 删除回调地址
+    pub fn unregister(&self, key_id: &str) -> Result<()> {
+        self.store.set_webhook(key_id, None)
+    }
+
+    /// 记录 Key 播放了流，流停止时通知该 Key
+    pub fn subscribe(&self, stream: &str, key_id: &str) {
+        self.owners.lock().unwrap().entry(stream.to_string()).or_default().insert(key_id.to_string());
+    }
+
+    /// 在后台接收流停止事件并发送回调
+    pub fn spawn(self: &Arc<Self>, mut stopped: broadcast::Receiver<StoppedEvent>) {
+        let webhooks = self.clone();
+        tokio::spawn(async move {
+            loop {
+                match stopped.recv().await {
+                    Ok(event) => webhooks.notify(event),
+                    Err(broadcast::error::RecvError::Lagged(n)) => warn!("停止通知回调落后，丢弃 {} 个事件", n),
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+
+    fn notify(&self, event: StoppedEvent) {
+        // 重启次数用尽的流仍保留在列表中，之后超时移除时还会再通知一次
+        let owners = {
+            let mut owners = self.owners.lock().unwrap();
+            match event.reason {
+                StopReason::Exhausted => owners.get(&event.stream).cloned(),
+                _ => owners.remove(&event.stream),
+            }
+        };
+        let Some(owners) = owners else {
+            return;
+        };
+        let body = match serde_json::to_value(Notification { event: &event, at: now_secs() }) {
+            Ok(body) => body,
+            Err(e) => {
+                warn!("生成停止通知失败: {}", e);
+                return;
+            }
+        };
+        for key_id in owners {
+            let url = match self.store.webhook(&key_id) {
+                Ok(Some(url)) => url,
+                Ok(None) => continue,
+                Err(e) => {
+                    warn!("读取停止通知回调地址失败: {:#}", e);
+                    continue;
+                }
+            };
+            self.jobs.enqueue(Job::StopWebhook { stream: event.stream.clone(), url, body: body.clone() });
+        }
+    }
+}